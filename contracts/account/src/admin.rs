@@ -0,0 +1,266 @@
+//! Timelock on sensitive admin operations.
+//!
+//! By default the owner makes every change directly. Once the owner sets an
+//! admin delay, the operations in [`AdminOp`] can only be queued with
+//! `queue_admin_op` and applied by `execute_admin_op` at least
+//! `delay_ledgers` ledgers later, giving the real owner a window to cancel an
+//! operation queued with a compromised key:
+//!
+//! - ownership and signers: `propose_owner`, `set_multisig`, `add_signer`,
+//!   `remove_signer`, `rotate_signer`, and naming an inheritance beneficiary;
+//! - lowering or removing the admin delay itself;
+//! - the second factor, the escalation policy and the policy modules;
+//! - raising, repointing or clearing the per-transaction value cap.
+//!
+//! Some changes have their own delay instead: upgrades, token limit
+//! increases, guardian changes and unfreezing. Changes that only tighten
+//! policy apply at once. The rest are not covered and apply at once even
+//! with a delay set, so they offer no reaction window: clearing the rate
+//! limit or the destination allowlist, lifting a denial, leaving safe mode,
+//! the backup key, session keys, observers and the registry.
+//!
+//! Like the other timelocks there is a single pending slot: queuing while an
+//! operation is waiting fails with `TimelockConflict`. A change of owner
+//! discards the pending operation.
+
+use soroban_sdk::{contracttype, symbol_short, Address, BytesN, Env, Vec};
+
+use crate::{events, ContractError, DataKey, EscalationPolicy, MaxTxValue, Signer};
+
+/// An operation that waits out the admin delay once one is set.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AdminOp {
+    /// `propose_owner(new_owner)`
+    ProposeOwner(Address),
+    /// `set_multisig(signers, threshold)`
+    SetMultisig(Vec<Signer>, u32),
+    /// `add_signer(signer)`
+    AddSigner(Signer),
+    /// `remove_signer(key)`
+    RemoveSigner(BytesN<32>),
+    /// `rotate_signer(old_key, new_key)`
+    RotateSigner(BytesN<32>, BytesN<32>),
+    /// `set_admin_delay(delay_ledgers)`, needed to lower the delay
+    SetAdminDelay(u32),
+    /// `set_two_factor(public_key, threshold)`
+    SetTwoFactor(BytesN<32>, i128),
+    /// `disable_two_factor()`
+    DisableTwoFactor,
+    /// `set_escalation_policy(policy)`
+    SetEscalationPolicy(EscalationPolicy),
+    /// `clear_escalation_policy()`
+    ClearEscalationPolicy,
+    /// `unregister_policy_module(module)`
+    UnregisterPolicyModule(Address),
+    /// `set_inheritance(beneficiary, inactivity_ledgers)`
+    SetInheritance(Address, u32),
+    /// `register_policy_module(module)`
+    RegisterPolicyModule(Address),
+    /// `set_max_tx_value(reference_token, oracle, amount)`
+    SetMaxTxValue(MaxTxValue),
+    /// `clear_max_tx_value()`
+    ClearMaxTxValue,
+}
+
+/// An admin operation waiting for its delay to pass.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PendingAdminOp {
+    pub op: AdminOp,
+    /// First ledger sequence at which the operation can be executed.
+    pub executable_at: u32,
+}
+
+/// Ledgers an admin operation waits between queuing and execution.
+pub fn delay(env: &Env) -> u32 {
+    env.storage()
+        .instance()
+        .get(&DataKey::AdminDelay)
+        .unwrap_or(0)
+}
+
+pub fn set_delay(env: &Env, delay_ledgers: u32) {
+    env.storage()
+        .instance()
+        .set(&DataKey::AdminDelay, &delay_ledgers);
+
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("adm_delay")),
+        delay_ledgers,
+    );
+}
+
+/// Fail with `AdminOpTimelocked` if admin operations must be queued.
+pub fn check_unlocked(env: &Env) -> Result<(), ContractError> {
+    if delay(env) > 0 {
+        return Err(ContractError::AdminOpTimelocked);
+    }
+    Ok(())
+}
+
+pub fn pending(env: &Env) -> Option<PendingAdminOp> {
+    env.storage().instance().get(&DataKey::PendingAdminOp)
+}
+
+pub fn queue(env: &Env, op: &AdminOp) -> Result<PendingAdminOp, ContractError> {
+    if pending(env).is_some() {
+        return Err(ContractError::TimelockConflict);
+    }
+
+    let pending = PendingAdminOp {
+        op: op.clone(),
+        executable_at: env.ledger().sequence().saturating_add(delay(env)),
+    };
+    env.storage()
+        .instance()
+        .set(&DataKey::PendingAdminOp, &pending);
+
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("adm_queue")),
+        (op.clone(), pending.executable_at),
+    );
+    Ok(pending)
+}
+
+pub fn cancel(env: &Env) -> Result<(), ContractError> {
+    let pending = pending(env).ok_or(ContractError::NoAdminOpPending)?;
+    discard(env);
+
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("adm_cncl")),
+        pending.op,
+    );
+    Ok(())
+}
+
+/// Remove the pending operation once its delay has passed, returning it.
+pub fn take_ready(env: &Env) -> Result<AdminOp, ContractError> {
+    let pending = pending(env).ok_or(ContractError::NoAdminOpPending)?;
+    if env.ledger().sequence() < pending.executable_at {
+        return Err(ContractError::TimelockNotExpired);
+    }
+    discard(env);
+
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("adm_exec")),
+        pending.op.clone(),
+    );
+    Ok(pending.op)
+}
+
+/// Drop the pending operation, if any.
+pub fn discard(env: &Env) {
+    env.storage().instance().remove(&DataKey::PendingAdminOp);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{AncoreAccount, AncoreAccountClient};
+    use soroban_sdk::testutils::{Address as _, Ledger as _};
+
+    const DELAY: u32 = 100;
+
+    fn setup(env: &Env) -> AncoreAccountClient<'_> {
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(env, &contract_id);
+        client.initialize(&Address::generate(env));
+        client.set_admin_delay(&DELAY);
+        client
+    }
+
+    fn advance(env: &Env, ledgers: u32) {
+        env.ledger().with_mut(|li| li.sequence_number += ledgers);
+    }
+
+    #[test]
+    fn test_admin_op_waits_for_delay() {
+        let env = Env::default();
+        let client = setup(&env);
+        let new_owner = Address::generate(&env);
+
+        assert_eq!(
+            client.try_propose_owner(&new_owner),
+            Err(Ok(ContractError::AdminOpTimelocked))
+        );
+        assert_eq!(
+            client.try_execute_admin_op(),
+            Err(Ok(ContractError::NoAdminOpPending))
+        );
+
+        let op = AdminOp::ProposeOwner(new_owner.clone());
+        client.queue_admin_op(&op);
+        assert_eq!(
+            client.get_pending_admin_op(),
+            Some(PendingAdminOp {
+                op: op.clone(),
+                executable_at: env.ledger().sequence() + DELAY,
+            })
+        );
+        assert_eq!(
+            client.try_queue_admin_op(&op),
+            Err(Ok(ContractError::TimelockConflict))
+        );
+
+        advance(&env, DELAY - 1);
+        assert_eq!(
+            client.try_execute_admin_op(),
+            Err(Ok(ContractError::TimelockNotExpired))
+        );
+        assert_eq!(client.get_owner_proposal(), None);
+
+        advance(&env, 1);
+        client.execute_admin_op();
+        assert_eq!(client.get_owner_proposal().unwrap().new_owner, new_owner);
+        assert_eq!(client.get_pending_admin_op(), None);
+    }
+
+    #[test]
+    fn test_cancel_admin_op() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        client.queue_admin_op(&AdminOp::ProposeOwner(Address::generate(&env)));
+        client.cancel_admin_op();
+        assert_eq!(client.get_pending_admin_op(), None);
+        assert_eq!(
+            client.try_cancel_admin_op(),
+            Err(Ok(ContractError::NoAdminOpPending))
+        );
+
+        advance(&env, DELAY);
+        assert_eq!(
+            client.try_execute_admin_op(),
+            Err(Ok(ContractError::NoAdminOpPending))
+        );
+        assert_eq!(client.get_owner_proposal(), None);
+    }
+
+    #[test]
+    fn test_lowering_admin_delay_is_timelocked() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        client.set_admin_delay(&(DELAY * 2));
+        assert_eq!(client.get_admin_delay(), DELAY * 2);
+        assert_eq!(
+            client.try_set_admin_delay(&0),
+            Err(Ok(ContractError::AdminOpTimelocked))
+        );
+
+        client.queue_admin_op(&AdminOp::SetAdminDelay(0));
+        advance(&env, DELAY * 2);
+        client.execute_admin_op();
+        assert_eq!(client.get_admin_delay(), 0);
+
+        let new_owner = Address::generate(&env);
+        client.propose_owner(&new_owner);
+        assert_eq!(client.get_owner_proposal().unwrap().new_owner, new_owner);
+    }
+}
@@ -0,0 +1,252 @@
+//! On-chain log of recent executions.
+//!
+//! Events are only kept by whoever indexes them. An owner who wants history
+//! that lives with the account can enable a bounded ring buffer of its most
+//! recent executions: who made the call, the target, the function, the
+//! ledger and a hash of what the target returned. Only the account writes
+//! to it, so the log cannot be edited from outside.
+//!
+//! The log is off until the owner sets a size. Each entry has its own
+//! storage slot and a fixed size, so recording costs one small write
+//! however large the log or the call's return value is.
+//! A failed call reverts along with its entry, so only executions that went
+//! through are logged.
+
+use soroban_sdk::{contracttype, symbol_short, xdr::ToXdr, Address, BytesN, Env, Symbol, Val, Vec};
+
+use crate::{events, ContractError, DataKey};
+
+/// Largest audit log an owner can configure.
+pub const AUDIT_LOG_MAX_SIZE: u32 = 256;
+
+/// Who authorized a logged execution.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AuditCaller {
+    /// The owner, directly or through a relayer
+    Owner,
+    /// A session key
+    SessionKey(BytesN<32>),
+    /// An approved multisig proposal
+    Proposal(u64),
+}
+
+/// One logged execution.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AuditEntry {
+    pub caller: AuditCaller,
+    pub to: Address,
+    pub function: Symbol,
+    /// Ledger sequence the call ran in
+    pub ledger: u32,
+    /// `sha256` of the XDR-encoded value the target returned
+    pub result_hash: BytesN<32>,
+}
+
+/// Ring buffer position: `count` entries were ever recorded into `size`
+/// slots, so the newest is in slot `(count - 1) % size`.
+#[contracttype]
+#[derive(Clone)]
+pub struct AuditLog {
+    pub size: u32,
+    pub count: u64,
+}
+
+fn log(env: &Env) -> AuditLog {
+    env.storage()
+        .instance()
+        .get(&DataKey::AuditLog)
+        .unwrap_or(AuditLog { size: 0, count: 0 })
+}
+
+pub fn size(env: &Env) -> u32 {
+    log(env).size
+}
+
+/// Resize the log, clearing it; `0` turns it off.
+pub fn set_size(env: &Env, size: u32) -> Result<(), ContractError> {
+    if size > AUDIT_LOG_MAX_SIZE {
+        return Err(ContractError::InvalidLimit);
+    }
+
+    let old = log(env);
+    for slot in 0..old.count.min(old.size as u64) {
+        env.storage()
+            .persistent()
+            .remove(&DataKey::AuditEntry(slot as u32));
+    }
+    if size == 0 {
+        env.storage().instance().remove(&DataKey::AuditLog);
+    } else {
+        env.storage()
+            .instance()
+            .set(&DataKey::AuditLog, &AuditLog { size, count: 0 });
+    }
+
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("audit_sz")),
+        size,
+    );
+    Ok(())
+}
+
+/// Log an execution that went through, if the log is on.
+pub fn record(env: &Env, caller: AuditCaller, to: &Address, function: &Symbol, result: Val) {
+    let mut log = log(env);
+    if log.size == 0 {
+        return;
+    }
+
+    let slot = (log.count % log.size as u64) as u32;
+    let entry = AuditEntry {
+        caller,
+        to: to.clone(),
+        function: function.clone(),
+        ledger: env.ledger().sequence(),
+        result_hash: env.crypto().sha256(&result.to_xdr(env)).into(),
+    };
+    env.storage()
+        .persistent()
+        .set(&DataKey::AuditEntry(slot), &entry);
+    log.count += 1;
+    env.storage().instance().set(&DataKey::AuditLog, &log);
+}
+
+/// Up to `limit` logged executions, newest first, skipping the `offset`
+/// most recent.
+pub fn list(env: &Env, offset: u32, limit: u32) -> Vec<AuditEntry> {
+    let log = log(env);
+    let mut entries = Vec::new(env);
+    let stored = log.count.min(log.size as u64);
+    let mut back = offset as u64;
+    while back < stored && entries.len() < limit {
+        let slot = ((log.count - 1 - back) % log.size as u64) as u32;
+        if let Some(entry) = env.storage().persistent().get(&DataKey::AuditEntry(slot)) {
+            entries.push_back(entry);
+        }
+        back += 1;
+    }
+    entries
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        testutils::{register_target, MockTargetClient},
+        AncoreAccount, AncoreAccountClient, Permission,
+    };
+    use ed25519_dalek::{Signer as _, SigningKey};
+    use soroban_sdk::{testutils::Address as _, vec, IntoVal};
+
+    fn setup(env: &Env) -> (AncoreAccountClient<'_>, MockTargetClient<'_>) {
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(env, &contract_id);
+        client.initialize(&Address::generate(env));
+        (client, MockTargetClient::new(env, &register_target(env)))
+    }
+
+    fn ping(env: &Env, client: &AncoreAccountClient, target: &Address, nonce: u64) {
+        client.execute(
+            target,
+            &Symbol::new(env, "ping"),
+            &Vec::new(env),
+            &0,
+            &nonce,
+        );
+    }
+
+    #[test]
+    fn test_audit_log_is_off_by_default() {
+        let env = Env::default();
+        let (client, target) = setup(&env);
+
+        ping(&env, &client, &target.address, 0);
+        assert_eq!(client.get_audit_log_size(), 0);
+        assert!(client.get_audit_log(&0, &10).is_empty());
+    }
+
+    #[test]
+    fn test_audit_log_keeps_most_recent_executions() {
+        let env = Env::default();
+        let (client, target) = setup(&env);
+        client.set_audit_log_size(&3);
+
+        for nonce in 0..4 {
+            ping(&env, &client, &target.address, nonce);
+        }
+        client.execute(
+            &target.address,
+            &Symbol::new(&env, "transfer"),
+            &vec![
+                &env,
+                client.address.into_val(&env),
+                target.address.into_val(&env),
+                5i128.into_val(&env),
+            ],
+            &0,
+            &4,
+        );
+
+        let log = client.get_audit_log(&0, &10);
+        assert_eq!(log.len(), 3);
+        let newest = log.get_unchecked(0);
+        assert_eq!(newest.caller, AuditCaller::Owner);
+        assert_eq!(newest.to, target.address);
+        assert_eq!(newest.function, Symbol::new(&env, "transfer"));
+        assert_eq!(newest.ledger, env.ledger().sequence());
+        assert_eq!(log.get_unchecked(1).function, Symbol::new(&env, "ping"));
+
+        // Paging walks back from the newest entry.
+        assert_eq!(
+            client.get_audit_log(&1, &1),
+            vec![&env, log.get_unchecked(1)]
+        );
+        assert!(client.get_audit_log(&3, &10).is_empty());
+
+        // Resizing clears the log.
+        client.set_audit_log_size(&5);
+        assert!(client.get_audit_log(&0, &10).is_empty());
+        assert_eq!(
+            client.try_set_audit_log_size(&(AUDIT_LOG_MAX_SIZE + 1)),
+            Err(Ok(ContractError::InvalidLimit))
+        );
+    }
+
+    #[test]
+    fn test_audit_log_records_session_key_and_result() {
+        let env = Env::default();
+        let (client, target) = setup(&env);
+        client.set_audit_log_size(&4);
+
+        let key = SigningKey::from_bytes(&[3u8; 32]);
+        let session_pk = BytesN::from_array(&env, &key.verifying_key().to_bytes());
+        client.add_session_key(&session_pk, &1000, &vec![&env, Permission::Execute]);
+
+        let received = Symbol::new(&env, "received");
+        let args = vec![&env, target.address.into_val(&env)];
+        target.transfer(&client.address, &target.address, &7);
+        let payload = client.get_session_payload(&target.address, &received, &args, &0);
+        let signature = BytesN::from_array(&env, &key.sign(&payload.to_array()).to_bytes());
+        client.execute_with_session(
+            &session_pk,
+            &signature,
+            &target.address,
+            &received,
+            &args,
+            &0,
+        );
+
+        let entry = client.get_audit_log(&0, &1).get_unchecked(0);
+        assert_eq!(entry.caller, AuditCaller::SessionKey(session_pk));
+        assert_eq!(entry.function, received);
+        let result: Val = 7i128.into_val(&env);
+        assert_eq!(
+            entry.result_hash,
+            BytesN::from(env.crypto().sha256(&result.to_xdr(&env)))
+        );
+    }
+}
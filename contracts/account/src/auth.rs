@@ -0,0 +1,11 @@
+//! Signatures made by the keys that control the account.
+
+use soroban_sdk::{contracttype, BytesN};
+
+/// Signature presented to `__check_auth` when the account authorizes a call.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AccountSignature {
+    pub public_key: BytesN<32>,
+    pub signature: BytesN<64>,
+}
@@ -0,0 +1,343 @@
+//! Backup-key recovery.
+//!
+//! The owner can register the sha256 hash of a single ed25519 backup key
+//! (e.g. a hardware key kept offline). Whoever controls that key can start a
+//! recovery to a new owner by signing a challenge; the change only happens
+//! after [`BACKUP_RECOVERY_DELAY`], during which the current owner can veto
+//! it.
+//!
+//! ## Precedence
+//! - The current owner always wins: a pending backup recovery is discarded
+//!   by `cancel_backup_recovery` and by any change of owner.
+//! - Only one backup recovery can be pending; it must be vetoed or
+//!   finalized before another can start.
+//! - A pending backup recovery blocks queuing token limit increases, so the
+//!   account cannot be loosened while its ownership is contested. Tightening
+//!   limits stays allowed, and a pending increase never blocks a recovery.
+//! - Each challenge embeds a counter that advances on every initiation, so a
+//!   captured signature cannot restart a vetoed recovery.
+
+use soroban_sdk::{contracttype, symbol_short, xdr::ToXdr, Address, Bytes, BytesN, Env};
+
+use crate::{events, ContractError, DataKey};
+
+/// Seconds between initiating and finalizing a backup recovery.
+pub const BACKUP_RECOVERY_DELAY: u64 = 7 * 86_400;
+
+const CHALLENGE_DOMAIN: &[u8] = b"ancore-backup-recovery";
+
+/// A backup recovery waiting for its timelock.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BackupRecovery {
+    pub new_owner: Address,
+    pub executable_at: u64,
+}
+
+pub fn set_key_hash(env: &Env, key_hash: &BytesN<32>) {
+    env.storage()
+        .instance()
+        .set(&DataKey::BackupKeyHash, key_hash);
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("bk_set")),
+        key_hash.clone(),
+    );
+}
+
+/// Forget the backup key, e.g. because the owner changed.
+pub fn clear_key_hash(env: &Env) {
+    env.storage().instance().remove(&DataKey::BackupKeyHash);
+}
+
+pub fn key_hash(env: &Env) -> Option<BytesN<32>> {
+    env.storage().instance().get(&DataKey::BackupKeyHash)
+}
+
+/// Message the backup key signs to start a recovery to `new_owner`, bound to
+/// this account and network.
+pub fn challenge(env: &Env, new_owner: &Address) -> BytesN<32> {
+    let counter: u32 = env
+        .storage()
+        .instance()
+        .get(&DataKey::BackupChallengeCounter)
+        .unwrap_or(0);
+
+    let mut preimage = Bytes::from_slice(env, CHALLENGE_DOMAIN);
+    preimage.append(&env.current_contract_address().to_xdr(env));
+    preimage.append(&Bytes::from(env.ledger().network_id()));
+    preimage.append(&new_owner.clone().to_xdr(env));
+    preimage.extend_from_array(&counter.to_be_bytes());
+    env.crypto().sha256(&preimage).into()
+}
+
+pub fn initiate(
+    env: &Env,
+    public_key: &BytesN<32>,
+    new_owner: &Address,
+    signature: &BytesN<64>,
+) -> Result<(), ContractError> {
+    let key_hash = key_hash(env).ok_or(ContractError::BackupKeyMismatch)?;
+    let presented: BytesN<32> = env
+        .crypto()
+        .sha256(&Bytes::from_array(env, &public_key.to_array()))
+        .into();
+    if presented != key_hash {
+        return Err(ContractError::BackupKeyMismatch);
+    }
+    if pending(env).is_some() {
+        return Err(ContractError::RecoveryAlreadyPending);
+    }
+
+    let challenge = challenge(env, new_owner);
+    env.crypto()
+        .ed25519_verify(public_key, &challenge.into(), signature);
+
+    let counter: u32 = env
+        .storage()
+        .instance()
+        .get(&DataKey::BackupChallengeCounter)
+        .unwrap_or(0);
+    env.storage()
+        .instance()
+        .set(&DataKey::BackupChallengeCounter, &(counter + 1));
+
+    let recovery = BackupRecovery {
+        new_owner: new_owner.clone(),
+        executable_at: env.ledger().timestamp() + BACKUP_RECOVERY_DELAY,
+    };
+    env.storage()
+        .instance()
+        .set(&DataKey::BackupRecovery, &recovery);
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("bk_init")),
+        recovery,
+    );
+    Ok(())
+}
+
+pub fn pending(env: &Env) -> Option<BackupRecovery> {
+    env.storage().instance().get(&DataKey::BackupRecovery)
+}
+
+pub fn cancel(env: &Env) -> Result<(), ContractError> {
+    let recovery = pending(env).ok_or(ContractError::NoRecoveryPending)?;
+    discard(env);
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("bk_cancel")),
+        recovery.new_owner,
+    );
+    Ok(())
+}
+
+/// Drop any pending backup recovery, e.g. because the owner changed.
+pub fn discard(env: &Env) {
+    env.storage().instance().remove(&DataKey::BackupRecovery);
+}
+
+/// Complete a pending recovery whose timelock has passed, returning the new
+/// owner.
+pub fn finalize(env: &Env) -> Result<Address, ContractError> {
+    let recovery = pending(env).ok_or(ContractError::NoRecoveryPending)?;
+    if env.ledger().timestamp() < recovery.executable_at {
+        return Err(ContractError::TimelockNotExpired);
+    }
+
+    discard(env);
+    Ok(recovery.new_owner)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{AncoreAccount, AncoreAccountClient};
+    use ed25519_dalek::{Signer, SigningKey};
+    use soroban_sdk::testutils::{Address as _, Ledger as _};
+
+    struct Setup<'a> {
+        env: Env,
+        owner: Address,
+        backup_key: SigningKey,
+        client: AncoreAccountClient<'a>,
+    }
+
+    fn setup<'a>() -> Setup<'a> {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(&env, &contract_id);
+        let owner = Address::generate(&env);
+        client.initialize(&owner);
+
+        let backup_key = SigningKey::from_bytes(&[4u8; 32]);
+        let key_hash: BytesN<32> = env
+            .crypto()
+            .sha256(&Bytes::from_array(
+                &env,
+                &backup_key.verifying_key().to_bytes(),
+            ))
+            .into();
+        client.set_backup_key_hash(&key_hash);
+
+        Setup {
+            env,
+            owner,
+            backup_key,
+            client,
+        }
+    }
+
+    fn public_key(env: &Env, key: &SigningKey) -> BytesN<32> {
+        BytesN::from_array(env, &key.verifying_key().to_bytes())
+    }
+
+    fn sign_challenge(s: &Setup, key: &SigningKey, new_owner: &Address) -> BytesN<64> {
+        let challenge = s.client.get_backup_recovery_challenge(new_owner);
+        BytesN::from_array(&s.env, &key.sign(&challenge.to_array()).to_bytes())
+    }
+
+    #[test]
+    fn test_backup_recovery_after_timelock() {
+        let s = setup();
+        let new_owner = Address::generate(&s.env);
+        let signature = sign_challenge(&s, &s.backup_key, &new_owner);
+
+        s.client.initiate_backup_recovery(
+            &public_key(&s.env, &s.backup_key),
+            &new_owner,
+            &signature,
+        );
+        assert_eq!(
+            s.client.get_backup_recovery(),
+            Some(BackupRecovery {
+                new_owner: new_owner.clone(),
+                executable_at: BACKUP_RECOVERY_DELAY,
+            })
+        );
+
+        s.env
+            .ledger()
+            .with_mut(|li| li.timestamp = BACKUP_RECOVERY_DELAY - 1);
+        assert_eq!(
+            s.client.try_finalize_backup_recovery(),
+            Err(Ok(ContractError::TimelockNotExpired))
+        );
+        assert_eq!(s.client.get_owner(), s.owner);
+
+        s.env
+            .ledger()
+            .with_mut(|li| li.timestamp = BACKUP_RECOVERY_DELAY);
+        s.client.finalize_backup_recovery();
+        assert_eq!(s.client.get_owner(), new_owner);
+        assert_eq!(s.client.get_backup_recovery(), None);
+        assert_eq!(s.client.get_metrics().recoveries, 1);
+    }
+
+    #[test]
+    fn test_backup_challenge_binds_network() {
+        let s = setup();
+        let new_owner = Address::generate(&s.env);
+        let challenge = s.client.get_backup_recovery_challenge(&new_owner);
+
+        s.env.ledger().with_mut(|li| li.network_id = [9u8; 32]);
+        assert_ne!(
+            s.client.get_backup_recovery_challenge(&new_owner),
+            challenge
+        );
+    }
+
+    #[test]
+    fn test_backup_recovery_vetoed_by_owner() {
+        let s = setup();
+        let new_owner = Address::generate(&s.env);
+        let backup_pk = public_key(&s.env, &s.backup_key);
+        let signature = sign_challenge(&s, &s.backup_key, &new_owner);
+
+        s.client
+            .initiate_backup_recovery(&backup_pk, &new_owner, &signature);
+        s.client.cancel_backup_recovery();
+        assert_eq!(s.client.get_backup_recovery(), None);
+
+        s.env
+            .ledger()
+            .with_mut(|li| li.timestamp = BACKUP_RECOVERY_DELAY);
+        assert_eq!(
+            s.client.try_finalize_backup_recovery(),
+            Err(Ok(ContractError::NoRecoveryPending))
+        );
+        assert_eq!(s.client.get_owner(), s.owner);
+
+        // The vetoed signature cannot restart the recovery.
+        assert!(s
+            .client
+            .try_initiate_backup_recovery(&backup_pk, &new_owner, &signature)
+            .is_err());
+        assert_eq!(s.client.get_backup_recovery(), None);
+    }
+
+    #[test]
+    fn test_backup_recovery_rejects_unknown_key() {
+        let s = setup();
+        let new_owner = Address::generate(&s.env);
+        let impostor = SigningKey::from_bytes(&[5u8; 32]);
+        let signature = sign_challenge(&s, &impostor, &new_owner);
+
+        assert_eq!(
+            s.client.try_initiate_backup_recovery(
+                &public_key(&s.env, &impostor),
+                &new_owner,
+                &signature
+            ),
+            Err(Ok(ContractError::BackupKeyMismatch))
+        );
+    }
+
+    #[test]
+    fn test_backup_recovery_blocks_limit_increase() {
+        let s = setup();
+        let token = Address::generate(&s.env);
+        s.client.set_token_limit(&token, &1_000, &86_400);
+        s.client.set_token_limit(&token, &2_000, &86_400);
+
+        let new_owner = Address::generate(&s.env);
+        let signature = sign_challenge(&s, &s.backup_key, &new_owner);
+        s.client.initiate_backup_recovery(
+            &public_key(&s.env, &s.backup_key),
+            &new_owner,
+            &signature,
+        );
+
+        let other_token = Address::generate(&s.env);
+        s.client.set_token_limit(&other_token, &1_000, &86_400);
+        assert_eq!(
+            s.client.try_set_token_limit(&other_token, &5_000, &86_400),
+            Err(Ok(ContractError::TimelockConflict))
+        );
+        s.client.set_token_limit(&other_token, &500, &86_400);
+
+        // Vetoing the recovery lifts the restriction.
+        s.client.cancel_backup_recovery();
+        s.client.set_token_limit(&other_token, &5_000, &86_400);
+        assert!(s.client.get_pending_token_limit(&other_token).is_some());
+    }
+
+    #[test]
+    fn test_backup_recovery_single_pending() {
+        let s = setup();
+        let backup_pk = public_key(&s.env, &s.backup_key);
+        let first = Address::generate(&s.env);
+        let signature = sign_challenge(&s, &s.backup_key, &first);
+        s.client
+            .initiate_backup_recovery(&backup_pk, &first, &signature);
+
+        let second = Address::generate(&s.env);
+        let signature = sign_challenge(&s, &s.backup_key, &second);
+        assert_eq!(
+            s.client
+                .try_initiate_backup_recovery(&backup_pk, &second, &signature),
+            Err(Ok(ContractError::RecoveryAlreadyPending))
+        );
+    }
+}
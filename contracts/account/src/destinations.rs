@@ -0,0 +1,240 @@
+//! Destination policy for outgoing calls.
+//!
+//! The owner can restrict the account to an allowlist of contracts, for
+//! example a treasury that should only ever talk to a few known venues.
+//! While the allowlist is set, every call the account makes, whoever
+//! authorized it, must target a listed contract or fails with
+//! `DestinationNotAllowed`. Token transfers count as calls to the token
+//! contract, so tokens the account moves must be listed too.
+//!
+//! Independently, the owner can deny single addresses, such as a contract
+//! found to be malicious, without locking down everything else. A denied
+//! address can neither be called nor receive a token transfer, and fails
+//! with `DestinationDenied`. Denial wins over the allowlist. Each denied
+//! address has its own storage entry, so the list has no size limit and
+//! checking it costs the same however long it grows.
+
+use soroban_sdk::{symbol_short, Address, Env, Vec};
+
+use crate::{events, ContractError, DataKey};
+
+/// Maximum number of allowlisted destinations, bounding the cost of each
+/// call check.
+pub const MAX_ALLOWED_DESTINATIONS: u32 = 32;
+
+pub fn allowlist(env: &Env) -> Option<Vec<Address>> {
+    env.storage().instance().get(&DataKey::DestinationAllowlist)
+}
+
+/// Only allow calls to `destinations` from now on.
+pub fn set_allowlist(env: &Env, destinations: &Vec<Address>) -> Result<(), ContractError> {
+    if destinations.len() > MAX_ALLOWED_DESTINATIONS {
+        return Err(ContractError::InvalidLimit);
+    }
+
+    env.storage()
+        .instance()
+        .set(&DataKey::DestinationAllowlist, destinations);
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("allow_set")),
+        destinations.clone(),
+    );
+    Ok(())
+}
+
+/// Allow calls to any destination again.
+pub fn clear_allowlist(env: &Env) {
+    env.storage()
+        .instance()
+        .remove(&DataKey::DestinationAllowlist);
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("allow_off")),
+        (),
+    );
+}
+
+pub fn is_denied(env: &Env, destination: &Address) -> bool {
+    env.storage()
+        .persistent()
+        .has(&DataKey::DeniedDestination(destination.clone()))
+}
+
+/// Block calls and transfers to `destination`.
+pub fn deny(env: &Env, destination: &Address) {
+    env.storage()
+        .persistent()
+        .set(&DataKey::DeniedDestination(destination.clone()), &true);
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("deny")),
+        destination.clone(),
+    );
+}
+
+/// Lift a denial made with [`deny`].
+pub fn undeny(env: &Env, destination: &Address) {
+    env.storage()
+        .persistent()
+        .remove(&DataKey::DeniedDestination(destination.clone()));
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("undeny")),
+        destination.clone(),
+    );
+}
+
+/// Check the account may call `to` and, for a token transfer, pay
+/// `recipient`.
+pub fn check(env: &Env, to: &Address, recipient: Option<&Address>) -> Result<(), ContractError> {
+    if is_denied(env, to) || recipient.is_some_and(|recipient| is_denied(env, recipient)) {
+        return Err(ContractError::DestinationDenied);
+    }
+    match allowlist(env) {
+        Some(allowed) if !allowed.contains(to) => Err(ContractError::DestinationNotAllowed),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        testutils::{register_target, MockTargetClient},
+        AncoreAccount, AncoreAccountClient, Call, Permission,
+    };
+    use ed25519_dalek::{Signer as _, SigningKey};
+    use soroban_sdk::{testutils::Address as _, vec, BytesN, IntoVal, Symbol};
+
+    fn setup(env: &Env) -> AncoreAccountClient<'_> {
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(env, &contract_id);
+        client.initialize(&Address::generate(env));
+        client
+    }
+
+    #[test]
+    fn test_allowlist_restricts_call_targets() {
+        let env = Env::default();
+        let client = setup(&env);
+        let venue = MockTargetClient::new(&env, &register_target(&env));
+        let other = register_target(&env);
+        let ping = Symbol::new(&env, "ping");
+
+        client.set_destination_allowlist(&vec![&env, venue.address.clone()]);
+        assert_eq!(
+            client.get_destination_allowlist(),
+            Some(vec![&env, venue.address.clone()])
+        );
+
+        client.execute(&venue.address, &ping, &Vec::new(&env), &0, &0);
+        assert_eq!(
+            client.try_execute(&other, &ping, &Vec::new(&env), &0, &1),
+            Err(Ok(ContractError::DestinationNotAllowed))
+        );
+
+        // One unlisted call fails the whole batch.
+        let call = |to: &Address| Call {
+            to: to.clone(),
+            function: ping.clone(),
+            args: Vec::new(&env),
+        };
+        assert_eq!(
+            client.try_execute_batch(&vec![&env, call(&venue.address), call(&other)], &0, &1),
+            Err(Ok(ContractError::DestinationNotAllowed))
+        );
+
+        client.clear_destination_allowlist();
+        assert_eq!(client.get_destination_allowlist(), None);
+        client.execute(&other, &ping, &Vec::new(&env), &0, &1);
+    }
+
+    #[test]
+    fn test_allowlist_is_bounded() {
+        let env = Env::default();
+        let client = setup(&env);
+        let mut destinations = Vec::new(&env);
+        for _ in 0..=MAX_ALLOWED_DESTINATIONS {
+            destinations.push_back(Address::generate(&env));
+        }
+
+        assert_eq!(
+            client.try_set_destination_allowlist(&destinations),
+            Err(Ok(ContractError::InvalidLimit))
+        );
+        assert_eq!(client.get_destination_allowlist(), None);
+    }
+
+    #[test]
+    fn test_denied_destination_cannot_be_called_or_paid() {
+        let env = Env::default();
+        let client = setup(&env);
+        let token = MockTargetClient::new(&env, &register_target(&env));
+        let scam = register_target(&env);
+        let ping = Symbol::new(&env, "ping");
+        let transfer = Symbol::new(&env, "transfer");
+        let pay = |to: &Address| {
+            vec![
+                &env,
+                client.address.into_val(&env),
+                to.into_val(&env),
+                5i128.into_val(&env),
+            ]
+        };
+
+        client.deny_destination(&scam);
+        assert_eq!(env.auths()[0].0, client.get_owner());
+        assert!(client.is_destination_denied(&scam));
+
+        assert_eq!(
+            client.try_execute(&scam, &ping, &Vec::new(&env), &0, &0),
+            Err(Ok(ContractError::DestinationDenied))
+        );
+        assert_eq!(
+            client.try_execute(&token.address, &transfer, &pay(&scam), &0, &0),
+            Err(Ok(ContractError::DestinationDenied))
+        );
+
+        // Denial wins over the allowlist.
+        client.set_destination_allowlist(&vec![&env, scam.clone()]);
+        assert_eq!(
+            client.try_execute(&scam, &ping, &Vec::new(&env), &0, &0),
+            Err(Ok(ContractError::DestinationDenied))
+        );
+        client.clear_destination_allowlist();
+
+        client.undeny_destination(&scam);
+        assert!(!client.is_destination_denied(&scam));
+        client.execute(&token.address, &transfer, &pay(&scam), &0, &0);
+        assert_eq!(token.received(&scam), 5);
+    }
+
+    #[test]
+    fn test_denylist_applies_to_session_keys() {
+        let env = Env::default();
+        let client = setup(&env);
+        let scam = register_target(&env);
+        client.deny_destination(&scam);
+
+        let key = SigningKey::from_bytes(&[4u8; 32]);
+        let session_pk = BytesN::from_array(&env, &key.verifying_key().to_bytes());
+        client.add_session_key(&session_pk, &1000, &vec![&env, Permission::Execute]);
+
+        let ping = Symbol::new(&env, "ping");
+        let payload = client.get_session_payload(&scam, &ping, &Vec::new(&env), &0);
+        let signature = BytesN::from_array(&env, &key.sign(&payload.to_array()).to_bytes());
+        assert_eq!(
+            client.try_execute_with_session(
+                &session_pk,
+                &signature,
+                &scam,
+                &ping,
+                &Vec::new(&env),
+                &0,
+            ),
+            Err(Ok(ContractError::DestinationDenied))
+        );
+    }
+}
@@ -0,0 +1,191 @@
+use soroban_sdk::contracterror;
+
+/// Errors returned by the account contract.
+///
+/// The discriminants are the contract error codes seen by clients and other
+/// contracts, so existing values must never be renumbered or reused. They
+/// surface as `soroban_sdk::Error`s of type `Contract` with the same code;
+/// [`ContractError::from_error`] maps them back.
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum ContractError {
+    /// An outgoing transfer would exceed the token's spend limit
+    SpendLimitExceeded = 1,
+    /// A limit amount or period is zero or negative
+    InvalidLimit = 2,
+    /// An amount calculation overflowed
+    Overflow = 3,
+    /// The value moved by a transaction exceeds the configured cap
+    TxValueExceeded = 4,
+    /// The price oracle returned a zero or negative price
+    InvalidPrice = 5,
+    /// A detected transfer moves nothing and no-op rejection is enabled
+    NoOp = 6,
+    /// No backup key is set, or the presented key does not match its hash
+    BackupKeyMismatch = 7,
+    /// A recovery is already in progress
+    RecoveryAlreadyPending = 8,
+    /// There is no recovery in progress
+    NoRecoveryPending = 9,
+    /// The recovery's timelock has not passed yet
+    TimelockNotExpired = 10,
+    /// A detected transfer has a negative amount
+    InvalidAmount = 11,
+    /// The observer registry is full
+    TooManyObservers = 12,
+    /// Another operation of the same or a conflicting class is already scheduled
+    TimelockConflict = 13,
+    /// Strict mode requires session keys to carry at least one permission
+    EmptyPermissions = 14,
+    /// The call is not allowed in the account's current mode
+    Unauthorized = 15,
+    /// The contract called by `execute` failed
+    InvocationFailed = 16,
+    /// The expected nonce does not match the account's current nonce
+    InvalidNonce = 17,
+    /// The signing key is not one of the account's keys
+    UnknownSigner = 18,
+    /// The session key has expired
+    SessionKeyExpired = 19,
+    /// The session key lacks a permission the operation needs
+    InsufficientPermission = 20,
+    /// The session key has used up its allowed number of operations
+    SessionKeyExhausted = 21,
+    /// The session key's activation time has not been reached
+    SessionKeyNotYetValid = 22,
+    /// The signing threshold is zero or more than the signers can meet, or a
+    /// signer has no weight
+    InvalidThreshold = 23,
+    /// The same signer appears more than once
+    DuplicateSigner = 24,
+    /// Too few signers signed to meet the threshold
+    ThresholdNotMet = 25,
+    /// There is no ownership transfer waiting to be accepted
+    NoOwnerProposal = 26,
+    /// The proposed owner did not accept in time
+    OwnerProposalExpired = 27,
+    /// No transaction proposal has this id
+    UnknownProposal = 28,
+    /// An admin delay is set, so the operation must go through `queue_admin_op`
+    AdminOpTimelocked = 29,
+    /// There is no admin operation queued
+    NoAdminOpPending = 30,
+    /// The caller is not one of the account's guardians
+    NotGuardian = 31,
+    /// A guardian is listed or approves more than once
+    DuplicateGuardian = 32,
+    /// There is no guardian change queued
+    NoGuardianChangePending = 33,
+    /// No beneficiary is set to inherit the account
+    NoInheritance = 34,
+    /// The owner has been active within the inactivity period
+    OwnerActive = 35,
+    /// The account is frozen
+    Frozen = 36,
+    /// The account is not frozen
+    NotFrozen = 37,
+    /// The owner has not requested an unfreeze
+    UnfreezeNotRequested = 38,
+    /// There is no upgrade proposed
+    NoUpgradePending = 39,
+    /// Storage was written by a newer version of the contract
+    InvalidStorageVersion = 40,
+    /// The paymaster quoted more than the most the owner agreed to repay
+    QuoteExceeded = 41,
+    /// A signed payload is past its expiry
+    PayloadExpired = 42,
+    /// A signed payload was signed for a different network
+    WrongNetwork = 43,
+    /// The destination allowlist does not include the call's target
+    DestinationNotAllowed = 44,
+    /// The call's target or transfer recipient is on the denylist
+    DestinationDenied = 45,
+    /// The rate limit's window has no calls left
+    RateLimited = 46,
+    /// A transfer above the two-factor threshold was not co-signed
+    SecondFactorRequired = 47,
+    /// The transfer's amount needs an approved proposal under the escalation policy
+    ProposalRequired = 48,
+    /// The maximum number of policy modules is already registered
+    TooManyPolicyModules = 49,
+    /// A policy module rejected the call or failed
+    PolicyRejected = 50,
+}
+
+impl ContractError {
+    /// Interpret an error returned by a call into the account.
+    ///
+    /// Returns `None` for host errors such as failed auth or a trap, and for
+    /// contract codes this version does not define.
+    pub fn from_error(error: soroban_sdk::Error) -> Option<Self> {
+        Self::try_from(error).ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{AncoreAccount, AncoreAccountClient};
+    use soroban_sdk::{
+        contract, contractimpl,
+        testutils::Address as _,
+        xdr::{ScErrorCode, ScErrorType},
+        Address, Env, Symbol, Vec,
+    };
+
+    /// A downstream contract that calls the account and decodes its errors.
+    #[contract]
+    struct Caller;
+
+    #[contractimpl]
+    impl Caller {
+        pub fn finalize(env: Env, account: Address) -> Option<u32> {
+            let result = env.try_invoke_contract::<(), soroban_sdk::Error>(
+                &account,
+                &Symbol::new(&env, "finalize_backup_recovery"),
+                Vec::new(&env),
+            );
+            match result {
+                Err(Ok(error)) => ContractError::from_error(error).map(|e| e as u32),
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn test_error_code_round_trip() {
+        let error: soroban_sdk::Error = ContractError::TimelockNotExpired.into();
+        assert!(error.is_type(ScErrorType::Contract));
+        assert_eq!(error.get_code(), 10);
+        assert_eq!(
+            ContractError::from_error(error),
+            Some(ContractError::TimelockNotExpired)
+        );
+
+        assert_eq!(
+            ContractError::from_error(soroban_sdk::Error::from_contract_error(9_999)),
+            None
+        );
+        assert_eq!(
+            ContractError::from_error(soroban_sdk::Error::from_type_and_code(
+                ScErrorType::Auth,
+                ScErrorCode::InvalidAction,
+            )),
+            None
+        );
+    }
+
+    #[test]
+    fn test_error_decoded_by_calling_contract() {
+        let env = Env::default();
+        let account = env.register_contract(None, AncoreAccount);
+        AncoreAccountClient::new(&env, &account).initialize(&Address::generate(&env));
+        let caller = env.register_contract(None, Caller);
+
+        assert_eq!(
+            CallerClient::new(&env, &caller).finalize(&account),
+            Some(ContractError::NoRecoveryPending as u32)
+        );
+    }
+}
@@ -0,0 +1,434 @@
+//! Approvals that scale with the size of a transfer.
+//!
+//! With multisig, every owner operation needs the same threshold. An
+//! escalation policy instead sets how many signers must approve a token
+//! transfer proposal by its amount, the way a treasury team signs off on
+//! payments:
+//!
+//! - below `two_signatures_from`, the proposing signer alone;
+//! - from `two_signatures_from` up to `full_quorum_above`, two signers;
+//! - above `full_quorum_above`, every signer, and not before
+//!   `timelock_ledgers` ledgers after the proposal was made.
+//!
+//! The escalated tiers never ask for less than the threshold: two signers
+//! whose weights fall short of it are not enough. Other proposals still
+//! need the threshold. Paths that do not go through a proposal, from
+//! `execute` to session keys, can carry no such approvals and fail with
+//! `ProposalRequired` for transfers of `two_signatures_from` or more.
+//!
+//! So that a single signer cannot split a large transfer into small ones,
+//! the small transfers executed without the threshold are totalled per
+//! token over [`SINGLE_SIGNER_WINDOW_LEDGERS`]. Once that total would reach
+//! `two_signatures_from`, a proposal needs the threshold again.
+//!
+//! Setting or clearing the policy is an admin operation, since it lets a
+//! single signer move small amounts.
+
+use soroban_sdk::{contracttype, symbol_short, Address, Env};
+
+use crate::{events, multisig, transfer, transfer::Transfer, Call, ContractError, DataKey};
+
+/// Ledgers over which small transfers made by a single signer are totalled,
+/// about a day.
+pub const SINGLE_SIGNER_WINDOW_LEDGERS: u32 = 17_280;
+
+/// Transfer amounts, in the token's own units, at which proposals need more
+/// approvals.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EscalationPolicy {
+    pub two_signatures_from: i128,
+    pub full_quorum_above: i128,
+    pub timelock_ledgers: u32,
+}
+
+/// A token's small transfers made by a single signer in the current window.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SingleSignerSpend {
+    pub spent: i128,
+    pub window_start: u32,
+}
+
+/// What a transfer proposal needs before it can execute.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Escalation {
+    /// One signer, within the single-signer total, or else the threshold
+    SingleSigner,
+    /// Approvals from this many current signers, meeting the threshold
+    Signers(u32),
+    /// Approvals from every current signer, `timelock_ledgers` after proposing
+    FullQuorum(u32),
+}
+
+pub fn get(env: &Env) -> Option<EscalationPolicy> {
+    env.storage().instance().get(&DataKey::EscalationPolicy)
+}
+
+/// Apply `policy` to transfer proposals.
+///
+/// Fails with `Unauthorized` unless multisig is enabled, and with
+/// `InvalidLimit` unless `0 < two_signatures_from <= full_quorum_above`.
+pub fn set(env: &Env, policy: &EscalationPolicy) -> Result<(), ContractError> {
+    if !multisig::is_enabled(env) {
+        return Err(ContractError::Unauthorized);
+    }
+    if policy.two_signatures_from <= 0 || policy.full_quorum_above < policy.two_signatures_from {
+        return Err(ContractError::InvalidLimit);
+    }
+
+    env.storage()
+        .instance()
+        .set(&DataKey::EscalationPolicy, policy);
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("esc_set")),
+        policy.clone(),
+    );
+    Ok(())
+}
+
+pub fn clear(env: &Env) {
+    env.storage().instance().remove(&DataKey::EscalationPolicy);
+    events::publish(env, (symbol_short!("ancore"), symbol_short!("esc_off")), ());
+}
+
+/// What proposing `call` escalates to, or `None` if it is not a transfer or
+/// no policy is set.
+pub fn for_proposal(env: &Env, call: &Call) -> Option<Escalation> {
+    let policy = get(env)?;
+    let transfer = transfer::detect(env, &call.to, &call.function, &call.args)?;
+    Some(if transfer.amount > policy.full_quorum_above {
+        Escalation::FullQuorum(policy.timelock_ledgers)
+    } else if transfer.amount >= policy.two_signatures_from {
+        Escalation::Signers(2)
+    } else {
+        Escalation::SingleSigner
+    })
+}
+
+/// Add a small transfer made without the threshold to its token's total.
+///
+/// Fails with `ThresholdNotMet` once the total would reach
+/// `two_signatures_from`.
+pub fn record_single_signer(env: &Env, call: &Call) -> Result<(), ContractError> {
+    let (Some(policy), Some(transfer)) = (
+        get(env),
+        transfer::detect(env, &call.to, &call.function, &call.args),
+    ) else {
+        return Ok(());
+    };
+
+    let now = env.ledger().sequence();
+    let mut spend = single_signer_spend(env, &transfer.token).unwrap_or(SingleSignerSpend {
+        spent: 0,
+        window_start: now,
+    });
+    if now
+        >= spend
+            .window_start
+            .saturating_add(SINGLE_SIGNER_WINDOW_LEDGERS)
+    {
+        spend = SingleSignerSpend {
+            spent: 0,
+            window_start: now,
+        };
+    }
+
+    spend.spent = spend
+        .spent
+        .checked_add(transfer.amount)
+        .ok_or(ContractError::Overflow)?;
+    if spend.spent >= policy.two_signatures_from {
+        return Err(ContractError::ThresholdNotMet);
+    }
+    env.storage()
+        .persistent()
+        .set(&DataKey::SingleSignerSpend(transfer.token), &spend);
+    Ok(())
+}
+
+/// Small transfers of `token` made by a single signer in the current window.
+pub fn single_signer_spend(env: &Env, token: &Address) -> Option<SingleSignerSpend> {
+    env.storage()
+        .persistent()
+        .get(&DataKey::SingleSignerSpend(token.clone()))
+}
+
+/// Check a transfer made outside a proposal is below `two_signatures_from`.
+pub fn check_direct(env: &Env, transfer: &Transfer) -> Result<(), ContractError> {
+    match get(env) {
+        Some(policy) if transfer.amount >= policy.two_signatures_from => {
+            Err(ContractError::ProposalRequired)
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        testutils::{register_target, MockTargetClient},
+        AdminOp, AncoreAccount, AncoreAccountClient, Signer,
+    };
+    use ed25519_dalek::{Signer as _, SigningKey};
+    use soroban_sdk::{
+        testutils::{Address as _, Ledger as _},
+        vec, Address, BytesN, IntoVal, Symbol, Vec,
+    };
+
+    struct Setup<'a> {
+        env: Env,
+        client: AncoreAccountClient<'a>,
+        keys: [SigningKey; 3],
+        token: MockTargetClient<'a>,
+        recipient: Address,
+    }
+
+    /// Three weight-1 signers with threshold 2, small transfers below 100,
+    /// full quorum above 1000 after 50 ledgers.
+    fn setup<'a>() -> Setup<'a> {
+        let env = Env::default();
+        env.mock_all_auths();
+        // Keep the account's entries live across a single-signer window.
+        env.ledger().with_mut(|li| {
+            li.min_persistent_entry_ttl = 2 * SINGLE_SIGNER_WINDOW_LEDGERS;
+            li.max_entry_ttl = 2 * SINGLE_SIGNER_WINDOW_LEDGERS;
+        });
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(&env, &contract_id);
+        client.initialize(&Address::generate(&env));
+        let keys = [1u8, 2, 3].map(|seed| SigningKey::from_bytes(&[seed; 32]));
+        let signers = Vec::from_array(
+            &env,
+            keys.clone().map(|key| Signer {
+                key: public_key(&env, &key),
+                weight: 1,
+            }),
+        );
+        client.set_multisig(&signers, &2);
+        client.set_escalation_policy(&EscalationPolicy {
+            two_signatures_from: 100,
+            full_quorum_above: 1000,
+            timelock_ledgers: 50,
+        });
+        let token = MockTargetClient::new(&env, &register_target(&env));
+        let recipient = Address::generate(&env);
+        Setup {
+            env,
+            client,
+            keys,
+            token,
+            recipient,
+        }
+    }
+
+    fn public_key(env: &Env, key: &SigningKey) -> BytesN<32> {
+        BytesN::from_array(env, &key.verifying_key().to_bytes())
+    }
+
+    fn transfer_call(s: &Setup, amount: i128) -> Call {
+        Call {
+            to: s.token.address.clone(),
+            function: Symbol::new(&s.env, "transfer"),
+            args: vec![
+                &s.env,
+                s.client.address.into_val(&s.env),
+                s.recipient.into_val(&s.env),
+                amount.into_val(&s.env),
+            ],
+        }
+    }
+
+    fn sign(s: &Setup, key: &SigningKey, id: u64, call: &Call) -> BytesN<64> {
+        let payload = s.client.get_proposal_payload(&id, call);
+        BytesN::from_array(&s.env, &key.sign(&payload.to_array()).to_bytes())
+    }
+
+    fn propose(s: &Setup, amount: i128) -> u64 {
+        let call = transfer_call(s, amount);
+        let id = s.client.get_next_proposal_id();
+        let key = &s.keys[0];
+        s.client
+            .propose_tx(&public_key(&s.env, key), &sign(s, key, id, &call), &call)
+    }
+
+    fn approve(s: &Setup, key: &SigningKey, id: u64) {
+        let call = s.client.get_proposal(&id).unwrap().call;
+        s.client
+            .approve_tx(&id, &public_key(&s.env, key), &sign(s, key, id, &call));
+    }
+
+    #[test]
+    fn test_small_transfer_needs_one_signer() {
+        let s = setup();
+
+        let id = propose(&s, 99);
+        assert!(s.client.execute_proposal(&id));
+        assert_eq!(s.token.received(&s.recipient), 99);
+    }
+
+    #[test]
+    fn test_medium_transfer_needs_two_signers() {
+        let s = setup();
+
+        let id = propose(&s, 1000);
+        assert_eq!(
+            s.client.try_execute_proposal(&id),
+            Err(Ok(ContractError::ThresholdNotMet))
+        );
+        approve(&s, &s.keys[1], id);
+        assert!(s.client.execute_proposal(&id));
+        assert_eq!(s.token.received(&s.recipient), 1000);
+    }
+
+    #[test]
+    fn test_large_transfer_needs_full_quorum_and_timelock() {
+        let s = setup();
+
+        let id = propose(&s, 1001);
+        approve(&s, &s.keys[1], id);
+        assert_eq!(
+            s.client.try_execute_proposal(&id),
+            Err(Ok(ContractError::ThresholdNotMet))
+        );
+        approve(&s, &s.keys[2], id);
+        assert_eq!(
+            s.client.try_execute_proposal(&id),
+            Err(Ok(ContractError::TimelockNotExpired))
+        );
+
+        s.env.ledger().with_mut(|li| li.sequence_number += 50);
+        assert!(s.client.execute_proposal(&id));
+        assert_eq!(s.token.received(&s.recipient), 1001);
+    }
+
+    #[test]
+    fn test_split_small_transfers_need_threshold() {
+        let s = setup();
+
+        for amount in [60, 39] {
+            let id = propose(&s, amount);
+            assert!(s.client.execute_proposal(&id));
+        }
+        assert_eq!(
+            s.client.get_single_signer_spend(&s.token.address),
+            Some(SingleSignerSpend {
+                spent: 99,
+                window_start: s.env.ledger().sequence(),
+            })
+        );
+
+        // A third small transfer would bring one signer's total to 100.
+        let id = propose(&s, 1);
+        assert_eq!(
+            s.client.try_execute_proposal(&id),
+            Err(Ok(ContractError::ThresholdNotMet))
+        );
+        approve(&s, &s.keys[1], id);
+        assert!(s.client.execute_proposal(&id));
+        assert_eq!(
+            s.client
+                .get_single_signer_spend(&s.token.address)
+                .unwrap()
+                .spent,
+            99
+        );
+
+        // The total starts over once the window has passed.
+        s.env
+            .ledger()
+            .with_mut(|li| li.sequence_number += SINGLE_SIGNER_WINDOW_LEDGERS);
+        let id = propose(&s, 99);
+        assert!(s.client.execute_proposal(&id));
+        assert_eq!(s.token.received(&s.recipient), 199);
+    }
+
+    #[test]
+    fn test_two_signers_must_meet_threshold() {
+        let s = setup();
+        let signers = vec![
+            &s.env,
+            Signer {
+                key: public_key(&s.env, &s.keys[0]),
+                weight: 1,
+            },
+            Signer {
+                key: public_key(&s.env, &s.keys[1]),
+                weight: 1,
+            },
+            Signer {
+                key: public_key(&s.env, &s.keys[2]),
+                weight: 3,
+            },
+        ];
+        s.client.set_multisig(&signers, &3);
+
+        let id = propose(&s, 500);
+        approve(&s, &s.keys[1], id);
+        assert_eq!(
+            s.client.try_execute_proposal(&id),
+            Err(Ok(ContractError::ThresholdNotMet))
+        );
+        approve(&s, &s.keys[2], id);
+        assert!(s.client.execute_proposal(&id));
+    }
+
+    #[test]
+    fn test_direct_paths_only_make_small_transfers() {
+        let s = setup();
+        let call = transfer_call(&s, 100);
+
+        assert_eq!(
+            s.client
+                .try_execute(&call.to, &call.function, &call.args, &0, &0),
+            Err(Ok(ContractError::ProposalRequired))
+        );
+        let call = transfer_call(&s, 99);
+        s.client
+            .execute(&call.to, &call.function, &call.args, &0, &0);
+        assert_eq!(s.token.received(&s.recipient), 99);
+    }
+
+    #[test]
+    fn test_escalation_policy_validation() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let client = AncoreAccountClient::new(&env, &env.register_contract(None, AncoreAccount));
+        client.initialize(&Address::generate(&env));
+        let policy = EscalationPolicy {
+            two_signatures_from: 100,
+            full_quorum_above: 1000,
+            timelock_ledgers: 50,
+        };
+
+        // Without multisig there is only one signer to escalate to.
+        assert_eq!(
+            client.try_set_escalation_policy(&policy),
+            Err(Ok(ContractError::Unauthorized))
+        );
+
+        let s = setup();
+        for (two_signatures_from, full_quorum_above) in [(0, 1000), (100, 99)] {
+            assert_eq!(
+                s.client.try_set_escalation_policy(&EscalationPolicy {
+                    two_signatures_from,
+                    full_quorum_above,
+                    timelock_ledgers: 50,
+                }),
+                Err(Ok(ContractError::InvalidLimit))
+            );
+        }
+
+        s.client.set_admin_delay(&10);
+        assert_eq!(
+            s.client.try_clear_escalation_policy(),
+            Err(Ok(ContractError::AdminOpTimelocked))
+        );
+        s.client.queue_admin_op(&AdminOp::ClearEscalationPolicy);
+        s.env.ledger().with_mut(|li| li.sequence_number += 10);
+        s.client.execute_admin_op();
+        assert_eq!(s.client.get_escalation_policy(), None);
+    }
+}
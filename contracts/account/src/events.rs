@@ -0,0 +1,401 @@
+//! Event publishing and the on-chain event commitment.
+//!
+//! Every event the account emits is also appended as a leaf to an
+//! incremental merkle tree for the current epoch. An auditor holding an
+//! off-chain export of the events can rebuild the tree and compare it with
+//! `get_event_root` to check that the export is complete and untampered.
+//!
+//! The commitment is on from the first event, including for accounts
+//! migrated from before it existed. Since each committed event costs a hash
+//! per tree level and a persistent write, the owner can turn it off with
+//! `set_event_commitment`; auditors see that in the tree, as below.
+//!
+//! ## Commitment format
+//! - Epoch: `ledger sequence / EVENT_EPOCH_LEDGERS`.
+//! - Leaf: `sha256(xdr(topics) || xdr(data))` of the published event.
+//! - Tree: fixed depth [`TREE_DEPTH`], leaves filled left to right, empty
+//!   positions hold the zero hash of their level (`Z0 = [0; 32]`,
+//!   `Zn+1 = sha256(Zn || Zn)`), parents are `sha256(left || right)`.
+//! - Only events emitted while the commitment is enabled are leaves. The
+//!   event toggling it is always committed, so the tree shows where the
+//!   committed range starts and ends.
+//!
+//! ## Storage lifetime
+//! Each epoch's tree is a persistent entry whose TTL is bumped to
+//! [`EVENT_TREE_TTL_LEDGERS`] on every append. Older epochs archive after
+//! that unless someone extends them; an archived root reads as empty.
+
+use soroban_sdk::{contracttype, symbol_short, xdr::ToXdr, Bytes, BytesN, Env, IntoVal, Val, Vec};
+
+use crate::DataKey;
+
+/// Number of ledgers in one commitment epoch (~1 day at 5s per ledger).
+pub const EVENT_EPOCH_LEDGERS: u32 = 17_280;
+
+/// Depth of the per-epoch merkle tree.
+pub const TREE_DEPTH: u32 = 32;
+
+/// Ledgers an epoch's tree stays live after its last append (~30 days).
+pub const EVENT_TREE_TTL_LEDGERS: u32 = 30 * EVENT_EPOCH_LEDGERS;
+
+/// Storage keys owned by this module. `DataKey` is at the 50-variant limit
+/// of a contract type union, so new keys get their own enum.
+#[contracttype]
+pub enum EventKey {
+    Commitment,
+}
+
+/// Frontier of an incremental merkle tree: the number of leaves appended
+/// and, per level, the last left-hand node still waiting for a sibling.
+#[contracttype]
+#[derive(Clone)]
+pub struct EventTree {
+    pub count: u32,
+    pub branch: Vec<BytesN<32>>,
+}
+
+/// Epoch that events published in the current ledger belong to.
+pub fn current_epoch(env: &Env) -> u32 {
+    env.ledger().sequence() / EVENT_EPOCH_LEDGERS
+}
+
+/// Publish an event, folding it into the current epoch's commitment if
+/// that is enabled.
+pub fn publish<T, D>(env: &Env, topics: T, data: D)
+where
+    T: IntoVal<Env, Vec<Val>>,
+    D: IntoVal<Env, Val>,
+{
+    let topics: Vec<Val> = topics.into_val(env);
+    let data: Val = data.into_val(env);
+
+    if commitment_enabled(env) {
+        let mut preimage = Bytes::new(env);
+        preimage.append(&topics.clone().to_xdr(env));
+        preimage.append(&data.to_xdr(env));
+        let leaf: BytesN<32> = env.crypto().sha256(&preimage).into();
+
+        append_leaf(env, current_epoch(env), leaf);
+    }
+    env.events().publish(topics, data);
+}
+
+pub fn commitment_enabled(env: &Env) -> bool {
+    env.storage()
+        .instance()
+        .get(&EventKey::Commitment)
+        .unwrap_or(true)
+}
+
+/// Turn the commitment on or off. The toggle event itself is committed
+/// either way.
+pub fn set_commitment(env: &Env, enabled: bool) {
+    if enabled {
+        env.storage().instance().set(&EventKey::Commitment, &true);
+    }
+    publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("evt_cmt")),
+        enabled,
+    );
+    if !enabled {
+        env.storage().instance().set(&EventKey::Commitment, &false);
+    }
+}
+
+/// Merkle root over every event published during `epoch`.
+pub fn root(env: &Env, epoch: u32) -> BytesN<32> {
+    let zero = BytesN::from_array(env, &[0u8; 32]);
+    let tree = load_tree(env, epoch);
+
+    let mut node = zero.clone();
+    let mut zero_at_level = zero;
+    let mut size = tree.count;
+    for level in 0..TREE_DEPTH {
+        node = if size & 1 == 1 {
+            hash_pair(env, &tree.branch.get_unchecked(level), &node)
+        } else {
+            hash_pair(env, &node, &zero_at_level)
+        };
+        zero_at_level = hash_pair(env, &zero_at_level, &zero_at_level);
+        size >>= 1;
+    }
+    node
+}
+
+fn append_leaf(env: &Env, epoch: u32, leaf: BytesN<32>) {
+    let mut tree = load_tree(env, epoch);
+    tree.count += 1;
+
+    let mut node = leaf;
+    let mut size = tree.count;
+    for level in 0..TREE_DEPTH {
+        if size & 1 == 1 {
+            tree.branch.set(level, node);
+            break;
+        }
+        node = hash_pair(env, &tree.branch.get_unchecked(level), &node);
+        size >>= 1;
+    }
+
+    let key = DataKey::EventTree(epoch);
+    env.storage().persistent().set(&key, &tree);
+    env.storage()
+        .persistent()
+        .extend_ttl(&key, EVENT_TREE_TTL_LEDGERS, EVENT_TREE_TTL_LEDGERS);
+}
+
+fn load_tree(env: &Env, epoch: u32) -> EventTree {
+    env.storage()
+        .persistent()
+        .get(&DataKey::EventTree(epoch))
+        .unwrap_or_else(|| {
+            let mut branch = Vec::new(env);
+            for _ in 0..TREE_DEPTH {
+                branch.push_back(BytesN::from_array(env, &[0u8; 32]));
+            }
+            EventTree { count: 0, branch }
+        })
+}
+
+fn hash_pair(env: &Env, left: &BytesN<32>, right: &BytesN<32>) -> BytesN<32> {
+    let mut preimage = Bytes::from_array(env, &left.to_array());
+    preimage.extend_from_array(&right.to_array());
+    env.crypto().sha256(&preimage).into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{testutils::register_target, AncoreAccount, AncoreAccountClient};
+    use soroban_sdk::{
+        symbol_short,
+        testutils::{storage::Persistent as _, Address as _, Events as _, Ledger as _},
+        Address, Symbol, TryFromVal,
+    };
+
+    fn sha256(env: &Env, bytes: &Bytes) -> BytesN<32> {
+        env.crypto().sha256(bytes).into()
+    }
+
+    fn concat(env: &Env, left: &BytesN<32>, right: &BytesN<32>) -> Bytes {
+        let mut bytes = Bytes::from_array(env, &left.to_array());
+        bytes.extend_from_array(&right.to_array());
+        bytes
+    }
+
+    /// Rebuild the root level by level from a plain list of leaves.
+    fn reference_root(env: &Env, leaves: &Vec<BytesN<32>>) -> BytesN<32> {
+        let mut zero = BytesN::from_array(env, &[0u8; 32]);
+        let mut level = leaves.clone();
+        for _ in 0..TREE_DEPTH {
+            if level.len() % 2 == 1 {
+                level.push_back(zero.clone());
+            }
+            let mut parents = Vec::new(env);
+            let mut i = 0;
+            while i < level.len() {
+                let left = level.get_unchecked(i);
+                let right = level.get_unchecked(i + 1);
+                parents.push_back(sha256(env, &concat(env, &left, &right)));
+                i += 2;
+            }
+            zero = sha256(env, &concat(env, &zero, &zero));
+            level = if parents.is_empty() {
+                Vec::from_array(env, [zero.clone()])
+            } else {
+                parents
+            };
+        }
+        level.get_unchecked(0)
+    }
+
+    /// Leaves for every event the contract has emitted, in order, after the
+    /// first `skip`.
+    fn exported_leaves(env: &Env, contract_id: &Address, skip: u32) -> Vec<BytesN<32>> {
+        let mut leaves = Vec::new(env);
+        let mut seen = 0;
+        for (emitter, topics, data) in env.events().all().iter() {
+            if &emitter != contract_id {
+                continue;
+            }
+            seen += 1;
+            if seen <= skip {
+                continue;
+            }
+            let mut preimage = topics.to_xdr(env);
+            preimage.append(&data.to_xdr(env));
+            leaves.push_back(sha256(env, &preimage));
+        }
+        leaves
+    }
+
+    #[test]
+    fn test_event_root_matches_exported_events() {
+        let env = Env::default();
+        env.mock_all_auths();
+        env.ledger()
+            .with_mut(|li| li.sequence_number = EVENT_EPOCH_LEDGERS * 3 + 10);
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(&env, &contract_id);
+
+        assert_eq!(
+            client.get_event_root(&3),
+            reference_root(&env, &Vec::new(&env))
+        );
+
+        client.initialize(&Address::generate(&env));
+
+        let session_pk = BytesN::from_array(&env, &[7u8; 32]);
+        client.add_session_key(&session_pk, &1000, &Vec::new(&env));
+
+        let target = register_target(&env);
+        for nonce in 0..3 {
+            client.execute(&target, &symbol_short!("ping"), &Vec::new(&env), &0, &nonce);
+        }
+        client.revoke_session_key(&session_pk);
+
+        let leaves = exported_leaves(&env, &contract_id, 0);
+        assert_eq!(leaves.len(), 6);
+        assert_eq!(client.get_event_root(&3), reference_root(&env, &leaves));
+
+        // Dropping or reordering an exported event no longer matches.
+        let mut truncated = leaves.clone();
+        truncated.pop_back();
+        assert_ne!(client.get_event_root(&3), reference_root(&env, &truncated));
+        let mut swapped = leaves.clone();
+        swapped.set(2, leaves.get_unchecked(3));
+        swapped.set(3, leaves.get_unchecked(2));
+        assert_ne!(client.get_event_root(&3), reference_root(&env, &swapped));
+    }
+
+    /// Topic name of the last event the contract emitted.
+    fn last_event(env: &Env) -> Val {
+        let (_, topics, _) = env.events().all().last().unwrap();
+        topics.get_unchecked(1)
+    }
+
+    #[test]
+    fn test_configuration_changes_publish_events() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(&env, &contract_id);
+        client.initialize(&Address::generate(&env));
+
+        client.heartbeat();
+        assert!(last_event(&env).shallow_eq(&symbol_short!("heartbeat").to_val()));
+        client.set_upgrade_delay(&(crate::UPGRADE_DELAY * 2));
+        assert!(last_event(&env).shallow_eq(&symbol_short!("upg_delay").to_val()));
+        client.set_unfreeze_delay(&60);
+        assert!(last_event(&env).shallow_eq(&symbol_short!("unfrz_dly").to_val()));
+
+        let previous = client.get_owner();
+        let new_owner = Address::generate(&env);
+        client.propose_owner(&new_owner);
+        client.accept_ownership();
+        let owner_change = env.events().all().iter().find(|(_, topics, _)| {
+            topics
+                .get_unchecked(1)
+                .shallow_eq(&symbol_short!("owner").to_val())
+        });
+        let (_, _, data) = owner_change.unwrap();
+        assert_eq!(
+            <(Address, Address)>::try_from_val(&env, &data).unwrap(),
+            (previous, new_owner)
+        );
+    }
+
+    #[test]
+    fn test_event_root_is_per_epoch() {
+        let env = Env::default();
+        env.mock_all_auths();
+        env.ledger()
+            .with_mut(|li| li.sequence_number = EVENT_EPOCH_LEDGERS - 1);
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(&env, &contract_id);
+
+        client.initialize(&Address::generate(&env));
+        let init_leaves = exported_leaves(&env, &contract_id, 0);
+        assert_eq!(
+            client.get_event_root(&0),
+            reference_root(&env, &init_leaves)
+        );
+
+        env.ledger()
+            .with_mut(|li| li.sequence_number = EVENT_EPOCH_LEDGERS);
+        client.execute(
+            &register_target(&env),
+            &Symbol::new(&env, "ping"),
+            &Vec::new(&env),
+            &0,
+            &0,
+        );
+        let mut leaves = exported_leaves(&env, &contract_id, 0);
+        let execute_leaf = leaves.pop_back_unchecked();
+
+        assert_eq!(
+            client.get_event_root(&0),
+            reference_root(&env, &init_leaves)
+        );
+        assert_eq!(
+            client.get_event_root(&1),
+            reference_root(&env, &Vec::from_array(&env, [execute_leaf]))
+        );
+    }
+
+    #[test]
+    fn test_event_commitment_is_on_by_default() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(&env, &contract_id);
+
+        client.initialize(&Address::generate(&env));
+        client.heartbeat();
+        assert!(client.get_event_commitment());
+        let committed = exported_leaves(&env, &contract_id, 0);
+        assert_eq!(client.get_event_root(&0), reference_root(&env, &committed));
+
+        // Turning it off is itself committed, so the tree shows where the
+        // committed range ends.
+        client.set_event_commitment(&false);
+        client.heartbeat();
+        let mut leaves = exported_leaves(&env, &contract_id, 0);
+        leaves.pop_back_unchecked();
+        assert_eq!(leaves.len(), committed.len() + 1);
+        assert!(!client.get_event_commitment());
+        assert_eq!(client.get_event_root(&0), reference_root(&env, &leaves));
+
+        let before = exported_leaves(&env, &contract_id, 0).len();
+        client.set_event_commitment(&true);
+        client.heartbeat();
+        leaves.append(&exported_leaves(&env, &contract_id, before));
+        assert_eq!(client.get_event_root(&0), reference_root(&env, &leaves));
+    }
+
+    #[test]
+    fn test_event_tree_ttl_is_extended() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(&env, &contract_id);
+        client.initialize(&Address::generate(&env));
+
+        env.as_contract(&contract_id, || {
+            assert_eq!(
+                env.storage().persistent().get_ttl(&DataKey::EventTree(0)),
+                EVENT_TREE_TTL_LEDGERS
+            );
+        });
+
+        env.ledger().with_mut(|li| li.sequence_number = 1_000);
+        client.heartbeat();
+        env.as_contract(&contract_id, || {
+            assert_eq!(
+                env.storage().persistent().get_ttl(&DataKey::EventTree(0)),
+                EVENT_TREE_TTL_LEDGERS
+            );
+        });
+    }
+}
@@ -0,0 +1,312 @@
+//! Emergency freeze.
+//!
+//! The owner or any guardian can freeze the account in one call when they
+//! suspect a key is compromised. While frozen, the account makes no calls
+//! and grants no session keys: `execute`, `execute_batch`,
+//! `execute_with_session`, `execute_proposal`, auth for calls to other
+//! contracts, adding or extending session keys, and upgrades all fail with
+//! `Frozen`. Managing the account itself, such as revoking keys or changing
+//! owner, keeps working.
+//!
+//! Unfreezing must not be a single owner call, since the owner key may be
+//! what was compromised. Either the owner requests it and waits out the
+//! unfreeze delay ([`UNFREEZE_DELAY`] by default), during which freezing
+//! again cancels the request, or a quorum of guardians unfreezes at once.
+
+use soroban_sdk::{symbol_short, Address, Env, Vec};
+
+use crate::{events, guardians, ContractError, DataKey};
+
+/// Seconds between an owner's unfreeze request and the unfreeze, unless the
+/// owner set another delay.
+pub const UNFREEZE_DELAY: u64 = 2 * 86_400;
+
+pub fn is_frozen(env: &Env) -> bool {
+    env.storage()
+        .instance()
+        .get(&DataKey::Frozen)
+        .unwrap_or(false)
+}
+
+/// Fail with `Frozen` while the account is frozen.
+pub fn check_not_frozen(env: &Env) -> Result<(), ContractError> {
+    if is_frozen(env) {
+        return Err(ContractError::Frozen);
+    }
+    Ok(())
+}
+
+/// Freeze the account, authorized by `caller`, who must be the owner or a
+/// guardian.
+pub fn freeze(env: &Env, owner: &Address, caller: &Address) -> Result<(), ContractError> {
+    caller.require_auth();
+    if caller != owner && !guardians::guardians(env).contains(caller) {
+        return Err(ContractError::Unauthorized);
+    }
+
+    env.storage().instance().set(&DataKey::Frozen, &true);
+    env.storage().instance().remove(&DataKey::UnfreezeAt);
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("frozen")),
+        caller.clone(),
+    );
+    Ok(())
+}
+
+/// Seconds an owner's unfreeze request waits.
+pub fn delay(env: &Env) -> u64 {
+    env.storage()
+        .instance()
+        .get(&DataKey::UnfreezeDelay)
+        .unwrap_or(UNFREEZE_DELAY)
+}
+
+/// Change the unfreeze delay; not allowed while frozen.
+pub fn set_delay(env: &Env, delay: u64) -> Result<(), ContractError> {
+    check_not_frozen(env)?;
+    env.storage()
+        .instance()
+        .set(&DataKey::UnfreezeDelay, &delay);
+
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("unfrz_dly")),
+        delay,
+    );
+    Ok(())
+}
+
+/// When the owner's pending unfreeze request matures, if there is one.
+pub fn unfreeze_at(env: &Env) -> Option<u64> {
+    env.storage().instance().get(&DataKey::UnfreezeAt)
+}
+
+/// Start the unfreeze delay on the owner's behalf.
+pub fn request_unfreeze(env: &Env) -> Result<u64, ContractError> {
+    if !is_frozen(env) {
+        return Err(ContractError::NotFrozen);
+    }
+    if unfreeze_at(env).is_some() {
+        return Err(ContractError::TimelockConflict);
+    }
+
+    let unfreeze_at = env.ledger().timestamp().saturating_add(delay(env));
+    env.storage()
+        .instance()
+        .set(&DataKey::UnfreezeAt, &unfreeze_at);
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("unfrz_req")),
+        unfreeze_at,
+    );
+    Ok(unfreeze_at)
+}
+
+/// Unfreeze once the owner's request has waited out the delay.
+pub fn unfreeze(env: &Env) -> Result<(), ContractError> {
+    if !is_frozen(env) {
+        return Err(ContractError::NotFrozen);
+    }
+    let unfreeze_at = unfreeze_at(env).ok_or(ContractError::UnfreezeNotRequested)?;
+    if env.ledger().timestamp() < unfreeze_at {
+        return Err(ContractError::TimelockNotExpired);
+    }
+
+    lift(env);
+    Ok(())
+}
+
+/// Unfreeze at once with the auth of a quorum of guardians.
+pub fn unfreeze_with_guardians(env: &Env, approvers: &Vec<Address>) -> Result<(), ContractError> {
+    if !is_frozen(env) {
+        return Err(ContractError::NotFrozen);
+    }
+    let guardians = guardians::guardians(env);
+    let mut seen = Vec::<Address>::new(env);
+    for approver in approvers.iter() {
+        if !guardians.contains(&approver) {
+            return Err(ContractError::NotGuardian);
+        }
+        if seen.contains(&approver) {
+            return Err(ContractError::DuplicateGuardian);
+        }
+        seen.push_back(approver);
+    }
+    let quorum = guardians::quorum(env);
+    if quorum == 0 || seen.len() < quorum {
+        return Err(ContractError::ThresholdNotMet);
+    }
+    for approver in approvers.iter() {
+        approver.require_auth();
+    }
+
+    lift(env);
+    Ok(())
+}
+
+fn lift(env: &Env) {
+    let storage = env.storage().instance();
+    storage.remove(&DataKey::Frozen);
+    storage.remove(&DataKey::UnfreezeAt);
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("unfrozen")),
+        (),
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutils::register_target;
+    use crate::{AncoreAccount, AncoreAccountClient, Call, Permission};
+    use soroban_sdk::Vec;
+    use soroban_sdk::{
+        testutils::{Address as _, Ledger as _},
+        vec, BytesN, Symbol,
+    };
+
+    fn setup(env: &Env) -> AncoreAccountClient<'_> {
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(env, &contract_id);
+        client.initialize(&Address::generate(env));
+        client
+    }
+
+    #[test]
+    fn test_freeze_blocks_calls_and_grants() {
+        let env = Env::default();
+        let client = setup(&env);
+        let target = register_target(&env);
+        let ping = Symbol::new(&env, "ping");
+        let key = BytesN::from_array(&env, &[1u8; 32]);
+        let permissions = vec![&env, Permission::Execute];
+        client.add_session_key(&key, &1000, &permissions);
+
+        client.freeze(&client.get_owner());
+        assert!(client.is_frozen());
+        assert_eq!(
+            client.try_execute(&target, &ping, &Vec::new(&env), &0, &0),
+            Err(Ok(ContractError::Frozen))
+        );
+        let call = Call {
+            to: target.clone(),
+            function: ping.clone(),
+            args: Vec::new(&env),
+        };
+        assert_eq!(
+            client.try_execute_batch(&vec![&env, call], &0, &0),
+            Err(Ok(ContractError::Frozen))
+        );
+        assert_eq!(
+            client.try_execute_with_session(
+                &key,
+                &BytesN::from_array(&env, &[0u8; 64]),
+                &target,
+                &ping,
+                &Vec::new(&env),
+                &0
+            ),
+            Err(Ok(ContractError::Frozen))
+        );
+        assert_eq!(
+            client.try_add_session_key(&BytesN::from_array(&env, &[2u8; 32]), &1000, &permissions),
+            Err(Ok(ContractError::Frozen))
+        );
+        assert_eq!(
+            client.try_extend_session_key(&key, &2000),
+            Err(Ok(ContractError::Frozen))
+        );
+
+        // Cleaning up stays possible.
+        client.revoke_session_key(&key);
+
+        client.request_unfreeze();
+        env.ledger().with_mut(|li| li.timestamp = UNFREEZE_DELAY);
+        client.unfreeze();
+        assert!(!client.is_frozen());
+        assert!(client.execute(&target, &ping, &Vec::new(&env), &0, &0));
+        assert_eq!(client.try_unfreeze(), Err(Ok(ContractError::NotFrozen)));
+    }
+
+    #[test]
+    fn test_owner_unfreeze_waits_for_delay() {
+        let env = Env::default();
+        let client = setup(&env);
+        let guardian = Address::generate(&env);
+        client.set_guardians(&vec![&env, guardian.clone()], &1);
+        client.freeze(&guardian);
+
+        assert_eq!(
+            client.try_unfreeze(),
+            Err(Ok(ContractError::UnfreezeNotRequested))
+        );
+        assert_eq!(client.request_unfreeze(), UNFREEZE_DELAY);
+        assert_eq!(
+            client.try_request_unfreeze(),
+            Err(Ok(ContractError::TimelockConflict))
+        );
+        assert_eq!(
+            client.try_unfreeze(),
+            Err(Ok(ContractError::TimelockNotExpired))
+        );
+
+        // A guardian freezing again cancels the request.
+        client.freeze(&guardian);
+        assert_eq!(client.get_unfreeze_at(), None);
+        env.ledger().with_mut(|li| li.timestamp = UNFREEZE_DELAY);
+        assert_eq!(
+            client.try_unfreeze(),
+            Err(Ok(ContractError::UnfreezeNotRequested))
+        );
+        assert_eq!(
+            client.try_set_unfreeze_delay(&0),
+            Err(Ok(ContractError::Frozen))
+        );
+        assert!(client.is_frozen());
+    }
+
+    #[test]
+    fn test_guardian_quorum_unfreezes_at_once() {
+        let env = Env::default();
+        let client = setup(&env);
+        let guardians = [0, 1, 2].map(|_| Address::generate(&env));
+        let [a, b, _] = &guardians;
+        client.set_guardians(&Vec::from_array(&env, guardians.clone()), &2);
+        client.freeze(&client.get_owner());
+
+        assert_eq!(
+            client.try_unfreeze_with_guardians(&vec![&env, a.clone()]),
+            Err(Ok(ContractError::ThresholdNotMet))
+        );
+        assert_eq!(
+            client.try_unfreeze_with_guardians(&vec![&env, a.clone(), a.clone()]),
+            Err(Ok(ContractError::DuplicateGuardian))
+        );
+        assert_eq!(
+            client.try_unfreeze_with_guardians(&vec![&env, a.clone(), client.get_owner()]),
+            Err(Ok(ContractError::NotGuardian))
+        );
+
+        client.unfreeze_with_guardians(&vec![&env, a.clone(), b.clone()]);
+        assert!(!client.is_frozen());
+    }
+
+    #[test]
+    fn test_guardians_can_freeze() {
+        let env = Env::default();
+        let client = setup(&env);
+        let guardian = Address::generate(&env);
+        client.set_guardians(&vec![&env, guardian.clone()], &1);
+
+        assert_eq!(
+            client.try_freeze(&Address::generate(&env)),
+            Err(Ok(ContractError::Unauthorized))
+        );
+        client.freeze(&guardian);
+        assert_eq!(env.auths()[0].0, guardian);
+        assert!(client.is_frozen());
+    }
+}
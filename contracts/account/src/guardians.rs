@@ -0,0 +1,517 @@
+//! Social recovery by guardians.
+//!
+//! The owner designates guardian addresses (friends, devices, or a recovery
+//! service) and a quorum. If the owner key is lost, one guardian starts a
+//! recovery to a new owner and the others approve it; once a quorum of the
+//! current guardians has approved and [`GUARDIAN_RECOVERY_DELAY`] has passed
+//! since it started, anyone can finalize it. Until then the current owner can
+//! veto it. Each stage emits an event (`rc_init`, `rc_appr`, `rc_done`,
+//! `rc_cancel`) so wallets can alert the owner while there is time to act.
+//!
+//! Only one guardian recovery can be pending, and any change of owner
+//! discards it. While it is pending, token limit increases cannot be queued.
+//!
+//! ## Guardian changes
+//! The first guardian set applies at once. After that, changing the set or
+//! lowering the change delay is queued for the current delay
+//! ([`GUARDIAN_CHANGE_DELAY`] by default), and the owner can cancel it until
+//! then. Otherwise a stolen owner key could swap in the thief's guardians
+//! before the real guardians recover the account, which is why the default
+//! delay outlasts [`GUARDIAN_RECOVERY_DELAY`]. Raising the delay applies at
+//! once. There is a single pending slot (`TimelockConflict`), and a change of
+//! owner discards the pending change.
+
+use soroban_sdk::{contracttype, symbol_short, Address, Env, Vec};
+
+use crate::{events, registry, ContractError, DataKey};
+
+/// Seconds between starting a guardian recovery and finalizing it.
+pub const GUARDIAN_RECOVERY_DELAY: u64 = 3 * 86_400;
+
+/// Seconds a guardian change waits unless the owner set another delay.
+pub const GUARDIAN_CHANGE_DELAY: u64 = 7 * 86_400;
+
+/// A guardian recovery collecting approvals and waiting for its timelock.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct GuardianRecovery {
+    pub new_owner: Address,
+    pub approvals: Vec<Address>,
+    pub executable_at: u64,
+}
+
+/// A guardian configuration waiting for the change delay to pass.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PendingGuardianChange {
+    pub guardians: Vec<Address>,
+    pub quorum: u32,
+    pub delay: u64,
+    pub executable_at: u64,
+}
+
+/// Replace the guardian set; an empty set with quorum 0 disables recovery.
+///
+/// Applies at once while no guardians are set, and is queued otherwise.
+pub fn set(env: &Env, guardians: &Vec<Address>, quorum: u32) -> Result<(), ContractError> {
+    let mut seen = Vec::<Address>::new(env);
+    for guardian in guardians.iter() {
+        if seen.contains(&guardian) {
+            return Err(ContractError::DuplicateGuardian);
+        }
+        seen.push_back(guardian);
+    }
+    let disabled = guardians.is_empty() && quorum == 0;
+    if !disabled && (quorum == 0 || quorum > guardians.len()) {
+        return Err(ContractError::InvalidThreshold);
+    }
+
+    if self::guardians(env).is_empty() {
+        apply(env, guardians, quorum, delay(env));
+        return Ok(());
+    }
+    queue(env, guardians, quorum, delay(env))
+}
+
+/// Seconds guardian changes wait in the queue.
+pub fn delay(env: &Env) -> u64 {
+    env.storage()
+        .instance()
+        .get(&DataKey::GuardianDelay)
+        .unwrap_or(GUARDIAN_CHANGE_DELAY)
+}
+
+/// Set the change delay, queuing it if it is shorter than the current one.
+pub fn set_delay(env: &Env, delay: u64) -> Result<(), ContractError> {
+    if delay >= self::delay(env) {
+        apply(env, &guardians(env), quorum(env), delay);
+        return Ok(());
+    }
+    queue(env, &guardians(env), quorum(env), delay)
+}
+
+pub fn pending_change(env: &Env) -> Option<PendingGuardianChange> {
+    env.storage()
+        .instance()
+        .get(&DataKey::PendingGuardianChange)
+}
+
+/// Apply the pending change once its delay has passed.
+pub fn apply_pending(env: &Env) -> Result<(), ContractError> {
+    let change = pending_change(env).ok_or(ContractError::NoGuardianChangePending)?;
+    if env.ledger().timestamp() < change.executable_at {
+        return Err(ContractError::TimelockNotExpired);
+    }
+
+    discard_change(env);
+    apply(env, &change.guardians, change.quorum, change.delay);
+    Ok(())
+}
+
+pub fn cancel_change(env: &Env) -> Result<(), ContractError> {
+    let change = pending_change(env).ok_or(ContractError::NoGuardianChangePending)?;
+    discard_change(env);
+
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("gd_cancel")),
+        (change.guardians, change.quorum, change.delay),
+    );
+    Ok(())
+}
+
+/// Drop any pending guardian change, e.g. because the owner changed.
+pub fn discard_change(env: &Env) {
+    env.storage()
+        .instance()
+        .remove(&DataKey::PendingGuardianChange);
+}
+
+fn queue(
+    env: &Env,
+    guardians: &Vec<Address>,
+    quorum: u32,
+    delay: u64,
+) -> Result<(), ContractError> {
+    if pending_change(env).is_some() {
+        return Err(ContractError::TimelockConflict);
+    }
+
+    let change = PendingGuardianChange {
+        guardians: guardians.clone(),
+        quorum,
+        delay,
+        executable_at: env.ledger().timestamp().saturating_add(self::delay(env)),
+    };
+    env.storage()
+        .instance()
+        .set(&DataKey::PendingGuardianChange, &change);
+
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("gd_queue")),
+        change,
+    );
+    Ok(())
+}
+
+fn apply(env: &Env, guardians: &Vec<Address>, quorum: u32, delay: u64) {
+    let storage = env.storage().instance();
+    storage.set(&DataKey::Guardians, guardians);
+    storage.set(&DataKey::GuardianQuorum, &quorum);
+    storage.set(&DataKey::GuardianDelay, &delay);
+
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("gd_set")),
+        (guardians.clone(), quorum, delay),
+    );
+    registry::notify(env);
+}
+
+pub fn guardians(env: &Env) -> Vec<Address> {
+    env.storage()
+        .instance()
+        .get(&DataKey::Guardians)
+        .unwrap_or_else(|| Vec::new(env))
+}
+
+pub fn quorum(env: &Env) -> u32 {
+    env.storage()
+        .instance()
+        .get(&DataKey::GuardianQuorum)
+        .unwrap_or(0)
+}
+
+pub fn pending(env: &Env) -> Option<GuardianRecovery> {
+    env.storage().instance().get(&DataKey::GuardianRecovery)
+}
+
+/// Start a recovery to `new_owner`, approved by the guardian starting it.
+pub fn initiate(env: &Env, guardian: &Address, new_owner: &Address) -> Result<(), ContractError> {
+    check_guardian(env, guardian)?;
+    if pending(env).is_some() {
+        return Err(ContractError::RecoveryAlreadyPending);
+    }
+
+    let recovery = GuardianRecovery {
+        new_owner: new_owner.clone(),
+        approvals: Vec::from_array(env, [guardian.clone()]),
+        executable_at: env.ledger().timestamp() + GUARDIAN_RECOVERY_DELAY,
+    };
+    env.storage()
+        .instance()
+        .set(&DataKey::GuardianRecovery, &recovery);
+
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("rc_init")),
+        (guardian.clone(), new_owner.clone(), recovery.executable_at),
+    );
+    Ok(())
+}
+
+pub fn approve(env: &Env, guardian: &Address) -> Result<(), ContractError> {
+    check_guardian(env, guardian)?;
+    let mut recovery = pending(env).ok_or(ContractError::NoRecoveryPending)?;
+    if recovery.approvals.contains(guardian) {
+        return Err(ContractError::DuplicateGuardian);
+    }
+
+    recovery.approvals.push_back(guardian.clone());
+    env.storage()
+        .instance()
+        .set(&DataKey::GuardianRecovery, &recovery);
+
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("rc_appr")),
+        (guardian.clone(), recovery.approvals.len(), quorum(env)),
+    );
+    Ok(())
+}
+
+/// Veto the pending recovery.
+pub fn cancel(env: &Env) -> Result<(), ContractError> {
+    let recovery = pending(env).ok_or(ContractError::NoRecoveryPending)?;
+    discard(env);
+
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("rc_cancel")),
+        recovery.new_owner,
+    );
+    Ok(())
+}
+
+/// Complete a recovery a quorum approved once its timelock has passed,
+/// returning the new owner.
+///
+/// Only approvals from guardians still in the set count.
+pub fn finalize(env: &Env) -> Result<Address, ContractError> {
+    let recovery = pending(env).ok_or(ContractError::NoRecoveryPending)?;
+    if env.ledger().timestamp() < recovery.executable_at {
+        return Err(ContractError::TimelockNotExpired);
+    }
+    let guardians = guardians(env);
+    let approvals = recovery
+        .approvals
+        .iter()
+        .filter(|guardian| guardians.contains(guardian))
+        .count() as u32;
+    if approvals < quorum(env) || approvals == 0 {
+        return Err(ContractError::ThresholdNotMet);
+    }
+
+    discard(env);
+    Ok(recovery.new_owner)
+}
+
+/// Drop any pending guardian recovery, e.g. because the owner changed.
+pub fn discard(env: &Env) {
+    env.storage().instance().remove(&DataKey::GuardianRecovery);
+}
+
+/// Require `guardian`'s auth and that it is in the guardian set.
+fn check_guardian(env: &Env, guardian: &Address) -> Result<(), ContractError> {
+    guardian.require_auth();
+    if !guardians(env).contains(guardian) {
+        return Err(ContractError::NotGuardian);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{AncoreAccount, AncoreAccountClient};
+    use soroban_sdk::{
+        symbol_short,
+        testutils::{Address as _, Events as _, Ledger as _},
+        vec, IntoVal, Symbol,
+    };
+
+    struct Setup<'a> {
+        env: Env,
+        client: AncoreAccountClient<'a>,
+        guardians: [Address; 3],
+    }
+
+    /// An account with three guardians and quorum 2.
+    fn setup<'a>() -> Setup<'a> {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(&env, &contract_id);
+        client.initialize(&Address::generate(&env));
+        let guardians = [0, 1, 2].map(|_| Address::generate(&env));
+        client.set_guardians(&Vec::from_array(&env, guardians.clone()), &2);
+        Setup {
+            env,
+            client,
+            guardians,
+        }
+    }
+
+    #[test]
+    fn test_guardian_quorum_recovers_after_delay() {
+        let s = setup();
+        let [a, b, _] = &s.guardians;
+        let new_owner = Address::generate(&s.env);
+
+        s.client.initiate_recovery(a, &new_owner);
+        assert_eq!(s.env.auths()[0].0, *a);
+        assert_eq!(
+            s.client.try_initiate_recovery(b, &new_owner),
+            Err(Ok(ContractError::RecoveryAlreadyPending))
+        );
+        assert_eq!(
+            s.client.try_approve_recovery(a),
+            Err(Ok(ContractError::DuplicateGuardian))
+        );
+
+        s.env
+            .ledger()
+            .with_mut(|li| li.timestamp = GUARDIAN_RECOVERY_DELAY);
+        assert_eq!(
+            s.client.try_finalize_recovery(),
+            Err(Ok(ContractError::ThresholdNotMet))
+        );
+
+        s.client.approve_recovery(b);
+        s.client.finalize_recovery();
+        assert_eq!(last_event(&s), symbol_short!("rc_done"));
+        assert_eq!(s.client.get_owner(), new_owner);
+        assert_eq!(s.client.get_recovery(), None);
+        assert_eq!(s.client.get_metrics().recoveries, 1);
+    }
+
+    fn last_event(s: &Setup) -> Symbol {
+        let (_, topics, _) = s.env.events().all().last().unwrap();
+        topics.get(1).unwrap().into_val(&s.env)
+    }
+
+    #[test]
+    fn test_owner_can_veto_recovery() {
+        let s = setup();
+        let [a, b, c] = &s.guardians;
+        let old_owner = s.client.get_owner();
+
+        s.client.initiate_recovery(a, &Address::generate(&s.env));
+        assert_eq!(last_event(&s), symbol_short!("rc_init"));
+        s.client.approve_recovery(b);
+        assert_eq!(last_event(&s), symbol_short!("rc_appr"));
+
+        s.client.cancel_recovery();
+        assert_eq!(s.env.auths()[0].0, old_owner);
+        assert_eq!(last_event(&s), symbol_short!("rc_cancel"));
+        assert_eq!(s.client.get_recovery(), None);
+        assert_eq!(
+            s.client.try_approve_recovery(c),
+            Err(Ok(ContractError::NoRecoveryPending))
+        );
+        assert_eq!(
+            s.client.try_cancel_recovery(),
+            Err(Ok(ContractError::NoRecoveryPending))
+        );
+
+        s.env
+            .ledger()
+            .with_mut(|li| li.timestamp = GUARDIAN_RECOVERY_DELAY);
+        assert_eq!(
+            s.client.try_finalize_recovery(),
+            Err(Ok(ContractError::NoRecoveryPending))
+        );
+        assert_eq!(s.client.get_owner(), old_owner);
+    }
+
+    #[test]
+    fn test_recovery_waits_for_delay() {
+        let s = setup();
+        let [a, b, _] = &s.guardians;
+        let old_owner = s.client.get_owner();
+
+        s.client.initiate_recovery(a, &Address::generate(&s.env));
+        s.client.approve_recovery(b);
+        assert_eq!(
+            s.client.get_recovery().unwrap().executable_at,
+            GUARDIAN_RECOVERY_DELAY
+        );
+        assert_eq!(
+            s.client.try_finalize_recovery(),
+            Err(Ok(ContractError::TimelockNotExpired))
+        );
+        assert_eq!(s.client.get_owner(), old_owner);
+    }
+
+    #[test]
+    fn test_only_guardians_take_part() {
+        let s = setup();
+        let [a, b, c] = &s.guardians;
+        let outsider = Address::generate(&s.env);
+
+        assert_eq!(
+            s.client.try_initiate_recovery(&outsider, &outsider),
+            Err(Ok(ContractError::NotGuardian))
+        );
+        s.client.initiate_recovery(a, &Address::generate(&s.env));
+        assert_eq!(
+            s.client.try_approve_recovery(&outsider),
+            Err(Ok(ContractError::NotGuardian))
+        );
+
+        // An approval from a guardian who was removed no longer counts.
+        s.client.approve_recovery(b);
+        s.client
+            .set_guardians(&vec![&s.env, a.clone(), c.clone()], &2);
+        s.env
+            .ledger()
+            .with_mut(|li| li.timestamp = GUARDIAN_CHANGE_DELAY);
+        s.client.apply_guardian_change();
+        assert_eq!(
+            s.client.try_finalize_recovery(),
+            Err(Ok(ContractError::ThresholdNotMet))
+        );
+    }
+
+    #[test]
+    fn test_set_guardians_rejects_invalid_config() {
+        let s = setup();
+        let [a, b, _] = &s.guardians;
+
+        assert_eq!(
+            s.client
+                .try_set_guardians(&vec![&s.env, a.clone(), a.clone()], &1),
+            Err(Ok(ContractError::DuplicateGuardian))
+        );
+        assert_eq!(
+            s.client
+                .try_set_guardians(&vec![&s.env, a.clone(), b.clone()], &3),
+            Err(Ok(ContractError::InvalidThreshold))
+        );
+        assert_eq!(
+            s.client.try_set_guardians(&vec![&s.env, a.clone()], &0),
+            Err(Ok(ContractError::InvalidThreshold))
+        );
+    }
+
+    #[test]
+    fn test_guardian_changes_are_delayed() {
+        let s = setup();
+        let [a, _, _] = &s.guardians;
+        let thief_guardian = Address::generate(&s.env);
+
+        s.client
+            .set_guardians(&vec![&s.env, a.clone(), thief_guardian.clone()], &1);
+        assert_eq!(s.client.get_guardians().len(), 3);
+        assert_eq!(
+            s.client.get_pending_guardian_change(),
+            Some(PendingGuardianChange {
+                guardians: vec![&s.env, a.clone(), thief_guardian],
+                quorum: 1,
+                delay: GUARDIAN_CHANGE_DELAY,
+                executable_at: GUARDIAN_CHANGE_DELAY,
+            })
+        );
+        assert_eq!(
+            s.client.try_set_guardian_delay(&0),
+            Err(Ok(ContractError::TimelockConflict))
+        );
+        assert_eq!(
+            s.client.try_apply_guardian_change(),
+            Err(Ok(ContractError::TimelockNotExpired))
+        );
+
+        s.client.cancel_guardian_change();
+        assert_eq!(s.client.get_pending_guardian_change(), None);
+        assert_eq!(
+            s.client.try_apply_guardian_change(),
+            Err(Ok(ContractError::NoGuardianChangePending))
+        );
+
+        s.client.set_guardians(&Vec::new(&s.env), &0);
+        s.env
+            .ledger()
+            .with_mut(|li| li.timestamp = GUARDIAN_CHANGE_DELAY);
+        s.client.apply_guardian_change();
+        assert!(s.client.get_guardians().is_empty());
+        assert_eq!(s.client.get_guardian_quorum(), 0);
+    }
+
+    #[test]
+    fn test_lowering_guardian_delay_is_queued() {
+        let s = setup();
+
+        s.client.set_guardian_delay(&(GUARDIAN_CHANGE_DELAY * 2));
+        assert_eq!(s.client.get_guardian_delay(), GUARDIAN_CHANGE_DELAY * 2);
+
+        s.client.set_guardian_delay(&86_400);
+        assert_eq!(s.client.get_guardian_delay(), GUARDIAN_CHANGE_DELAY * 2);
+        s.env
+            .ledger()
+            .with_mut(|li| li.timestamp = GUARDIAN_CHANGE_DELAY * 2);
+        s.client.apply_guardian_change();
+        assert_eq!(s.client.get_guardian_delay(), 86_400);
+        assert_eq!(s.client.get_guardians().len(), 3);
+    }
+}
@@ -0,0 +1,217 @@
+//! Dead-man switch: ownership passes to a beneficiary after inactivity.
+//!
+//! The owner names a beneficiary and an inactivity period in ledgers. Owner
+//! activity (executes, owner-signed auth, or an explicit heartbeat) records
+//! the current ledger; once `inactivity_ledgers` have passed since the last
+//! activity, the beneficiary can claim ownership. Any change of owner clears
+//! the configuration, as it belonged to the previous owner.
+//!
+//! A claim changes the owner without waiting out the admin delay, so naming
+//! a beneficiary is an admin operation itself, and the inactivity period can
+//! never be shorter than the admin delay. Otherwise a stolen owner key could
+//! name itself with a one-ledger period and take the account over at once.
+
+use soroban_sdk::{contracttype, symbol_short, Address, Env};
+
+use crate::{admin, events, ContractError, DataKey};
+
+/// Who inherits the account and after how long without owner activity.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Inheritance {
+    pub beneficiary: Address,
+    pub inactivity_ledgers: u32,
+}
+
+pub fn set(env: &Env, beneficiary: &Address, inactivity_ledgers: u32) -> Result<(), ContractError> {
+    if inactivity_ledgers == 0 || inactivity_ledgers < admin::delay(env) {
+        return Err(ContractError::InvalidLimit);
+    }
+
+    let inheritance = Inheritance {
+        beneficiary: beneficiary.clone(),
+        inactivity_ledgers,
+    };
+    env.storage()
+        .instance()
+        .set(&DataKey::Inheritance, &inheritance);
+    touch(env);
+
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("inh_set")),
+        inheritance,
+    );
+    Ok(())
+}
+
+pub fn get(env: &Env) -> Option<Inheritance> {
+    env.storage().instance().get(&DataKey::Inheritance)
+}
+
+/// Remove the configuration, e.g. because the owner changed.
+pub fn clear(env: &Env) {
+    env.storage().instance().remove(&DataKey::Inheritance);
+}
+
+/// Record an explicit owner heartbeat.
+pub fn heartbeat(env: &Env) {
+    touch(env);
+
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("heartbeat")),
+        env.ledger().sequence(),
+    );
+}
+
+/// Record owner activity at the current ledger.
+pub fn touch(env: &Env) {
+    env.storage()
+        .instance()
+        .set(&DataKey::LastActivity, &env.ledger().sequence());
+}
+
+/// Ledger of the last recorded owner activity.
+pub fn last_activity(env: &Env) -> u32 {
+    env.storage()
+        .instance()
+        .get(&DataKey::LastActivity)
+        .unwrap_or(0)
+}
+
+/// Check the beneficiary's auth and that the owner has been inactive long
+/// enough, returning the beneficiary.
+pub fn claim(env: &Env) -> Result<Address, ContractError> {
+    let inheritance = get(env).ok_or(ContractError::NoInheritance)?;
+    inheritance.beneficiary.require_auth();
+    // A period set before the admin delay was raised still waits it out.
+    let inactivity_ledgers = inheritance.inactivity_ledgers.max(admin::delay(env));
+    let claimable_at = last_activity(env).saturating_add(inactivity_ledgers);
+    if env.ledger().sequence() < claimable_at {
+        return Err(ContractError::OwnerActive);
+    }
+    Ok(inheritance.beneficiary)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutils::register_target;
+    use crate::{AdminOp, AncoreAccount, AncoreAccountClient};
+    use soroban_sdk::{
+        testutils::{Address as _, Ledger as _},
+        Symbol, Vec,
+    };
+
+    const INACTIVITY: u32 = 1_000;
+
+    fn setup(env: &Env) -> (AncoreAccountClient<'_>, Address) {
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(env, &contract_id);
+        client.initialize(&Address::generate(env));
+        let beneficiary = Address::generate(env);
+        client.set_inheritance(&beneficiary, &INACTIVITY);
+        (client, beneficiary)
+    }
+
+    fn advance(env: &Env, ledgers: u32) {
+        env.ledger().with_mut(|li| li.sequence_number += ledgers);
+    }
+
+    #[test]
+    fn test_beneficiary_claims_after_inactivity() {
+        let env = Env::default();
+        let (client, beneficiary) = setup(&env);
+
+        advance(&env, INACTIVITY - 1);
+        assert_eq!(
+            client.try_claim_inheritance(),
+            Err(Ok(ContractError::OwnerActive))
+        );
+
+        advance(&env, 1);
+        client.claim_inheritance();
+        assert_eq!(env.auths()[0].0, beneficiary);
+        assert_eq!(client.get_owner(), beneficiary);
+        assert_eq!(client.get_inheritance(), None);
+        assert_eq!(
+            client.try_claim_inheritance(),
+            Err(Ok(ContractError::NoInheritance))
+        );
+    }
+
+    #[test]
+    fn test_owner_activity_resets_inactivity() {
+        let env = Env::default();
+        let (client, _) = setup(&env);
+        let owner = client.get_owner();
+        let target = register_target(&env);
+
+        advance(&env, INACTIVITY - 1);
+        client.execute(&target, &Symbol::new(&env, "ping"), &Vec::new(&env), &0, &0);
+        advance(&env, INACTIVITY - 1);
+        client.heartbeat();
+        assert_eq!(client.get_last_activity(), env.ledger().sequence());
+        advance(&env, INACTIVITY - 1);
+        assert_eq!(
+            client.try_claim_inheritance(),
+            Err(Ok(ContractError::OwnerActive))
+        );
+        assert_eq!(client.get_owner(), owner);
+
+        client.clear_inheritance();
+        advance(&env, INACTIVITY);
+        assert_eq!(
+            client.try_claim_inheritance(),
+            Err(Ok(ContractError::NoInheritance))
+        );
+    }
+
+    #[test]
+    fn test_inactivity_period_must_be_positive() {
+        let env = Env::default();
+        let (client, beneficiary) = setup(&env);
+
+        assert_eq!(
+            client.try_set_inheritance(&beneficiary, &0),
+            Err(Ok(ContractError::InvalidLimit))
+        );
+    }
+
+    #[test]
+    fn test_inheritance_cannot_bypass_admin_delay() {
+        let env = Env::default();
+        let (client, _) = setup(&env);
+        let owner = client.get_owner();
+        let attacker = Address::generate(&env);
+        client.set_admin_delay(&INACTIVITY);
+
+        // A stolen owner key cannot name itself at once.
+        assert_eq!(
+            client.try_set_inheritance(&attacker, &1),
+            Err(Ok(ContractError::AdminOpTimelocked))
+        );
+
+        // Nor queue a period shorter than the delay.
+        client.queue_admin_op(&AdminOp::SetInheritance(attacker.clone(), 1));
+        advance(&env, INACTIVITY);
+        assert_eq!(
+            client.try_execute_admin_op(),
+            Err(Ok(ContractError::InvalidLimit))
+        );
+        client.cancel_admin_op();
+
+        // An earlier, shorter period also waits out the delay.
+        client.set_admin_delay(&(INACTIVITY * 3));
+        advance(&env, INACTIVITY);
+        assert_eq!(
+            client.try_claim_inheritance(),
+            Err(Ok(ContractError::OwnerActive))
+        );
+        assert_eq!(client.get_owner(), owner);
+        advance(&env, INACTIVITY);
+        client.claim_inheritance();
+    }
+}
@@ -10,19 +10,155 @@
 //! ## Features
 //! - Signature validation
 //! - Session key support
-//! - Upgradeable via proxy pattern
+//! - Upgradeable in place
 //! - Multi-signature support
+//!
+//! ## Timelocks
+//! Delayed operations are grouped into classes, each with one pending slot:
+//! token limit increases (one slot per token), backup recovery, guardian
+//! recovery, and admin operations once an admin delay is set. Scheduling
+//! into an occupied slot, or a limit increase while a recovery is pending,
+//! fails with `TimelockConflict`. An operation becomes final from the exact
+//! timestamp it was scheduled for, so reaching it is never ambiguous.
 
 use soroban_sdk::{
-    contract, contractimpl, contracttype, Address, BytesN, Env, Vec,
+    auth::{Context, CustomAccountInterface},
+    contract, contractimpl, contracttype,
+    crypto::Hash,
+    symbol_short, Address, Bytes, BytesN, Env, IntoVal, Map, Symbol, TryFromVal, Val, Vec,
+};
+
+mod admin;
+mod audit;
+mod auth;
+mod backup;
+mod destinations;
+mod error;
+mod escalation;
+mod events;
+mod freeze;
+mod guardians;
+mod inheritance;
+mod limits;
+mod metrics;
+mod migration;
+mod multisig;
+mod nonce;
+mod observers;
+mod ownership;
+mod paymaster;
+mod permissions;
+mod policy_modules;
+mod proposals;
+mod rate_limit;
+mod registry;
+mod session;
+mod signed;
+#[cfg(test)]
+mod testutils;
+mod transfer;
+mod two_factor;
+mod tx_value;
+mod upgrade;
+
+pub use admin::{AdminOp, PendingAdminOp};
+pub use audit::{AuditCaller, AuditEntry, AUDIT_LOG_MAX_SIZE};
+pub use auth::AccountSignature;
+pub use backup::{BackupRecovery, BACKUP_RECOVERY_DELAY};
+pub use destinations::MAX_ALLOWED_DESTINATIONS;
+pub use error::ContractError;
+pub use escalation::{EscalationPolicy, SingleSignerSpend};
+pub use freeze::UNFREEZE_DELAY;
+pub use guardians::{
+    GuardianRecovery, PendingGuardianChange, GUARDIAN_CHANGE_DELAY, GUARDIAN_RECOVERY_DELAY,
+};
+pub use inheritance::Inheritance;
+pub use limits::{PendingTokenLimit, TokenLimit, DAILY_LIMIT_PERIOD};
+pub use metrics::Metrics;
+pub use migration::STORAGE_VERSION;
+pub use multisig::Signer;
+pub use nonce::DEFAULT_CHANNEL;
+pub use observers::{
+    Observer, ObserverClient, MAX_OBSERVERS, OBSERVE_EXECUTE, OBSERVE_RECOVERY,
+    OBSERVE_SESSION_KEY, OBSERVE_TRANSFER,
 };
+pub use ownership::{PendingOwner, OWNER_PROPOSAL_TTL};
+pub use paymaster::{Paymaster, PaymasterClient};
+pub use permissions::Permission;
+pub use policy_modules::{CallContext, PolicyModule, PolicyModuleClient, MAX_POLICY_MODULES};
+pub use proposals::TxProposal;
+pub use rate_limit::{RateLimit, RATE_LIMIT_MAX_EXECUTIONS};
+pub use registry::{Registry, RegistryClient};
+pub use session::SessionSpendLimit;
+pub use signed::SignedCall;
+pub use two_factor::TwoFactor;
+pub use tx_value::{MaxTxValue, PriceOracle, PriceOracleClient, PRICE_SCALE};
+pub use upgrade::{PendingUpgrade, UPGRADE_DELAY};
 
 #[contracttype]
 #[derive(Clone)]
 pub struct SessionKey {
     pub public_key: BytesN<32>,
+    pub valid_after: u64,
     pub expires_at: u64,
-    pub permissions: Vec<u32>,
+    pub permissions: Vec<Permission>,
+    pub spend_limits: Vec<SessionSpendLimit>,
+    pub allowed_contracts: Vec<Address>,
+    pub allowed_functions: Vec<Symbol>,
+    pub max_uses: u32,
+    pub uses: u32,
+}
+
+/// One call in an `execute_batch`
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Call {
+    pub to: Address,
+    pub function: Symbol,
+    pub args: Vec<Val>,
+}
+
+/// Non-secret account configuration, enough to rebuild the account off-chain
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AccountSnapshot {
+    pub owner: Address,
+    /// Next nonce on the default channel
+    pub nonce: u64,
+    /// 32-byte hash; `Bytes` because SDK 21 cannot nest `BytesN` in `Option`
+    /// fields of contract types.
+    pub backup_key_hash: Option<Bytes>,
+    pub signers: Vec<Signer>,
+    pub threshold: u32,
+    pub guardians: Vec<Address>,
+    pub guardian_quorum: u32,
+    /// Seconds guardian changes wait
+    pub guardian_delay: u64,
+    /// Ledgers admin operations wait
+    pub admin_delay: u32,
+    /// Seconds upgrades wait
+    pub upgrade_delay: u64,
+    /// Seconds an unfreeze requested by the owner waits
+    pub unfreeze_delay: u64,
+    /// The optional policies below hold at most one entry, empty when unset;
+    /// SDK 21 cannot nest contract types in `Option` fields either.
+    pub inheritance: Vec<Inheritance>,
+    pub max_tx_value: Vec<MaxTxValue>,
+    pub rate_limit: Vec<RateLimit>,
+    pub two_factor: Vec<TwoFactor>,
+    pub escalation_policy: Vec<EscalationPolicy>,
+}
+
+/// `value` as an `AccountSnapshot` list of at most one entry.
+fn optional<T>(env: &Env, value: Option<T>) -> Vec<T>
+where
+    T: IntoVal<Env, Val> + TryFromVal<Env, Val>,
+{
+    let mut list = Vec::new(env);
+    if let Some(value) = value {
+        list.push_back(value);
+    }
+    list
 }
 
 #[contracttype]
@@ -30,6 +166,53 @@ pub enum DataKey {
     Owner,
     Nonce,
     SessionKey(BytesN<32>),
+    EventTree(u32),
+    TokenLimit(Address),
+    PendingTokenLimit(Address),
+    MaxTxValue,
+    RejectNoOp,
+    BackupKeyHash,
+    BackupChallengeCounter,
+    BackupRecovery,
+    Metrics,
+    Observers,
+    StrictSessionPermissions,
+    SafeAddress,
+    SessionNonce(BytesN<32>),
+    SessionKeyIndex,
+    Signers,
+    Threshold,
+    PendingOwner,
+    Proposal(u64),
+    NextProposalId,
+    AdminDelay,
+    PendingAdminOp,
+    Guardians,
+    GuardianQuorum,
+    GuardianRecovery,
+    GuardianDelay,
+    PendingGuardianChange,
+    Inheritance,
+    LastActivity,
+    Frozen,
+    UnfreezeAt,
+    UnfreezeDelay,
+    PendingUpgrade,
+    UpgradeDelay,
+    StorageVersion,
+    Registry,
+    NonceChannel(u32),
+    AuditLog,
+    AuditEntry(u32),
+    DestinationAllowlist,
+    DeniedDestination(Address),
+    RateLimit,
+    RecentExecutions,
+    TwoFactor,
+    EscalationPolicy,
+    ProposalLedger(u64),
+    PolicyModules,
+    SingleSignerSpend(Address),
 }
 
 #[contract]
@@ -45,6 +228,13 @@ impl AncoreAccount {
 
         env.storage().instance().set(&DataKey::Owner, &owner);
         env.storage().instance().set(&DataKey::Nonce, &0u64);
+        migration::set_current(&env);
+
+        events::publish(
+            &env,
+            (symbol_short!("ancore"), symbol_short!("init")),
+            owner,
+        );
     }
 
     /// Get the account owner
@@ -55,128 +245,3868 @@ impl AncoreAccount {
             .expect("Not initialized")
     }
 
-    /// Get the current nonce
-    pub fn get_nonce(env: Env) -> u64 {
-        env.storage()
-            .instance()
-            .get(&DataKey::Nonce)
-            .unwrap_or(0)
+    /// Propose `new_owner` as the next owner
+    ///
+    /// Nothing changes until the new owner calls `accept_ownership`, which
+    /// must happen within `OWNER_PROPOSAL_TTL`. A new proposal replaces the
+    /// previous one.
+    pub fn propose_owner(env: Env, new_owner: Address) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+        admin::check_unlocked(&env)?;
+
+        ownership::propose(&env, &new_owner);
+        Ok(())
+    }
+
+    /// Accept a pending ownership proposal, authorized by the proposed owner
+    ///
+    /// Any pending backup recovery and multisig signer set are discarded.
+    pub fn accept_ownership(env: Env) -> Result<(), ContractError> {
+        let old_owner = Self::get_owner(env.clone());
+        let new_owner = ownership::accept(&env)?;
+
+        events::publish(
+            &env,
+            (symbol_short!("ancore"), symbol_short!("own_acc")),
+            (old_owner, new_owner),
+        );
+        Ok(())
+    }
+
+    /// Withdraw the pending ownership proposal
+    pub fn cancel_owner_proposal(env: Env) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        ownership::cancel(&env)
+    }
+
+    /// Get the pending ownership proposal, if any
+    pub fn get_owner_proposal(env: Env) -> Option<PendingOwner> {
+        ownership::pending(&env)
+    }
+
+    /// Point the account at an Ancore registry and keep it up to date
+    ///
+    /// The account's current owner and guardians are pushed at once, and
+    /// again on every owner or guardian change. Fails if the registry does
+    /// not accept the account, e.g. one not deployed by its factory.
+    pub fn set_registry(env: Env, registry: Address) {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        registry::set(&env, &registry);
+    }
+
+    /// Get the registry the account reports to, if any
+    pub fn get_registry(env: Env) -> Option<Address> {
+        registry::get(&env)
+    }
+
+    /// Hand ownership to a set of weighted ed25519 signer keys
+    ///
+    /// The account becomes its own owner, so every operation that needs the
+    /// owner's auth must then be signed by distinct signers whose weights add
+    /// up to at least `threshold`. Calling it again replaces the signer set.
+    /// A pending backup recovery is discarded.
+    pub fn set_multisig(
+        env: Env,
+        signers: Vec<Signer>,
+        threshold: u32,
+    ) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+        admin::check_unlocked(&env)?;
+
+        multisig::enable(&env, &signers, threshold)
+    }
+
+    /// Add a signer to the multisig signer set
+    ///
+    /// Fails with `Unauthorized` unless multisig is enabled.
+    pub fn add_signer(env: Env, signer: Signer) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+        admin::check_unlocked(&env)?;
+
+        multisig::add(&env, &signer)
+    }
+
+    /// Remove a signer from the multisig signer set
+    ///
+    /// Fails with `InvalidThreshold` if the remaining signers' weights could
+    /// no longer reach the threshold.
+    pub fn remove_signer(env: Env, key: BytesN<32>) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+        admin::check_unlocked(&env)?;
+
+        multisig::remove(&env, &key)
+    }
+
+    /// Replace a multisig signer's key, keeping its weight
+    pub fn rotate_signer(
+        env: Env,
+        old_key: BytesN<32>,
+        new_key: BytesN<32>,
+    ) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+        admin::check_unlocked(&env)?;
+
+        multisig::rotate(&env, &old_key, &new_key)
+    }
+
+    /// Require `public_key` to co-sign transfers of more than `threshold`
+    ///
+    /// Such transfers then only run through `execute_with_second_factor`;
+    /// everywhere else they fail with `SecondFactorRequired`. The threshold
+    /// is in each token's own units. Replaces any registered device. Fails
+    /// with `InvalidLimit` for a negative threshold.
+    pub fn set_two_factor(
+        env: Env,
+        public_key: BytesN<32>,
+        threshold: i128,
+    ) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+        admin::check_unlocked(&env)?;
+
+        two_factor::set(&env, &public_key, threshold)
+    }
+
+    /// Stop requiring a second factor
+    pub fn disable_two_factor(env: Env) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+        admin::check_unlocked(&env)?;
+
+        two_factor::disable(&env);
+        Ok(())
+    }
+
+    /// Get the registered second factor, if any
+    pub fn get_two_factor(env: Env) -> Option<TwoFactor> {
+        two_factor::get(&env)
+    }
+
+    /// Scale the approvals token transfer proposals need with their amount
+    ///
+    /// Below `two_signatures_from` the proposing signer is enough, up to
+    /// `full_quorum_above` two signers, and above it every signer once
+    /// `timelock_ledgers` have passed since the proposal. Transfers outside
+    /// proposals fail with `ProposalRequired` from `two_signatures_from`.
+    /// Fails with `Unauthorized` unless multisig is enabled.
+    pub fn set_escalation_policy(env: Env, policy: EscalationPolicy) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+        admin::check_unlocked(&env)?;
+
+        escalation::set(&env, &policy)
+    }
+
+    /// Go back to the threshold for every proposal
+    pub fn clear_escalation_policy(env: Env) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+        admin::check_unlocked(&env)?;
+
+        escalation::clear(&env);
+        Ok(())
+    }
+
+    /// Get the escalation policy, if one is set
+    pub fn get_escalation_policy(env: Env) -> Option<EscalationPolicy> {
+        escalation::get(&env)
+    }
+
+    /// Get the small transfers of `token` single signers made in the current
+    /// window, if any
+    pub fn get_single_signer_spend(env: Env, token: Address) -> Option<SingleSignerSpend> {
+        escalation::single_signer_spend(&env, &token)
+    }
+
+    /// Get the multisig signer keys, empty unless `set_multisig` was called
+    pub fn get_signers(env: Env) -> Vec<Signer> {
+        multisig::signers(&env)
+    }
+
+    /// Get the signer weight needed to act as the owner
+    pub fn get_threshold(env: Env) -> u32 {
+        multisig::threshold(&env)
+    }
+
+    /// Propose replacing the account's code with the uploaded wasm
+    /// `wasm_hash`
+    ///
+    /// The upgrade can be applied with `execute_upgrade` once the upgrade
+    /// delay has passed. Only one upgrade can be pending
+    /// (`TimelockConflict`), and none can be proposed while frozen.
+    pub fn propose_upgrade(
+        env: Env,
+        wasm_hash: BytesN<32>,
+    ) -> Result<PendingUpgrade, ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        upgrade::propose(&env, &wasm_hash)
+    }
+
+    /// Apply the proposed upgrade once its delay has passed
+    ///
+    /// Anyone can call this. The address and storage are kept.
+    pub fn execute_upgrade(env: Env) -> Result<(), ContractError> {
+        upgrade::execute(&env)
+    }
+
+    /// Drop the proposed upgrade
+    pub fn cancel_upgrade(env: Env) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        upgrade::cancel(&env)
+    }
+
+    /// Get the proposed upgrade, if any
+    pub fn get_pending_upgrade(env: Env) -> Option<PendingUpgrade> {
+        upgrade::pending(&env)
+    }
+
+    /// Bring storage written by older code up to `STORAGE_VERSION`
+    ///
+    /// Call after `execute_upgrade`. Old session keys cannot be enumerated,
+    /// so `session_keys` lists the ones to convert; batches can be migrated
+    /// in several calls. Returns how many keys were converted.
+    pub fn migrate(env: Env, session_keys: Vec<BytesN<32>>) -> Result<u32, ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        migration::migrate(&env, &session_keys)
+    }
+
+    /// Get the storage layout version the account is in
+    pub fn get_storage_version(env: Env) -> u32 {
+        migration::version(&env)
+    }
+
+    /// Set how long proposed upgrades wait, in seconds
+    ///
+    /// Fails with `InvalidLimit` below `UPGRADE_DELAY`.
+    pub fn set_upgrade_delay(env: Env, delay: u64) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        upgrade::set_delay(&env, delay)
+    }
+
+    /// Get how long proposed upgrades wait, in seconds
+    pub fn get_upgrade_delay(env: Env) -> u64 {
+        upgrade::delay(&env)
+    }
+
+    /// Set how many ledgers admin operations wait in the queue
+    ///
+    /// While the delay is non-zero, `propose_owner`, `set_multisig`, the
+    /// signer changes, the two-factor and escalation policy changes, policy
+    /// module changes, `set_inheritance`, and raising or clearing the value
+    /// cap fail with `AdminOpTimelocked` and must be queued with
+    /// `queue_admin_op` instead. Raising the delay applies at once; lowering
+    /// it must itself be queued as `AdminOp::SetAdminDelay`.
+    pub fn set_admin_delay(env: Env, delay_ledgers: u32) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+        if delay_ledgers < admin::delay(&env) {
+            return Err(ContractError::AdminOpTimelocked);
+        }
+
+        admin::set_delay(&env, delay_ledgers);
+        Ok(())
+    }
+
+    /// Get the admin delay in ledgers
+    pub fn get_admin_delay(env: Env) -> u32 {
+        admin::delay(&env)
+    }
+
+    /// Queue an admin operation to run after the admin delay
+    ///
+    /// Only one operation can be queued at a time (`TimelockConflict`).
+    pub fn queue_admin_op(env: Env, op: AdminOp) -> Result<PendingAdminOp, ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        admin::queue(&env, &op)
+    }
+
+    /// Apply the queued admin operation once its delay has passed
+    ///
+    /// Anyone can call this; it fails with `TimelockNotExpired` before the
+    /// operation's `executable_at` ledger.
+    pub fn execute_admin_op(env: Env) -> Result<(), ContractError> {
+        let op = admin::take_ready(&env)?;
+        Self::apply_admin_op(&env, &op)
+    }
+
+    /// Drop the queued admin operation
+    pub fn cancel_admin_op(env: Env) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        admin::cancel(&env)
+    }
+
+    /// Get the queued admin operation, if any
+    pub fn get_pending_admin_op(env: Env) -> Option<PendingAdminOp> {
+        admin::pending(&env)
+    }
+
+    /// Get the next nonce on `channel`
+    pub fn get_nonce(env: Env, channel: u32) -> u64 {
+        nonce::get(&env, channel)
+    }
+
+    /// Cancel every unused nonce on `channel` up to and including `up_to`
+    ///
+    /// Payloads signed for those nonces can no longer run, and the channel
+    /// continues at `up_to + 1`. Fails with `InvalidNonce` if `up_to` was
+    /// already consumed.
+    pub fn invalidate_nonces(env: Env, channel: u32, up_to: u64) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        nonce::invalidate(&env, channel, up_to)
+    }
+
+    /// Get a read-only snapshot of the account configuration for backups
+    ///
+    /// Covers ownership, signers, guardians and recovery, the delays, and
+    /// the account-wide spending policies. Per-token spend limits are read
+    /// with `get_token_limit`.
+    pub fn snapshot(env: Env) -> AccountSnapshot {
+        AccountSnapshot {
+            owner: Self::get_owner(env.clone()),
+            nonce: nonce::get(&env, DEFAULT_CHANNEL),
+            backup_key_hash: backup::key_hash(&env).map(Bytes::from),
+            signers: multisig::signers(&env),
+            threshold: multisig::threshold(&env),
+            guardians: guardians::guardians(&env),
+            guardian_quorum: guardians::quorum(&env),
+            guardian_delay: guardians::delay(&env),
+            admin_delay: admin::delay(&env),
+            upgrade_delay: upgrade::delay(&env),
+            unfreeze_delay: freeze::delay(&env),
+            inheritance: optional(&env, inheritance::get(&env)),
+            max_tx_value: optional(&env, tx_value::get(&env)),
+            rate_limit: optional(&env, rate_limit::get(&env)),
+            two_factor: optional(&env, two_factor::get(&env)),
+            escalation_policy: optional(&env, escalation::get(&env)),
+        }
     }
 
     /// Execute a transaction
     ///
     /// # Security
-    /// - Must verify caller is owner or valid session key
-    /// - Must check and increment nonce
-    /// - Must validate signature
+    /// - Requires the owner's auth. Once multisig is enabled the account is
+    ///   its own owner, and `__check_auth` only accepts signatures meeting
+    ///   the threshold. Session keys cannot authorize it; they use
+    ///   `execute_with_session`
+    /// - `expected_nonce` must equal the next nonce on `channel`, or the call
+    ///   fails with `InvalidNonce`; the nonce is incremented only when the
+    ///   call succeeds. Channels are independent, so calls on one never wait
+    ///   on another
+    /// - Outgoing token transfers are metered against the token's spend limit
+    ///   and the per-transaction value cap
+    /// - Negative-amount transfers fail with `InvalidAmount`
+    /// - Zero-amount transfers fail with `NoOp` without consuming the nonce
+    ///   when `set_reject_noop` is enabled
+    /// - In safe mode only transfers to the safe address are allowed; anything
+    ///   else fails with `Unauthorized`
+    /// - Transfers above the two-factor threshold fail with
+    ///   `SecondFactorRequired`; they need `execute_with_second_factor`
+    /// - If the call to `to` fails, the whole execute is reverted and fails
+    ///   with `InvocationFailed`
     pub fn execute(
         env: Env,
         to: Address,
         function: soroban_sdk::Symbol,
         args: Vec<soroban_sdk::Val>,
-    ) -> bool {
-        // TODO: Implement signature validation
-        // TODO: Check nonce
-        // TODO: Execute call
-        // TODO: Increment nonce
-
+        channel: u32,
+        expected_nonce: u64,
+    ) -> Result<bool, ContractError> {
         let owner = Self::get_owner(env.clone());
         owner.require_auth();
 
-        // Increment nonce
-        let current_nonce: u64 = Self::get_nonce(env.clone());
-        env.storage().instance().set(&DataKey::Nonce, &(current_nonce + 1));
+        Self::execute_call(
+            &env,
+            &mut tx_value::Meter::new(&env),
+            &to,
+            &function,
+            &args,
+            channel,
+            expected_nonce,
+            Approval::Caller,
+        )
+    }
 
-        true
+    /// Execute a call the owner signed off-chain, submitted by anyone
+    ///
+    /// `owner_signature` holds multisig signer signatures over
+    /// `get_signed_payload_hash(payload)` meeting the threshold. The call
+    /// runs as in `execute` at `payload.nonce` on `payload.channel`; a
+    /// payload past `payload.expires_at` fails with `PayloadExpired`. The
+    /// owner does not need to be the transaction source or authorize
+    /// anything else.
+    pub fn execute_signed(
+        env: Env,
+        payload: SignedCall,
+        owner_signature: Vec<AccountSignature>,
+    ) -> Result<bool, ContractError> {
+        signed::verify(&env, &payload, &owner_signature)?;
+
+        let call = payload.call;
+        Self::execute_call(
+            &env,
+            &mut tx_value::Meter::new(&env),
+            &call.to,
+            &call.function,
+            &call.args,
+            payload.channel,
+            payload.nonce,
+            Approval::Caller,
+        )
     }
 
-    /// Add a session key
-    pub fn add_session_key(
+    /// Execute a call co-signed by the second-factor device
+    ///
+    /// Like `execute`, but transfers above the two-factor threshold are
+    /// allowed: `device_signature` is the device key's signature over
+    /// `get_two_factor_payload` for this call, channel and nonce. Fails with
+    /// `Unauthorized` if no second factor is registered.
+    pub fn execute_with_second_factor(
         env: Env,
-        public_key: BytesN<32>,
-        expires_at: u64,
-        permissions: Vec<u32>,
-    ) {
+        to: Address,
+        function: Symbol,
+        args: Vec<Val>,
+        channel: u32,
+        expected_nonce: u64,
+        device_signature: BytesN<64>,
+    ) -> Result<bool, ContractError> {
         let owner = Self::get_owner(env.clone());
         owner.require_auth();
+        let payload = two_factor::payload(&env, &to, &function, &args, channel, expected_nonce);
+        two_factor::verify(&env, &payload, &device_signature)?;
 
-        let session_key = SessionKey {
-            public_key: public_key.clone(),
-            expires_at,
-            permissions,
-        };
+        Self::execute_call(
+            &env,
+            &mut tx_value::Meter::new(&env),
+            &to,
+            &function,
+            &args,
+            channel,
+            expected_nonce,
+            Approval::SecondFactor,
+        )
+    }
 
-        env.storage()
-            .persistent()
-            .set(&DataKey::SessionKey(public_key), &session_key);
+    /// Get the hash the second-factor device signs to co-sign a call
+    pub fn get_two_factor_payload(
+        env: Env,
+        to: Address,
+        function: Symbol,
+        args: Vec<Val>,
+        channel: u32,
+        nonce: u64,
+    ) -> BytesN<32> {
+        two_factor::payload(&env, &to, &function, &args, channel, nonce)
     }
 
-    /// Revoke a session key
-    pub fn revoke_session_key(env: Env, public_key: BytesN<32>) {
+    /// Get the hash the owner signs to authorize `payload` for `execute_signed`
+    pub fn get_signed_payload_hash(env: Env, payload: SignedCall) -> BytesN<32> {
+        signed::payload_hash(&env, &payload)
+    }
+
+    /// Execute a relayed call and have a paymaster reimburse the relayer
+    ///
+    /// Runs `call` exactly as `execute` does, then asks `paymaster` to pay
+    /// `relayer` the `fee` it spent submitting the transaction. The owner's
+    /// authorization covers the paymaster, relayer and fee. If the paymaster
+    /// refuses, the call is reverted and the nonce is not consumed.
+    pub fn execute_sponsored(
+        env: Env,
+        call: Call,
+        channel: u32,
+        expected_nonce: u64,
+        paymaster: Address,
+        relayer: Address,
+        fee: i128,
+    ) -> Result<bool, ContractError> {
+        Self::execute(
+            env.clone(),
+            call.to,
+            call.function,
+            call.args,
+            channel,
+            expected_nonce,
+        )?;
+        paymaster::charge(&env, &paymaster, &relayer, fee);
+        Ok(true)
+    }
+
+    /// Execute a relayed call and repay the relayer's fee in a token
+    ///
+    /// Like `execute_sponsored`, but the account pays instead of a
+    /// sponsorship: `paymaster` reimburses `relayer` the `fee` and takes the
+    /// fee converted into `token` at its quote. The owner caps that amount at
+    /// `max_amount` (`QuoteExceeded`), and it counts against the token's
+    /// spend limit and, together with the call's own transfers, the value
+    /// cap. The repayment is part of the call it pays for, so the rate limit
+    /// counts them once.
+    #[allow(clippy::too_many_arguments)]
+    pub fn execute_sponsored_in_token(
+        env: Env,
+        call: Call,
+        channel: u32,
+        expected_nonce: u64,
+        paymaster: Address,
+        relayer: Address,
+        fee: i128,
+        token: Address,
+        max_amount: i128,
+    ) -> Result<bool, ContractError> {
         let owner = Self::get_owner(env.clone());
         owner.require_auth();
 
-        env.storage()
-            .persistent()
-            .remove(&DataKey::SessionKey(public_key));
-    }
+        let mut meter = tx_value::Meter::new(&env);
+        Self::execute_call(
+            &env,
+            &mut meter,
+            &call.to,
+            &call.function,
+            &call.args,
+            channel,
+            expected_nonce,
+            Approval::Caller,
+        )?;
 
-    /// Get a session key
-    pub fn get_session_key(env: Env, public_key: BytesN<32>) -> Option<SessionKey> {
-        env.storage()
-            .persistent()
-            .get(&DataKey::SessionKey(public_key))
+        let amount = paymaster::quote(&env, &paymaster, fee, &token, max_amount)?;
+        let args = paymaster::repayment_args(&env, &paymaster, amount);
+        Self::check_policies(
+            &env,
+            &mut meter,
+            &token,
+            &symbol_short!("transfer"),
+            &args,
+            Approval::Caller,
+        )?;
+        paymaster::repay(&env, &paymaster, &relayer, fee, &token, amount);
+        Ok(true)
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use soroban_sdk::{testutils::Address as _, Address, Env};
+    /// Execute several calls atomically under a single nonce and authorization
+    ///
+    /// The calls run in order with the same checks as `execute`; the value cap
+    /// applies to their combined transfers. If any call fails, every call is
+    /// reverted and the nonce is not consumed.
+    pub fn execute_batch(
+        env: Env,
+        calls: Vec<Call>,
+        channel: u32,
+        expected_nonce: u64,
+    ) -> Result<bool, ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+        freeze::check_not_frozen(&env)?;
+        nonce::check(&env, channel, expected_nonce)?;
 
-    #[test]
-    fn test_initialize() {
-        let env = Env::default();
-        let contract_id = env.register_contract(None, AncoreAccount);
-        let client = AncoreAccountClient::new(&env, &contract_id);
+        let mut meter = tx_value::Meter::new(&env);
+        for call in calls.iter() {
+            let result = Self::call(
+                &env,
+                &mut meter,
+                &call.to,
+                &call.function,
+                &call.args,
+                Approval::Caller,
+            )?;
+            audit::record(&env, AuditCaller::Owner, &call.to, &call.function, result);
+        }
 
-        let owner = Address::generate(&env);
-        client.initialize(&owner);
+        nonce::advance(&env, channel, expected_nonce);
+        metrics::bump(&env, |m| m.executes += 1);
+        inheritance::touch(&env);
 
-        assert_eq!(client.get_owner(), owner);
-        assert_eq!(client.get_nonce(), 0);
+        events::publish(
+            &env,
+            (symbol_short!("ancore"), symbol_short!("batch")),
+            (channel, expected_nonce, calls.clone()),
+        );
+        for call in calls.iter() {
+            Self::notify_call(&env, &call.to, &call.function, &call.args);
+        }
+
+        Ok(true)
     }
 
-    #[test]
-    fn test_add_session_key() {
-        let env = Env::default();
-        let contract_id = env.register_contract(None, AncoreAccount);
-        let client = AncoreAccountClient::new(&env, &contract_id);
+    /// Propose a call for the owner's signers to approve
+    ///
+    /// `signature` is `signer`'s signature over
+    /// `get_proposal_payload(get_next_proposal_id(), call)` and counts as its
+    /// approval. Returns the new proposal's id.
+    pub fn propose_tx(
+        env: Env,
+        signer: BytesN<32>,
+        signature: BytesN<64>,
+        call: Call,
+    ) -> Result<u64, ContractError> {
+        proposals::propose(&env, &signer, &signature, &call)
+    }
 
-        let owner = Address::generate(&env);
-        client.initialize(&owner);
+    /// Approve a proposal with a signer's signature over its payload
+    pub fn approve_tx(
+        env: Env,
+        proposal_id: u64,
+        signer: BytesN<32>,
+        signature: BytesN<64>,
+    ) -> Result<(), ContractError> {
+        proposals::approve(&env, proposal_id, &signer, &signature)
+    }
 
-        env.mock_all_auths();
+    /// Reject a proposal with a signer's signature over
+    /// `get_rejection_payload(proposal_id)`
+    ///
+    /// Once the signers who have not rejected it can no longer meet the
+    /// threshold, the proposal is dropped.
+    pub fn reject_tx(
+        env: Env,
+        proposal_id: u64,
+        signer: BytesN<32>,
+        signature: BytesN<64>,
+    ) -> Result<(), ContractError> {
+        proposals::reject(&env, proposal_id, &signer, &signature)
+    }
 
-        let session_pk = BytesN::from_array(&env, &[1u8; 32]);
-        let expires_at = 1000u64;
-        let permissions = Vec::new(&env);
+    /// Make an approved proposal's call
+    ///
+    /// Anyone can call this once the current signers' approvals meet the
+    /// threshold (`ThresholdNotMet` until then). The call goes through the
+    /// same checks as `execute`; the owner's nonce is not touched.
+    pub fn execute_proposal(env: Env, proposal_id: u64) -> Result<bool, ContractError> {
+        freeze::check_not_frozen(&env)?;
+        let proposal = proposals::take_approved(&env, proposal_id)?;
+        let call = proposal.call;
 
-        client.add_session_key(&session_pk, &expires_at, &permissions);
+        let mut meter = tx_value::Meter::new(&env);
+        let result = Self::call(
+            &env,
+            &mut meter,
+            &call.to,
+            &call.function,
+            &call.args,
+            Approval::Proposal,
+        )?;
+        audit::record(
+            &env,
+            AuditCaller::Proposal(proposal_id),
+            &call.to,
+            &call.function,
+            result,
+        );
+        metrics::bump(&env, |m| m.executes += 1);
+        inheritance::touch(&env);
 
-        let session_key = client.get_session_key(&session_pk);
-        assert!(session_key.is_some());
+        events::publish(
+            &env,
+            (
+                symbol_short!("ancore"),
+                symbol_short!("tx_exec"),
+                proposal_id,
+            ),
+            (call.to.clone(), call.function.clone(), call.args.clone()),
+        );
+        Self::notify_call(&env, &call.to, &call.function, &call.args);
+
+        Ok(true)
     }
 
-    #[test]
-    #[should_panic(expected = "Already initialized")]
-    fn test_double_initialize() {
+    /// Get a pending proposal
+    pub fn get_proposal(env: Env, proposal_id: u64) -> Option<TxProposal> {
+        proposals::get(&env, proposal_id)
+    }
+
+    /// Get the id the next proposal will get
+    pub fn get_next_proposal_id(env: Env) -> u64 {
+        proposals::next_id(&env)
+    }
+
+    /// Get the hash a signer signs to propose or approve `call` as `proposal_id`
+    pub fn get_proposal_payload(env: Env, proposal_id: u64, call: Call) -> BytesN<32> {
+        proposals::approval_payload(&env, proposal_id, &call)
+    }
+
+    /// Get the hash a signer signs to reject `proposal_id`
+    pub fn get_rejection_payload(env: Env, proposal_id: u64) -> BytesN<32> {
+        proposals::rejection_payload(&env, proposal_id)
+    }
+
+    /// Execute a transaction authorized by a session key instead of the owner
+    ///
+    /// `signature` must be the session key's ed25519 signature over
+    /// `get_session_payload(to, function, args, nonce)`, and `nonce` must
+    /// equal the key's own `get_session_nonce`; the owner's nonce is not
+    /// touched. The key must be live and hold a permission covering the call;
+    /// the call then goes through the same checks as `execute`.
+    pub fn execute_with_session(
+        env: Env,
+        session_pk: BytesN<32>,
+        signature: BytesN<64>,
+        to: Address,
+        function: Symbol,
+        args: Vec<Val>,
+        nonce: u64,
+    ) -> Result<bool, ContractError> {
+        freeze::check_not_frozen(&env)?;
+        let current_nonce = session::nonce(&env, &session_pk);
+        if nonce != current_nonce {
+            return Err(ContractError::InvalidNonce);
+        }
+        let payload = session::execute_payload(&env, &to, &function, &args, nonce);
+        let mut session_key = session::authorize(&env, &session_pk, &payload, &signature)?;
+        session::check_call(&env, &mut session_key, &to, &function, &args)?;
+
+        let mut meter = tx_value::Meter::new(&env);
+        let result = Self::call(&env, &mut meter, &to, &function, &args, Approval::Caller)?;
+
+        session::save(&env, &session_key);
+        audit::record(
+            &env,
+            AuditCaller::SessionKey(session_pk.clone()),
+            &to,
+            &function,
+            result,
+        );
+        session::set_nonce(&env, &session_pk, current_nonce + 1);
+        metrics::bump(&env, |m| m.executes += 1);
+
+        events::publish(
+            &env,
+            (symbol_short!("ancore"), symbol_short!("sk_exec")),
+            (
+                current_nonce,
+                session_pk,
+                to.clone(),
+                function.clone(),
+                args.clone(),
+            ),
+        );
+        Self::notify_call(&env, &to, &function, &args);
+
+        Ok(true)
+    }
+
+    /// Cap how much of `token` a session key can move per `period_ledgers`
+    ///
+    /// Tokens without a cap are not limited. Exceeding a cap fails the session
+    /// call with `InsufficientPermission`.
+    pub fn set_session_spend_limit(
+        env: Env,
+        public_key: BytesN<32>,
+        token: Address,
+        max_amount: i128,
+        period_ledgers: u32,
+    ) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        session::set_spend_limit(&env, &public_key, &token, max_amount, period_ledgers)
+    }
+
+    /// Restrict a session key to calling the given contracts
+    ///
+    /// An empty list lets the key call any contract.
+    pub fn set_session_allowed_contracts(
+        env: Env,
+        public_key: BytesN<32>,
+        contracts: Vec<Address>,
+    ) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        session::set_allowed_contracts(&env, &public_key, &contracts)
+    }
+
+    /// Restrict a session key to calling the given function names
+    ///
+    /// The list applies on every contract the key may call. An empty list lets
+    /// the key call any function.
+    pub fn set_session_allowed_functions(
+        env: Env,
+        public_key: BytesN<32>,
+        functions: Vec<Symbol>,
+    ) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        session::set_allowed_functions(&env, &public_key, &functions)
+    }
+
+    /// Limit how many operations a session key can authorize in total
+    ///
+    /// Zero removes the limit. Once the key has been used `max_uses` times it
+    /// is rejected with `SessionKeyExhausted`.
+    pub fn set_session_max_uses(
+        env: Env,
+        public_key: BytesN<32>,
+        max_uses: u32,
+    ) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        session::set_max_uses(&env, &public_key, max_uses)
+    }
+
+    /// Keep a session key unusable until the ledger timestamp `valid_after`
+    ///
+    /// Until then the key is rejected with `SessionKeyNotYetValid`, which lets
+    /// the owner provision keys ahead of the window they are meant for.
+    pub fn set_session_valid_after(
+        env: Env,
+        public_key: BytesN<32>,
+        valid_after: u64,
+    ) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        session::set_valid_after(&env, &public_key, valid_after)
+    }
+
+    /// Get the nonce a session key must use for its next `execute_with_session`
+    pub fn get_session_nonce(env: Env, public_key: BytesN<32>) -> u64 {
+        session::nonce(&env, &public_key)
+    }
+
+    /// Get the hash a session key signs for `execute_with_session`
+    pub fn get_session_payload(
+        env: Env,
+        to: Address,
+        function: Symbol,
+        args: Vec<Val>,
+        nonce: u64,
+    ) -> BytesN<32> {
+        session::execute_payload(&env, &to, &function, &args, nonce)
+    }
+
+    /// Add a session key
+    ///
+    /// In strict mode (`set_strict_session_permissions`) a key without any
+    /// permissions is rejected with `EmptyPermissions`.
+    pub fn add_session_key(
+        env: Env,
+        public_key: BytesN<32>,
+        expires_at: u64,
+        permissions: Vec<Permission>,
+    ) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+        freeze::check_not_frozen(&env)?;
+
+        if permissions.is_empty() && Self::get_strict_session_permissions(env.clone()) {
+            return Err(ContractError::EmptyPermissions);
+        }
+
+        let session_key = SessionKey {
+            public_key: public_key.clone(),
+            valid_after: 0,
+            expires_at,
+            permissions,
+            spend_limits: Vec::new(&env),
+            allowed_contracts: Vec::new(&env),
+            allowed_functions: Vec::new(&env),
+            max_uses: 0,
+            uses: 0,
+        };
+
+        env.storage()
+            .persistent()
+            .set(&DataKey::SessionKey(public_key.clone()), &session_key);
+        session::add_to_index(&env, &public_key);
+        metrics::bump(&env, |m| m.session_keys_created += 1);
+
+        events::publish(
+            &env,
+            (symbol_short!("ancore"), symbol_short!("sk_add")),
+            (public_key.clone(), expires_at),
+        );
+        observers::notify(&env, OBSERVE_SESSION_KEY, (true, public_key));
+        Ok(())
+    }
+
+    /// Change when a session key expires
+    ///
+    /// Renews a key in place, so its nonce, usage counters, and restrictions
+    /// carry over. Works on expired keys that have not been pruned yet.
+    pub fn extend_session_key(
+        env: Env,
+        public_key: BytesN<32>,
+        new_expires_at: u64,
+    ) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+        freeze::check_not_frozen(&env)?;
+
+        session::extend(&env, &public_key, new_expires_at)
+    }
+
+    /// Revoke a session key
+    ///
+    /// Revocation removes every piece of per-key state, so a key added again
+    /// later starts fresh rather than inheriting old usage. The key's nonce is
+    /// the exception: it keeps counting so old signatures stay unusable.
+    pub fn revoke_session_key(env: Env, public_key: BytesN<32>) {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        session::remove(&env, &public_key);
+
+        events::publish(
+            &env,
+            (symbol_short!("ancore"), symbol_short!("sk_revoke")),
+            public_key.clone(),
+        );
+        observers::notify(&env, OBSERVE_SESSION_KEY, (false, public_key));
+    }
+
+    /// Remove up to `limit` expired session keys from storage
+    ///
+    /// Anyone can call this to reclaim the storage of keys that can no longer
+    /// be used. Returns how many keys were removed.
+    pub fn prune_expired(env: Env, limit: u32) -> u32 {
+        let pruned = session::prune_expired(&env, limit);
+        for public_key in pruned.iter() {
+            events::publish(
+                &env,
+                (
+                    symbol_short!("ancore"),
+                    symbol_short!("sk_prune"),
+                    public_key.clone(),
+                ),
+                (),
+            );
+            observers::notify(&env, OBSERVE_SESSION_KEY, (false, public_key));
+        }
+        pruned.len()
+    }
+
+    /// Get a session key
+    pub fn get_session_key(env: Env, public_key: BytesN<32>) -> Option<SessionKey> {
+        session::get(&env, &public_key)
+    }
+
+    /// List registered session keys, `limit` at a time from position `offset`
+    ///
+    /// Keys are returned in the order they were first added.
+    pub fn list_session_keys(env: Env, offset: u32, limit: u32) -> Vec<SessionKey> {
+        session::list(&env, offset, limit)
+    }
+
+    /// Verify a signature over `message_hash` made by one of the account's keys
+    ///
+    /// Modeled on EIP-1271 `isValidSignature`: returns `false` unless `key` is
+    /// a multisig signer or a live session key, so unknown, revoked, and
+    /// expired keys answer `false`. For an accepted key the signature is
+    /// checked with `ed25519_verify`, which cannot report a mismatch: a bad
+    /// signature on a known key fails the call outright instead of returning
+    /// `false`.
+    pub fn verify_message(
+        env: Env,
+        message_hash: BytesN<32>,
+        signature: BytesN<64>,
+        key: BytesN<32>,
+    ) -> bool {
+        let is_owner_key = multisig::is_owner_key(&env, &key);
+        let is_session_key = session::get(&env, &key)
+            .is_some_and(|session_key| session::is_live(&env, &session_key));
+        if !is_owner_key && !is_session_key {
+            return false;
+        }
+
+        env.crypto()
+            .ed25519_verify(&key, &message_hash.into(), &signature);
+        true
+    }
+
+    /// Check whether every permission in `proposed` is covered by a live session key
+    ///
+    /// Lets clients validate a delegated permission set up front: a proposed
+    /// permission is covered when one of the parent's permissions allows
+    /// everything it does. Returns `false` when the parent key is unknown,
+    /// expired, or not yet valid.
+    pub fn is_permission_subset(
+        env: Env,
+        parent_public_key: BytesN<32>,
+        proposed: Vec<Permission>,
+    ) -> bool {
+        let Some(parent) = session::get(&env, &parent_public_key) else {
+            return false;
+        };
+        if !session::is_live(&env, &parent) {
+            return false;
+        }
+
+        permissions::is_subset(&parent.permissions, &proposed)
+    }
+
+    /// Set the spend limit for outgoing transfers of `token`
+    ///
+    /// Applies to every transfer the account makes, whoever authorized it.
+    /// Lowering the amount or lengthening the period takes effect at once;
+    /// any loosening is queued for `LIMIT_INCREASE_DELAY` seconds.
+    pub fn set_token_limit(
+        env: Env,
+        token: Address,
+        amount_per_period: i128,
+        period: u64,
+    ) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        limits::set(&env, &token, amount_per_period, period)
+    }
+
+    /// Get the spend limit in force for `token` and its current usage
+    pub fn get_token_limit(env: Env, token: Address) -> Option<TokenLimit> {
+        limits::get(&env, &token)
+    }
+
+    /// Get the queued increase of the spend limit for `token`, if any
+    pub fn get_pending_token_limit(env: Env, token: Address) -> Option<PendingTokenLimit> {
+        limits::pending(&env, &token)
+    }
+
+    /// Cap the total value moved by a single transaction
+    ///
+    /// Outgoing transfers are priced in `reference_token` through `oracle`
+    /// and summed per transaction, rounding each converted value up;
+    /// exceeding `amount` fails with `TxValueExceeded`. Lowering the cap
+    /// applies at once; raising it or changing the reference token or
+    /// oracle is an admin operation.
+    pub fn set_max_tx_value(
+        env: Env,
+        reference_token: Address,
+        oracle: Address,
+        amount: i128,
+    ) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        let config = MaxTxValue {
+            reference_token,
+            oracle,
+            amount,
+        };
+        if tx_value::loosens(&env, &config) {
+            admin::check_unlocked(&env)?;
+        }
+        tx_value::set(&env, &config)
+    }
+
+    /// Remove the per-transaction value cap, an admin operation
+    pub fn clear_max_tx_value(env: Env) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+        admin::check_unlocked(&env)?;
+
+        tx_value::clear(&env);
+        Ok(())
+    }
+
+    /// Get the per-transaction value cap, if one is configured
+    pub fn get_max_tx_value(env: Env) -> Option<MaxTxValue> {
+        tx_value::get(&env)
+    }
+
+    /// Reject detected zero-amount transfers instead of spending a nonce on them
+    ///
+    /// Off by default, since some calls legitimately transfer zero.
+    pub fn set_reject_noop(env: Env, enabled: bool) {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        env.storage().instance().set(&DataKey::RejectNoOp, &enabled);
+        events::publish(
+            &env,
+            (symbol_short!("ancore"), symbol_short!("noop_pol")),
+            enabled,
+        );
+    }
+
+    /// Whether zero-amount transfers are rejected
+    pub fn get_reject_noop(env: Env) -> bool {
+        env.storage()
+            .instance()
+            .get(&DataKey::RejectNoOp)
+            .unwrap_or(false)
+    }
+
+    /// Restrict `execute` to token transfers to `safe_address`
+    ///
+    /// Meant for when a compromise is suspected but recovery cannot happen
+    /// yet: funds can still be moved out to safety, nothing else runs.
+    pub fn enter_safe_mode(env: Env, safe_address: Address) {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        env.storage()
+            .instance()
+            .set(&DataKey::SafeAddress, &safe_address);
+        events::publish(
+            &env,
+            (symbol_short!("ancore"), symbol_short!("safe_on")),
+            safe_address,
+        );
+    }
+
+    /// Leave safe mode and allow any call again
+    pub fn exit_safe_mode(env: Env) {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        env.storage().instance().remove(&DataKey::SafeAddress);
+        events::publish(
+            &env,
+            (symbol_short!("ancore"), symbol_short!("safe_off")),
+            (),
+        );
+    }
+
+    /// Get the safe address while safe mode is active
+    pub fn get_safe_address(env: Env) -> Option<Address> {
+        env.storage().instance().get(&DataKey::SafeAddress)
+    }
+
+    /// Only allow calls to the contracts in `destinations`
+    ///
+    /// Applies to every execute path, including session keys and approved
+    /// proposals. Token transfers are calls to the token contract, so moved
+    /// tokens must be listed. Fails with `InvalidLimit` above
+    /// `MAX_ALLOWED_DESTINATIONS`.
+    pub fn set_destination_allowlist(
+        env: Env,
+        destinations: Vec<Address>,
+    ) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        destinations::set_allowlist(&env, &destinations)
+    }
+
+    /// Remove the destination allowlist and allow calls to any contract again
+    pub fn clear_destination_allowlist(env: Env) {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        destinations::clear_allowlist(&env);
+    }
+
+    /// Get the destination allowlist, if one is set
+    pub fn get_destination_allowlist(env: Env) -> Option<Vec<Address>> {
+        destinations::allowlist(&env)
+    }
+
+    /// Never call or pay `destination` from this account
+    ///
+    /// Applies to every execute path, including session keys and approved
+    /// proposals, and takes precedence over the allowlist.
+    pub fn deny_destination(env: Env, destination: Address) {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        destinations::deny(&env, &destination);
+    }
+
+    /// Allow calls and transfers to a denied `destination` again
+    pub fn undeny_destination(env: Env, destination: Address) {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        destinations::undeny(&env, &destination);
+    }
+
+    /// Check whether `destination` is on the denylist
+    pub fn is_destination_denied(env: Env, destination: Address) -> bool {
+        destinations::is_denied(&env, &destination)
+    }
+
+    /// Allow at most `max_executions` calls in any `window_ledgers` ledgers
+    ///
+    /// Applies to every execute path, including session keys, and counts
+    /// each call of a batch. Further calls fail with `RateLimited` until
+    /// earlier ones leave the rolling window. Fails with `InvalidLimit` for a
+    /// zero value or above `RATE_LIMIT_MAX_EXECUTIONS`.
+    pub fn set_rate_limit(
+        env: Env,
+        max_executions: u32,
+        window_ledgers: u32,
+    ) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        rate_limit::set(&env, max_executions, window_ledgers)
+    }
+
+    /// Remove the rate limit
+    pub fn clear_rate_limit(env: Env) {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        rate_limit::clear(&env);
+    }
+
+    /// Get the rate limit, if one is set
+    pub fn get_rate_limit(env: Env) -> Option<RateLimit> {
+        rate_limit::get(&env)
+    }
+
+    /// Require every new session key to carry at least one permission
+    ///
+    /// Off by default. Existing keys are not affected.
+    pub fn set_strict_session_permissions(env: Env, enabled: bool) {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        env.storage()
+            .instance()
+            .set(&DataKey::StrictSessionPermissions, &enabled);
+        events::publish(
+            &env,
+            (symbol_short!("ancore"), symbol_short!("sk_strict")),
+            enabled,
+        );
+    }
+
+    /// Whether session keys without permissions are rejected
+    pub fn get_strict_session_permissions(env: Env) -> bool {
+        env.storage()
+            .instance()
+            .get(&DataKey::StrictSessionPermissions)
+            .unwrap_or(false)
+    }
+
+    /// Register the sha256 hash of an ed25519 backup key that can recover the account
+    pub fn set_backup_key_hash(env: Env, key_hash: BytesN<32>) {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        backup::set_key_hash(&env, &key_hash);
+    }
+
+    /// Get the registered backup key hash, if any
+    pub fn get_backup_key_hash(env: Env) -> Option<BytesN<32>> {
+        backup::key_hash(&env)
+    }
+
+    /// Get the challenge the backup key must sign to recover to `new_owner`
+    pub fn get_backup_recovery_challenge(env: Env, new_owner: Address) -> BytesN<32> {
+        backup::challenge(&env, &new_owner)
+    }
+
+    /// Start a backup-key recovery to `new_owner`
+    ///
+    /// Callable by anyone holding the backup key: `public_key` must hash to
+    /// the registered backup key hash and `signature` must cover the current
+    /// challenge. The owner can veto until `BACKUP_RECOVERY_DELAY` passes.
+    pub fn initiate_backup_recovery(
+        env: Env,
+        public_key: BytesN<32>,
+        new_owner: Address,
+        signature: BytesN<64>,
+    ) -> Result<(), ContractError> {
+        backup::initiate(&env, &public_key, &new_owner, &signature)
+    }
+
+    /// Veto the pending backup-key recovery
+    pub fn cancel_backup_recovery(env: Env) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        backup::cancel(&env)
+    }
+
+    /// Hand ownership to the recovering key's chosen owner once the timelock passed
+    pub fn finalize_backup_recovery(env: Env) -> Result<(), ContractError> {
+        let old_owner = Self::get_owner(env.clone());
+        let new_owner = backup::finalize(&env)?;
+
+        ownership::set_owner(&env, &new_owner);
+        metrics::bump(&env, |m| m.recoveries += 1);
+        events::publish(
+            &env,
+            (symbol_short!("ancore"), symbol_short!("bk_done")),
+            (old_owner.clone(), new_owner.clone()),
+        );
+        observers::notify(&env, OBSERVE_RECOVERY, (old_owner, new_owner));
+        Ok(())
+    }
+
+    /// Get the pending backup-key recovery, if any
+    pub fn get_backup_recovery(env: Env) -> Option<BackupRecovery> {
+        backup::pending(&env)
+    }
+
+    /// Designate the guardians who can recover the account, and how many of
+    /// them must approve a recovery
+    ///
+    /// `quorum` must be between 1 and the number of guardians
+    /// (`InvalidThreshold`); an empty set with quorum 0 disables guardian
+    /// recovery. The first guardian set applies at once; later changes are
+    /// queued for the guardian change delay.
+    pub fn set_guardians(
+        env: Env,
+        guardians: Vec<Address>,
+        quorum: u32,
+    ) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        guardians::set(&env, &guardians, quorum)
+    }
+
+    /// Get the guardian addresses
+    pub fn get_guardians(env: Env) -> Vec<Address> {
+        guardians::guardians(&env)
+    }
+
+    /// Get how many guardians must approve a recovery
+    pub fn get_guardian_quorum(env: Env) -> u32 {
+        guardians::quorum(&env)
+    }
+
+    /// Set how long guardian changes wait, in seconds
+    ///
+    /// Raising the delay applies at once; lowering it is queued like a
+    /// guardian change.
+    pub fn set_guardian_delay(env: Env, delay: u64) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        guardians::set_delay(&env, delay)
+    }
+
+    /// Get how long guardian changes wait, in seconds
+    pub fn get_guardian_delay(env: Env) -> u64 {
+        guardians::delay(&env)
+    }
+
+    /// Apply the queued guardian change once its delay has passed
+    pub fn apply_guardian_change(env: Env) -> Result<(), ContractError> {
+        guardians::apply_pending(&env)
+    }
+
+    /// Drop the queued guardian change
+    pub fn cancel_guardian_change(env: Env) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        guardians::cancel_change(&env)
+    }
+
+    /// Get the queued guardian change, if any
+    pub fn get_pending_guardian_change(env: Env) -> Option<PendingGuardianChange> {
+        guardians::pending_change(&env)
+    }
+
+    /// Start a guardian recovery to `new_owner`, authorized by `guardian`
+    ///
+    /// The starting guardian's approval is counted. Fails with
+    /// `RecoveryAlreadyPending` if a guardian recovery is already underway.
+    pub fn initiate_recovery(
+        env: Env,
+        guardian: Address,
+        new_owner: Address,
+    ) -> Result<(), ContractError> {
+        guardians::initiate(&env, &guardian, &new_owner)
+    }
+
+    /// Approve the pending guardian recovery, authorized by `guardian`
+    pub fn approve_recovery(env: Env, guardian: Address) -> Result<(), ContractError> {
+        guardians::approve(&env, &guardian)
+    }
+
+    /// Hand ownership to the recovery's new owner once a quorum approved it
+    /// and `GUARDIAN_RECOVERY_DELAY` has passed
+    pub fn finalize_recovery(env: Env) -> Result<(), ContractError> {
+        let old_owner = Self::get_owner(env.clone());
+        let new_owner = guardians::finalize(&env)?;
+
+        ownership::set_owner(&env, &new_owner);
+        metrics::bump(&env, |m| m.recoveries += 1);
+        events::publish(
+            &env,
+            (symbol_short!("ancore"), symbol_short!("rc_done")),
+            (old_owner.clone(), new_owner.clone()),
+        );
+        observers::notify(&env, OBSERVE_RECOVERY, (old_owner, new_owner));
+        Ok(())
+    }
+
+    /// Veto the pending guardian recovery
+    pub fn cancel_recovery(env: Env) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        guardians::cancel(&env)
+    }
+
+    /// Get the pending guardian recovery, if any
+    pub fn get_recovery(env: Env) -> Option<GuardianRecovery> {
+        guardians::pending(&env)
+    }
+
+    /// Name a beneficiary who can claim the account after `inactivity_ledgers`
+    /// ledgers without owner activity
+    ///
+    /// Executes, owner-signed auth, and `heartbeat` count as activity.
+    /// Replaces any earlier beneficiary and counts as activity itself.
+    /// Claiming hands over ownership, so this is an admin operation, and
+    /// fails with `InvalidLimit` for a period shorter than the admin delay.
+    pub fn set_inheritance(
+        env: Env,
+        beneficiary: Address,
+        inactivity_ledgers: u32,
+    ) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+        admin::check_unlocked(&env)?;
+
+        inheritance::set(&env, &beneficiary, inactivity_ledgers)
+    }
+
+    /// Remove the beneficiary
+    pub fn clear_inheritance(env: Env) {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        inheritance::clear(&env);
+        events::publish(
+            &env,
+            (symbol_short!("ancore"), symbol_short!("inh_clear")),
+            (),
+        );
+    }
+
+    /// Get the beneficiary and inactivity period, if set
+    pub fn get_inheritance(env: Env) -> Option<Inheritance> {
+        inheritance::get(&env)
+    }
+
+    /// Record owner activity without doing anything else
+    pub fn heartbeat(env: Env) {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        inheritance::heartbeat(&env);
+    }
+
+    /// Get the ledger of the last recorded owner activity
+    pub fn get_last_activity(env: Env) -> u32 {
+        inheritance::last_activity(&env)
+    }
+
+    /// Hand ownership to the beneficiary, authorized by the beneficiary, once
+    /// the owner has been inactive for the configured period
+    ///
+    /// Fails with `OwnerActive` before then.
+    pub fn claim_inheritance(env: Env) -> Result<(), ContractError> {
+        let old_owner = Self::get_owner(env.clone());
+        let new_owner = inheritance::claim(&env)?;
+
+        ownership::set_owner(&env, &new_owner);
+        events::publish(
+            &env,
+            (symbol_short!("ancore"), symbol_short!("inh_claim")),
+            (old_owner.clone(), new_owner.clone()),
+        );
+        observers::notify(&env, OBSERVE_RECOVERY, (old_owner, new_owner));
+        Ok(())
+    }
+
+    /// Freeze the account, authorized by `caller`, the owner or a guardian
+    ///
+    /// Until unfrozen, the account makes no calls and grants no session
+    /// keys; those operations fail with `Frozen`.
+    pub fn freeze(env: Env, caller: Address) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        freeze::freeze(&env, &owner, &caller)
+    }
+
+    /// Start the unfreeze delay, after which the owner can unfreeze
+    ///
+    /// Returns when the unfreeze becomes possible. Freezing again cancels
+    /// the request.
+    pub fn request_unfreeze(env: Env) -> Result<u64, ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        freeze::request_unfreeze(&env)
+    }
+
+    /// Lift the freeze once the owner's unfreeze request has waited out the
+    /// unfreeze delay
+    pub fn unfreeze(env: Env) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        freeze::unfreeze(&env)
+    }
+
+    /// Lift the freeze at once, authorized by a quorum of distinct guardians
+    pub fn unfreeze_with_guardians(env: Env, guardians: Vec<Address>) -> Result<(), ContractError> {
+        freeze::unfreeze_with_guardians(&env, &guardians)
+    }
+
+    /// Set how long an owner's unfreeze request waits, in seconds
+    ///
+    /// Fails with `Frozen` while the account is frozen.
+    pub fn set_unfreeze_delay(env: Env, delay: u64) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        freeze::set_delay(&env, delay)
+    }
+
+    /// Get how long an owner's unfreeze request waits, in seconds
+    pub fn get_unfreeze_delay(env: Env) -> u64 {
+        freeze::delay(&env)
+    }
+
+    /// Get when the owner's pending unfreeze request matures, if any
+    pub fn get_unfreeze_at(env: Env) -> Option<u64> {
+        freeze::unfreeze_at(&env)
+    }
+
+    /// Check whether the account is frozen
+    pub fn is_frozen(env: Env) -> bool {
+        freeze::is_frozen(&env)
+    }
+
+    /// Subscribe an observer contract to the event kinds set in `event_mask`
+    ///
+    /// `event_mask` combines the `OBSERVE_*` flags. Registering an existing
+    /// observer replaces its mask. At most `MAX_OBSERVERS` can be registered;
+    /// an observer that fails never blocks the account.
+    pub fn register_observer(
+        env: Env,
+        observer: Address,
+        event_mask: u32,
+    ) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        observers::register(&env, &observer, event_mask)
+    }
+
+    /// Stop notifying an observer contract
+    pub fn unregister_observer(env: Env, observer: Address) {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        observers::unregister(&env, &observer);
+    }
+
+    /// Get the registered observers and their event masks
+    pub fn get_observers(env: Env) -> Map<Address, u32> {
+        observers::all(&env)
+    }
+
+    /// Consult a policy module contract before every call the account makes
+    ///
+    /// The module's `validate(ctx)` must return `true` for each call, or the
+    /// call fails with `PolicyRejected`; a module that fails rejects it too.
+    /// Registering a module twice has no effect. At most
+    /// `MAX_POLICY_MODULES` can be registered (`TooManyPolicyModules`). A
+    /// module sees every call the account makes, so this is an admin
+    /// operation.
+    pub fn register_policy_module(env: Env, module: Address) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+        admin::check_unlocked(&env)?;
+
+        policy_modules::register(&env, &module)
+    }
+
+    /// Stop consulting a policy module
+    pub fn unregister_policy_module(env: Env, module: Address) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+        admin::check_unlocked(&env)?;
+
+        policy_modules::unregister(&env, &module);
+        Ok(())
+    }
+
+    /// Get the registered policy modules, in the order they are consulted
+    pub fn get_policy_modules(env: Env) -> Vec<Address> {
+        policy_modules::all(&env)
+    }
+
+    /// Get operational counters for executes, session keys, and recoveries
+    pub fn get_metrics(env: Env) -> Metrics {
+        metrics::get(&env)
+    }
+
+    /// Keep the last `size` executions in the on-chain audit log
+    ///
+    /// `0` turns the log off. Changing the size clears the log. Fails with
+    /// `InvalidLimit` above `AUDIT_LOG_MAX_SIZE`.
+    pub fn set_audit_log_size(env: Env, size: u32) -> Result<(), ContractError> {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        audit::set_size(&env, size)
+    }
+
+    /// Get how many executions the audit log keeps
+    pub fn get_audit_log_size(env: Env) -> u32 {
+        audit::size(&env)
+    }
+
+    /// List logged executions, newest first, `limit` at a time after skipping
+    /// the `offset` most recent
+    pub fn get_audit_log(env: Env, offset: u32, limit: u32) -> Vec<AuditEntry> {
+        audit::list(&env, offset, limit)
+    }
+
+    /// Turn committing events to the per-epoch merkle tree on or off
+    ///
+    /// On by default. Turning it off saves a persistent write per event;
+    /// events emitted while it is off are not in the tree, and the toggle
+    /// event itself is always committed.
+    pub fn set_event_commitment(env: Env, enabled: bool) {
+        let owner = Self::get_owner(env.clone());
+        owner.require_auth();
+
+        events::set_commitment(&env, enabled);
+    }
+
+    /// Whether events are committed to the per-epoch merkle tree
+    pub fn get_event_commitment(env: Env) -> bool {
+        events::commitment_enabled(&env)
+    }
+
+    /// Get the merkle root committing to every event emitted during `epoch`
+    /// while the commitment was enabled
+    ///
+    /// Epochs are `EVENT_EPOCH_LEDGERS` ledgers long; see the `events` module
+    /// for how leaves and the tree are built.
+    pub fn get_event_root(env: Env, epoch: u32) -> BytesN<32> {
+        events::root(&env, epoch)
+    }
+}
+
+#[contractimpl]
+impl CustomAccountInterface for AncoreAccount {
+    type Signature = Vec<AccountSignature>;
+    type Error = ContractError;
+
+    /// Authorize calls made in the account's name by other contracts
+    ///
+    /// The payload must be signed by enough multisig signers to meet the
+    /// threshold, or by a single live session key with permissions covering each call. The authorized calls go
+    /// through the same transfer policies as `execute`, so spend limits, the
+    /// value cap, and safe mode cannot be bypassed by having a token ask the
+    /// account for auth directly. Calls to the account itself apply those
+    /// policies in the entrypoint, and session keys cannot authorize them.
+    fn __check_auth(
+        env: Env,
+        signature_payload: Hash<32>,
+        signatures: Vec<AccountSignature>,
+        auth_contexts: Vec<Context>,
+    ) -> Result<(), ContractError> {
+        let payload: BytesN<32> = signature_payload.into();
+        let mut session_key = None;
+        match signatures.first() {
+            Some(signature)
+                if signatures.len() == 1
+                    && !multisig::is_owner_key(&env, &signature.public_key) =>
+            {
+                session_key = Some(session::authorize(
+                    &env,
+                    &signature.public_key,
+                    &payload,
+                    &signature.signature,
+                )?);
+            }
+            _ => {
+                multisig::verify(&env, &payload, &signatures)?;
+                inheritance::touch(&env);
+            }
+        }
+
+        let mut meter = tx_value::Meter::new(&env);
+        for context in auth_contexts.iter() {
+            match context {
+                Context::Contract(call) => {
+                    if call.contract != env.current_contract_address() {
+                        freeze::check_not_frozen(&env)?;
+                        Self::check_call(
+                            &env,
+                            &mut meter,
+                            &call.contract,
+                            &call.fn_name,
+                            &call.args,
+                            Approval::Caller,
+                        )?;
+                    }
+                    if let Some(session_key) = &mut session_key {
+                        session::check_call(
+                            &env,
+                            session_key,
+                            &call.contract,
+                            &call.fn_name,
+                            &call.args,
+                        )?;
+                    }
+                }
+                Context::CreateContractHostFn(_) => {
+                    freeze::check_not_frozen(&env)?;
+                    if Self::get_safe_address(env.clone()).is_some() {
+                        return Err(ContractError::Unauthorized);
+                    }
+                    // Session keys are delegated calls, not deployments.
+                    if session_key.is_some() {
+                        return Err(ContractError::InsufficientPermission);
+                    }
+                }
+            }
+        }
+
+        if let Some(session_key) = session_key {
+            session::save(&env, &session_key);
+        }
+        Ok(())
+    }
+}
+
+/// Approval a call carries beyond the auth of whoever made it
+#[derive(Clone, Copy, PartialEq)]
+enum Approval {
+    /// Only the caller's own auth
+    Caller,
+    /// Co-signed by the second-factor device
+    SecondFactor,
+    /// An approved multisig proposal
+    Proposal,
+}
+
+impl AncoreAccount {
+    /// Run an authorized call for `execute` and its signed and co-signed
+    /// variants
+    #[allow(clippy::too_many_arguments)]
+    fn execute_call(
+        env: &Env,
+        meter: &mut tx_value::Meter,
+        to: &Address,
+        function: &Symbol,
+        args: &Vec<Val>,
+        channel: u32,
+        expected_nonce: u64,
+        approval: Approval,
+    ) -> Result<bool, ContractError> {
+        freeze::check_not_frozen(env)?;
+        nonce::check(env, channel, expected_nonce)?;
+
+        let result = Self::call(env, meter, to, function, args, approval)?;
+
+        nonce::advance(env, channel, expected_nonce);
+        audit::record(env, AuditCaller::Owner, to, function, result);
+        metrics::bump(env, |m| m.executes += 1);
+        inheritance::touch(env);
+
+        events::publish(
+            env,
+            (symbol_short!("ancore"), symbol_short!("execute")),
+            (
+                channel,
+                expected_nonce,
+                to.clone(),
+                function.clone(),
+                args.clone(),
+            ),
+        );
+        Self::notify_call(env, to, function, args);
+
+        Ok(true)
+    }
+
+    /// Carry out an admin operation whose delay has passed
+    fn apply_admin_op(env: &Env, op: &AdminOp) -> Result<(), ContractError> {
+        match op {
+            AdminOp::ProposeOwner(new_owner) => {
+                ownership::propose(env, new_owner);
+                Ok(())
+            }
+            AdminOp::SetMultisig(signers, threshold) => multisig::enable(env, signers, *threshold),
+            AdminOp::AddSigner(signer) => multisig::add(env, signer),
+            AdminOp::RemoveSigner(key) => multisig::remove(env, key),
+            AdminOp::RotateSigner(old_key, new_key) => multisig::rotate(env, old_key, new_key),
+            AdminOp::SetAdminDelay(delay_ledgers) => {
+                admin::set_delay(env, *delay_ledgers);
+                Ok(())
+            }
+            AdminOp::SetTwoFactor(public_key, threshold) => {
+                two_factor::set(env, public_key, *threshold)
+            }
+            AdminOp::DisableTwoFactor => {
+                two_factor::disable(env);
+                Ok(())
+            }
+            AdminOp::SetEscalationPolicy(policy) => escalation::set(env, policy),
+            AdminOp::ClearEscalationPolicy => {
+                escalation::clear(env);
+                Ok(())
+            }
+            AdminOp::UnregisterPolicyModule(module) => {
+                policy_modules::unregister(env, module);
+                Ok(())
+            }
+            AdminOp::SetInheritance(beneficiary, inactivity_ledgers) => {
+                inheritance::set(env, beneficiary, *inactivity_ledgers)
+            }
+            AdminOp::RegisterPolicyModule(module) => policy_modules::register(env, module),
+            AdminOp::SetMaxTxValue(config) => tx_value::set(env, config),
+            AdminOp::ClearMaxTxValue => {
+                tx_value::clear(env);
+                Ok(())
+            }
+        }
+    }
+
+    /// Make one call on behalf of the account, enforcing the transfer policies
+    ///
+    /// An error must be returned from the entrypoint, which rolls back the
+    /// spend recorded here and anything earlier calls did.
+    fn call(
+        env: &Env,
+        meter: &mut tx_value::Meter,
+        to: &Address,
+        function: &Symbol,
+        args: &Vec<Val>,
+        approval: Approval,
+    ) -> Result<Val, ContractError> {
+        Self::check_call(env, meter, to, function, args, approval)?;
+
+        match env.try_invoke_contract::<Val, soroban_sdk::Error>(to, function, args.clone()) {
+            Ok(Ok(result)) => Ok(result),
+            _ => Err(ContractError::InvocationFailed),
+        }
+    }
+
+    /// Apply the rate limit, the destination policy, safe mode, the second
+    /// factor, escalation, spend limits, the value cap, and the policy
+    /// modules to a call
+    fn check_call(
+        env: &Env,
+        meter: &mut tx_value::Meter,
+        to: &Address,
+        function: &Symbol,
+        args: &Vec<Val>,
+        approval: Approval,
+    ) -> Result<(), ContractError> {
+        rate_limit::record(env)?;
+        Self::check_policies(env, meter, to, function, args, approval)
+    }
+
+    /// Everything `check_call` applies except the rate limit
+    fn check_policies(
+        env: &Env,
+        meter: &mut tx_value::Meter,
+        to: &Address,
+        function: &Symbol,
+        args: &Vec<Val>,
+        approval: Approval,
+    ) -> Result<(), ContractError> {
+        let detected = transfer::detect(env, to, function, args);
+        destinations::check(env, to, detected.as_ref().map(|transfer| &transfer.to))?;
+        if let Some(safe_address) = Self::get_safe_address(env.clone()) {
+            if detected.as_ref().map(|transfer| &transfer.to) != Some(&safe_address) {
+                return Err(ContractError::Unauthorized);
+            }
+        }
+        if let Some(transfer) = &detected {
+            // A negative amount would credit spend accounting instead of debiting it.
+            if transfer.amount < 0 {
+                return Err(ContractError::InvalidAmount);
+            }
+            if transfer.amount == 0 && Self::get_reject_noop(env.clone()) {
+                return Err(ContractError::NoOp);
+            }
+            two_factor::check(env, transfer, approval == Approval::SecondFactor)?;
+            if approval != Approval::Proposal {
+                escalation::check_direct(env, transfer)?;
+            }
+            limits::record_spend(env, transfer)?;
+            meter.add(env, transfer)?;
+        }
+        policy_modules::check(env, to, function, args)
+    }
+
+    /// Tell observers about a call that went through
+    fn notify_call(env: &Env, to: &Address, function: &Symbol, args: &Vec<Val>) {
+        observers::notify(env, OBSERVE_EXECUTE, (to.clone(), function.clone()));
+        if let Some(transfer) = transfer::detect(env, to, function, args) {
+            observers::notify(
+                env,
+                OBSERVE_TRANSFER,
+                (transfer.token, transfer.to, transfer.amount),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use super::*;
+    use crate::testutils::{register_target, MockTargetClient};
+    use ed25519_dalek::{Signer as _, SigningKey};
+    use soroban_sdk::{
+        testutils::{
+            Address as _, AuthorizedFunction, AuthorizedInvocation, Ledger as _, MockAuth,
+            MockAuthInvoke,
+        },
+        vec,
+        xdr::{
+            AccountId, Hash, HashIdPreimage, HashIdPreimageSorobanAuthorization,
+            InvokeContractArgs, Limits, PublicKey, ScAddress, ScVal, SorobanAddressCredentials,
+            SorobanAuthorizationEntry, SorobanAuthorizedFunction, SorobanAuthorizedInvocation,
+            SorobanCredentials, ToXdr, Uint256, VecM, WriteXdr,
+        },
+        Address, Env, IntoVal, Symbol, TryFromVal, Val,
+    };
+
+    #[test]
+    fn test_initialize() {
+        let env = Env::default();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(&env, &contract_id);
+
+        let owner = Address::generate(&env);
+        client.initialize(&owner);
+
+        assert_eq!(client.get_owner(), owner);
+        assert_eq!(client.get_nonce(&0), 0);
+    }
+
+    #[test]
+    fn test_add_session_key() {
+        let env = Env::default();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(&env, &contract_id);
+
+        let owner = Address::generate(&env);
+        client.initialize(&owner);
+
+        env.mock_all_auths();
+
+        let session_pk = BytesN::from_array(&env, &[1u8; 32]);
+        let expires_at = 1000u64;
+        let permissions = Vec::new(&env);
+
+        client.add_session_key(&session_pk, &expires_at, &permissions);
+
+        let session_key = client.get_session_key(&session_pk);
+        assert!(session_key.is_some());
+    }
+
+    #[test]
+    #[should_panic(expected = "Already initialized")]
+    fn test_double_initialize() {
+        let env = Env::default();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(&env, &contract_id);
+
+        let owner = Address::generate(&env);
+        client.initialize(&owner);
+        client.initialize(&owner); // Should panic
+    }
+
+    fn account_address(env: &Env, signing_key: &SigningKey) -> Address {
+        let key = signing_key.verifying_key().to_bytes();
+        let address = ScAddress::Account(AccountId(PublicKey::PublicKeyTypeEd25519(Uint256(key))));
+        Address::try_from_val(env, &address).unwrap()
+    }
+
+    fn key_signer(env: &Env, signing_key: &SigningKey) -> Signer {
+        Signer {
+            key: BytesN::from_array(env, &signing_key.verifying_key().to_bytes()),
+            weight: 1,
+        }
+    }
+
+    fn sign(env: &Env, signing_key: &SigningKey, message: &BytesN<32>) -> BytesN<64> {
+        BytesN::from_array(env, &signing_key.sign(&message.to_array()).to_bytes())
+    }
+
+    #[test]
+    fn test_verify_message() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(&env, &contract_id);
+
+        let owner_key = SigningKey::from_bytes(&[1u8; 32]);
+        client.initialize(&Address::generate(&env));
+        client.set_multisig(&vec![&env, key_signer(&env, &owner_key)], &1);
+
+        let session_signer = SigningKey::from_bytes(&[2u8; 32]);
+        let session_pk = BytesN::from_array(&env, &session_signer.verifying_key().to_bytes());
+        client.add_session_key(&session_pk, &1000, &Vec::new(&env));
+
+        let message = BytesN::from_array(&env, &[9u8; 32]);
+        let owner_pk = BytesN::from_array(&env, &owner_key.verifying_key().to_bytes());
+
+        assert!(client.verify_message(&message, &sign(&env, &owner_key, &message), &owner_pk));
+        assert!(client.verify_message(
+            &message,
+            &sign(&env, &session_signer, &message),
+            &session_pk
+        ));
+
+        // A valid signature from a key the account doesn't trust.
+        let stranger = SigningKey::from_bytes(&[3u8; 32]);
+        let stranger_pk = BytesN::from_array(&env, &stranger.verifying_key().to_bytes());
+        assert!(!client.verify_message(&message, &sign(&env, &stranger, &message), &stranger_pk));
+
+        // A trusted key with a signature over a different message traps in
+        // `ed25519_verify` rather than returning `false`.
+        let other = BytesN::from_array(&env, &[8u8; 32]);
+        assert!(client
+            .try_verify_message(&message, &sign(&env, &owner_key, &other), &owner_pk)
+            .is_err());
+    }
+
+    #[test]
+    fn test_verify_message_rejects_revoked_and_expired_session_keys() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(&env, &contract_id);
+        client.initialize(&Address::generate(&env));
+
+        let message = BytesN::from_array(&env, &[9u8; 32]);
+        let revoked = SigningKey::from_bytes(&[2u8; 32]);
+        let revoked_pk = BytesN::from_array(&env, &revoked.verifying_key().to_bytes());
+        client.add_session_key(&revoked_pk, &1000, &Vec::new(&env));
+        client.revoke_session_key(&revoked_pk);
+        assert!(!client.verify_message(&message, &sign(&env, &revoked, &message), &revoked_pk));
+
+        let expiring = SigningKey::from_bytes(&[3u8; 32]);
+        let expiring_pk = BytesN::from_array(&env, &expiring.verifying_key().to_bytes());
+        client.add_session_key(&expiring_pk, &1000, &Vec::new(&env));
+        let signature = sign(&env, &expiring, &message);
+        assert!(client.verify_message(&message, &signature, &expiring_pk));
+        env.ledger().with_mut(|li| li.timestamp = 1000);
+        assert!(!client.verify_message(&message, &signature, &expiring_pk));
+    }
+
+    #[test]
+    fn test_check_auth_requires_owner_signature() {
+        let env = Env::default();
+        let owner_key = SigningKey::from_bytes(&[1u8; 32]);
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(&env, &contract_id);
+        client.initialize(&account_address(&env, &owner_key));
+
+        let payload = BytesN::from_array(&env, &[9u8; 32]);
+        let signed_by = |key: &SigningKey| {
+            vec![
+                &env,
+                AccountSignature {
+                    public_key: BytesN::from_array(&env, &key.verifying_key().to_bytes()),
+                    signature: sign(&env, key, &payload),
+                },
+            ]
+        };
+
+        // The key behind a classic owner address is not a signer of its own.
+        assert_eq!(
+            env.try_invoke_contract_check_auth::<ContractError>(
+                &contract_id,
+                &payload,
+                signed_by(&owner_key).into_val(&env),
+                &Vec::new(&env),
+            ),
+            Err(Ok(ContractError::UnknownSigner))
+        );
+
+        env.mock_all_auths();
+        client.set_multisig(&vec![&env, key_signer(&env, &owner_key)], &1);
+        assert_eq!(
+            env.try_invoke_contract_check_auth::<ContractError>(
+                &contract_id,
+                &payload,
+                signed_by(&owner_key).into_val(&env),
+                &Vec::new(&env),
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            env.try_invoke_contract_check_auth::<ContractError>(
+                &contract_id,
+                &payload,
+                signed_by(&SigningKey::from_bytes(&[2u8; 32])).into_val(&env),
+                &Vec::new(&env),
+            ),
+            Err(Ok(ContractError::UnknownSigner))
+        );
+    }
+
+    #[test]
+    fn test_check_auth_enforces_spend_limits() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let owner_key = SigningKey::from_bytes(&[1u8; 32]);
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(&env, &contract_id);
+        client.initialize(&Address::generate(&env));
+        client.set_multisig(&vec![&env, key_signer(&env, &owner_key)], &1);
+
+        let token = Address::generate(&env);
+        client.set_token_limit(&token, &500, &86_400);
+
+        let payload = BytesN::from_array(&env, &[9u8; 32]);
+        let signature = vec![
+            &env,
+            AccountSignature {
+                public_key: BytesN::from_array(&env, &owner_key.verifying_key().to_bytes()),
+                signature: sign(&env, &owner_key, &payload),
+            },
+        ];
+        let transfer = |amount: i128| {
+            vec![
+                &env,
+                Context::Contract(soroban_sdk::auth::ContractContext {
+                    contract: token.clone(),
+                    fn_name: Symbol::new(&env, "transfer"),
+                    args: vec![
+                        &env,
+                        contract_id.into_val(&env),
+                        Address::generate(&env).into_val(&env),
+                        amount.into_val(&env),
+                    ],
+                }),
+            ]
+        };
+
+        assert_eq!(
+            env.try_invoke_contract_check_auth::<ContractError>(
+                &contract_id,
+                &payload,
+                signature.clone().into_val(&env),
+                &transfer(400),
+            ),
+            Ok(())
+        );
+        assert_eq!(client.get_token_limit(&token).unwrap().spent, 400);
+        assert_eq!(
+            env.try_invoke_contract_check_auth::<ContractError>(
+                &contract_id,
+                &payload,
+                signature.into_val(&env),
+                &transfer(101),
+            ),
+            Err(Ok(ContractError::SpendLimitExceeded))
+        );
+    }
+
+    #[test]
+    fn test_readded_session_key_starts_fresh() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(&env, &contract_id);
+        client.initialize(&Address::generate(&env));
+
+        let session_pk = BytesN::from_array(&env, &[1u8; 32]);
+        client.add_session_key(
+            &session_pk,
+            &1000,
+            &Vec::from_array(&env, [Permission::Execute]),
+        );
+        client.revoke_session_key(&session_pk);
+        assert!(client.get_session_key(&session_pk).is_none());
+
+        let permissions = Vec::from_array(&env, [Permission::CallContract(contract_id.clone())]);
+        client.add_session_key(&session_pk, &500, &permissions);
+        let session_key = client.get_session_key(&session_pk).unwrap();
+        assert_eq!(session_key.expires_at, 500);
+        assert_eq!(session_key.permissions, permissions);
+    }
+
+    #[test]
+    fn test_execute_invokes_target() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(&env, &contract_id);
+        client.initialize(&Address::generate(&env));
+
+        let token = register_target(&env);
+        let recipient = Address::generate(&env);
+        let args: Vec<Val> = vec![
+            &env,
+            contract_id.into_val(&env),
+            recipient.into_val(&env),
+            250i128.into_val(&env),
+        ];
+
+        assert!(client.execute(&token, &Symbol::new(&env, "transfer"), &args, &0, &0));
+        assert_eq!(
+            MockTargetClient::new(&env, &token).received(&recipient),
+            250
+        );
+        assert_eq!(client.get_nonce(&0), 1);
+    }
+
+    #[test]
+    fn test_execute_batch_is_atomic() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(&env, &contract_id);
+        client.initialize(&Address::generate(&env));
+
+        let token = MockTargetClient::new(&env, &register_target(&env));
+        let alice = Address::generate(&env);
+        let bob = Address::generate(&env);
+        let transfer = |to: &Address, amount: i128| Call {
+            to: token.address.clone(),
+            function: Symbol::new(&env, "transfer"),
+            args: vec![
+                &env,
+                contract_id.into_val(&env),
+                to.into_val(&env),
+                amount.into_val(&env),
+            ],
+        };
+        let fail = Call {
+            to: token.address.clone(),
+            function: Symbol::new(&env, "fail"),
+            args: Vec::new(&env),
+        };
+
+        assert!(client.execute_batch(
+            &vec![&env, transfer(&alice, 100), transfer(&bob, 50)],
+            &0,
+            &0
+        ));
+        assert_eq!(token.received(&alice), 100);
+        assert_eq!(token.received(&bob), 50);
+        assert_eq!(client.get_nonce(&0), 1);
+        assert_eq!(client.get_metrics().executes, 1);
+
+        // A failing call undoes the calls before it.
+        assert_eq!(
+            client.try_execute_batch(&vec![&env, transfer(&alice, 10), fail], &0, &1),
+            Err(Ok(ContractError::InvocationFailed))
+        );
+        assert_eq!(token.received(&alice), 100);
+        assert_eq!(client.get_nonce(&0), 1);
+
+        assert_eq!(
+            client.try_execute_batch(&Vec::new(&env), &0, &0),
+            Err(Ok(ContractError::InvalidNonce))
+        );
+    }
+
+    #[test]
+    fn test_execute_rejects_unexpected_nonce() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(&env, &contract_id);
+        client.initialize(&Address::generate(&env));
+
+        let target = register_target(&env);
+        let ping = Symbol::new(&env, "ping");
+        let args: Vec<Val> = Vec::new(&env);
+
+        client.execute(&target, &ping, &args, &0, &0);
+        assert_eq!(
+            client.try_execute(&target, &ping, &args, &0, &0),
+            Err(Ok(ContractError::InvalidNonce))
+        );
+        assert_eq!(
+            client.try_execute(&target, &ping, &args, &0, &2),
+            Err(Ok(ContractError::InvalidNonce))
+        );
+        assert_eq!(client.get_nonce(&0), 1);
+
+        client.execute(&target, &ping, &args, &0, &1);
+        assert_eq!(client.get_nonce(&0), 2);
+    }
+
+    #[test]
+    fn test_failed_call_reverts_execute() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(&env, &contract_id);
+        client.initialize(&Address::generate(&env));
+
+        let target = register_target(&env);
+        assert_eq!(
+            client.try_execute(&target, &Symbol::new(&env, "fail"), &Vec::new(&env), &0, &0),
+            Err(Ok(ContractError::InvocationFailed))
+        );
+
+        // Spend metered before the call is rolled back with it.
+        let missing_token = Address::generate(&env);
+        client.set_token_limit(&missing_token, &1_000, &86_400);
+        let args: Vec<Val> = vec![
+            &env,
+            contract_id.into_val(&env),
+            Address::generate(&env).into_val(&env),
+            400i128.into_val(&env),
+        ];
+        assert_eq!(
+            client.try_execute(
+                &missing_token,
+                &Symbol::new(&env, "transfer"),
+                &args,
+                &0,
+                &0
+            ),
+            Err(Ok(ContractError::InvocationFailed))
+        );
+        assert_eq!(client.get_token_limit(&missing_token).unwrap().spent, 0);
+        assert_eq!(client.get_nonce(&0), 0);
+    }
+
+    #[test]
+    fn test_zero_amount_transfer_rejected_when_enabled() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(&env, &contract_id);
+        client.initialize(&Address::generate(&env));
+
+        let token = register_target(&env);
+        let transfer = Symbol::new(&env, "transfer");
+        let zero_transfer: Vec<Val> = vec![
+            &env,
+            contract_id.into_val(&env),
+            Address::generate(&env).into_val(&env),
+            0i128.into_val(&env),
+        ];
+
+        client.set_reject_noop(&true);
+        assert!(client.get_reject_noop());
+        assert_eq!(
+            client.try_execute(&token, &transfer, &zero_transfer, &0, &0),
+            Err(Ok(ContractError::NoOp))
+        );
+        assert_eq!(client.get_nonce(&0), 0);
+    }
+
+    #[test]
+    fn test_safe_mode_only_allows_transfers_to_safe_address() {
         let env = Env::default();
+        env.mock_all_auths();
         let contract_id = env.register_contract(None, AncoreAccount);
         let client = AncoreAccountClient::new(&env, &contract_id);
+        client.initialize(&Address::generate(&env));
 
-        let owner = Address::generate(&env);
-        client.initialize(&owner);
-        client.initialize(&owner); // Should panic
+        let token = register_target(&env);
+        let transfer = Symbol::new(&env, "transfer");
+        let safe_address = Address::generate(&env);
+        let transfer_to = |to: &Address| -> Vec<Val> {
+            vec![
+                &env,
+                contract_id.into_val(&env),
+                to.into_val(&env),
+                100i128.into_val(&env),
+            ]
+        };
+
+        client.enter_safe_mode(&safe_address);
+        assert_eq!(client.get_safe_address(), Some(safe_address.clone()));
+
+        assert!(client.execute(&token, &transfer, &transfer_to(&safe_address), &0, &0));
+        assert_eq!(
+            client.try_execute(
+                &token,
+                &transfer,
+                &transfer_to(&Address::generate(&env)),
+                &0,
+                &1
+            ),
+            Err(Ok(ContractError::Unauthorized))
+        );
+        assert_eq!(
+            client.try_execute(
+                &token,
+                &Symbol::new(&env, "approve"),
+                &Vec::new(&env),
+                &0,
+                &1
+            ),
+            Err(Ok(ContractError::Unauthorized))
+        );
+        assert_eq!(client.get_nonce(&0), 1);
+
+        client.exit_safe_mode();
+        assert_eq!(client.get_safe_address(), None);
+        assert!(client.execute(
+            &token,
+            &transfer,
+            &transfer_to(&Address::generate(&env)),
+            &0,
+            &1
+        ));
+    }
+
+    #[test]
+    fn test_zero_amount_transfer_allowed_by_default() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(&env, &contract_id);
+        client.initialize(&Address::generate(&env));
+
+        let token = register_target(&env);
+        let transfer = Symbol::new(&env, "transfer");
+        let zero_transfer: Vec<Val> = vec![
+            &env,
+            contract_id.into_val(&env),
+            Address::generate(&env).into_val(&env),
+            0i128.into_val(&env),
+        ];
+
+        assert!(!client.get_reject_noop());
+        client.execute(&token, &transfer, &zero_transfer, &0, &0);
+
+        client.set_reject_noop(&true);
+        client.set_reject_noop(&false);
+        client.execute(&token, &transfer, &zero_transfer, &0, &1);
+        assert_eq!(client.get_nonce(&0), 2);
+    }
+
+    #[test]
+    fn test_is_permission_subset() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(&env, &contract_id);
+        client.initialize(&Address::generate(&env));
+
+        let token = Address::generate(&env);
+        let other = Address::generate(&env);
+        let parent_pk = BytesN::from_array(&env, &[1u8; 32]);
+        client.add_session_key(
+            &parent_pk,
+            &1000,
+            &vec![
+                &env,
+                Permission::CallContract(other.clone()),
+                Permission::Transfer(token.clone(), 100),
+            ],
+        );
+
+        let subset = |proposed: Vec<Permission>| client.is_permission_subset(&parent_pk, &proposed);
+        assert!(subset(Vec::new(&env)));
+        assert!(subset(vec![
+            &env,
+            Permission::Transfer(token.clone(), 40),
+            Permission::Transfer(other.clone(), 1_000),
+        ]));
+        assert!(!subset(vec![
+            &env,
+            Permission::Transfer(token.clone(), 101)
+        ]));
+        assert!(!subset(vec![&env, Permission::CallContract(token.clone())]));
+        assert!(!subset(vec![&env, Permission::Execute]));
+
+        let unknown_pk = BytesN::from_array(&env, &[2u8; 32]);
+        assert!(!client.is_permission_subset(&unknown_pk, &Vec::new(&env)));
+
+        env.ledger().with_mut(|li| li.timestamp = 1000);
+        assert!(!subset(Vec::new(&env)));
+    }
+
+    #[test]
+    fn test_strict_mode_rejects_empty_session_permissions() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(&env, &contract_id);
+        client.initialize(&Address::generate(&env));
+
+        let open_pk = BytesN::from_array(&env, &[1u8; 32]);
+        client.add_session_key(&open_pk, &1000, &Vec::new(&env));
+        assert!(client.get_session_key(&open_pk).is_some());
+
+        client.set_strict_session_permissions(&true);
+        let scoped_pk = BytesN::from_array(&env, &[2u8; 32]);
+        client.add_session_key(
+            &scoped_pk,
+            &1000,
+            &Vec::from_array(&env, [Permission::Execute]),
+        );
+        assert!(client.get_session_key(&scoped_pk).is_some());
+
+        let empty_pk = BytesN::from_array(&env, &[3u8; 32]);
+        assert_eq!(
+            client.try_add_session_key(&empty_pk, &1000, &Vec::new(&env)),
+            Err(Ok(ContractError::EmptyPermissions))
+        );
+        assert!(client.get_session_key(&empty_pk).is_none());
+        assert!(client.get_session_key(&open_pk).is_some());
+    }
+
+    #[test]
+    fn test_contract_owner_authorizes_admin_calls() {
+        let env = Env::default();
+        env.mock_all_auths();
+
+        let parent_id = env.register_contract(None, AncoreAccount);
+        let parent = AncoreAccountClient::new(&env, &parent_id);
+        parent.initialize(&Address::generate(&env));
+
+        let child_id = env.register_contract(None, AncoreAccount);
+        let child = AncoreAccountClient::new(&env, &child_id);
+        child.initialize(&parent_id);
+        assert_eq!(child.get_owner(), parent_id);
+
+        let session_pk = BytesN::from_array(&env, &[1u8; 32]);
+        let permissions: Vec<Permission> = Vec::new(&env);
+        child.add_session_key(&session_pk, &1000, &permissions);
+
+        assert_eq!(
+            env.auths(),
+            std::vec![(
+                parent_id.clone(),
+                AuthorizedInvocation {
+                    function: AuthorizedFunction::Contract((
+                        child_id.clone(),
+                        Symbol::new(&env, "add_session_key"),
+                        (session_pk.clone(), 1000u64, permissions).into_val(&env),
+                    )),
+                    sub_invocations: std::vec![],
+                }
+            )]
+        );
+        assert!(child.get_session_key(&session_pk).is_some());
+    }
+
+    #[test]
+    fn test_contract_owner_execute_requires_owner_contract_auth() {
+        let env = Env::default();
+
+        let parent_id = env.register_contract(None, AncoreAccount);
+        let child_id = env.register_contract(None, AncoreAccount);
+        let child = AncoreAccountClient::new(&env, &child_id);
+        child.initialize(&parent_id);
+
+        let target = register_target(&env);
+        let function = Symbol::new(&env, "ping");
+        let args: Vec<Val> = Vec::new(&env);
+
+        // Without the owning account's authorization the call is rejected.
+        assert!(child
+            .try_execute(&target, &function, &args, &0, &0)
+            .is_err());
+        assert_eq!(child.get_nonce(&0), 0);
+
+        // Authorization scoped to the owning contract address is accepted.
+        child
+            .mock_auths(&[MockAuth {
+                address: &parent_id,
+                invoke: &MockAuthInvoke {
+                    contract: &child_id,
+                    fn_name: "execute",
+                    args: (target.clone(), function.clone(), args.clone(), 0u32, 0u64)
+                        .into_val(&env),
+                    sub_invokes: &[],
+                },
+            }])
+            .execute(&target, &function, &args, &0, &0);
+        assert_eq!(child.get_nonce(&0), 1);
+    }
+
+    /// Auth entry for `address` calling `contract.execute(args)`, signed for
+    /// the account's `__check_auth` by `key`.
+    fn signed_execute_auth(
+        env: &Env,
+        address: &Address,
+        contract: &Address,
+        args: Vec<Val>,
+        key: &SigningKey,
+    ) -> SorobanAuthorizationEntry {
+        let mut sc_args = std::vec::Vec::new();
+        for arg in args.iter() {
+            sc_args.push(ScVal::try_from_val(env, &arg).unwrap());
+        }
+        let invocation = SorobanAuthorizedInvocation {
+            function: SorobanAuthorizedFunction::ContractFn(InvokeContractArgs {
+                contract_address: contract.into(),
+                function_name: "execute".try_into().unwrap(),
+                args: sc_args.try_into().unwrap(),
+            }),
+            sub_invocations: VecM::default(),
+        };
+        let nonce = 1;
+        let signature_expiration_ledger = env.ledger().sequence() + 100;
+        let preimage = HashIdPreimage::SorobanAuthorization(HashIdPreimageSorobanAuthorization {
+            network_id: Hash(env.ledger().network_id().to_array()),
+            nonce,
+            signature_expiration_ledger,
+            invocation: invocation.clone(),
+        });
+        let payload: BytesN<32> = env
+            .crypto()
+            .sha256(&Bytes::from_slice(
+                env,
+                &preimage.to_xdr(Limits::none()).unwrap(),
+            ))
+            .into();
+        let signatures = vec![
+            env,
+            AccountSignature {
+                public_key: BytesN::from_array(env, &key.verifying_key().to_bytes()),
+                signature: sign(env, key, &payload),
+            },
+        ];
+
+        SorobanAuthorizationEntry {
+            credentials: SorobanCredentials::Address(SorobanAddressCredentials {
+                address: address.into(),
+                nonce,
+                signature_expiration_ledger,
+                signature: ScVal::try_from_val(env, &signatures.to_val()).unwrap(),
+            }),
+            root_invocation: invocation,
+        }
+    }
+
+    #[test]
+    fn test_account_owned_account_executes_through_chain() {
+        let env = Env::default();
+        let parent_key = SigningKey::from_bytes(&[1u8; 32]);
+        let parent_id = env.register_contract(None, AncoreAccount);
+        let parent = AncoreAccountClient::new(&env, &parent_id);
+        let child_id = env.register_contract(None, AncoreAccount);
+        let child = AncoreAccountClient::new(&env, &child_id);
+        let token = MockTargetClient::new(&env, &register_target(&env));
+        let recipient = Address::generate(&env);
+
+        env.mock_all_auths();
+        parent.initialize(&Address::generate(&env));
+        parent.set_multisig(&vec![&env, key_signer(&env, &parent_key)], &1);
+        child.initialize(&parent_id);
+
+        // The parent's execute calls the child's execute, which moves tokens.
+        let inner_args: Vec<Val> = (child_id.clone(), recipient.clone(), 5i128).into_val(&env);
+        let outer_args: Vec<Val> = (
+            token.address.clone(),
+            Symbol::new(&env, "transfer"),
+            inner_args,
+            0u32,
+            0u64,
+        )
+            .into_val(&env);
+        let call = |key: &SigningKey| {
+            env.set_auths(&[signed_execute_auth(
+                &env,
+                &parent_id,
+                &parent_id,
+                (
+                    child_id.clone(),
+                    Symbol::new(&env, "execute"),
+                    outer_args.clone(),
+                    0u32,
+                    0u64,
+                )
+                    .into_val(&env),
+                key,
+            )]);
+            parent.try_execute(
+                &child_id,
+                &Symbol::new(&env, "execute"),
+                &outer_args,
+                &0,
+                &0,
+            )
+        };
+
+        // A key the parent does not know cannot drive the chain.
+        assert!(call(&SigningKey::from_bytes(&[2u8; 32])).is_err());
+        assert_eq!(child.get_nonce(&0), 0);
+
+        // The parent's signer authorizes the parent, and the parent in turn
+        // authorizes the child it owns as its direct caller.
+        assert_eq!(call(&parent_key), Ok(Ok(true)));
+        assert_eq!(parent.get_nonce(&0), 1);
+        assert_eq!(child.get_nonce(&0), 1);
+        assert_eq!(token.received(&recipient), 5);
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(&env, &contract_id);
+
+        client.initialize(&Address::generate(&env));
+        client.execute(
+            &register_target(&env),
+            &Symbol::new(&env, "ping"),
+            &Vec::new(&env),
+            &0,
+            &0,
+        );
+
+        let signers = vec![&env, key_signer(&env, &SigningKey::from_bytes(&[1u8; 32]))];
+        client.set_multisig(&signers, &1);
+
+        let key_hash = BytesN::from_array(&env, &[6u8; 32]);
+        client.set_backup_key_hash(&key_hash);
+        let guardians = Vec::from_array(&env, [Address::generate(&env), Address::generate(&env)]);
+        client.set_guardians(&guardians, &2);
+        let guardian_delay = client.get_guardian_delay() * 2;
+        client.set_guardian_delay(&guardian_delay);
+        client.set_upgrade_delay(&(UPGRADE_DELAY * 2));
+        client.set_unfreeze_delay(&60);
+        let beneficiary = Address::generate(&env);
+        client.set_inheritance(&beneficiary, &1_000);
+        let max_tx_value = MaxTxValue {
+            reference_token: Address::generate(&env),
+            oracle: Address::generate(&env),
+            amount: 5_000,
+        };
+        client.set_max_tx_value(
+            &max_tx_value.reference_token,
+            &max_tx_value.oracle,
+            &max_tx_value.amount,
+        );
+        client.set_rate_limit(&10, &100);
+        let device = BytesN::from_array(&env, &[7u8; 32]);
+        client.set_two_factor(&device, &500);
+        let policy = EscalationPolicy {
+            two_signatures_from: 100,
+            full_quorum_above: 1_000,
+            timelock_ledgers: 50,
+        };
+        client.set_escalation_policy(&policy);
+        client.set_admin_delay(&10);
+
+        assert_eq!(
+            client.snapshot(),
+            AccountSnapshot {
+                owner: contract_id,
+                nonce: 1,
+                backup_key_hash: Some(key_hash.into()),
+                signers,
+                threshold: 1,
+                guardians,
+                guardian_quorum: 2,
+                guardian_delay,
+                admin_delay: 10,
+                upgrade_delay: UPGRADE_DELAY * 2,
+                unfreeze_delay: 60,
+                inheritance: Vec::from_array(
+                    &env,
+                    [Inheritance {
+                        beneficiary,
+                        inactivity_ledgers: 1_000,
+                    }]
+                ),
+                max_tx_value: Vec::from_array(&env, [max_tx_value]),
+                rate_limit: Vec::from_array(
+                    &env,
+                    [RateLimit {
+                        max_executions: 10,
+                        window_ledgers: 100,
+                    }]
+                ),
+                two_factor: Vec::from_array(
+                    &env,
+                    [TwoFactor {
+                        public_key: device,
+                        threshold: 500,
+                    }]
+                ),
+                escalation_policy: Vec::from_array(&env, [policy]),
+            }
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Not initialized")]
+    fn test_snapshot_uninitialized() {
+        let env = Env::default();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(&env, &contract_id);
+
+        client.snapshot();
+    }
+
+    /// Hex-encoded XDR of a value. Off-chain signers and indexers decode these
+    /// types, so any layout change must show up as a snapshot diff here.
+    fn xdr_hex(env: &Env, value: impl IntoVal<Env, Val>) -> std::string::String {
+        use core::fmt::Write;
+        let mut hex = std::string::String::new();
+        for byte in value.into_val(env).to_xdr(env).iter() {
+            write!(hex, "{:02x}", byte).unwrap();
+        }
+        hex
+    }
+
+    /// Fixed contract address (id bytes `0x00..=0x1f`) for snapshots.
+    fn snapshot_address(env: &Env) -> Address {
+        Address::from_string(&soroban_sdk::String::from_str(
+            env,
+            "CAAACAQDAQCQMBYIBEFAWDANBYHRAEISCMKBKFQXDAMRUGY4DUPB6N4O",
+        ))
+    }
+
+    #[test]
+    fn test_session_key_xdr_snapshot() {
+        let env = Env::default();
+        let session_key = SessionKey {
+            public_key: BytesN::from_array(&env, &[0xab; 32]),
+            valid_after: 1_699_000_000,
+            expires_at: 1_700_000_000,
+            permissions: Vec::from_array(
+                &env,
+                [
+                    Permission::Execute,
+                    Permission::CallContract(snapshot_address(&env)),
+                    Permission::Transfer(snapshot_address(&env), 500),
+                ],
+            ),
+            spend_limits: Vec::from_array(
+                &env,
+                [SessionSpendLimit {
+                    token: snapshot_address(&env),
+                    max_amount: 1_000,
+                    period_ledgers: 17_280,
+                    spent: 250,
+                    window_start: 42,
+                }],
+            ),
+            allowed_contracts: Vec::from_array(&env, [snapshot_address(&env)]),
+            allowed_functions: Vec::from_array(&env, [symbol_short!("transfer")]),
+            max_uses: 5,
+            uses: 2,
+        };
+
+        assert_eq!(
+            xdr_hex(&env, session_key),
+            concat!(
+                "0000001100000001000000090000000f00000011616c6c6f7765645f636f6e74",
+                "7261637473000000000000100000000100000001000000120000000100010203",
+                "0405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f0000000f",
+                "00000011616c6c6f7765645f66756e6374696f6e730000000000001000000001",
+                "000000010000000f000000087472616e736665720000000f0000000a65787069",
+                "7265735f6174000000000005000000006553f1000000000f000000086d61785f",
+                "7573657300000003000000050000000f0000000b7065726d697373696f6e7300",
+                "0000001000000001000000030000001000000001000000010000000f00000007",
+                "45786563757465000000001000000001000000020000000f0000000c43616c6c",
+                "436f6e74726163740000001200000001000102030405060708090a0b0c0d0e0f",
+                "101112131415161718191a1b1c1d1e1f0000001000000001000000030000000f",
+                "000000085472616e736665720000001200000001000102030405060708090a0b",
+                "0c0d0e0f101112131415161718191a1b1c1d1e1f0000000a0000000000000000",
+                "00000000000001f40000000f0000000a7075626c69635f6b657900000000000d",
+                "00000020abababababababababababababababababababababababababababab",
+                "abababab0000000f0000000c7370656e645f6c696d6974730000001000000001",
+                "000000010000001100000001000000050000000f0000000a6d61785f616d6f75",
+                "6e7400000000000a000000000000000000000000000003e80000000f0000000e",
+                "706572696f645f6c656467657273000000000003000043800000000f00000005",
+                "7370656e740000000000000a000000000000000000000000000000fa0000000f",
+                "00000005746f6b656e0000000000001200000001000102030405060708090a0b",
+                "0c0d0e0f101112131415161718191a1b1c1d1e1f0000000f0000000c77696e64",
+                "6f775f7374617274000000030000002a0000000f000000047573657300000003",
+                "000000020000000f0000000b76616c69645f6166746572000000000500000000",
+                "6544aec0",
+            )
+        );
+    }
+
+    #[test]
+    fn test_pending_owner_xdr_snapshot() {
+        let env = Env::default();
+        let proposal = PendingOwner {
+            new_owner: snapshot_address(&env),
+            expires_at: 1_700_604_800,
+        };
+
+        assert_eq!(
+            xdr_hex(&env, proposal),
+            concat!(
+                "0000001100000001000000020000000f0000000a657870697265735f61740000",
+                "0000000500000000655d2b800000000f000000096e65775f6f776e6572000000",
+                "0000001200000001000102030405060708090a0b0c0d0e0f1011121314151617",
+                "18191a1b1c1d1e1f",
+            )
+        );
+    }
+
+    #[test]
+    fn test_call_xdr_snapshot() {
+        let env = Env::default();
+        let call = Call {
+            to: snapshot_address(&env),
+            function: Symbol::new(&env, "transfer"),
+            args: vec![&env, 5i128.into_val(&env)],
+        };
+
+        assert_eq!(
+            xdr_hex(&env, call),
+            concat!(
+                "0000001100000001000000030000000f00000004617267730000001000000001",
+                "000000010000000a000000000000000000000000000000050000000f00000008",
+                "66756e6374696f6e0000000f000000087472616e736665720000000f00000002",
+                "746f00000000001200000001000102030405060708090a0b0c0d0e0f10111213",
+                "1415161718191a1b1c1d1e1f",
+            )
+        );
+    }
+
+    #[test]
+    fn test_tx_proposal_xdr_snapshot() {
+        let env = Env::default();
+        let proposal = TxProposal {
+            call: Call {
+                to: snapshot_address(&env),
+                function: Symbol::new(&env, "ping"),
+                args: Vec::new(&env),
+            },
+            approvals: vec![&env, BytesN::from_array(&env, &[0x01; 32])],
+            rejections: vec![&env, BytesN::from_array(&env, &[0x02; 32])],
+        };
+
+        assert_eq!(
+            xdr_hex(&env, proposal),
+            concat!(
+                "0000001100000001000000030000000f00000009617070726f76616c73000000",
+                "0000001000000001000000010000000d00000020010101010101010101010101",
+                "01010101010101010101010101010101010101010000000f0000000463616c6c",
+                "0000001100000001000000030000000f00000004617267730000001000000001",
+                "000000000000000f0000000866756e6374696f6e0000000f0000000470696e67",
+                "0000000f00000002746f00000000001200000001000102030405060708090a0b",
+                "0c0d0e0f101112131415161718191a1b1c1d1e1f0000000f0000000a72656a65",
+                "6374696f6e7300000000001000000001000000010000000d0000002002020202",
+                "02020202020202020202020202020202020202020202020202020202",
+            )
+        );
+    }
+
+    #[test]
+    fn test_pending_admin_op_xdr_snapshot() {
+        let env = Env::default();
+        let pending = PendingAdminOp {
+            op: AdminOp::RotateSigner(
+                BytesN::from_array(&env, &[0x01; 32]),
+                BytesN::from_array(&env, &[0x02; 32]),
+            ),
+            executable_at: 1_000,
+        };
+
+        assert_eq!(
+            xdr_hex(&env, pending),
+            concat!(
+                "0000001100000001000000020000000f0000000d65786563757461626c655f61",
+                "7400000000000003000003e80000000f000000026f7000000000001000000001",
+                "000000030000000f0000000c526f746174655369676e65720000000d00000020",
+                "0101010101010101010101010101010101010101010101010101010101010101",
+                "0000000d00000020020202020202020202020202020202020202020202020202",
+                "0202020202020202",
+            )
+        );
+    }
+
+    #[test]
+    fn test_account_signature_xdr_snapshot() {
+        let env = Env::default();
+        let signature = AccountSignature {
+            public_key: BytesN::from_array(&env, &[0x01; 32]),
+            signature: BytesN::from_array(&env, &[0x02; 64]),
+        };
+
+        assert_eq!(
+            xdr_hex(&env, signature),
+            concat!(
+                "0000001100000001000000020000000f0000000a7075626c69635f6b65790000",
+                "0000000d00000020010101010101010101010101010101010101010101010101",
+                "01010101010101010000000f000000097369676e61747572650000000000000d",
+                "0000004002020202020202020202020202020202020202020202020202020202",
+                "0202020202020202020202020202020202020202020202020202020202020202",
+                "02020202",
+            )
+        );
+    }
+
+    #[test]
+    fn test_account_snapshot_xdr_snapshot() {
+        let env = Env::default();
+        let snapshot = AccountSnapshot {
+            owner: snapshot_address(&env),
+            nonce: 42,
+            backup_key_hash: Some(Bytes::from_array(&env, &[0x22; 32])),
+            signers: Vec::from_array(
+                &env,
+                [Signer {
+                    key: BytesN::from_array(&env, &[0x33; 32]),
+                    weight: 5,
+                }],
+            ),
+            threshold: 1,
+            guardians: Vec::from_array(&env, [snapshot_address(&env)]),
+            guardian_quorum: 1,
+            guardian_delay: 60,
+            admin_delay: 7,
+            upgrade_delay: 120,
+            unfreeze_delay: 30,
+            inheritance: Vec::from_array(
+                &env,
+                [Inheritance {
+                    beneficiary: snapshot_address(&env),
+                    inactivity_ledgers: 100,
+                }],
+            ),
+            max_tx_value: Vec::from_array(
+                &env,
+                [MaxTxValue {
+                    reference_token: snapshot_address(&env),
+                    oracle: snapshot_address(&env),
+                    amount: 1_000,
+                }],
+            ),
+            rate_limit: Vec::from_array(
+                &env,
+                [RateLimit {
+                    max_executions: 10,
+                    window_ledgers: 20,
+                }],
+            ),
+            two_factor: Vec::from_array(
+                &env,
+                [TwoFactor {
+                    public_key: BytesN::from_array(&env, &[0x44; 32]),
+                    threshold: 500,
+                }],
+            ),
+            escalation_policy: Vec::from_array(
+                &env,
+                [EscalationPolicy {
+                    two_signatures_from: 100,
+                    full_quorum_above: 1_000,
+                    timelock_ledgers: 50,
+                }],
+            ),
+        };
+
+        assert_eq!(
+            xdr_hex(&env, snapshot),
+            concat!(
+                "0000001100000001000000100000000f0000000b61646d696e5f64656c617900",
+                "00000003000000070000000f0000000f6261636b75705f6b65795f6861736800",
+                "0000000d00000020222222222222222222222222222222222222222222222222",
+                "22222222222222220000000f00000011657363616c6174696f6e5f706f6c6963",
+                "790000000000001000000001000000010000001100000001000000030000000f",
+                "0000001166756c6c5f71756f72756d5f61626f76650000000000000a00000000",
+                "0000000000000000000003e80000000f0000001074696d656c6f636b5f6c6564",
+                "6765727300000003000000320000000f0000001374776f5f7369676e61747572",
+                "65735f66726f6d000000000a000000000000000000000000000000640000000f",
+                "0000000e677561726469616e5f64656c6179000000000005000000000000003c",
+                "0000000f0000000f677561726469616e5f71756f72756d000000000300000001",
+                "0000000f00000009677561726469616e73000000000000100000000100000001",
+                "0000001200000001000102030405060708090a0b0c0d0e0f1011121314151617",
+                "18191a1b1c1d1e1f0000000f0000000b696e6865726974616e63650000000010",
+                "00000001000000010000001100000001000000020000000f0000000b62656e65",
+                "66696369617279000000001200000001000102030405060708090a0b0c0d0e0f",
+                "101112131415161718191a1b1c1d1e1f0000000f00000012696e616374697669",
+                "74795f6c656467657273000000000003000000640000000f0000000c6d61785f",
+                "74785f76616c7565000000100000000100000001000000110000000100000003",
+                "0000000f00000006616d6f756e7400000000000a000000000000000000000000",
+                "000003e80000000f000000066f7261636c650000000000120000000100010203",
+                "0405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f0000000f",
+                "0000000f7265666572656e63655f746f6b656e00000000120000000100010203",
+                "0405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f0000000f",
+                "000000056e6f6e636500000000000005000000000000002a0000000f00000005",
+                "6f776e65720000000000001200000001000102030405060708090a0b0c0d0e0f",
+                "101112131415161718191a1b1c1d1e1f0000000f0000000a726174655f6c696d",
+                "697400000000001000000001000000010000001100000001000000020000000f",
+                "0000000e6d61785f657865637574696f6e730000000000030000000a0000000f",
+                "0000000e77696e646f775f6c656467657273000000000003000000140000000f",
+                "000000077369676e657273000000001000000001000000010000001100000001",
+                "000000020000000f000000036b6579000000000d000000203333333333333333",
+                "3333333333333333333333333333333333333333333333330000000f00000006",
+                "776569676874000000000003000000050000000f000000097468726573686f6c",
+                "6400000000000003000000010000000f0000000a74776f5f666163746f720000",
+                "0000001000000001000000010000001100000001000000020000000f0000000a",
+                "7075626c69635f6b657900000000000d00000020444444444444444444444444",
+                "44444444444444444444444444444444444444440000000f0000000974687265",
+                "73686f6c640000000000000a000000000000000000000000000001f40000000f",
+                "0000000e756e667265657a655f64656c6179000000000005000000000000001e",
+                "0000000f0000000d757067726164655f64656c61790000000000000500000000",
+                "00000078",
+            )
+        );
+    }
+
+    #[test]
+    fn test_token_limit_xdr_snapshot() {
+        let env = Env::default();
+        let limit = TokenLimit {
+            amount_per_period: 1_000_000,
+            period: 86_400,
+            spent: -5,
+            window_start: 1_700_000_000,
+        };
+        let pending = PendingTokenLimit {
+            amount_per_period: i128::MAX,
+            period: 3_600,
+            effective_at: 1_700_086_400,
+        };
+
+        assert_eq!(
+            xdr_hex(&env, limit),
+            concat!(
+                "0000001100000001000000040000000f00000011616d6f756e745f7065725f70",
+                "6572696f640000000000000a000000000000000000000000000f42400000000f",
+                "00000006706572696f6400000000000500000000000151800000000f00000005",
+                "7370656e740000000000000afffffffffffffffffffffffffffffffb0000000f",
+                "0000000c77696e646f775f737461727400000005000000006553f100",
+            )
+        );
+        assert_eq!(
+            xdr_hex(&env, pending),
+            concat!(
+                "0000001100000001000000030000000f00000011616d6f756e745f7065725f70",
+                "6572696f640000000000000a7fffffffffffffffffffffffffffffff0000000f",
+                "0000000c6566666563746976655f61740000000500000000655542800000000f",
+                "00000006706572696f640000000000050000000000000e10",
+            )
+        );
+    }
+
+    #[test]
+    fn test_max_tx_value_xdr_snapshot() {
+        let env = Env::default();
+        let config = MaxTxValue {
+            reference_token: snapshot_address(&env),
+            oracle: snapshot_address(&env),
+            amount: 5_000_000,
+        };
+
+        assert_eq!(
+            xdr_hex(&env, config),
+            concat!(
+                "0000001100000001000000030000000f00000006616d6f756e7400000000000a",
+                "000000000000000000000000004c4b400000000f000000066f7261636c650000",
+                "0000001200000001000102030405060708090a0b0c0d0e0f1011121314151617",
+                "18191a1b1c1d1e1f0000000f0000000f7265666572656e63655f746f6b656e00",
+                "0000001200000001000102030405060708090a0b0c0d0e0f1011121314151617",
+                "18191a1b1c1d1e1f",
+            )
+        );
+    }
+
+    #[test]
+    fn test_backup_recovery_xdr_snapshot() {
+        let env = Env::default();
+        let recovery = BackupRecovery {
+            new_owner: snapshot_address(&env),
+            executable_at: 604_800,
+        };
+
+        assert_eq!(
+            xdr_hex(&env, recovery),
+            concat!(
+                "0000001100000001000000020000000f0000000d65786563757461626c655f61",
+                "74000000000000050000000000093a800000000f000000096e65775f6f776e65",
+                "720000000000001200000001000102030405060708090a0b0c0d0e0f10111213",
+                "1415161718191a1b1c1d1e1f",
+            )
+        );
+    }
+
+    #[test]
+    fn test_guardian_recovery_xdr_snapshot() {
+        let env = Env::default();
+        let recovery = GuardianRecovery {
+            new_owner: snapshot_address(&env),
+            approvals: vec![&env, snapshot_address(&env)],
+            executable_at: 1_700_259_200,
+        };
+
+        assert_eq!(
+            xdr_hex(&env, recovery),
+            concat!(
+                "0000001100000001000000030000000f00000009617070726f76616c73000000",
+                "0000001000000001000000010000001200000001000102030405060708090a0b",
+                "0c0d0e0f101112131415161718191a1b1c1d1e1f0000000f0000000d65786563",
+                "757461626c655f617400000000000005000000006557e5800000000f00000009",
+                "6e65775f6f776e65720000000000001200000001000102030405060708090a0b",
+                "0c0d0e0f101112131415161718191a1b1c1d1e1f",
+            )
+        );
+    }
+
+    #[test]
+    fn test_pending_guardian_change_xdr_snapshot() {
+        let env = Env::default();
+        let change = PendingGuardianChange {
+            guardians: vec![&env, snapshot_address(&env)],
+            quorum: 1,
+            delay: 604_800,
+            executable_at: 1_700_604_800,
+        };
+
+        assert_eq!(
+            xdr_hex(&env, change),
+            concat!(
+                "0000001100000001000000040000000f0000000564656c617900000000000005",
+                "0000000000093a800000000f0000000d65786563757461626c655f6174000000",
+                "0000000500000000655d2b800000000f00000009677561726469616e73000000",
+                "0000001000000001000000010000001200000001000102030405060708090a0b",
+                "0c0d0e0f101112131415161718191a1b1c1d1e1f0000000f0000000671756f72",
+                "756d00000000000300000001",
+            )
+        );
+    }
+
+    #[test]
+    fn test_inheritance_xdr_snapshot() {
+        let env = Env::default();
+        let inheritance = Inheritance {
+            beneficiary: snapshot_address(&env),
+            inactivity_ledgers: 6_307_200,
+        };
+
+        assert_eq!(
+            xdr_hex(&env, inheritance),
+            concat!(
+                "0000001100000001000000020000000f0000000b62656e656669636961727900",
+                "0000001200000001000102030405060708090a0b0c0d0e0f1011121314151617",
+                "18191a1b1c1d1e1f0000000f00000012696e61637469766974795f6c65646765",
+                "727300000000000300603d80",
+            )
+        );
+    }
+
+    #[test]
+    fn test_signed_call_xdr_snapshot() {
+        let env = Env::default();
+        let payload = SignedCall {
+            call: Call {
+                to: snapshot_address(&env),
+                function: Symbol::new(&env, "ping"),
+                args: Vec::new(&env),
+            },
+            network_id: BytesN::from_array(&env, &[7u8; 32]),
+            channel: 2,
+            nonce: 3,
+            expires_at: 1_700_000_000,
+        };
+
+        assert_eq!(
+            xdr_hex(&env, payload),
+            concat!(
+                "0000001100000001000000050000000f0000000463616c6c0000001100000001",
+                "000000030000000f00000004617267730000001000000001000000000000000f",
+                "0000000866756e6374696f6e0000000f0000000470696e670000000f00000002",
+                "746f00000000001200000001000102030405060708090a0b0c0d0e0f10111213",
+                "1415161718191a1b1c1d1e1f0000000f000000076368616e6e656c0000000003",
+                "000000020000000f0000000a657870697265735f617400000000000500000000",
+                "6553f1000000000f0000000a6e6574776f726b5f696400000000000d00000020",
+                "0707070707070707070707070707070707070707070707070707070707070707",
+                "0000000f000000056e6f6e6365000000000000050000000000000003",
+            )
+        );
+    }
+
+    #[test]
+    fn test_audit_entry_xdr_snapshot() {
+        let env = Env::default();
+        let entry = AuditEntry {
+            caller: AuditCaller::SessionKey(BytesN::from_array(&env, &[0x01; 32])),
+            to: snapshot_address(&env),
+            function: Symbol::new(&env, "ping"),
+            ledger: 42,
+            result_hash: BytesN::from_array(&env, &[0x02; 32]),
+        };
+
+        assert_eq!(
+            xdr_hex(&env, entry),
+            concat!(
+                "0000001100000001000000050000000f0000000663616c6c6572000000000010",
+                "00000001000000020000000f0000000a53657373696f6e4b657900000000000d",
+                "0000002001010101010101010101010101010101010101010101010101010101",
+                "010101010000000f0000000866756e6374696f6e0000000f0000000470696e67",
+                "0000000f000000066c65646765720000000000030000002a0000000f0000000b",
+                "726573756c745f68617368000000000d00000020020202020202020202020202",
+                "02020202020202020202020202020202020202020000000f00000002746f0000",
+                "0000001200000001000102030405060708090a0b0c0d0e0f1011121314151617",
+                "18191a1b1c1d1e1f",
+            )
+        );
+    }
+
+    #[test]
+    fn test_rate_limit_xdr_snapshot() {
+        let env = Env::default();
+        let limit = RateLimit {
+            max_executions: 20,
+            window_ledgers: 720,
+        };
+
+        assert_eq!(
+            xdr_hex(&env, limit),
+            concat!(
+                "0000001100000001000000020000000f0000000e6d61785f657865637574696f",
+                "6e73000000000003000000140000000f0000000e77696e646f775f6c65646765",
+                "7273000000000003000002d0",
+            )
+        );
+    }
+
+    #[test]
+    fn test_two_factor_xdr_snapshot() {
+        let env = Env::default();
+        let two_factor = TwoFactor {
+            public_key: BytesN::from_array(&env, &[0x06; 32]),
+            threshold: 10_000_000_000,
+        };
+
+        assert_eq!(
+            xdr_hex(&env, two_factor),
+            concat!(
+                "0000001100000001000000020000000f0000000a7075626c69635f6b65790000",
+                "0000000d00000020060606060606060606060606060606060606060606060606",
+                "06060606060606060000000f000000097468726573686f6c640000000000000a",
+                "000000000000000000000002540be400",
+            )
+        );
+    }
+
+    #[test]
+    fn test_escalation_policy_xdr_snapshot() {
+        let env = Env::default();
+        let policy = EscalationPolicy {
+            two_signatures_from: 1_000,
+            full_quorum_above: 100_000,
+            timelock_ledgers: 17_280,
+        };
+
+        assert_eq!(
+            xdr_hex(&env, policy),
+            concat!(
+                "0000001100000001000000030000000f0000001166756c6c5f71756f72756d5f",
+                "61626f76650000000000000a000000000000000000000000000186a00000000f",
+                "0000001074696d656c6f636b5f6c65646765727300000003000043800000000f",
+                "0000001374776f5f7369676e6174757265735f66726f6d000000000a00000000",
+                "0000000000000000000003e8",
+            )
+        );
+    }
+
+    #[test]
+    fn test_call_context_xdr_snapshot() {
+        let env = Env::default();
+        let ctx = CallContext {
+            account: snapshot_address(&env),
+            to: snapshot_address(&env),
+            function: Symbol::new(&env, "ping"),
+            args: vec![&env, 5u32.into_val(&env)],
+        };
+
+        assert_eq!(
+            xdr_hex(&env, ctx),
+            concat!(
+                "0000001100000001000000040000000f000000076163636f756e740000000012",
+                "00000001000102030405060708090a0b0c0d0e0f101112131415161718191a1b",
+                "1c1d1e1f0000000f000000046172677300000010000000010000000100000003",
+                "000000050000000f0000000866756e6374696f6e0000000f0000000470696e67",
+                "0000000f00000002746f00000000001200000001000102030405060708090a0b",
+                "0c0d0e0f101112131415161718191a1b1c1d1e1f",
+            )
+        );
+    }
+
+    #[test]
+    fn test_single_signer_spend_xdr_snapshot() {
+        let env = Env::default();
+        let spend = SingleSignerSpend {
+            spent: 250,
+            window_start: 40,
+        };
+
+        assert_eq!(
+            xdr_hex(&env, spend),
+            concat!(
+                "0000001100000001000000020000000f000000057370656e740000000000000a",
+                "000000000000000000000000000000fa0000000f0000000c77696e646f775f73",
+                "746172740000000300000028",
+            )
+        );
+    }
+
+    #[test]
+    fn test_pending_upgrade_xdr_snapshot() {
+        let env = Env::default();
+        let upgrade = PendingUpgrade {
+            wasm_hash: BytesN::from_array(&env, &[0x07; 32]),
+            executable_at: 1_700_172_800,
+        };
+
+        assert_eq!(
+            xdr_hex(&env, upgrade),
+            concat!(
+                "0000001100000001000000020000000f0000000d65786563757461626c655f61",
+                "740000000000000500000000655694000000000f000000097761736d5f686173",
+                "680000000000000d000000200707070707070707070707070707070707070707",
+                "070707070707070707070707",
+            )
+        );
+    }
+
+    #[test]
+    fn test_metrics_xdr_snapshot() {
+        let env = Env::default();
+        let metrics = Metrics {
+            executes: 12,
+            session_keys_created: 3,
+            recoveries: 1,
+        };
+
+        assert_eq!(
+            xdr_hex(&env, metrics),
+            concat!(
+                "0000001100000001000000030000000f00000008657865637574657300000005",
+                "000000000000000c0000000f0000000a7265636f766572696573000000000005",
+                "00000000000000010000000f0000001473657373696f6e5f6b6579735f637265",
+                "61746564000000050000000000000003",
+            )
+        );
+    }
+
+    #[test]
+    fn test_data_key_xdr_snapshot() {
+        let env = Env::default();
+
+        assert_eq!(
+            xdr_hex(&env, DataKey::Owner),
+            "0000001000000001000000010000000f000000054f776e6572000000"
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::Nonce),
+            "0000001000000001000000010000000f000000054e6f6e6365000000"
+        );
+        assert_eq!(
+            xdr_hex(
+                &env,
+                DataKey::SessionKey(BytesN::from_array(&env, &[0x01; 32]))
+            ),
+            concat!(
+                "0000001000000001000000020000000f0000000a53657373696f6e4b657900",
+                "000000000d000000200101010101010101010101010101010101010101010101",
+                "010101010101010101",
+            )
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::TokenLimit(snapshot_address(&env))),
+            concat!(
+                "0000001000000001000000020000000f0000000a546f6b656e4c696d69740000",
+                "0000001200000001000102030405060708090a0b0c0d0e0f1011121314151617",
+                "18191a1b1c1d1e1f",
+            )
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::PendingTokenLimit(snapshot_address(&env))),
+            concat!(
+                "0000001000000001000000020000000f0000001150656e64696e67546f6b656e",
+                "4c696d69740000000000001200000001000102030405060708090a0b0c0d0e0f",
+                "101112131415161718191a1b1c1d1e1f",
+            )
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::MaxTxValue),
+            "0000001000000001000000010000000f0000000a4d6178547856616c75650000"
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::RejectNoOp),
+            "0000001000000001000000010000000f0000000a52656a6563744e6f4f700000"
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::BackupKeyHash),
+            concat!(
+                "0000001000000001000000010000000f0000000d4261636b75704b6579486173",
+                "68000000",
+            )
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::BackupChallengeCounter),
+            concat!(
+                "0000001000000001000000010000000f000000164261636b75704368616c6c65",
+                "6e6765436f756e7465720000",
+            )
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::BackupRecovery),
+            concat!(
+                "0000001000000001000000010000000f0000000e4261636b75705265636f7665",
+                "72790000",
+            )
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::Metrics),
+            "0000001000000001000000010000000f000000074d65747269637300"
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::Observers),
+            "0000001000000001000000010000000f000000094f6273657276657273000000"
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::StrictSessionPermissions),
+            concat!(
+                "0000001000000001000000010000000f0000001853747269637453657373696f",
+                "6e5065726d697373696f6e73",
+            )
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::SafeAddress),
+            "0000001000000001000000010000000f0000000b536166654164647265737300"
+        );
+        assert_eq!(
+            xdr_hex(
+                &env,
+                DataKey::SessionNonce(BytesN::from_array(&env, &[0x01; 32]))
+            ),
+            concat!(
+                "0000001000000001000000020000000f0000000c53657373696f6e4e6f6e6365",
+                "0000000d00000020010101010101010101010101010101010101010101010101",
+                "0101010101010101",
+            )
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::SessionKeyIndex),
+            concat!(
+                "0000001000000001000000010000000f0000000f53657373696f6e4b6579496e",
+                "64657800",
+            )
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::Signers),
+            "0000001000000001000000010000000f000000075369676e65727300"
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::Threshold),
+            "0000001000000001000000010000000f000000095468726573686f6c64000000"
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::PendingOwner),
+            "0000001000000001000000010000000f0000000c50656e64696e674f776e6572"
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::Proposal(7)),
+            concat!(
+                "0000001000000001000000020000000f0000000850726f706f73616c00000005",
+                "0000000000000007",
+            )
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::NextProposalId),
+            concat!(
+                "0000001000000001000000010000000f0000000e4e65787450726f706f73616c",
+                "49640000",
+            )
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::AdminDelay),
+            "0000001000000001000000010000000f0000000a41646d696e44656c61790000"
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::PendingAdminOp),
+            concat!(
+                "0000001000000001000000010000000f0000000e50656e64696e6741646d696e",
+                "4f700000",
+            )
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::Guardians),
+            "0000001000000001000000010000000f00000009477561726469616e73000000"
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::GuardianQuorum),
+            concat!(
+                "0000001000000001000000010000000f0000000e477561726469616e51756f72",
+                "756d0000",
+            )
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::GuardianRecovery),
+            concat!(
+                "0000001000000001000000010000000f00000010477561726469616e5265636f",
+                "76657279",
+            )
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::GuardianDelay),
+            concat!(
+                "0000001000000001000000010000000f0000000d477561726469616e44656c61",
+                "79000000",
+            )
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::PendingGuardianChange),
+            concat!(
+                "0000001000000001000000010000000f0000001550656e64696e674775617264",
+                "69616e4368616e6765000000",
+            )
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::Inheritance),
+            "0000001000000001000000010000000f0000000b496e6865726974616e636500"
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::LastActivity),
+            "0000001000000001000000010000000f0000000c4c6173744163746976697479"
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::Frozen),
+            "0000001000000001000000010000000f0000000646726f7a656e0000"
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::UnfreezeAt),
+            "0000001000000001000000010000000f0000000a556e667265657a6541740000"
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::UnfreezeDelay),
+            concat!(
+                "0000001000000001000000010000000f0000000d556e667265657a6544656c61",
+                "79000000",
+            )
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::PendingUpgrade),
+            concat!(
+                "0000001000000001000000010000000f0000000e50656e64696e675570677261",
+                "64650000",
+            )
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::UpgradeDelay),
+            "0000001000000001000000010000000f0000000c5570677261646544656c6179"
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::StorageVersion),
+            concat!(
+                "0000001000000001000000010000000f0000000e53746f726167655665727369",
+                "6f6e0000",
+            )
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::Registry),
+            "0000001000000001000000010000000f000000085265676973747279"
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::NonceChannel(2)),
+            concat!(
+                "0000001000000001000000020000000f0000000c4e6f6e63654368616e6e656c",
+                "0000000300000002",
+            )
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::AuditLog),
+            "0000001000000001000000010000000f0000000841756469744c6f67"
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::AuditEntry(3)),
+            concat!(
+                "0000001000000001000000020000000f0000000a4175646974456e7472790000",
+                "0000000300000003",
+            )
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::DestinationAllowlist),
+            concat!(
+                "0000001000000001000000010000000f0000001444657374696e6174696f6e41",
+                "6c6c6f776c697374",
+            )
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::DeniedDestination(snapshot_address(&env))),
+            concat!(
+                "0000001000000001000000020000000f0000001144656e69656444657374696e",
+                "6174696f6e0000000000001200000001000102030405060708090a0b0c0d0e0f",
+                "101112131415161718191a1b1c1d1e1f",
+            )
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::RateLimit),
+            "0000001000000001000000010000000f00000009526174654c696d6974000000"
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::RecentExecutions),
+            concat!(
+                "0000001000000001000000010000000f00000010526563656e74457865637574",
+                "696f6e73",
+            )
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::TwoFactor),
+            "0000001000000001000000010000000f0000000954776f466163746f72000000"
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::EscalationPolicy),
+            concat!(
+                "0000001000000001000000010000000f00000010457363616c6174696f6e506f",
+                "6c696379",
+            )
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::ProposalLedger(3)),
+            concat!(
+                "0000001000000001000000020000000f0000000e50726f706f73616c4c656467",
+                "65720000000000050000000000000003",
+            )
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::PolicyModules),
+            concat!(
+                "0000001000000001000000010000000f0000000d506f6c6963794d6f64756c65",
+                "73000000",
+            )
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::SingleSignerSpend(snapshot_address(&env))),
+            concat!(
+                "0000001000000001000000020000000f0000001153696e676c655369676e6572",
+                "5370656e640000000000001200000001000102030405060708090a0b0c0d0e0f",
+                "101112131415161718191a1b1c1d1e1f",
+            )
+        );
+        assert_eq!(
+            xdr_hex(&env, events::EventKey::Commitment),
+            "0000001000000001000000010000000f0000000a436f6d6d69746d656e740000"
+        );
+        assert_eq!(
+            xdr_hex(&env, DataKey::EventTree(7)),
+            "0000001000000001000000020000000f000000094576656e74547265650000000000000300000007"
+        );
+    }
+
+    #[test]
+    fn test_data_keys_do_not_collide() {
+        let env = Env::default();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let address = snapshot_address(&env);
+        let other_address = Address::generate(&env);
+        let keys = [
+            DataKey::Owner,
+            DataKey::Nonce,
+            DataKey::SessionKey(BytesN::from_array(&env, &[0x00; 32])),
+            DataKey::SessionKey(BytesN::from_array(&env, &[0x01; 32])),
+            DataKey::SessionKey(BytesN::from_array(&env, &[0xff; 32])),
+            DataKey::EventTree(0),
+            DataKey::EventTree(1),
+            DataKey::EventTree(u32::MAX),
+            DataKey::TokenLimit(address.clone()),
+            DataKey::TokenLimit(other_address.clone()),
+            DataKey::TokenLimit(contract_id.clone()),
+            DataKey::PendingTokenLimit(address.clone()),
+            DataKey::PendingTokenLimit(other_address),
+            DataKey::PendingTokenLimit(contract_id.clone()),
+            DataKey::MaxTxValue,
+            DataKey::RejectNoOp,
+            DataKey::BackupKeyHash,
+            DataKey::BackupChallengeCounter,
+            DataKey::BackupRecovery,
+            DataKey::Metrics,
+            DataKey::Observers,
+            DataKey::StrictSessionPermissions,
+            DataKey::SafeAddress,
+            DataKey::SessionNonce(BytesN::from_array(&env, &[0x00; 32])),
+            DataKey::SessionNonce(BytesN::from_array(&env, &[0xff; 32])),
+            DataKey::SessionKeyIndex,
+            DataKey::Signers,
+            DataKey::Threshold,
+            DataKey::PendingOwner,
+            DataKey::Proposal(0),
+            DataKey::Proposal(u64::MAX),
+            DataKey::NextProposalId,
+            DataKey::AdminDelay,
+            DataKey::PendingAdminOp,
+            DataKey::Guardians,
+            DataKey::GuardianQuorum,
+            DataKey::GuardianRecovery,
+            DataKey::GuardianDelay,
+            DataKey::PendingGuardianChange,
+            DataKey::Inheritance,
+            DataKey::LastActivity,
+            DataKey::Frozen,
+            DataKey::UnfreezeAt,
+            DataKey::UnfreezeDelay,
+            DataKey::PendingUpgrade,
+            DataKey::UpgradeDelay,
+            DataKey::StorageVersion,
+            DataKey::Registry,
+            DataKey::NonceChannel(0),
+            DataKey::NonceChannel(u32::MAX),
+            DataKey::AuditLog,
+            DataKey::AuditEntry(0),
+            DataKey::AuditEntry(u32::MAX),
+            DataKey::DestinationAllowlist,
+            DataKey::DeniedDestination(address),
+            DataKey::DeniedDestination(contract_id.clone()),
+            DataKey::RateLimit,
+            DataKey::RecentExecutions,
+            DataKey::TwoFactor,
+            DataKey::EscalationPolicy,
+            DataKey::ProposalLedger(0),
+            DataKey::ProposalLedger(u64::MAX),
+            DataKey::PolicyModules,
+            DataKey::SingleSignerSpend(contract_id.clone()),
+        ];
+
+        env.as_contract(&contract_id, || {
+            let storage = env.storage().persistent();
+            for (i, key) in keys.iter().enumerate() {
+                storage.set(key, &(i as u32));
+            }
+            for (i, key) in keys.iter().enumerate() {
+                assert_eq!(storage.get::<_, u32>(key), Some(i as u32));
+            }
+
+            // Keys owned by modules live in their own enums.
+            storage.set(&events::EventKey::Commitment, &u32::MAX);
+            for (i, key) in keys.iter().enumerate() {
+                assert_eq!(storage.get::<_, u32>(key), Some(i as u32));
+            }
+        });
+    }
+
+    #[test]
+    fn test_event_tree_xdr_snapshot() {
+        let env = Env::default();
+        let tree = events::EventTree {
+            count: 2,
+            branch: Vec::from_array(&env, [BytesN::from_array(&env, &[0x11; 32])]),
+        };
+
+        assert_eq!(
+            xdr_hex(&env, tree),
+            concat!(
+                "0000001100000001000000020000000f000000066272616e63680000000000",
+                "1000000001000000010000000d00000020111111111111111111111111111111",
+                "11111111111111111111111111111111110000000f00000005636f756e74000000",
+                "0000000300000002",
+            )
+        );
     }
 }
@@ -0,0 +1,683 @@
+//! Account-level per-token spend limits.
+//!
+//! A limit caps how much of a token can leave the account per period,
+//! whoever authorized the call. Periods follow a fixed ledger-timestamp
+//! schedule: windows start at multiples of the period since the Unix epoch,
+//! so a [`DAILY_LIMIT_PERIOD`] limit resets at 00:00 UTC however the account was used.
+//! Changing a limit's period moves its current window onto the new schedule.
+//! This caps the owner key itself, not just session keys. Tightening a limit takes effect immediately;
+//! loosening it (a larger amount or a shorter period) is queued and only
+//! applies after [`LIMIT_INCREASE_DELAY`], so a stolen owner key cannot
+//! lift the cap and drain the account in one go.
+//!
+//! Each token has a single pending slot: queuing another increase while one
+//! is waiting fails with `TimelockConflict` rather than silently restarting
+//! the delay. Setting any non-increasing limit applies at once and clears
+//! the slot. No increase can be queued while a backup or guardian recovery
+//! is pending.
+
+use soroban_sdk::{contracttype, symbol_short, Address, Env};
+
+use crate::{backup, events, guardians, transfer::Transfer, ContractError, DataKey};
+
+/// Seconds a limit increase waits before it applies.
+pub const LIMIT_INCREASE_DELAY: u64 = 86_400;
+
+/// Period of a daily limit, in seconds.
+pub const DAILY_LIMIT_PERIOD: u64 = 86_400;
+
+/// A token's spend limit and the usage in its current window.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TokenLimit {
+    pub amount_per_period: i128,
+    pub period: u64,
+    pub spent: i128,
+    pub window_start: u64,
+}
+
+/// A limit increase waiting for its delay to pass.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PendingTokenLimit {
+    pub amount_per_period: i128,
+    pub period: u64,
+    pub effective_at: u64,
+}
+
+/// Set or queue a new limit for `token`.
+pub fn set(
+    env: &Env,
+    token: &Address,
+    amount_per_period: i128,
+    period: u64,
+) -> Result<(), ContractError> {
+    if amount_per_period <= 0 || period == 0 {
+        return Err(ContractError::InvalidLimit);
+    }
+
+    let now = env.ledger().timestamp();
+    let current = settle(env, token);
+    let is_increase = match &current {
+        Some(limit) => amount_per_period > limit.amount_per_period || period < limit.period,
+        None => false,
+    };
+
+    if is_increase {
+        if pending(env, token).is_some()
+            || backup::pending(env).is_some()
+            || guardians::pending(env).is_some()
+        {
+            return Err(ContractError::TimelockConflict);
+        }
+
+        let pending = PendingTokenLimit {
+            amount_per_period,
+            period,
+            effective_at: now + LIMIT_INCREASE_DELAY,
+        };
+        env.storage()
+            .persistent()
+            .set(&DataKey::PendingTokenLimit(token.clone()), &pending);
+        events::publish(
+            env,
+            (
+                symbol_short!("ancore"),
+                symbol_short!("lim_queue"),
+                token.clone(),
+            ),
+            (amount_per_period, period, pending.effective_at),
+        );
+        return Ok(());
+    }
+
+    let limit = match current {
+        Some(limit) => with_terms(limit, amount_per_period, period, now),
+        None => TokenLimit {
+            amount_per_period,
+            period,
+            spent: 0,
+            window_start: window_start(now, period),
+        },
+    };
+    save(env, token, &limit);
+    // A tightened limit supersedes any increase still waiting.
+    env.storage()
+        .persistent()
+        .remove(&DataKey::PendingTokenLimit(token.clone()));
+    events::publish(
+        env,
+        (
+            symbol_short!("ancore"),
+            symbol_short!("lim_set"),
+            token.clone(),
+        ),
+        (amount_per_period, period),
+    );
+    Ok(())
+}
+
+/// The limit currently in force for `token`, with any due increase applied.
+pub fn get(env: &Env, token: &Address) -> Option<TokenLimit> {
+    let limit = load(env, token)?;
+    let now = env.ledger().timestamp();
+    match pending(env, token) {
+        Some(pending) if now >= pending.effective_at => Some(with_terms(
+            limit,
+            pending.amount_per_period,
+            pending.period,
+            now,
+        )),
+        _ => Some(limit),
+    }
+}
+
+/// The queued limit increase for `token`, if any.
+pub fn pending(env: &Env, token: &Address) -> Option<PendingTokenLimit> {
+    env.storage()
+        .persistent()
+        .get(&DataKey::PendingTokenLimit(token.clone()))
+}
+
+/// Meter an outgoing transfer, approval or burn against its token's limit.
+pub fn record_spend(env: &Env, transfer: &Transfer) -> Result<(), ContractError> {
+    let Some(mut limit) = settle(env, &transfer.token) else {
+        return Ok(());
+    };
+
+    let now = env.ledger().timestamp();
+    if now >= limit.window_start.saturating_add(limit.period) {
+        limit.spent = 0;
+        limit.window_start = window_start(now, limit.period);
+    }
+
+    let spent = limit
+        .spent
+        .checked_add(transfer.amount)
+        .ok_or(ContractError::Overflow)?;
+    if spent > limit.amount_per_period {
+        return Err(ContractError::SpendLimitExceeded);
+    }
+    limit.spent = spent;
+
+    save(env, &transfer.token, &limit);
+    Ok(())
+}
+
+/// Persist a queued increase whose delay has passed and return the limit.
+fn settle(env: &Env, token: &Address) -> Option<TokenLimit> {
+    let limit = get(env, token)?;
+    if pending(env, token).is_some_and(|p| env.ledger().timestamp() >= p.effective_at) {
+        save(env, token, &limit);
+        env.storage()
+            .persistent()
+            .remove(&DataKey::PendingTokenLimit(token.clone()));
+    }
+    Some(limit)
+}
+
+/// `limit` with a new amount and period.
+///
+/// A changed period moves the window onto the new period's schedule, keeping
+/// what was spent if the old window is still running.
+fn with_terms(limit: TokenLimit, amount_per_period: i128, period: u64, now: u64) -> TokenLimit {
+    if period == limit.period {
+        return TokenLimit {
+            amount_per_period,
+            ..limit
+        };
+    }
+
+    let running = now < limit.window_start.saturating_add(limit.period);
+    TokenLimit {
+        amount_per_period,
+        period,
+        spent: if running { limit.spent } else { 0 },
+        window_start: window_start(now, period),
+    }
+}
+
+/// Start of the scheduled window of length `period` containing `now`.
+fn window_start(now: u64, period: u64) -> u64 {
+    now - now % period
+}
+
+fn load(env: &Env, token: &Address) -> Option<TokenLimit> {
+    env.storage()
+        .persistent()
+        .get(&DataKey::TokenLimit(token.clone()))
+}
+
+fn save(env: &Env, token: &Address, limit: &TokenLimit) {
+    env.storage()
+        .persistent()
+        .set(&DataKey::TokenLimit(token.clone()), limit);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{testutils::register_target, AncoreAccount, AncoreAccountClient};
+    use soroban_sdk::{
+        testutils::{Address as _, Ledger as _},
+        vec, IntoVal, Symbol, Val, Vec,
+    };
+
+    const DAY: u64 = DAILY_LIMIT_PERIOD;
+
+    fn setup(env: &Env) -> (Address, AncoreAccountClient<'_>) {
+        env.mock_all_auths();
+        env.ledger().with_mut(|li| li.timestamp = 1_000);
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(env, &contract_id);
+        client.initialize(&Address::generate(env));
+        (contract_id, client)
+    }
+
+    fn transfer_args(env: &Env, account: &Address, amount: i128) -> Vec<Val> {
+        vec![
+            env,
+            account.into_val(env),
+            Address::generate(env).into_val(env),
+            amount.into_val(env),
+        ]
+    }
+
+    fn advance(env: &Env, seconds: u64) {
+        env.ledger().with_mut(|li| li.timestamp += seconds);
+    }
+
+    #[test]
+    fn test_transfer_within_limit() {
+        let env = Env::default();
+        let (account, client) = setup(&env);
+        let token = register_target(&env);
+        let other_token = register_target(&env);
+        let transfer = Symbol::new(&env, "transfer");
+
+        client.set_token_limit(&token, &1_000, &DAY);
+        client.execute(
+            &token,
+            &transfer,
+            &transfer_args(&env, &account, 600),
+            &0,
+            &0,
+        );
+        client.execute(
+            &token,
+            &transfer,
+            &transfer_args(&env, &account, 400),
+            &0,
+            &1,
+        );
+        client.execute(
+            &other_token,
+            &transfer,
+            &transfer_args(&env, &account, 5_000),
+            &0,
+            &2,
+        );
+
+        assert_eq!(
+            client.get_token_limit(&token),
+            Some(TokenLimit {
+                amount_per_period: 1_000,
+                period: DAY,
+                spent: 1_000,
+                window_start: 0,
+            })
+        );
+        assert_eq!(client.get_token_limit(&other_token), None);
+        assert_eq!(client.get_nonce(&0), 3);
+    }
+
+    #[test]
+    fn test_transfer_over_limit_is_blocked() {
+        let env = Env::default();
+        let (account, client) = setup(&env);
+        let token = register_target(&env);
+        let transfer = Symbol::new(&env, "transfer");
+
+        client.set_token_limit(&token, &1_000, &DAY);
+        client.execute(
+            &token,
+            &transfer,
+            &transfer_args(&env, &account, 600),
+            &0,
+            &0,
+        );
+
+        let result = client.try_execute(
+            &token,
+            &transfer,
+            &transfer_args(&env, &account, 500),
+            &0,
+            &1,
+        );
+        assert_eq!(result, Err(Ok(ContractError::SpendLimitExceeded)));
+        assert_eq!(client.get_token_limit(&token).unwrap().spent, 600);
+        assert_eq!(client.get_nonce(&0), 1);
+
+        // Transfers from another address are not the account spending.
+        let foreign = transfer_args(&env, &Address::generate(&env), 5_000);
+        client.execute(&token, &transfer, &foreign, &0, &1);
+
+        // The window resets once the period has elapsed.
+        advance(&env, DAY);
+        client.execute(
+            &token,
+            &transfer,
+            &transfer_args(&env, &account, 500),
+            &0,
+            &2,
+        );
+        let limit = client.get_token_limit(&token).unwrap();
+        assert_eq!(limit.spent, 500);
+        assert_eq!(limit.window_start, DAY);
+    }
+
+    #[test]
+    fn test_daily_limit_resets_at_utc_midnight() {
+        let env = Env::default();
+        let (account, client) = setup(&env);
+        let token = register_target(&env);
+        let transfer = Symbol::new(&env, "transfer");
+        client.set_token_limit(&token, &1_000, &DAY);
+
+        env.ledger().with_mut(|li| li.timestamp = DAY - 1);
+        client.execute(
+            &token,
+            &transfer,
+            &transfer_args(&env, &account, 1_000),
+            &0,
+            &0,
+        );
+
+        // A second later is a new day, even though a full period has not
+        // passed since the spend.
+        env.ledger().with_mut(|li| li.timestamp = DAY);
+        client.execute(
+            &token,
+            &transfer,
+            &transfer_args(&env, &account, 1_000),
+            &0,
+            &1,
+        );
+        assert_eq!(
+            client.try_execute(&token, &transfer, &transfer_args(&env, &account, 1), &0, &2,),
+            Err(Ok(ContractError::SpendLimitExceeded))
+        );
+    }
+
+    #[test]
+    fn test_period_change_follows_new_schedule() {
+        const HOUR: u64 = 3_600;
+        let env = Env::default();
+        let (account, client) = setup(&env);
+        let token = register_target(&env);
+        let transfer = Symbol::new(&env, "transfer");
+        client.set_token_limit(&token, &1_000, &DAY);
+        client.execute(
+            &token,
+            &transfer,
+            &transfer_args(&env, &account, 40),
+            &0,
+            &0,
+        );
+
+        // Tightening a daily limit to hourly is queued, as a shorter period
+        // can let more out per day.
+        client.set_token_limit(&token, &50, &HOUR);
+        assert_eq!(client.get_token_limit(&token).unwrap().period, DAY);
+
+        env.ledger()
+            .with_mut(|li| li.timestamp = 1_000 + LIMIT_INCREASE_DELAY + 100);
+        assert_eq!(
+            client.get_token_limit(&token),
+            Some(TokenLimit {
+                amount_per_period: 50,
+                period: HOUR,
+                spent: 0,
+                window_start: LIMIT_INCREASE_DELAY,
+            })
+        );
+        client.execute(
+            &token,
+            &transfer,
+            &transfer_args(&env, &account, 50),
+            &0,
+            &1,
+        );
+        assert_eq!(
+            client.try_execute(&token, &transfer, &transfer_args(&env, &account, 1), &0, &2),
+            Err(Ok(ContractError::SpendLimitExceeded))
+        );
+
+        // The next hour starts on the hourly schedule, not an hour after the
+        // daily window began.
+        env.ledger()
+            .with_mut(|li| li.timestamp = LIMIT_INCREASE_DELAY + HOUR);
+        client.execute(
+            &token,
+            &transfer,
+            &transfer_args(&env, &account, 50),
+            &0,
+            &2,
+        );
+
+        // Going back to daily applies at once and realigns to midnight,
+        // keeping what the running window already spent.
+        client.set_token_limit(&token, &50, &DAY);
+        assert_eq!(
+            client.get_token_limit(&token),
+            Some(TokenLimit {
+                amount_per_period: 50,
+                period: DAY,
+                spent: 50,
+                window_start: LIMIT_INCREASE_DELAY,
+            })
+        );
+    }
+
+    #[test]
+    fn test_transfer_from_and_burn_are_metered() {
+        let env = Env::default();
+        let (account, client) = setup(&env);
+        let token = register_target(&env);
+        let recipient = Address::generate(&env);
+        let transfer_from = Symbol::new(&env, "transfer_from");
+        let transfer_from_args = |amount: i128| {
+            vec![
+                &env,
+                account.into_val(&env),
+                account.into_val(&env),
+                recipient.into_val(&env),
+                amount.into_val(&env),
+            ]
+        };
+        let burn = Symbol::new(&env, "burn");
+        let burn_args = |amount: i128| vec![&env, account.into_val(&env), amount.into_val(&env)];
+        client.set_token_limit(&token, &1_000, &DAY);
+
+        client.execute(&token, &transfer_from, &transfer_from_args(600), &0, &0);
+        client.execute(&token, &burn, &burn_args(300), &0, &1);
+        assert_eq!(client.get_token_limit(&token).unwrap().spent, 900);
+
+        assert_eq!(
+            client.try_execute(&token, &transfer_from, &transfer_from_args(101), &0, &2),
+            Err(Ok(ContractError::SpendLimitExceeded))
+        );
+        assert_eq!(
+            client.try_execute(&token, &burn, &burn_args(101), &0, &2),
+            Err(Ok(ContractError::SpendLimitExceeded))
+        );
+    }
+
+    #[test]
+    fn test_approval_above_limit_is_blocked() {
+        let env = Env::default();
+        let (account, client) = setup(&env);
+        let token = register_target(&env);
+        let approve = Symbol::new(&env, "approve");
+        let spender = Address::generate(&env);
+        let approve_args = |amount: i128| {
+            vec![
+                &env,
+                account.into_val(&env),
+                spender.into_val(&env),
+                amount.into_val(&env),
+                1_000u32.into_val(&env),
+            ]
+        };
+        client.set_token_limit(&token, &1_000, &DAY);
+
+        // The spender could take the whole allowance, so it all counts.
+        assert_eq!(
+            client.try_execute(&token, &approve, &approve_args(1_001), &0, &0),
+            Err(Ok(ContractError::SpendLimitExceeded))
+        );
+        client.execute(&token, &approve, &approve_args(600), &0, &0);
+        assert_eq!(client.get_token_limit(&token).unwrap().spent, 600);
+
+        // Revoking the allowance moves nothing.
+        client.execute(&token, &approve, &approve_args(0), &0, &1);
+        assert_eq!(client.get_token_limit(&token).unwrap().spent, 600);
+    }
+
+    #[test]
+    fn test_limit_increase_is_timelocked() {
+        let env = Env::default();
+        let (account, client) = setup(&env);
+        let token = register_target(&env);
+        let transfer = Symbol::new(&env, "transfer");
+
+        client.set_token_limit(&token, &1_000, &DAY);
+        client.set_token_limit(&token, &5_000, &DAY);
+
+        assert_eq!(
+            client.get_token_limit(&token).unwrap().amount_per_period,
+            1_000
+        );
+        assert_eq!(
+            client.get_pending_token_limit(&token),
+            Some(PendingTokenLimit {
+                amount_per_period: 5_000,
+                period: DAY,
+                effective_at: 1_000 + LIMIT_INCREASE_DELAY,
+            })
+        );
+        let result = client.try_execute(
+            &token,
+            &transfer,
+            &transfer_args(&env, &account, 2_000),
+            &0,
+            &0,
+        );
+        assert_eq!(result, Err(Ok(ContractError::SpendLimitExceeded)));
+
+        advance(&env, LIMIT_INCREASE_DELAY);
+        assert_eq!(
+            client.get_token_limit(&token).unwrap().amount_per_period,
+            5_000
+        );
+        client.execute(
+            &token,
+            &transfer,
+            &transfer_args(&env, &account, 2_000),
+            &0,
+            &0,
+        );
+        assert_eq!(client.get_pending_token_limit(&token), None);
+    }
+
+    #[test]
+    fn test_second_limit_increase_conflicts() {
+        let env = Env::default();
+        let (_, client) = setup(&env);
+        let token = register_target(&env);
+
+        client.set_token_limit(&token, &1_000, &DAY);
+        client.set_token_limit(&token, &5_000, &DAY);
+        advance(&env, DAY / 2);
+
+        assert_eq!(
+            client.try_set_token_limit(&token, &9_000, &DAY),
+            Err(Ok(ContractError::TimelockConflict))
+        );
+        assert_eq!(
+            client.get_pending_token_limit(&token).unwrap().effective_at,
+            1_000 + LIMIT_INCREASE_DELAY
+        );
+
+        // Other tokens have their own slot.
+        let other_token = register_target(&env);
+        client.set_token_limit(&other_token, &1_000, &DAY);
+        client.set_token_limit(&other_token, &5_000, &DAY);
+        assert!(client.get_pending_token_limit(&other_token).is_some());
+
+        // Once the first increase has applied, the slot is free again.
+        advance(&env, DAY / 2);
+        client.set_token_limit(&token, &9_000, &DAY);
+        assert_eq!(
+            client.get_token_limit(&token).unwrap().amount_per_period,
+            5_000
+        );
+        assert_eq!(
+            client
+                .get_pending_token_limit(&token)
+                .unwrap()
+                .amount_per_period,
+            9_000
+        );
+    }
+
+    #[test]
+    fn test_limit_decrease_applies_immediately() {
+        let env = Env::default();
+        let (account, client) = setup(&env);
+        let token = register_target(&env);
+        let transfer = Symbol::new(&env, "transfer");
+
+        client.set_token_limit(&token, &1_000, &DAY);
+        client.set_token_limit(&token, &5_000, &DAY);
+        client.set_token_limit(&token, &300, &DAY);
+
+        assert_eq!(
+            client.get_token_limit(&token).unwrap().amount_per_period,
+            300
+        );
+        assert_eq!(client.get_pending_token_limit(&token), None);
+        let result = client.try_execute(
+            &token,
+            &transfer,
+            &transfer_args(&env, &account, 400),
+            &0,
+            &0,
+        );
+        assert_eq!(result, Err(Ok(ContractError::SpendLimitExceeded)));
+
+        // A shorter period is a loosening and waits like a larger amount.
+        client.set_token_limit(&token, &300, &(DAY / 2));
+        assert_eq!(client.get_token_limit(&token).unwrap().period, DAY);
+        assert!(client.get_pending_token_limit(&token).is_some());
+    }
+
+    #[test]
+    fn test_negative_transfer_amount_rejected() {
+        let env = Env::default();
+        let (account, client) = setup(&env);
+        let token = register_target(&env);
+        let transfer = Symbol::new(&env, "transfer");
+
+        client.set_token_limit(&token, &1_000, &DAY);
+        client.execute(
+            &token,
+            &transfer,
+            &transfer_args(&env, &account, 900),
+            &0,
+            &0,
+        );
+
+        assert_eq!(
+            client.try_execute(
+                &token,
+                &transfer,
+                &transfer_args(&env, &account, -500),
+                &0,
+                &1
+            ),
+            Err(Ok(ContractError::InvalidAmount))
+        );
+        assert_eq!(client.get_token_limit(&token).unwrap().spent, 900);
+        assert_eq!(client.get_nonce(&0), 1);
+
+        // Without a limit the amount is still rejected.
+        let other_token = register_target(&env);
+        assert_eq!(
+            client.try_execute(
+                &other_token,
+                &transfer,
+                &transfer_args(&env, &account, -1),
+                &0,
+                &1
+            ),
+            Err(Ok(ContractError::InvalidAmount))
+        );
+    }
+
+    #[test]
+    fn test_invalid_limit_rejected() {
+        let env = Env::default();
+        let (_, client) = setup(&env);
+        let token = register_target(&env);
+
+        assert_eq!(
+            client.try_set_token_limit(&token, &0, &DAY),
+            Err(Ok(ContractError::InvalidLimit))
+        );
+        assert_eq!(
+            client.try_set_token_limit(&token, &100, &0),
+            Err(Ok(ContractError::InvalidLimit))
+        );
+    }
+}
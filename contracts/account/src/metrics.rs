@@ -0,0 +1,91 @@
+//! Operational counters for dashboards.
+//!
+//! Counters only move on successful operations: a failing invocation rolls
+//! back all of its storage writes, so failures cannot be counted on-chain.
+
+use soroban_sdk::{contracttype, Env};
+
+use crate::DataKey;
+
+#[contracttype]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Metrics {
+    pub executes: u64,
+    pub session_keys_created: u64,
+    pub recoveries: u64,
+}
+
+pub fn get(env: &Env) -> Metrics {
+    env.storage()
+        .instance()
+        .get(&DataKey::Metrics)
+        .unwrap_or_default()
+}
+
+pub fn bump(env: &Env, update: impl FnOnce(&mut Metrics)) {
+    let mut metrics = get(env);
+    update(&mut metrics);
+    env.storage().instance().set(&DataKey::Metrics, &metrics);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{testutils::register_target, AncoreAccount, AncoreAccountClient, ContractError};
+    use soroban_sdk::{testutils::Address as _, vec, Address, BytesN, IntoVal, Symbol, Vec};
+
+    #[test]
+    fn test_metrics_count_successful_operations() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(&env, &contract_id);
+        client.initialize(&Address::generate(&env));
+        assert_eq!(client.get_metrics(), Metrics::default());
+
+        let target = register_target(&env);
+        for nonce in 0..3 {
+            client.execute(
+                &target,
+                &Symbol::new(&env, "ping"),
+                &Vec::new(&env),
+                &0,
+                &nonce,
+            );
+        }
+        for i in 0..2u8 {
+            let session_pk = BytesN::from_array(&env, &[i; 32]);
+            client.add_session_key(&session_pk, &1000, &Vec::new(&env));
+        }
+        client.revoke_session_key(&BytesN::from_array(&env, &[0u8; 32]));
+
+        // A rejected execute leaves no trace in the counters.
+        let token = register_target(&env);
+        client.set_token_limit(&token, &10, &86_400);
+        let transfer_args = vec![
+            &env,
+            contract_id.into_val(&env),
+            target.into_val(&env),
+            11i128.into_val(&env),
+        ];
+        assert_eq!(
+            client.try_execute(
+                &token,
+                &Symbol::new(&env, "transfer"),
+                &transfer_args,
+                &0,
+                &3
+            ),
+            Err(Ok(ContractError::SpendLimitExceeded))
+        );
+
+        assert_eq!(
+            client.get_metrics(),
+            Metrics {
+                executes: 3,
+                session_keys_created: 2,
+                recoveries: 0,
+            }
+        );
+    }
+}
@@ -0,0 +1,161 @@
+//! Storage schema versioning and post-upgrade migration.
+//!
+//! Every account records the layout its storage is in under
+//! `DataKey::StorageVersion`; accounts created before versioning have no
+//! entry and are version 1. After an upgrade to code expecting a newer
+//! layout, the owner calls `migrate` to rewrite old entries in place.
+//!
+//! New `DataKey` variants are appended and keep existing keys readable, so
+//! only values whose shape changed need rewriting:
+//!
+//! - Version 1 to 2: session keys held `public_key`, `expires_at`, and
+//!   numeric `permissions`, where `1` allowed any call. They become
+//!   [`SessionKey`]s with `Permission::Execute` for code `1` (other codes
+//!   granted nothing and are dropped), no restrictions, and an index entry.
+//!
+//! Session keys cannot be enumerated in version 1, so `migrate` takes the
+//! public keys to convert and can be called in several batches. Keys already
+//! in the current shape are left alone.
+
+use soroban_sdk::{contracttype, symbol_short, BytesN, Env, Map, Symbol, TryFromVal, Val, Vec};
+
+use crate::{events, session, ContractError, DataKey, Permission, SessionKey};
+
+/// Storage layout this code reads and writes.
+pub const STORAGE_VERSION: u32 = 2;
+
+/// Session key layout of storage version 1.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SessionKeyV1 {
+    pub public_key: BytesN<32>,
+    pub expires_at: u64,
+    pub permissions: Vec<u32>,
+}
+
+/// Version 1 code for a session key allowed to make any call.
+const V1_PERMISSION_EXECUTE: u32 = 1;
+
+pub fn version(env: &Env) -> u32 {
+    env.storage()
+        .instance()
+        .get(&DataKey::StorageVersion)
+        .unwrap_or(1)
+}
+
+pub fn set_current(env: &Env) {
+    env.storage()
+        .instance()
+        .set(&DataKey::StorageVersion, &STORAGE_VERSION);
+}
+
+/// Rewrite the given version 1 session keys and mark storage current,
+/// returning how many keys were converted.
+pub fn migrate(env: &Env, public_keys: &Vec<BytesN<32>>) -> Result<u32, ContractError> {
+    let from = version(env);
+    if from > STORAGE_VERSION {
+        return Err(ContractError::InvalidStorageVersion);
+    }
+
+    let mut migrated = 0;
+    for public_key in public_keys.iter() {
+        if migrate_session_key(env, &public_key) {
+            migrated += 1;
+        }
+    }
+    set_current(env);
+
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("migrated")),
+        (from, STORAGE_VERSION, migrated),
+    );
+    Ok(migrated)
+}
+
+fn migrate_session_key(env: &Env, public_key: &BytesN<32>) -> bool {
+    let key = DataKey::SessionKey(public_key.clone());
+    let Some(raw) = env.storage().persistent().get::<_, Map<Symbol, Val>>(&key) else {
+        return false;
+    };
+    // Fields added in version 2 mark a key that needs no conversion.
+    if raw.contains_key(Symbol::new(env, "valid_after")) {
+        return false;
+    }
+
+    let old = SessionKeyV1::try_from_val(env, &Val::from(raw)).unwrap();
+    let mut permissions = Vec::new(env);
+    if old.permissions.contains(V1_PERMISSION_EXECUTE) {
+        permissions.push_back(Permission::Execute);
+    }
+    let session_key = SessionKey {
+        public_key: old.public_key,
+        valid_after: 0,
+        expires_at: old.expires_at,
+        permissions,
+        spend_limits: Vec::new(env),
+        allowed_contracts: Vec::new(env),
+        allowed_functions: Vec::new(env),
+        max_uses: 0,
+        uses: 0,
+    };
+    session::save(env, &session_key);
+    session::add_to_index(env, public_key);
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{AncoreAccount, AncoreAccountClient};
+    use soroban_sdk::{testutils::Address as _, vec, Address};
+
+    #[test]
+    fn test_migrate_v1_session_keys() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(&env, &contract_id);
+        client.initialize(&Address::generate(&env));
+        assert_eq!(client.get_storage_version(), STORAGE_VERSION);
+
+        let executor = BytesN::from_array(&env, &[1u8; 32]);
+        let other = BytesN::from_array(&env, &[2u8; 32]);
+        let current = BytesN::from_array(&env, &[3u8; 32]);
+        client.add_session_key(&current, &500, &vec![&env, Permission::Execute]);
+
+        // Rewind the account to the version 1 layout.
+        env.as_contract(&contract_id, || {
+            env.storage().instance().remove(&DataKey::StorageVersion);
+            for (public_key, code) in [(&executor, 1u32), (&other, 7)] {
+                let old = SessionKeyV1 {
+                    public_key: public_key.clone(),
+                    expires_at: 1000,
+                    permissions: vec![&env, code],
+                };
+                env.storage()
+                    .persistent()
+                    .set(&DataKey::SessionKey(public_key.clone()), &old);
+            }
+        });
+        assert_eq!(client.get_storage_version(), 1);
+
+        let keys = vec![&env, executor.clone(), other.clone(), current.clone()];
+        assert_eq!(client.migrate(&keys), 2);
+        assert_eq!(client.get_storage_version(), STORAGE_VERSION);
+
+        let migrated = client.get_session_key(&executor).unwrap();
+        assert_eq!(migrated.expires_at, 1000);
+        assert_eq!(migrated.permissions, vec![&env, Permission::Execute]);
+        assert!(client
+            .get_session_key(&other)
+            .unwrap()
+            .permissions
+            .is_empty());
+        assert_eq!(client.get_session_key(&current).unwrap().expires_at, 500);
+        assert_eq!(client.list_session_keys(&0, &10).len(), 3);
+
+        // Running it again changes nothing.
+        assert_eq!(client.migrate(&keys), 0);
+    }
+}
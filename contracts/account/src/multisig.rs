@@ -0,0 +1,436 @@
+//! Threshold signing by several weighted owner keys.
+//!
+//! By default the account has a single owner address whose auth every owner
+//! operation requires. Enabling multisig registers a set of ed25519 signer
+//! keys, each with a weight, and a threshold, and makes the account its own
+//! owner: every `owner.require_auth()` then reaches the account's
+//! `__check_auth`, which only accepts a payload whose distinct signers'
+//! weights add up to at least `threshold`. Plain M-of-N is every weight 1.
+//!
+//! Once enabled, signers can be added, removed, or rotated one at a time; a
+//! removal that would leave less weight than the threshold is refused.
+
+use soroban_sdk::{contracttype, symbol_short, BytesN, Env, Vec};
+
+use crate::{events, ownership, AccountSignature, ContractError, DataKey};
+
+/// An owner key and how much its signature counts towards the threshold.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Signer {
+    pub key: BytesN<32>,
+    pub weight: u32,
+}
+
+/// Register `signers` with a weight `threshold` and hand ownership to them.
+pub fn enable(env: &Env, signers: &Vec<Signer>, threshold: u32) -> Result<(), ContractError> {
+    let mut keys = Vec::<BytesN<32>>::new(env);
+    for signer in signers.iter() {
+        if keys.contains(&signer.key) {
+            return Err(ContractError::DuplicateSigner);
+        }
+        if signer.weight == 0 {
+            return Err(ContractError::InvalidThreshold);
+        }
+        keys.push_back(signer.key);
+    }
+    if threshold == 0 || threshold > total_weight(signers)? {
+        return Err(ContractError::InvalidThreshold);
+    }
+
+    ownership::set_owner(env, &env.current_contract_address());
+    let storage = env.storage().instance();
+    storage.set(&DataKey::Signers, signers);
+    storage.set(&DataKey::Threshold, &threshold);
+
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("multisig")),
+        (signers.clone(), threshold),
+    );
+    Ok(())
+}
+
+/// Add a signer to an enabled signer set.
+pub fn add(env: &Env, signer: &Signer) -> Result<(), ContractError> {
+    let mut signers = enabled_signers(env)?;
+    if signers.iter().any(|existing| existing.key == signer.key) {
+        return Err(ContractError::DuplicateSigner);
+    }
+    if signer.weight == 0 {
+        return Err(ContractError::InvalidThreshold);
+    }
+    signers.push_back(signer.clone());
+    total_weight(&signers)?;
+    env.storage().instance().set(&DataKey::Signers, &signers);
+
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("sig_add")),
+        (signer.key.clone(), signer.weight),
+    );
+    Ok(())
+}
+
+/// Remove a signer, unless the rest could no longer meet the threshold.
+pub fn remove(env: &Env, key: &BytesN<32>) -> Result<(), ContractError> {
+    let mut signers = enabled_signers(env)?;
+    let i = position(&signers, key)?;
+    signers.remove(i);
+    if total_weight(&signers)? < threshold(env) {
+        return Err(ContractError::InvalidThreshold);
+    }
+    env.storage().instance().set(&DataKey::Signers, &signers);
+
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("sig_rm")),
+        key.clone(),
+    );
+    Ok(())
+}
+
+/// Replace a signer's key, keeping its weight.
+pub fn rotate(env: &Env, old_key: &BytesN<32>, new_key: &BytesN<32>) -> Result<(), ContractError> {
+    let mut signers = enabled_signers(env)?;
+    let i = position(&signers, old_key)?;
+    if signers.iter().any(|existing| existing.key == *new_key) {
+        return Err(ContractError::DuplicateSigner);
+    }
+    let mut signer = signers.get_unchecked(i);
+    signer.key = new_key.clone();
+    signers.set(i, signer);
+    env.storage().instance().set(&DataKey::Signers, &signers);
+
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("sig_rot")),
+        (old_key.clone(), new_key.clone()),
+    );
+    Ok(())
+}
+
+fn enabled_signers(env: &Env) -> Result<Vec<Signer>, ContractError> {
+    if !is_enabled(env) {
+        return Err(ContractError::Unauthorized);
+    }
+    Ok(signers(env))
+}
+
+fn position(signers: &Vec<Signer>, key: &BytesN<32>) -> Result<u32, ContractError> {
+    signers
+        .iter()
+        .position(|signer| signer.key == *key)
+        .map(|i| i as u32)
+        .ok_or(ContractError::UnknownSigner)
+}
+
+fn total_weight(signers: &Vec<Signer>) -> Result<u32, ContractError> {
+    signers.iter().try_fold(0u32, |total, signer| {
+        total
+            .checked_add(signer.weight)
+            .ok_or(ContractError::Overflow)
+    })
+}
+
+/// Forget the signer set, once ownership has moved elsewhere.
+pub fn clear(env: &Env) {
+    let storage = env.storage().instance();
+    storage.remove(&DataKey::Signers);
+    storage.remove(&DataKey::Threshold);
+}
+
+/// Whether ownership is held by the signer set rather than a single address.
+pub fn is_enabled(env: &Env) -> bool {
+    env.storage()
+        .instance()
+        .get(&DataKey::Owner)
+        .is_some_and(|owner: soroban_sdk::Address| owner == env.current_contract_address())
+}
+
+pub fn signers(env: &Env) -> Vec<Signer> {
+    env.storage()
+        .instance()
+        .get(&DataKey::Signers)
+        .unwrap_or_else(|| Vec::new(env))
+}
+
+pub fn threshold(env: &Env) -> u32 {
+    env.storage()
+        .instance()
+        .get(&DataKey::Threshold)
+        .unwrap_or(0)
+}
+
+/// Signers that sign as the owner and the weight they must reach.
+///
+/// Only keys configured on the account count. Without multisig there are
+/// none: a classic owner's key is never derived from its address, since the
+/// contract cannot see that account's own signers and thresholds.
+pub fn owner_signers(env: &Env) -> (Vec<Signer>, u32) {
+    if is_enabled(env) {
+        return (signers(env), threshold(env));
+    }
+    (Vec::new(env), 1)
+}
+
+/// Whether `key` is one of the keys that sign as the owner.
+pub fn is_owner_key(env: &Env, key: &BytesN<32>) -> bool {
+    let (signers, _) = owner_signers(env);
+    signers.iter().any(|signer| signer.key == *key)
+}
+
+/// Check that `signatures` are valid owner signatures meeting the threshold.
+///
+/// A bad signature fails the call outright, as `ed25519_verify` traps.
+pub fn verify(
+    env: &Env,
+    payload: &BytesN<32>,
+    signatures: &Vec<AccountSignature>,
+) -> Result<(), ContractError> {
+    let (signers, threshold) = owner_signers(env);
+    let mut signed = Vec::<BytesN<32>>::new(env);
+    let mut weight: u32 = 0;
+    for signature in signatures.iter() {
+        let signer = signers
+            .iter()
+            .find(|signer| signer.key == signature.public_key)
+            .ok_or(ContractError::UnknownSigner)?;
+        if signed.contains(&signature.public_key) {
+            return Err(ContractError::DuplicateSigner);
+        }
+
+        env.crypto().ed25519_verify(
+            &signature.public_key,
+            &payload.clone().into(),
+            &signature.signature,
+        );
+        signed.push_back(signature.public_key);
+        weight = weight.saturating_add(signer.weight);
+    }
+
+    if weight < threshold {
+        return Err(ContractError::ThresholdNotMet);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{AncoreAccount, AncoreAccountClient, Permission};
+    use ed25519_dalek::{Signer as _, SigningKey};
+    use soroban_sdk::{
+        auth::{Context, ContractContext},
+        testutils::Address as _,
+        vec, Address, IntoVal, Symbol,
+    };
+
+    struct Setup<'a> {
+        env: Env,
+        client: AncoreAccountClient<'a>,
+        keys: [SigningKey; 3],
+    }
+
+    fn setup<'a>() -> Setup<'a> {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(&env, &contract_id);
+        client.initialize(&Address::generate(&env));
+        let keys = [1u8, 2, 3].map(|seed| SigningKey::from_bytes(&[seed; 32]));
+        Setup { env, client, keys }
+    }
+
+    fn public_key(env: &Env, key: &SigningKey) -> BytesN<32> {
+        BytesN::from_array(env, &key.verifying_key().to_bytes())
+    }
+
+    fn signer(env: &Env, key: &SigningKey, weight: u32) -> Signer {
+        Signer {
+            key: public_key(env, key),
+            weight,
+        }
+    }
+
+    fn check_auth(s: &Setup, signers: &[&SigningKey]) -> Result<(), ContractError> {
+        let payload = BytesN::from_array(&s.env, &[9u8; 32]);
+        let mut signatures = Vec::new(&s.env);
+        for key in signers {
+            signatures.push_back(AccountSignature {
+                public_key: public_key(&s.env, key),
+                signature: BytesN::from_array(&s.env, &key.sign(&payload.to_array()).to_bytes()),
+            });
+        }
+        s.env
+            .try_invoke_contract_check_auth::<ContractError>(
+                &s.client.address,
+                &payload,
+                signatures.into_val(&s.env),
+                &Vec::new(&s.env),
+            )
+            .map_err(|error| error.unwrap())
+    }
+
+    #[test]
+    fn test_multisig_requires_threshold_of_signers() {
+        let s = setup();
+        let [a, b, c] = &s.keys;
+        let signers = vec![
+            &s.env,
+            signer(&s.env, a, 1),
+            signer(&s.env, b, 1),
+            signer(&s.env, c, 1),
+        ];
+        s.client.set_multisig(&signers, &2);
+
+        assert_eq!(s.client.get_owner(), s.client.address);
+        assert_eq!(s.client.get_signers(), signers);
+        assert_eq!(s.client.get_threshold(), 2);
+
+        assert_eq!(check_auth(&s, &[a, c]), Ok(()));
+        assert_eq!(check_auth(&s, &[c, b, a]), Ok(()));
+        assert_eq!(check_auth(&s, &[b]), Err(ContractError::ThresholdNotMet));
+        assert_eq!(check_auth(&s, &[b, b]), Err(ContractError::DuplicateSigner));
+        let outsider = SigningKey::from_bytes(&[4u8; 32]);
+        assert_eq!(
+            check_auth(&s, &[a, &outsider]),
+            Err(ContractError::UnknownSigner)
+        );
+    }
+
+    #[test]
+    fn test_weighted_signers_meet_threshold_together() {
+        let s = setup();
+        let [ceo, cfo, ops] = &s.keys;
+        let signers = vec![
+            &s.env,
+            signer(&s.env, ceo, 50),
+            signer(&s.env, cfo, 30),
+            signer(&s.env, ops, 10),
+        ];
+        s.client.set_multisig(&signers, &60);
+
+        assert_eq!(check_auth(&s, &[ceo, ops]), Ok(()));
+        assert_eq!(check_auth(&s, &[ceo, cfo]), Ok(()));
+        assert_eq!(check_auth(&s, &[ceo]), Err(ContractError::ThresholdNotMet));
+        assert_eq!(
+            check_auth(&s, &[cfo, ops]),
+            Err(ContractError::ThresholdNotMet)
+        );
+    }
+
+    #[test]
+    fn test_multisig_rejects_invalid_config() {
+        let s = setup();
+        let [a, b, _] = &s.keys;
+        let signers = vec![&s.env, signer(&s.env, a, 1), signer(&s.env, b, 2)];
+
+        assert_eq!(
+            s.client.try_set_multisig(&signers, &0),
+            Err(Ok(ContractError::InvalidThreshold))
+        );
+        assert_eq!(
+            s.client.try_set_multisig(&signers, &4),
+            Err(Ok(ContractError::InvalidThreshold))
+        );
+        assert_eq!(
+            s.client
+                .try_set_multisig(&vec![&s.env, signer(&s.env, a, 0)], &1),
+            Err(Ok(ContractError::InvalidThreshold))
+        );
+        assert_eq!(
+            s.client.try_set_multisig(
+                &vec![&s.env, signer(&s.env, a, 1), signer(&s.env, a, 1)],
+                &1
+            ),
+            Err(Ok(ContractError::DuplicateSigner))
+        );
+        assert_ne!(s.client.get_owner(), s.client.address);
+    }
+
+    #[test]
+    fn test_session_key_cannot_authorize_owner_calls() {
+        let s = setup();
+        let [a, _, _] = &s.keys;
+        s.client
+            .set_multisig(&vec![&s.env, signer(&s.env, a, 1)], &1);
+
+        let session = SigningKey::from_bytes(&[5u8; 32]);
+        let permissions = vec![&s.env, Permission::Execute];
+        s.client
+            .add_session_key(&public_key(&s.env, &session), &1000, &permissions);
+
+        let payload = BytesN::from_array(&s.env, &[9u8; 32]);
+        let signature = AccountSignature {
+            public_key: public_key(&s.env, &session),
+            signature: BytesN::from_array(&s.env, &session.sign(&payload.to_array()).to_bytes()),
+        };
+        let context = Context::Contract(ContractContext {
+            contract: s.client.address.clone(),
+            fn_name: Symbol::new(&s.env, "set_multisig"),
+            args: Vec::new(&s.env),
+        });
+        assert_eq!(
+            s.env.try_invoke_contract_check_auth::<ContractError>(
+                &s.client.address,
+                &payload,
+                vec![&s.env, signature].into_val(&s.env),
+                &vec![&s.env, context],
+            ),
+            Err(Ok(ContractError::InsufficientPermission))
+        );
+    }
+
+    #[test]
+    fn test_manage_signers() {
+        let s = setup();
+        let [a, b, c] = &s.keys;
+        assert_eq!(
+            s.client.try_add_signer(&signer(&s.env, a, 1)),
+            Err(Ok(ContractError::Unauthorized))
+        );
+
+        s.client.set_multisig(
+            &vec![&s.env, signer(&s.env, a, 1), signer(&s.env, b, 1)],
+            &2,
+        );
+        s.client.add_signer(&signer(&s.env, c, 1));
+        assert_eq!(
+            s.client.try_add_signer(&signer(&s.env, c, 3)),
+            Err(Ok(ContractError::DuplicateSigner))
+        );
+        assert_eq!(check_auth(&s, &[b, c]), Ok(()));
+
+        s.client.remove_signer(&public_key(&s.env, b));
+        assert_eq!(check_auth(&s, &[b, c]), Err(ContractError::UnknownSigner));
+        // Two signers left for a threshold of two: neither can go.
+        assert_eq!(
+            s.client.try_remove_signer(&public_key(&s.env, a)),
+            Err(Ok(ContractError::InvalidThreshold))
+        );
+
+        let replacement = SigningKey::from_bytes(&[6u8; 32]);
+        s.client
+            .rotate_signer(&public_key(&s.env, c), &public_key(&s.env, &replacement));
+        assert_eq!(
+            s.client.get_signers(),
+            vec![
+                &s.env,
+                signer(&s.env, a, 1),
+                signer(&s.env, &replacement, 1)
+            ]
+        );
+        assert_eq!(check_auth(&s, &[a, &replacement]), Ok(()));
+        assert_eq!(
+            s.client
+                .try_rotate_signer(&public_key(&s.env, c), &public_key(&s.env, b)),
+            Err(Ok(ContractError::UnknownSigner))
+        );
+        assert_eq!(
+            s.client
+                .try_rotate_signer(&public_key(&s.env, a), &public_key(&s.env, &replacement)),
+            Err(Ok(ContractError::DuplicateSigner))
+        );
+    }
+}
@@ -0,0 +1,176 @@
+//! Parallel nonce channels.
+//!
+//! Every owner call carries a `(channel, nonce)` pair. Each channel has its
+//! own strictly sequential nonce, so independent flows (a trading bot,
+//! subscriptions, manual wallet use) each take their own channel and never
+//! wait on one another, while calls within a channel still run in order and
+//! at most once.
+//!
+//! Channel 0 is the account's original nonce and stays under
+//! `DataKey::Nonce`, so existing accounts need no migration. Other channels
+//! are created on first use under `DataKey::NonceChannel`.
+//!
+//! The owner can skip a channel ahead with `invalidate_nonces`, cancelling
+//! payloads signed for nonces that were never submitted, such as a stale
+//! intent still held by a relayer, without a transaction per nonce.
+
+use soroban_sdk::{symbol_short, Env};
+
+use crate::{events, ContractError, DataKey};
+
+/// The channel of the account's original, single nonce.
+pub const DEFAULT_CHANNEL: u32 = 0;
+
+/// Next nonce on `channel`.
+pub fn get(env: &Env, channel: u32) -> u64 {
+    if channel == DEFAULT_CHANNEL {
+        env.storage().instance().get(&DataKey::Nonce).unwrap_or(0)
+    } else {
+        env.storage()
+            .persistent()
+            .get(&DataKey::NonceChannel(channel))
+            .unwrap_or(0)
+    }
+}
+
+fn set(env: &Env, channel: u32, nonce: u64) {
+    if channel == DEFAULT_CHANNEL {
+        env.storage().instance().set(&DataKey::Nonce, &nonce);
+    } else {
+        env.storage()
+            .persistent()
+            .set(&DataKey::NonceChannel(channel), &nonce);
+    }
+}
+
+/// Check `expected` is the next nonce on `channel`, failing with
+/// `InvalidNonce` otherwise.
+pub fn check(env: &Env, channel: u32, expected: u64) -> Result<(), ContractError> {
+    if expected != get(env, channel) {
+        return Err(ContractError::InvalidNonce);
+    }
+    Ok(())
+}
+
+/// Consume `nonce` on `channel` after a successful call.
+pub fn advance(env: &Env, channel: u32, nonce: u64) {
+    set(env, channel, nonce + 1);
+}
+
+/// Consume every unused nonce on `channel` up to and including `up_to`.
+///
+/// Fails with `InvalidNonce` if `up_to` is already consumed, since nonces
+/// never move backwards.
+pub fn invalidate(env: &Env, channel: u32, up_to: u64) -> Result<(), ContractError> {
+    let from = get(env, channel);
+    if up_to < from {
+        return Err(ContractError::InvalidNonce);
+    }
+    let next = up_to.checked_add(1).ok_or(ContractError::InvalidNonce)?;
+    set(env, channel, next);
+
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("nonce_inv")),
+        (channel, from, up_to),
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        testutils::{register_target, MockTargetClient},
+        AncoreAccount, AncoreAccountClient,
+    };
+    use soroban_sdk::{testutils::Address as _, vec, Address, IntoVal, Symbol};
+
+    fn setup(env: &Env) -> (AncoreAccountClient<'_>, MockTargetClient<'_>) {
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(env, &contract_id);
+        client.initialize(&Address::generate(env));
+        (client, MockTargetClient::new(env, &register_target(env)))
+    }
+
+    #[test]
+    fn test_channels_advance_independently() {
+        let env = Env::default();
+        let (client, target) = setup(&env);
+        let args = vec![
+            &env,
+            client.address.into_val(&env),
+            target.address.into_val(&env),
+            1i128.into_val(&env),
+        ];
+        let transfer = Symbol::new(&env, "transfer");
+
+        client.execute(&target.address, &transfer, &args, &7, &0);
+        client.execute(&target.address, &transfer, &args, &7, &1);
+        client.execute(&target.address, &transfer, &args, &0, &0);
+
+        assert_eq!(client.get_nonce(&7), 2);
+        assert_eq!(client.get_nonce(&0), 1);
+        assert_eq!(client.get_nonce(&3), 0);
+        assert_eq!(client.snapshot().nonce, 1);
+        assert_eq!(target.received(&target.address), 3);
+    }
+
+    #[test]
+    fn test_channel_rejects_reused_and_skipped_nonces() {
+        let env = Env::default();
+        let (client, target) = setup(&env);
+        let args = vec![
+            &env,
+            client.address.into_val(&env),
+            target.address.into_val(&env),
+            1i128.into_val(&env),
+        ];
+        let transfer = Symbol::new(&env, "transfer");
+        client.execute(&target.address, &transfer, &args, &5, &0);
+
+        for nonce in [0, 2] {
+            assert_eq!(
+                client.try_execute(&target.address, &transfer, &args, &5, &nonce),
+                Err(Ok(ContractError::InvalidNonce))
+            );
+        }
+        assert_eq!(client.get_nonce(&5), 1);
+    }
+
+    #[test]
+    fn test_invalidate_nonces_cancels_pending_nonces() {
+        let env = Env::default();
+        let (client, target) = setup(&env);
+        let args = vec![
+            &env,
+            client.address.into_val(&env),
+            target.address.into_val(&env),
+            1i128.into_val(&env),
+        ];
+        let transfer = Symbol::new(&env, "transfer");
+        client.execute(&target.address, &transfer, &args, &4, &0);
+
+        client.invalidate_nonces(&4, &9);
+        assert_eq!(env.auths()[0].0, client.get_owner());
+        assert_eq!(client.get_nonce(&4), 10);
+        assert_eq!(client.get_nonce(&0), 0);
+        assert_eq!(
+            client.try_execute(&target.address, &transfer, &args, &4, &9),
+            Err(Ok(ContractError::InvalidNonce))
+        );
+        client.execute(&target.address, &transfer, &args, &4, &10);
+
+        // Consumed nonces cannot be reopened.
+        assert_eq!(
+            client.try_invalidate_nonces(&4, &10),
+            Err(Ok(ContractError::InvalidNonce))
+        );
+        assert_eq!(
+            client.try_invalidate_nonces(&4, &u64::MAX),
+            Err(Ok(ContractError::InvalidNonce))
+        );
+        assert_eq!(client.get_nonce(&4), 11);
+    }
+}
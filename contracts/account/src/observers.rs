@@ -0,0 +1,250 @@
+//! Observer contracts notified of account activity.
+//!
+//! Contracts cannot reach off-chain services, but the account can call other
+//! contracts. The owner registers observer contracts with a bitmask of the
+//! event kinds they care about; each matching event triggers a synchronous
+//! `on_account_event(account, kind, data)` call. Notifications never revert
+//! the account operation: a failing observer is reported with an `obs_fail`
+//! event and otherwise ignored.
+
+use soroban_sdk::{contractclient, symbol_short, vec, Address, Env, IntoVal, Map, Symbol, Val};
+
+use crate::{events, ContractError, DataKey};
+
+/// A call was executed.
+pub const OBSERVE_EXECUTE: u32 = 1 << 0;
+/// An outgoing token transfer was executed.
+pub const OBSERVE_TRANSFER: u32 = 1 << 1;
+/// A session key was added or revoked.
+pub const OBSERVE_SESSION_KEY: u32 = 1 << 2;
+/// Ownership was recovered.
+pub const OBSERVE_RECOVERY: u32 = 1 << 3;
+
+/// Maximum number of registered observers, bounding the cost of each event.
+pub const MAX_OBSERVERS: u32 = 8;
+
+/// Interface observer contracts implement.
+#[contractclient(name = "ObserverClient")]
+pub trait Observer {
+    fn on_account_event(env: Env, account: Address, kind: u32, data: Val);
+}
+
+pub fn register(env: &Env, observer: &Address, event_mask: u32) -> Result<(), ContractError> {
+    let mut observers = all(env);
+    if !observers.contains_key(observer.clone()) && observers.len() >= MAX_OBSERVERS {
+        return Err(ContractError::TooManyObservers);
+    }
+
+    observers.set(observer.clone(), event_mask);
+    env.storage()
+        .instance()
+        .set(&DataKey::Observers, &observers);
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("obs_reg")),
+        (observer.clone(), event_mask),
+    );
+    Ok(())
+}
+
+pub fn unregister(env: &Env, observer: &Address) {
+    let mut observers = all(env);
+    if observers.remove(observer.clone()).is_some() {
+        env.storage()
+            .instance()
+            .set(&DataKey::Observers, &observers);
+        events::publish(
+            env,
+            (symbol_short!("ancore"), symbol_short!("obs_unreg")),
+            observer.clone(),
+        );
+    }
+}
+
+pub fn all(env: &Env) -> Map<Address, u32> {
+    env.storage()
+        .instance()
+        .get(&DataKey::Observers)
+        .unwrap_or_else(|| Map::new(env))
+}
+
+/// Notify every observer subscribed to `kind`, ignoring their failures.
+pub fn notify(env: &Env, kind: u32, data: impl IntoVal<Env, Val>) {
+    let observers = all(env);
+    if observers.is_empty() {
+        return;
+    }
+
+    let data: Val = data.into_val(env);
+    let args = vec![
+        env,
+        env.current_contract_address().into_val(env),
+        kind.into_val(env),
+        data,
+    ];
+    for (observer, mask) in observers.iter() {
+        if mask & kind == 0 {
+            continue;
+        }
+        let result = env.try_invoke_contract::<(), soroban_sdk::Error>(
+            &observer,
+            &Symbol::new(env, "on_account_event"),
+            args.clone(),
+        );
+        if result.is_err() {
+            events::publish(
+                env,
+                (symbol_short!("ancore"), symbol_short!("obs_fail")),
+                (observer, kind),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{testutils::register_target, AncoreAccount, AncoreAccountClient};
+    use soroban_sdk::{
+        contract, contractimpl,
+        testutils::{Address as _, Events as _},
+        TryFromVal, Vec,
+    };
+
+    #[contract]
+    struct RecordingObserver;
+
+    #[contractimpl]
+    impl RecordingObserver {
+        pub fn on_account_event(env: Env, _account: Address, kind: u32, _data: Val) {
+            let mut kinds = Self::kinds(env.clone());
+            kinds.push_back(kind);
+            env.storage()
+                .instance()
+                .set(&symbol_short!("kinds"), &kinds);
+        }
+
+        pub fn kinds(env: Env) -> Vec<u32> {
+            env.storage()
+                .instance()
+                .get(&symbol_short!("kinds"))
+                .unwrap_or_else(|| Vec::new(&env))
+        }
+    }
+
+    mod reverting {
+        use soroban_sdk::{contract, contractimpl, Address, Env, Val};
+
+        #[contract]
+        pub struct RevertingObserver;
+
+        #[contractimpl]
+        impl RevertingObserver {
+            pub fn on_account_event(_env: Env, _account: Address, _kind: u32, _data: Val) {
+                panic!("observer failed");
+            }
+        }
+    }
+
+    fn setup(env: &Env) -> AncoreAccountClient<'_> {
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(env, &contract_id);
+        client.initialize(&Address::generate(env));
+        client
+    }
+
+    fn recorded_kinds(env: &Env, observer: &Address) -> Vec<u32> {
+        RecordingObserverClient::new(env, observer).kinds()
+    }
+
+    #[test]
+    fn test_observer_notified_of_subscribed_events_only() {
+        let env = Env::default();
+        let client = setup(&env);
+        let observer = env.register_contract(None, RecordingObserver);
+        client.register_observer(&observer, &OBSERVE_SESSION_KEY);
+
+        let public_key = soroban_sdk::BytesN::from_array(&env, &[1u8; 32]);
+        client.add_session_key(&public_key, &1000, &Vec::new(&env));
+        client.execute(
+            &register_target(&env),
+            &symbol_short!("ping"),
+            &Vec::new(&env),
+            &0,
+            &0,
+        );
+        client.revoke_session_key(&public_key);
+
+        assert_eq!(
+            recorded_kinds(&env, &observer),
+            vec![&env, OBSERVE_SESSION_KEY, OBSERVE_SESSION_KEY]
+        );
+    }
+
+    #[test]
+    fn test_unregistered_observer_not_notified() {
+        let env = Env::default();
+        let client = setup(&env);
+        let observer = env.register_contract(None, RecordingObserver);
+        client.register_observer(&observer, &OBSERVE_EXECUTE);
+        client.unregister_observer(&observer);
+
+        client.execute(
+            &register_target(&env),
+            &symbol_short!("ping"),
+            &Vec::new(&env),
+            &0,
+            &0,
+        );
+
+        assert!(client.get_observers().is_empty());
+        assert!(recorded_kinds(&env, &observer).is_empty());
+    }
+
+    #[test]
+    fn test_failing_observer_does_not_block_account() {
+        let env = Env::default();
+        let client = setup(&env);
+        let reverting = env.register_contract(None, reverting::RevertingObserver);
+        client.register_observer(&reverting, &OBSERVE_EXECUTE);
+
+        assert!(client.execute(
+            &register_target(&env),
+            &symbol_short!("ping"),
+            &Vec::new(&env),
+            &0,
+            &0
+        ));
+        assert_eq!(client.get_nonce(&0), 1);
+
+        let (_, topics, data) = env.events().all().last().unwrap();
+        assert_eq!(
+            topics,
+            (symbol_short!("ancore"), symbol_short!("obs_fail")).into_val(&env)
+        );
+        assert_eq!(
+            <(Address, u32)>::try_from_val(&env, &data).unwrap(),
+            (reverting, OBSERVE_EXECUTE)
+        );
+    }
+
+    #[test]
+    fn test_observer_registry_is_bounded() {
+        let env = Env::default();
+        let client = setup(&env);
+        for _ in 0..MAX_OBSERVERS {
+            client.register_observer(&Address::generate(&env), &OBSERVE_EXECUTE);
+        }
+
+        assert_eq!(
+            client.try_register_observer(&Address::generate(&env), &OBSERVE_EXECUTE),
+            Err(Ok(ContractError::TooManyObservers))
+        );
+
+        // Updating the mask of a registered observer still works when full.
+        let (existing, _) = client.get_observers().iter().next().unwrap();
+        client.register_observer(&existing, &OBSERVE_TRANSFER);
+        assert_eq!(client.get_observers().get(existing), Some(OBSERVE_TRANSFER));
+    }
+}
@@ -0,0 +1,253 @@
+//! Two-step ownership transfer.
+//!
+//! The owner proposes a new owner, who must accept within
+//! [`OWNER_PROPOSAL_TTL`]; until then the current owner stays in control and
+//! can cancel. A mistyped address therefore never receives the account, it
+//! just lets the proposal lapse.
+
+use soroban_sdk::{contracttype, symbol_short, Address, Env};
+
+use crate::{
+    admin, backup, events, guardians, inheritance, multisig, registry, session, two_factor,
+    upgrade, ContractError, DataKey,
+};
+
+/// Seconds a proposed owner has to accept.
+pub const OWNER_PROPOSAL_TTL: u64 = 7 * 86_400;
+
+/// An ownership transfer waiting for the new owner to accept.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PendingOwner {
+    pub new_owner: Address,
+    pub expires_at: u64,
+}
+
+/// Propose `new_owner`, replacing any earlier proposal.
+pub fn propose(env: &Env, new_owner: &Address) {
+    let proposal = PendingOwner {
+        new_owner: new_owner.clone(),
+        expires_at: env.ledger().timestamp().saturating_add(OWNER_PROPOSAL_TTL),
+    };
+    env.storage()
+        .instance()
+        .set(&DataKey::PendingOwner, &proposal);
+
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("own_prop")),
+        (new_owner.clone(), proposal.expires_at),
+    );
+}
+
+pub fn pending(env: &Env) -> Option<PendingOwner> {
+    env.storage().instance().get(&DataKey::PendingOwner)
+}
+
+pub fn cancel(env: &Env) -> Result<(), ContractError> {
+    let proposal = pending(env).ok_or(ContractError::NoOwnerProposal)?;
+    env.storage().instance().remove(&DataKey::PendingOwner);
+
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("own_cncl")),
+        proposal.new_owner,
+    );
+    Ok(())
+}
+
+/// Complete the pending proposal with the proposed owner's auth, returning
+/// the new owner.
+pub fn accept(env: &Env) -> Result<Address, ContractError> {
+    let proposal = pending(env).ok_or(ContractError::NoOwnerProposal)?;
+    proposal.new_owner.require_auth();
+    if env.ledger().timestamp() >= proposal.expires_at {
+        return Err(ContractError::OwnerProposalExpired);
+    }
+
+    set_owner(env, &proposal.new_owner);
+    Ok(proposal.new_owner)
+}
+
+/// Make `new_owner` the owner, dropping state tied to the previous one.
+///
+/// Pending backup and guardian recoveries, owner proposals, guardian
+/// changes, admin operations, and upgrades are discarded, as are any
+/// multisig signer set and inheritance configuration. Every key the previous
+/// owner could have planted is dropped too: session keys are revoked (each
+/// with an `sk_revoke` event), and the second factor and backup key are
+/// cleared, so a recovery locks out whoever held the old owner key. Every
+/// owner change, whatever path it took, publishes one `owner` event.
+pub fn set_owner(env: &Env, new_owner: &Address) {
+    let storage = env.storage().instance();
+    let previous: Address = storage.get(&DataKey::Owner).unwrap();
+    storage.set(&DataKey::Owner, new_owner);
+    storage.remove(&DataKey::PendingOwner);
+    backup::discard(env);
+    guardians::discard(env);
+    guardians::discard_change(env);
+    admin::discard(env);
+    upgrade::discard(env);
+    multisig::clear(env);
+    inheritance::clear(env);
+    backup::clear_key_hash(env);
+    if two_factor::get(env).is_some() {
+        two_factor::disable(env);
+    }
+    for public_key in session::remove_all(env).iter() {
+        events::publish(
+            env,
+            (symbol_short!("ancore"), symbol_short!("sk_revoke")),
+            public_key,
+        );
+    }
+
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("owner")),
+        (previous, new_owner.clone()),
+    );
+    registry::notify(env);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        guardians::GUARDIAN_RECOVERY_DELAY, AccountSignature, AncoreAccount, AncoreAccountClient,
+        Permission, Signer,
+    };
+    use ed25519_dalek::{Signer as _, SigningKey};
+    use soroban_sdk::{
+        testutils::{Address as _, Ledger as _},
+        vec, BytesN, IntoVal, Vec,
+    };
+
+    fn setup(env: &Env) -> AncoreAccountClient<'_> {
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(env, &contract_id);
+        client.initialize(&Address::generate(env));
+        client
+    }
+
+    #[test]
+    fn test_two_step_ownership_transfer() {
+        let env = Env::default();
+        let client = setup(&env);
+        let old_owner = client.get_owner();
+        let new_owner = Address::generate(&env);
+
+        assert_eq!(
+            client.try_accept_ownership(),
+            Err(Ok(ContractError::NoOwnerProposal))
+        );
+
+        client.propose_owner(&new_owner);
+        assert_eq!(client.get_owner(), old_owner);
+        assert_eq!(
+            client.get_owner_proposal(),
+            Some(PendingOwner {
+                new_owner: new_owner.clone(),
+                expires_at: OWNER_PROPOSAL_TTL,
+            })
+        );
+
+        client.accept_ownership();
+        assert_eq!(env.auths()[0].0, new_owner);
+        assert_eq!(client.get_owner(), new_owner);
+        assert_eq!(client.get_owner_proposal(), None);
+    }
+
+    #[test]
+    fn test_owner_proposal_expires_or_is_cancelled() {
+        let env = Env::default();
+        let client = setup(&env);
+        let old_owner = client.get_owner();
+
+        client.propose_owner(&Address::generate(&env));
+        env.ledger()
+            .with_mut(|li| li.timestamp = OWNER_PROPOSAL_TTL);
+        assert_eq!(
+            client.try_accept_ownership(),
+            Err(Ok(ContractError::OwnerProposalExpired))
+        );
+
+        client.propose_owner(&Address::generate(&env));
+        client.cancel_owner_proposal();
+        assert_eq!(
+            client.try_accept_ownership(),
+            Err(Ok(ContractError::NoOwnerProposal))
+        );
+        assert_eq!(
+            client.try_cancel_owner_proposal(),
+            Err(Ok(ContractError::NoOwnerProposal))
+        );
+        assert_eq!(client.get_owner(), old_owner);
+    }
+
+    #[test]
+    fn test_ownership_transfer_leaves_multisig() {
+        let env = Env::default();
+        let client = setup(&env);
+        let signer = Signer {
+            key: BytesN::from_array(&env, &[1u8; 32]),
+            weight: 1,
+        };
+        client.set_multisig(&vec![&env, signer], &1);
+
+        let new_owner = Address::generate(&env);
+        client.propose_owner(&new_owner);
+        client.accept_ownership();
+
+        assert_eq!(client.get_owner(), new_owner);
+        assert!(client.get_signers().is_empty());
+        assert_eq!(client.get_threshold(), 0);
+    }
+
+    #[test]
+    fn test_guardian_recovery_revokes_planted_keys() {
+        let env = Env::default();
+        let client = setup(&env);
+        let guardians = [0, 1].map(|_| Address::generate(&env));
+        client.set_guardians(&Vec::from_array(&env, guardians.clone()), &2);
+
+        // Whoever stole the owner key plants a session key, a second factor
+        // and a backup key.
+        let planted = SigningKey::from_bytes(&[3u8; 32]);
+        let planted_pk = BytesN::from_array(&env, &planted.verifying_key().to_bytes());
+        let permissions = Vec::from_array(&env, [Permission::Execute]);
+        client.add_session_key(&planted_pk, &u64::MAX, &permissions);
+        client.set_two_factor(&planted_pk, &0);
+        client.set_backup_key_hash(&BytesN::from_array(&env, &[5u8; 32]));
+
+        let payload = BytesN::from_array(&env, &[9u8; 32]);
+        let check_auth = || {
+            let signature = AccountSignature {
+                public_key: planted_pk.clone(),
+                signature: BytesN::from_array(&env, &planted.sign(&payload.to_array()).to_bytes()),
+            };
+            env.try_invoke_contract_check_auth::<ContractError>(
+                &client.address,
+                &payload,
+                vec![&env, signature].into_val(&env),
+                &Vec::new(&env),
+            )
+            .map_err(|error| error.unwrap())
+        };
+        assert_eq!(check_auth(), Ok(()));
+
+        let new_owner = Address::generate(&env);
+        client.initiate_recovery(&guardians[0], &new_owner);
+        client.approve_recovery(&guardians[1]);
+        env.ledger()
+            .with_mut(|li| li.timestamp = GUARDIAN_RECOVERY_DELAY);
+        client.finalize_recovery();
+
+        assert_eq!(client.get_owner(), new_owner);
+        assert_eq!(check_auth(), Err(ContractError::UnknownSigner));
+        assert!(client.list_session_keys(&0, &10).is_empty());
+        assert_eq!(client.get_two_factor(), None);
+        assert_eq!(client.get_backup_key_hash(), None);
+    }
+}
@@ -0,0 +1,366 @@
+//! Fee sponsorship through a paymaster.
+//!
+//! A relayer pays the network fee for a sponsored call and is reimbursed by
+//! a paymaster contract on the account's behalf. The account authorizes the
+//! paymaster's `sponsor(account, relayer, fee)` itself, after the owner has
+//! approved the call together with the paymaster, relayer and fee, so a
+//! relayer can neither inflate its fee nor charge someone else's
+//! sponsorship.
+//!
+//! Instead of drawing on a sponsorship, the account can repay the fee in a
+//! token at the paymaster's quote. The owner caps the repayment, and the
+//! account authorizes the paymaster to take exactly the quoted amount.
+
+use soroban_sdk::{
+    auth::{ContractContext, InvokerContractAuthEntry, SubContractInvocation},
+    contractclient, symbol_short, vec, Address, Env, IntoVal, Val, Vec,
+};
+
+use crate::{events, ContractError};
+
+/// The part of the paymaster interface the account calls.
+#[contractclient(name = "PaymasterClient")]
+pub trait Paymaster {
+    fn sponsor(env: Env, account: Address, relayer: Address, fee: i128);
+    fn quote(env: Env, fee: i128, token: Address) -> i128;
+    fn sponsor_for_token(
+        env: Env,
+        account: Address,
+        relayer: Address,
+        fee: i128,
+        token: Address,
+    ) -> i128;
+}
+
+/// Have `paymaster` reimburse `relayer` the `fee` for the current call.
+///
+/// Panics, reverting the call, if the paymaster refuses.
+pub fn charge(env: &Env, paymaster: &Address, relayer: &Address, fee: i128) {
+    PaymasterClient::new(env, paymaster).sponsor(&env.current_contract_address(), relayer, &fee);
+
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("sponsored")),
+        (paymaster.clone(), relayer.clone(), fee),
+    );
+}
+
+/// How much of `token` `paymaster` wants for `fee`, failing with
+/// `QuoteExceeded` above `max_amount`.
+pub fn quote(
+    env: &Env,
+    paymaster: &Address,
+    fee: i128,
+    token: &Address,
+    max_amount: i128,
+) -> Result<i128, ContractError> {
+    let amount = PaymasterClient::new(env, paymaster).quote(&fee, token);
+    if amount > max_amount {
+        return Err(ContractError::QuoteExceeded);
+    }
+    Ok(amount)
+}
+
+/// Arguments of the token transfer that repays `paymaster` `amount`.
+pub fn repayment_args(env: &Env, paymaster: &Address, amount: i128) -> Vec<Val> {
+    vec![
+        env,
+        env.current_contract_address().into_val(env),
+        paymaster.into_val(env),
+        amount.into_val(env),
+    ]
+}
+
+/// Have `paymaster` reimburse `relayer` the `fee` for the current call,
+/// repaid with `amount` of `token`.
+///
+/// Panics, reverting the call, if the paymaster refuses or takes anything
+/// other than exactly that repayment.
+pub fn repay(
+    env: &Env,
+    paymaster: &Address,
+    relayer: &Address,
+    fee: i128,
+    token: &Address,
+    amount: i128,
+) {
+    env.authorize_as_current_contract(vec![
+        env,
+        InvokerContractAuthEntry::Contract(SubContractInvocation {
+            context: ContractContext {
+                contract: token.clone(),
+                fn_name: symbol_short!("transfer"),
+                args: repayment_args(env, paymaster, amount),
+            },
+            sub_invocations: Vec::new(env),
+        }),
+    ]);
+    PaymasterClient::new(env, paymaster).sponsor_for_token(
+        &env.current_contract_address(),
+        relayer,
+        &fee,
+        token,
+    );
+
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("repaid")),
+        (
+            paymaster.clone(),
+            relayer.clone(),
+            fee,
+            token.clone(),
+            amount,
+        ),
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        testutils::{register_target, MockTargetClient},
+        AncoreAccount, AncoreAccountClient, Call, ContractError,
+    };
+    use soroban_sdk::{
+        contract, contractimpl,
+        testutils::{Address as _, MockAuth, MockAuthInvoke},
+        token, Symbol,
+    };
+
+    /// Paymaster that records what it was asked to pay, up to a fee cap.
+    #[contract]
+    struct RecordingPaymaster;
+
+    #[contractimpl]
+    impl RecordingPaymaster {
+        pub fn sponsor(env: Env, account: Address, relayer: Address, fee: i128) {
+            account.require_auth();
+            if fee > 100 {
+                panic!("fee too high");
+            }
+            env.storage()
+                .instance()
+                .set(&symbol_short!("last"), &(account, relayer, fee));
+        }
+
+        pub fn last(env: Env) -> Option<(Address, Address, i128)> {
+            env.storage().instance().get(&symbol_short!("last"))
+        }
+
+        /// Quotes every fee at two units of any token.
+        pub fn quote(_env: Env, fee: i128, _token: Address) -> i128 {
+            fee * 2
+        }
+
+        pub fn sponsor_for_token(
+            env: Env,
+            account: Address,
+            relayer: Address,
+            fee: i128,
+            token: Address,
+        ) -> i128 {
+            account.require_auth();
+            let amount = fee * 2;
+            token::Client::new(&env, &token).transfer(
+                &account,
+                &env.current_contract_address(),
+                &amount,
+            );
+            env.storage()
+                .instance()
+                .set(&symbol_short!("last"), &(account, relayer, fee));
+            amount
+        }
+    }
+
+    struct Setup<'a> {
+        client: AncoreAccountClient<'a>,
+        paymaster: RecordingPaymasterClient<'a>,
+        target: MockTargetClient<'a>,
+        recipient: Address,
+        call: Call,
+    }
+
+    fn setup(env: &Env) -> Setup<'_> {
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(env, &contract_id);
+        client.initialize(&Address::generate(env));
+        let paymaster = env.register_contract(None, RecordingPaymaster);
+        let target = register_target(env);
+        let recipient = Address::generate(env);
+        let call = Call {
+            to: target.clone(),
+            function: Symbol::new(env, "transfer"),
+            args: vec![
+                env,
+                contract_id.into_val(env),
+                recipient.into_val(env),
+                5i128.into_val(env),
+            ],
+        };
+        Setup {
+            client,
+            paymaster: RecordingPaymasterClient::new(env, &paymaster),
+            target: MockTargetClient::new(env, &target),
+            recipient,
+            call,
+        }
+    }
+
+    #[test]
+    fn test_execute_sponsored_charges_paymaster() {
+        let env = Env::default();
+        let s = setup(&env);
+        let relayer = Address::generate(&env);
+
+        s.client
+            .execute_sponsored(&s.call, &0, &0, &s.paymaster.address, &relayer, &40);
+        assert_eq!(env.auths()[0].0, s.client.get_owner());
+        assert_eq!(s.client.get_nonce(&0), 1);
+        assert_eq!(s.target.received(&s.recipient), 5);
+        assert_eq!(
+            s.paymaster.last(),
+            Some((s.client.address.clone(), relayer, 40))
+        );
+    }
+
+    #[test]
+    fn test_execute_sponsored_in_token_repays_quote() {
+        let env = Env::default();
+        let s = setup(&env);
+        let relayer = Address::generate(&env);
+        let usdc = env.register_stellar_asset_contract_v2(Address::generate(&env));
+        let usdc_client = token::Client::new(&env, &usdc.address());
+        token::StellarAssetClient::new(&env, &usdc.address()).mint(&s.client.address, &1_000);
+
+        // Only the owner signs; the paymaster's pull is authorized by the
+        // account itself.
+        let owner = s.client.get_owner();
+        let args = (
+            s.call.clone(),
+            0u32,
+            0u64,
+            s.paymaster.address.clone(),
+            relayer.clone(),
+            40i128,
+            usdc.address(),
+            80i128,
+        );
+        env.mock_auths(&[MockAuth {
+            address: &owner,
+            invoke: &MockAuthInvoke {
+                contract: &s.client.address,
+                fn_name: "execute_sponsored_in_token",
+                args: args.into_val(&env),
+                sub_invokes: &[],
+            },
+        }]);
+        s.client.execute_sponsored_in_token(
+            &s.call,
+            &0,
+            &0,
+            &s.paymaster.address,
+            &relayer,
+            &40,
+            &usdc.address(),
+            &80,
+        );
+
+        assert_eq!(s.client.get_nonce(&0), 1);
+        assert_eq!(usdc_client.balance(&s.client.address), 920);
+        assert_eq!(usdc_client.balance(&s.paymaster.address), 80);
+        assert_eq!(
+            s.paymaster.last(),
+            Some((s.client.address.clone(), relayer, 40))
+        );
+    }
+
+    #[test]
+    fn test_repayment_shares_the_call_value_cap_and_rate_limit() {
+        let env = Env::default();
+        let s = setup(&env);
+        let relayer = Address::generate(&env);
+        let usdc = env.register_stellar_asset_contract_v2(Address::generate(&env));
+        token::StellarAssetClient::new(&env, &usdc.address()).mint(&s.client.address, &1_000);
+        let pay = |amount: i128| Call {
+            to: usdc.address(),
+            function: Symbol::new(&env, "transfer"),
+            args: vec![
+                &env,
+                s.client.address.into_val(&env),
+                s.recipient.into_val(&env),
+                amount.into_val(&env),
+            ],
+        };
+        s.client
+            .set_max_tx_value(&usdc.address(), &Address::generate(&env), &500);
+        s.client.set_rate_limit(&1, &10);
+
+        // 450 for the call and 80 for the fee together exceed the cap.
+        assert_eq!(
+            s.client.try_execute_sponsored_in_token(
+                &pay(450),
+                &0,
+                &0,
+                &s.paymaster.address,
+                &relayer,
+                &40,
+                &usdc.address(),
+                &80,
+            ),
+            Err(Ok(ContractError::TxValueExceeded))
+        );
+
+        // The call and its repayment count once against the rate limit.
+        s.client.execute_sponsored_in_token(
+            &pay(420),
+            &0,
+            &0,
+            &s.paymaster.address,
+            &relayer,
+            &40,
+            &usdc.address(),
+            &80,
+        );
+        assert_eq!(s.client.get_nonce(&0), 1);
+    }
+
+    #[test]
+    fn test_quote_above_max_amount_fails() {
+        let env = Env::default();
+        let s = setup(&env);
+        let usdc = env.register_stellar_asset_contract_v2(Address::generate(&env));
+
+        assert_eq!(
+            s.client.try_execute_sponsored_in_token(
+                &s.call,
+                &0,
+                &0,
+                &s.paymaster.address,
+                &Address::generate(&env),
+                &40,
+                &usdc.address(),
+                &79,
+            ),
+            Err(Ok(ContractError::QuoteExceeded))
+        );
+        assert_eq!(s.client.get_nonce(&0), 0);
+    }
+
+    #[test]
+    fn test_refused_sponsorship_reverts_call() {
+        let env = Env::default();
+        let s = setup(&env);
+        let relayer = Address::generate(&env);
+
+        let result =
+            s.client
+                .try_execute_sponsored(&s.call, &0, &0, &s.paymaster.address, &relayer, &500);
+        assert!(result.is_err());
+        assert_eq!(s.client.get_nonce(&0), 0);
+        assert_eq!(s.target.received(&s.recipient), 0);
+        assert_eq!(s.paymaster.last(), None);
+    }
+}
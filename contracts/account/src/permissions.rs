@@ -0,0 +1,60 @@
+//! Typed session key permissions.
+//!
+//! A session key holds a list of [`Permission`]s, each granting one kind of
+//! call. A call the key authorizes must be covered by at least one of them;
+//! restrictions set separately on the key (allowed contracts, spend limits,
+//! use counts) apply on top.
+
+use soroban_sdk::{contracttype, Address, Env, Symbol, Val, Vec};
+
+use crate::transfer;
+
+/// Something a session key is allowed to do.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Permission {
+    /// Make any call.
+    Execute,
+    /// Call any function on the given contract.
+    CallContract(Address),
+    /// Transfer at most the given amount of a token in one call.
+    Transfer(Address, i128),
+}
+
+/// Whether one of `permissions` allows the call.
+pub fn permits(
+    env: &Env,
+    permissions: &Vec<Permission>,
+    to: &Address,
+    function: &Symbol,
+    args: &Vec<Val>,
+) -> bool {
+    let transfer = transfer::detect(env, to, function, args);
+    permissions.iter().any(|permission| match permission {
+        Permission::Execute => true,
+        Permission::CallContract(contract) => contract == *to,
+        Permission::Transfer(token, max_amount) => transfer
+            .as_ref()
+            .is_some_and(|transfer| transfer.token == token && transfer.amount <= max_amount),
+    })
+}
+
+/// Whether everything `permission` allows is also allowed by `parent`.
+pub fn covers(parent: &Permission, permission: &Permission) -> bool {
+    match (parent, permission) {
+        (Permission::Execute, _) => true,
+        (Permission::CallContract(contract), Permission::CallContract(other)) => contract == other,
+        (Permission::CallContract(contract), Permission::Transfer(token, _)) => contract == token,
+        (Permission::Transfer(token, max_amount), Permission::Transfer(other, amount)) => {
+            token == other && amount <= max_amount
+        }
+        _ => false,
+    }
+}
+
+/// Whether every permission in `proposed` is covered by one in `parent`.
+pub fn is_subset(parent: &Vec<Permission>, proposed: &Vec<Permission>) -> bool {
+    proposed
+        .iter()
+        .all(|permission| parent.iter().any(|granted| covers(&granted, &permission)))
+}
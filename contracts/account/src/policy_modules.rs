@@ -0,0 +1,264 @@
+//! External policy contracts consulted before every call.
+//!
+//! The built-in policies cover common needs, but each new kind would
+//! otherwise take an upgrade of the account. The owner can instead register
+//! policy module contracts implementing [`PolicyModule`]. Before the account
+//! makes any call, whoever authorized it, each module's `validate` is
+//! called with a [`CallContext`] describing it. The call only goes ahead if
+//! every module returns `true`: a module that returns `false` or fails
+//! rejects it with `PolicyRejected`, so a broken module can never be
+//! skipped by making it fail.
+//!
+//! Registering and unregistering modules are admin operations: a module
+//! sees every call the account makes and can block them all, and removing
+//! one lifts its restrictions. A module that rejects everything still
+//! leaves the owner able to unregister it, as that makes no call.
+
+use soroban_sdk::{
+    contractclient, contracttype, symbol_short, vec, Address, Env, IntoVal, Symbol, Val, Vec,
+};
+
+use crate::{events, ContractError, DataKey};
+
+/// Maximum number of registered policy modules, bounding the cost of each
+/// call.
+pub const MAX_POLICY_MODULES: u32 = 8;
+
+/// A call the account is about to make, as shown to policy modules.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CallContext {
+    /// The account making the call
+    pub account: Address,
+    pub to: Address,
+    pub function: Symbol,
+    pub args: Vec<Val>,
+}
+
+/// Interface policy module contracts implement.
+#[contractclient(name = "PolicyModuleClient")]
+pub trait PolicyModule {
+    /// Whether the account may make the call described by `ctx`.
+    fn validate(env: Env, ctx: CallContext) -> bool;
+}
+
+pub fn all(env: &Env) -> Vec<Address> {
+    env.storage()
+        .instance()
+        .get(&DataKey::PolicyModules)
+        .unwrap_or_else(|| Vec::new(env))
+}
+
+pub fn register(env: &Env, module: &Address) -> Result<(), ContractError> {
+    let mut modules = all(env);
+    if modules.contains(module) {
+        return Ok(());
+    }
+    if modules.len() >= MAX_POLICY_MODULES {
+        return Err(ContractError::TooManyPolicyModules);
+    }
+
+    modules.push_back(module.clone());
+    env.storage()
+        .instance()
+        .set(&DataKey::PolicyModules, &modules);
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("pol_reg")),
+        module.clone(),
+    );
+    Ok(())
+}
+
+pub fn unregister(env: &Env, module: &Address) {
+    let mut modules = all(env);
+    if let Some(index) = modules.first_index_of(module) {
+        modules.remove(index);
+        env.storage()
+            .instance()
+            .set(&DataKey::PolicyModules, &modules);
+        events::publish(
+            env,
+            (symbol_short!("ancore"), symbol_short!("pol_unreg")),
+            module.clone(),
+        );
+    }
+}
+
+/// Ask every module whether the account may call `function` on `to`.
+pub fn check(
+    env: &Env,
+    to: &Address,
+    function: &Symbol,
+    args: &Vec<Val>,
+) -> Result<(), ContractError> {
+    let modules = all(env);
+    if modules.is_empty() {
+        return Ok(());
+    }
+
+    let ctx = CallContext {
+        account: env.current_contract_address(),
+        to: to.clone(),
+        function: function.clone(),
+        args: args.clone(),
+    };
+    let validate_args = vec![env, ctx.into_val(env)];
+    for module in modules.iter() {
+        let result = env.try_invoke_contract::<bool, soroban_sdk::Error>(
+            &module,
+            &Symbol::new(env, "validate"),
+            validate_args.clone(),
+        );
+        if result != Ok(Ok(true)) {
+            return Err(ContractError::PolicyRejected);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        testutils::{register_target, MockTargetClient},
+        AdminOp, AncoreAccount, AncoreAccountClient, Permission,
+    };
+    use ed25519_dalek::{Signer as _, SigningKey};
+    use soroban_sdk::{
+        contract, contractimpl,
+        testutils::{Address as _, Ledger as _},
+        BytesN,
+    };
+
+    /// Rejects calls to `ping` and remembers the last context it saw.
+    #[contract]
+    struct NoPing;
+
+    #[contractimpl]
+    impl NoPing {
+        pub fn validate(env: Env, ctx: CallContext) -> bool {
+            let allowed = ctx.function != Symbol::new(&env, "ping");
+            env.storage().instance().set(&symbol_short!("last"), &ctx);
+            allowed
+        }
+
+        pub fn last(env: Env) -> Option<CallContext> {
+            env.storage().instance().get(&symbol_short!("last"))
+        }
+    }
+
+    fn setup(env: &Env) -> (AncoreAccountClient<'_>, MockTargetClient<'_>) {
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(env, &contract_id);
+        client.initialize(&Address::generate(env));
+        (client, MockTargetClient::new(env, &register_target(env)))
+    }
+
+    fn transfer_args(env: &Env, client: &AncoreAccountClient, to: &Address) -> Vec<Val> {
+        vec![
+            env,
+            client.address.into_val(env),
+            to.into_val(env),
+            3i128.into_val(env),
+        ]
+    }
+
+    #[test]
+    fn test_policy_module_validates_every_call() {
+        let env = Env::default();
+        let (client, target) = setup(&env);
+        let module = env.register_contract(None, NoPing);
+        client.register_policy_module(&module);
+        assert_eq!(client.get_policy_modules(), vec![&env, module.clone()]);
+
+        let ping = Symbol::new(&env, "ping");
+        assert_eq!(
+            client.try_execute(&target.address, &ping, &Vec::new(&env), &0, &0),
+            Err(Ok(ContractError::PolicyRejected))
+        );
+
+        let transfer = Symbol::new(&env, "transfer");
+        let args = transfer_args(&env, &client, &target.address);
+        client.execute(&target.address, &transfer, &args, &0, &0);
+        assert_eq!(
+            NoPingClient::new(&env, &module).last(),
+            Some(CallContext {
+                account: client.address.clone(),
+                to: target.address.clone(),
+                function: transfer,
+                args,
+            })
+        );
+
+        // Session keys are checked too.
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let session_pk = BytesN::from_array(&env, &key.verifying_key().to_bytes());
+        client.add_session_key(&session_pk, &1000, &vec![&env, Permission::Execute]);
+        let payload = client.get_session_payload(&target.address, &ping, &Vec::new(&env), &0);
+        let signature = BytesN::from_array(&env, &key.sign(&payload.to_array()).to_bytes());
+        assert_eq!(
+            client.try_execute_with_session(
+                &session_pk,
+                &signature,
+                &target.address,
+                &ping,
+                &Vec::new(&env),
+                &0,
+            ),
+            Err(Ok(ContractError::PolicyRejected))
+        );
+    }
+
+    #[test]
+    fn test_failing_policy_module_rejects_calls() {
+        let env = Env::default();
+        let (client, target) = setup(&env);
+        // The mock target has no `validate`, so consulting it fails.
+        client.register_policy_module(&register_target(&env));
+
+        let args = transfer_args(&env, &client, &target.address);
+        assert_eq!(
+            client.try_execute(
+                &target.address,
+                &Symbol::new(&env, "transfer"),
+                &args,
+                &0,
+                &0
+            ),
+            Err(Ok(ContractError::PolicyRejected))
+        );
+    }
+
+    #[test]
+    fn test_policy_modules_are_bounded_and_changes_are_timelocked() {
+        let env = Env::default();
+        let (client, _) = setup(&env);
+        let first = Address::generate(&env);
+        for _ in 0..MAX_POLICY_MODULES - 1 {
+            client.register_policy_module(&Address::generate(&env));
+        }
+        client.register_policy_module(&first);
+        client.register_policy_module(&first);
+        assert_eq!(client.get_policy_modules().len(), MAX_POLICY_MODULES);
+        assert_eq!(
+            client.try_register_policy_module(&Address::generate(&env)),
+            Err(Ok(ContractError::TooManyPolicyModules))
+        );
+
+        client.set_admin_delay(&10);
+        assert_eq!(
+            client.try_register_policy_module(&first),
+            Err(Ok(ContractError::AdminOpTimelocked))
+        );
+        assert_eq!(
+            client.try_unregister_policy_module(&first),
+            Err(Ok(ContractError::AdminOpTimelocked))
+        );
+        client.queue_admin_op(&AdminOp::UnregisterPolicyModule(first.clone()));
+        env.ledger().with_mut(|li| li.sequence_number += 10);
+        client.execute_admin_op();
+        assert!(!client.get_policy_modules().contains(&first));
+    }
+}
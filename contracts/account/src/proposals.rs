@@ -0,0 +1,437 @@
+//! On-chain transaction proposals for the owner's signers.
+//!
+//! Signers who cannot gather signatures off-chain can coordinate here
+//! instead: one signer proposes a call, the others approve or reject it one
+//! at a time with their own signatures, and anyone can execute it once the
+//! approving weight meets the threshold. Votes are counted against the
+//! signer set at execution time, so a removed signer's approval no longer
+//! counts. A proposal that enough signers reject to make the threshold
+//! unreachable is dropped.
+//!
+//! Proposals need multisig: without it the account has no configured
+//! signers, so nobody can propose or vote.
+//!
+//! Under an escalation policy, token transfer proposals need as many
+//! signers as their amount calls for instead of the threshold; see the
+//! `escalation` module.
+
+use soroban_sdk::{contracttype, symbol_short, xdr::ToXdr, Bytes, BytesN, Env, Vec};
+
+use crate::{
+    escalation, escalation::Escalation, events, multisig, Call, ContractError, DataKey, Signer,
+};
+
+const APPROVE_DOMAIN: &[u8] = b"ancore-proposal-approve";
+const REJECT_DOMAIN: &[u8] = b"ancore-proposal-reject";
+
+/// A call waiting for enough signers to approve it.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TxProposal {
+    pub call: Call,
+    pub approvals: Vec<BytesN<32>>,
+    pub rejections: Vec<BytesN<32>>,
+}
+
+/// Hash a signer signs to approve (or propose) `call` as proposal `id`.
+///
+/// `sha256("ancore-proposal-approve" || account || network_id || id || call)`,
+/// with the account and call XDR-encoded, the 32-byte network id, and the id
+/// as 8 big-endian bytes.
+pub fn approval_payload(env: &Env, id: u64, call: &Call) -> BytesN<32> {
+    let mut preimage = Bytes::from_slice(env, APPROVE_DOMAIN);
+    preimage.append(&env.current_contract_address().to_xdr(env));
+    preimage.append(&Bytes::from(env.ledger().network_id()));
+    preimage.extend_from_array(&id.to_be_bytes());
+    preimage.append(&call.clone().to_xdr(env));
+    env.crypto().sha256(&preimage).into()
+}
+
+/// Hash a signer signs to reject proposal `id`.
+///
+/// `sha256("ancore-proposal-reject" || account || network_id || id)`.
+pub fn rejection_payload(env: &Env, id: u64) -> BytesN<32> {
+    let mut preimage = Bytes::from_slice(env, REJECT_DOMAIN);
+    preimage.append(&env.current_contract_address().to_xdr(env));
+    preimage.append(&Bytes::from(env.ledger().network_id()));
+    preimage.extend_from_array(&id.to_be_bytes());
+    env.crypto().sha256(&preimage).into()
+}
+
+pub fn get(env: &Env, id: u64) -> Option<TxProposal> {
+    env.storage().persistent().get(&DataKey::Proposal(id))
+}
+
+/// Id the next proposal will get.
+pub fn next_id(env: &Env) -> u64 {
+    env.storage()
+        .instance()
+        .get(&DataKey::NextProposalId)
+        .unwrap_or(0)
+}
+
+/// Record `call` as a new proposal, approved by the signer proposing it.
+pub fn propose(
+    env: &Env,
+    signer: &BytesN<32>,
+    signature: &BytesN<64>,
+    call: &Call,
+) -> Result<u64, ContractError> {
+    let id = next_id(env);
+    verify_signer(env, signer, &approval_payload(env, id, call), signature)?;
+
+    let proposal = TxProposal {
+        call: call.clone(),
+        approvals: Vec::from_array(env, [signer.clone()]),
+        rejections: Vec::new(env),
+    };
+    env.storage()
+        .persistent()
+        .set(&DataKey::Proposal(id), &proposal);
+    env.storage()
+        .persistent()
+        .set(&DataKey::ProposalLedger(id), &env.ledger().sequence());
+    env.storage()
+        .instance()
+        .set(&DataKey::NextProposalId, &(id + 1));
+
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("tx_prop"), id),
+        (signer.clone(), call.clone()),
+    );
+    Ok(id)
+}
+
+pub fn approve(
+    env: &Env,
+    id: u64,
+    signer: &BytesN<32>,
+    signature: &BytesN<64>,
+) -> Result<(), ContractError> {
+    let mut proposal = get(env, id).ok_or(ContractError::UnknownProposal)?;
+    check_not_voted(&proposal, signer)?;
+    verify_signer(
+        env,
+        signer,
+        &approval_payload(env, id, &proposal.call),
+        signature,
+    )?;
+
+    proposal.approvals.push_back(signer.clone());
+    env.storage()
+        .persistent()
+        .set(&DataKey::Proposal(id), &proposal);
+
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("tx_appr"), id),
+        signer.clone(),
+    );
+    Ok(())
+}
+
+/// Record a rejection, dropping the proposal once it can no longer pass.
+pub fn reject(
+    env: &Env,
+    id: u64,
+    signer: &BytesN<32>,
+    signature: &BytesN<64>,
+) -> Result<(), ContractError> {
+    let mut proposal = get(env, id).ok_or(ContractError::UnknownProposal)?;
+    check_not_voted(&proposal, signer)?;
+    verify_signer(env, signer, &rejection_payload(env, id), signature)?;
+
+    proposal.rejections.push_back(signer.clone());
+    let (signers, threshold) = multisig::owner_signers(env);
+    let reachable = weight(&signers, |key| !proposal.rejections.contains(key));
+    if reachable < threshold {
+        remove(env, id);
+    } else {
+        env.storage()
+            .persistent()
+            .set(&DataKey::Proposal(id), &proposal);
+    }
+
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("tx_rej"), id),
+        signer.clone(),
+    );
+    Ok(())
+}
+
+/// Remove an approved proposal so its call can be made, failing with
+/// `ThresholdNotMet` while the approvals fall short.
+///
+/// A transfer escalated to the full quorum also fails with
+/// `TimelockNotExpired` until its timelock has passed.
+pub fn take_approved(env: &Env, id: u64) -> Result<TxProposal, ContractError> {
+    let proposal = get(env, id).ok_or(ContractError::UnknownProposal)?;
+    let (signers, threshold) = multisig::owner_signers(env);
+    let approved = |key: &BytesN<32>| proposal.approvals.contains(key);
+    match escalation::for_proposal(env, &proposal.call) {
+        None => {
+            if weight(&signers, approved) < threshold {
+                return Err(ContractError::ThresholdNotMet);
+            }
+        }
+        Some(Escalation::SingleSigner) => {
+            if count(&signers, approved) == 0 {
+                return Err(ContractError::ThresholdNotMet);
+            }
+            if weight(&signers, approved) < threshold {
+                escalation::record_single_signer(env, &proposal.call)?;
+            }
+        }
+        Some(Escalation::Signers(needed)) => {
+            if count(&signers, approved) < needed || weight(&signers, approved) < threshold {
+                return Err(ContractError::ThresholdNotMet);
+            }
+        }
+        Some(Escalation::FullQuorum(timelock_ledgers)) => {
+            if count(&signers, approved) < signers.len() {
+                return Err(ContractError::ThresholdNotMet);
+            }
+            // Proposals made before escalation existed have no ledger and
+            // must be made again.
+            let proposed_at: Option<u32> =
+                env.storage().persistent().get(&DataKey::ProposalLedger(id));
+            let unlocked_at = proposed_at.map(|ledger| ledger.saturating_add(timelock_ledgers));
+            if unlocked_at.map_or(true, |ledger| env.ledger().sequence() < ledger) {
+                return Err(ContractError::TimelockNotExpired);
+            }
+        }
+    }
+
+    remove(env, id);
+    Ok(proposal)
+}
+
+fn remove(env: &Env, id: u64) {
+    env.storage().persistent().remove(&DataKey::Proposal(id));
+    env.storage()
+        .persistent()
+        .remove(&DataKey::ProposalLedger(id));
+}
+
+fn check_not_voted(proposal: &TxProposal, signer: &BytesN<32>) -> Result<(), ContractError> {
+    if proposal.approvals.contains(signer) || proposal.rejections.contains(signer) {
+        return Err(ContractError::DuplicateSigner);
+    }
+    Ok(())
+}
+
+/// Check `signer` is an owner signer and signed `payload`.
+///
+/// A bad signature fails the call outright, as `ed25519_verify` traps.
+fn verify_signer(
+    env: &Env,
+    signer: &BytesN<32>,
+    payload: &BytesN<32>,
+    signature: &BytesN<64>,
+) -> Result<(), ContractError> {
+    if !multisig::is_owner_key(env, signer) {
+        return Err(ContractError::UnknownSigner);
+    }
+    env.crypto()
+        .ed25519_verify(signer, &payload.clone().into(), signature);
+    Ok(())
+}
+
+/// Number of signers whose key passes `counts`.
+fn count(signers: &Vec<Signer>, counts: impl Fn(&BytesN<32>) -> bool) -> u32 {
+    signers.iter().filter(|signer| counts(&signer.key)).count() as u32
+}
+
+/// Total weight of the signers whose key passes `counts`.
+fn weight(signers: &Vec<Signer>, counts: impl Fn(&BytesN<32>) -> bool) -> u32 {
+    signers
+        .iter()
+        .filter(|signer| counts(&signer.key))
+        .fold(0u32, |total, signer| total.saturating_add(signer.weight))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutils::{register_target, MockTargetClient};
+    use crate::{AncoreAccount, AncoreAccountClient};
+    use ed25519_dalek::{Signer as _, SigningKey};
+    use soroban_sdk::{
+        testutils::{Address as _, Ledger as _},
+        vec, Address, IntoVal, Symbol,
+    };
+
+    struct Setup<'a> {
+        env: Env,
+        client: AncoreAccountClient<'a>,
+        keys: [SigningKey; 3],
+        target: Address,
+    }
+
+    /// An account owned by three weight-1 signers with threshold 2.
+    fn setup<'a>() -> Setup<'a> {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(&env, &contract_id);
+        client.initialize(&Address::generate(&env));
+        let keys = [1u8, 2, 3].map(|seed| SigningKey::from_bytes(&[seed; 32]));
+        let signers = Vec::from_array(
+            &env,
+            keys.clone().map(|key| Signer {
+                key: public_key(&env, &key),
+                weight: 1,
+            }),
+        );
+        client.set_multisig(&signers, &2);
+        let target = register_target(&env);
+        Setup {
+            env,
+            client,
+            keys,
+            target,
+        }
+    }
+
+    fn public_key(env: &Env, key: &SigningKey) -> BytesN<32> {
+        BytesN::from_array(env, &key.verifying_key().to_bytes())
+    }
+
+    fn sign(env: &Env, key: &SigningKey, payload: &BytesN<32>) -> BytesN<64> {
+        BytesN::from_array(env, &key.sign(&payload.to_array()).to_bytes())
+    }
+
+    fn transfer_call(s: &Setup, to: &Address, amount: i128) -> Call {
+        Call {
+            to: s.target.clone(),
+            function: Symbol::new(&s.env, "transfer"),
+            args: vec![
+                &s.env,
+                s.client.address.into_val(&s.env),
+                to.into_val(&s.env),
+                amount.into_val(&s.env),
+            ],
+        }
+    }
+
+    fn propose(s: &Setup, key: &SigningKey, call: &Call) -> u64 {
+        let id = s.client.get_next_proposal_id();
+        let payload = s.client.get_proposal_payload(&id, call);
+        s.client
+            .propose_tx(&public_key(&s.env, key), &sign(&s.env, key, &payload), call)
+    }
+
+    fn approve(s: &Setup, key: &SigningKey, id: u64) -> Result<(), ContractError> {
+        let call = s.client.get_proposal(&id).unwrap().call;
+        let payload = s.client.get_proposal_payload(&id, &call);
+        s.client
+            .try_approve_tx(&id, &public_key(&s.env, key), &sign(&s.env, key, &payload))
+            .map(|_| ())
+            .map_err(|error| error.unwrap())
+    }
+
+    fn reject(s: &Setup, key: &SigningKey, id: u64) -> Result<(), ContractError> {
+        let payload = s.client.get_rejection_payload(&id);
+        s.client
+            .try_reject_tx(&id, &public_key(&s.env, key), &sign(&s.env, key, &payload))
+            .map(|_| ())
+            .map_err(|error| error.unwrap())
+    }
+
+    #[test]
+    fn test_proposal_executes_once_approved() {
+        let s = setup();
+        let [a, b, _] = &s.keys;
+        let recipient = Address::generate(&s.env);
+        let call = transfer_call(&s, &recipient, 100);
+
+        let id = propose(&s, a, &call);
+        assert_eq!(id, 0);
+        assert_eq!(s.client.get_next_proposal_id(), 1);
+        assert_eq!(
+            s.client.try_execute_proposal(&id),
+            Err(Ok(ContractError::ThresholdNotMet))
+        );
+        assert_eq!(approve(&s, a, id), Err(ContractError::DuplicateSigner));
+
+        approve(&s, b, id).unwrap();
+        assert_eq!(
+            s.client.get_proposal(&id).unwrap().approvals,
+            vec![&s.env, public_key(&s.env, a), public_key(&s.env, b)]
+        );
+        assert!(s.client.execute_proposal(&id));
+
+        let target = MockTargetClient::new(&s.env, &s.target);
+        assert_eq!(target.received(&recipient), 100);
+        assert_eq!(s.client.get_proposal(&id), None);
+        assert_eq!(
+            s.client.try_execute_proposal(&id),
+            Err(Ok(ContractError::UnknownProposal))
+        );
+    }
+
+    #[test]
+    fn test_proposal_payloads_bind_network() {
+        let s = setup();
+        let call = transfer_call(&s, &Address::generate(&s.env), 10);
+        let approval = s.client.get_proposal_payload(&0, &call);
+        let rejection = s.client.get_rejection_payload(&0);
+
+        // The same account address on another network needs new signatures.
+        s.env.ledger().with_mut(|li| li.network_id = [9u8; 32]);
+        assert_ne!(s.client.get_proposal_payload(&0, &call), approval);
+        assert_ne!(s.client.get_rejection_payload(&0), rejection);
+    }
+
+    #[test]
+    fn test_rejected_proposal_is_dropped() {
+        let s = setup();
+        let [a, b, c] = &s.keys;
+        let call = transfer_call(&s, &Address::generate(&s.env), 100);
+        let id = propose(&s, a, &call);
+
+        reject(&s, b, id).unwrap();
+        assert_eq!(
+            s.client.get_proposal(&id).unwrap().rejections,
+            vec![&s.env, public_key(&s.env, b)]
+        );
+        assert_eq!(reject(&s, b, id), Err(ContractError::DuplicateSigner));
+
+        reject(&s, c, id).unwrap();
+        assert_eq!(s.client.get_proposal(&id), None);
+        assert_eq!(
+            s.client.try_approve_tx(
+                &id,
+                &public_key(&s.env, a),
+                &BytesN::from_array(&s.env, &[0u8; 64])
+            ),
+            Err(Ok(ContractError::UnknownProposal))
+        );
+    }
+
+    #[test]
+    fn test_only_current_signers_vote() {
+        let s = setup();
+        let [a, b, c] = &s.keys;
+        let call = transfer_call(&s, &Address::generate(&s.env), 100);
+        let id = propose(&s, a, &call);
+
+        let outsider = SigningKey::from_bytes(&[4u8; 32]);
+        assert_eq!(
+            approve(&s, &outsider, id),
+            Err(ContractError::UnknownSigner)
+        );
+
+        approve(&s, b, id).unwrap();
+        s.client.remove_signer(&public_key(&s.env, b));
+        assert_eq!(
+            s.client.try_execute_proposal(&id),
+            Err(Ok(ContractError::ThresholdNotMet))
+        );
+
+        approve(&s, c, id).unwrap();
+        assert!(s.client.execute_proposal(&id));
+    }
+}
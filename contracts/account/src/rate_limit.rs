@@ -0,0 +1,233 @@
+//! Cap on how often the account can make calls.
+//!
+//! Spend limits bound how much leaves the account, but not how fast a
+//! compromised automated key can fire calls at it. With a rate limit set,
+//! the account makes at most `max_executions` outgoing calls in any
+//! `window_ledgers` consecutive ledgers, whoever authorized them. Every
+//! call counts: each call of a batch and owner-signed auth of another
+//! contract. A token fee repayment is part of the call it pays for and is
+//! not counted again.
+//!
+//! The window is rolling: the account keeps the ledger of each recent call,
+//! so a burst straddling a window boundary is still capped. That history
+//! never holds more than `max_executions` entries, which is bounded by
+//! [`RATE_LIMIT_MAX_EXECUTIONS`].
+
+use soroban_sdk::{contracttype, symbol_short, Env, Vec};
+
+use crate::{events, ContractError, DataKey};
+
+/// Largest `max_executions` an owner can configure.
+pub const RATE_LIMIT_MAX_EXECUTIONS: u32 = 100;
+
+/// At most `max_executions` calls in any `window_ledgers` ledgers.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RateLimit {
+    pub max_executions: u32,
+    pub window_ledgers: u32,
+}
+
+pub fn get(env: &Env) -> Option<RateLimit> {
+    env.storage().instance().get(&DataKey::RateLimit)
+}
+
+/// Set the rate limit; calls already made still count towards it.
+pub fn set(env: &Env, max_executions: u32, window_ledgers: u32) -> Result<(), ContractError> {
+    if max_executions == 0 || max_executions > RATE_LIMIT_MAX_EXECUTIONS || window_ledgers == 0 {
+        return Err(ContractError::InvalidLimit);
+    }
+
+    env.storage().instance().set(
+        &DataKey::RateLimit,
+        &RateLimit {
+            max_executions,
+            window_ledgers,
+        },
+    );
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("rate_set")),
+        (max_executions, window_ledgers),
+    );
+    Ok(())
+}
+
+/// Remove the rate limit and forget recent calls.
+pub fn clear(env: &Env) {
+    env.storage().instance().remove(&DataKey::RateLimit);
+    env.storage().instance().remove(&DataKey::RecentExecutions);
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("rate_off")),
+        (),
+    );
+}
+
+/// Count a call, failing with `RateLimited` if the window is already full.
+pub fn record(env: &Env) -> Result<(), ContractError> {
+    let Some(limit) = get(env) else {
+        return Ok(());
+    };
+
+    let now = env.ledger().sequence();
+    let mut recent: Vec<u32> = env
+        .storage()
+        .instance()
+        .get(&DataKey::RecentExecutions)
+        .unwrap_or(Vec::new(env));
+    // Oldest first, so calls that left the window are at the front.
+    while let Some(ledger) = recent.first() {
+        if now.saturating_sub(ledger) < limit.window_ledgers {
+            break;
+        }
+        recent.pop_front();
+    }
+    if recent.len() >= limit.max_executions {
+        return Err(ContractError::RateLimited);
+    }
+
+    recent.push_back(now);
+    env.storage()
+        .instance()
+        .set(&DataKey::RecentExecutions, &recent);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        testutils::{register_target, MockTargetClient},
+        AncoreAccount, AncoreAccountClient, Call, Permission,
+    };
+    use ed25519_dalek::{Signer as _, SigningKey};
+    use soroban_sdk::{
+        testutils::{Address as _, Ledger as _},
+        vec, Address, BytesN, Symbol,
+    };
+
+    fn setup(env: &Env) -> (AncoreAccountClient<'_>, MockTargetClient<'_>) {
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(env, &contract_id);
+        client.initialize(&Address::generate(env));
+        (client, MockTargetClient::new(env, &register_target(env)))
+    }
+
+    fn ping(env: &Env, client: &AncoreAccountClient, target: &Address, nonce: u64) -> bool {
+        client
+            .try_execute(
+                target,
+                &Symbol::new(env, "ping"),
+                &Vec::new(env),
+                &0,
+                &nonce,
+            )
+            .is_ok()
+    }
+
+    fn advance(env: &Env, ledgers: u32) {
+        env.ledger().with_mut(|li| li.sequence_number += ledgers);
+    }
+
+    #[test]
+    fn test_rate_limit_caps_calls_in_rolling_window() {
+        let env = Env::default();
+        let (client, target) = setup(&env);
+        client.set_rate_limit(&2, &10);
+        assert_eq!(
+            client.get_rate_limit(),
+            Some(RateLimit {
+                max_executions: 2,
+                window_ledgers: 10,
+            })
+        );
+
+        assert!(ping(&env, &client, &target.address, 0));
+        advance(&env, 5);
+        assert!(ping(&env, &client, &target.address, 1));
+        assert_eq!(
+            client.try_execute(
+                &target.address,
+                &Symbol::new(&env, "ping"),
+                &Vec::new(&env),
+                &0,
+                &2,
+            ),
+            Err(Ok(ContractError::RateLimited))
+        );
+
+        // The first call leaves the window, the second is still in it.
+        advance(&env, 5);
+        assert!(ping(&env, &client, &target.address, 2));
+        assert!(!ping(&env, &client, &target.address, 3));
+
+        client.clear_rate_limit();
+        assert_eq!(client.get_rate_limit(), None);
+        assert!(ping(&env, &client, &target.address, 3));
+    }
+
+    #[test]
+    fn test_rate_limit_counts_each_batched_call() {
+        let env = Env::default();
+        let (client, target) = setup(&env);
+        client.set_rate_limit(&2, &10);
+        let call = Call {
+            to: target.address.clone(),
+            function: Symbol::new(&env, "ping"),
+            args: Vec::new(&env),
+        };
+
+        assert_eq!(
+            client.try_execute_batch(&vec![&env, call.clone(), call.clone(), call], &0, &0),
+            Err(Ok(ContractError::RateLimited))
+        );
+        // The failed batch counted nothing.
+        assert!(ping(&env, &client, &target.address, 0));
+        assert!(ping(&env, &client, &target.address, 1));
+    }
+
+    #[test]
+    fn test_rate_limit_applies_to_session_keys() {
+        let env = Env::default();
+        let (client, target) = setup(&env);
+        client.set_rate_limit(&1, &10);
+        assert!(ping(&env, &client, &target.address, 0));
+
+        let key = SigningKey::from_bytes(&[5u8; 32]);
+        let session_pk = BytesN::from_array(&env, &key.verifying_key().to_bytes());
+        client.add_session_key(&session_pk, &1000, &vec![&env, Permission::Execute]);
+        let ping = Symbol::new(&env, "ping");
+        let payload = client.get_session_payload(&target.address, &ping, &Vec::new(&env), &0);
+        let signature = BytesN::from_array(&env, &key.sign(&payload.to_array()).to_bytes());
+
+        assert_eq!(
+            client.try_execute_with_session(
+                &session_pk,
+                &signature,
+                &target.address,
+                &ping,
+                &Vec::new(&env),
+                &0,
+            ),
+            Err(Ok(ContractError::RateLimited))
+        );
+    }
+
+    #[test]
+    fn test_rate_limit_rejects_invalid_config() {
+        let env = Env::default();
+        let (client, _) = setup(&env);
+
+        for (max_executions, window_ledgers) in
+            [(0, 10), (RATE_LIMIT_MAX_EXECUTIONS + 1, 10), (1, 0)]
+        {
+            assert_eq!(
+                client.try_set_rate_limit(&max_executions, &window_ledgers),
+                Err(Ok(ContractError::InvalidLimit))
+            );
+        }
+        assert_eq!(client.get_rate_limit(), None);
+    }
+}
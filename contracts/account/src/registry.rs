@@ -0,0 +1,162 @@
+//! Keeping an account registry up to date.
+//!
+//! The owner can point the account at an Ancore registry, which indexes
+//! accounts by owner and guardian so wallets can find the accounts an
+//! address controls. Once set, every owner or guardian change is pushed to
+//! the registry with `update(account, owner, guardians)`. A failing registry
+//! never reverts the change that triggered it: the failure is reported with
+//! a `reg_fail` event, and the registry can be resynced later.
+
+use soroban_sdk::{contractclient, symbol_short, Address, Env, Vec};
+
+use crate::{events, guardians, DataKey};
+
+/// The part of the registry interface the account calls.
+#[contractclient(name = "RegistryClient")]
+pub trait Registry {
+    fn update(env: Env, account: Address, owner: Address, guardians: Vec<Address>);
+}
+
+pub fn get(env: &Env) -> Option<Address> {
+    env.storage().instance().get(&DataKey::Registry)
+}
+
+/// Point the account at `registry` and push its current state there.
+///
+/// Unlike later updates, a failure here reverts, so a registry that does
+/// not accept the account is never set.
+pub fn set(env: &Env, registry: &Address) {
+    env.storage().instance().set(&DataKey::Registry, registry);
+    RegistryClient::new(env, registry).update(
+        &env.current_contract_address(),
+        &owner(env),
+        &guardians::guardians(env),
+    );
+
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("reg_set")),
+        registry.clone(),
+    );
+}
+
+/// Push the current owner and guardians to the registry, if one is set.
+pub fn notify(env: &Env) {
+    let Some(registry) = get(env) else {
+        return;
+    };
+
+    let result = RegistryClient::new(env, &registry).try_update(
+        &env.current_contract_address(),
+        &owner(env),
+        &guardians::guardians(env),
+    );
+    if result.is_err() {
+        events::publish(
+            env,
+            (symbol_short!("ancore"), symbol_short!("reg_fail")),
+            registry,
+        );
+    }
+}
+
+fn owner(env: &Env) -> Address {
+    env.storage().instance().get(&DataKey::Owner).unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{AncoreAccount, AncoreAccountClient};
+    use soroban_sdk::{
+        contract, contractimpl,
+        testutils::{Address as _, Events as _},
+        vec, IntoVal,
+    };
+
+    /// Registry that remembers the last update, or fails once told to.
+    #[contract]
+    struct RecordingRegistry;
+
+    #[contractimpl]
+    impl RecordingRegistry {
+        pub fn update(env: Env, account: Address, owner: Address, guardians: Vec<Address>) {
+            account.require_auth();
+            if env.storage().instance().has(&symbol_short!("fail")) {
+                panic!("registry failed");
+            }
+            env.storage()
+                .instance()
+                .set(&symbol_short!("last"), &(owner, guardians));
+        }
+
+        pub fn last(env: Env) -> (Address, Vec<Address>) {
+            env.storage()
+                .instance()
+                .get(&symbol_short!("last"))
+                .unwrap()
+        }
+
+        pub fn fail(env: Env) {
+            env.storage().instance().set(&symbol_short!("fail"), &true);
+        }
+    }
+
+    fn setup(env: &Env) -> (AncoreAccountClient<'_>, RecordingRegistryClient<'_>) {
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(env, &contract_id);
+        client.initialize(&Address::generate(env));
+        let registry = env.register_contract(None, RecordingRegistry);
+        (client, RecordingRegistryClient::new(env, &registry))
+    }
+
+    #[test]
+    fn test_set_registry_pushes_current_state() {
+        let env = Env::default();
+        let (client, registry) = setup(&env);
+
+        client.set_registry(&registry.address);
+        assert_eq!(client.get_registry(), Some(registry.address.clone()));
+        assert_eq!(registry.last(), (client.get_owner(), Vec::new(&env)));
+    }
+
+    #[test]
+    fn test_owner_and_guardian_changes_are_pushed() {
+        let env = Env::default();
+        let (client, registry) = setup(&env);
+        client.set_registry(&registry.address);
+
+        let guardian = Address::generate(&env);
+        client.set_guardians(&vec![&env, guardian.clone()], &1);
+        assert_eq!(registry.last().1, vec![&env, guardian.clone()]);
+
+        let new_owner = Address::generate(&env);
+        client.propose_owner(&new_owner);
+        client.accept_ownership();
+        assert_eq!(registry.last(), (new_owner, vec![&env, guardian]));
+    }
+
+    #[test]
+    fn test_failing_registry_does_not_block_owner_change() {
+        let env = Env::default();
+        let (client, registry) = setup(&env);
+        client.set_registry(&registry.address);
+        registry.fail();
+
+        let new_owner = Address::generate(&env);
+        client.propose_owner(&new_owner);
+        client.accept_ownership();
+        assert_eq!(client.get_owner(), new_owner);
+
+        let failed = env.events().all().iter().any(|(_, topics, _)| {
+            topics
+                == vec![
+                    &env,
+                    symbol_short!("ancore").into_val(&env),
+                    symbol_short!("reg_fail").into_val(&env),
+                ]
+        });
+        assert!(failed);
+    }
+}
@@ -0,0 +1,1014 @@
+//! Session key authorization.
+//!
+//! A session key is an ed25519 key the owner delegates to, typically held by
+//! a dapp. To act for the account it must be registered, within its
+//! `valid_after`..`expires_at` window, and hold a
+//! [`Permission`](crate::Permission) covering each call; its signature is
+//! then checked over the payload of the operation it authorizes.
+//!
+//! The owner can narrow what a key may do after adding it: which contracts and
+//! functions it may call, how much of each token it may move per period, and
+//! how many operations it may authorize in total. A call outside those bounds
+//! fails with `InsufficientPermission`.
+
+use soroban_sdk::{
+    contracttype, symbol_short, xdr::ToXdr, Address, Bytes, BytesN, Env, Symbol, Val, Vec,
+};
+
+use crate::{
+    events, permissions, transfer, transfer::Transfer, ContractError, DataKey, SessionKey,
+};
+
+const EXECUTE_DOMAIN: &[u8] = b"ancore-session-execute";
+
+/// Cap on how much of a token a session key can move per period.
+///
+/// Periods are counted in ledgers; `spent` covers the window that started at
+/// ledger `window_start`.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SessionSpendLimit {
+    pub token: Address,
+    pub max_amount: i128,
+    pub period_ledgers: u32,
+    pub spent: i128,
+    pub window_start: u32,
+}
+
+pub fn get(env: &Env, public_key: &BytesN<32>) -> Option<SessionKey> {
+    env.storage()
+        .persistent()
+        .get(&DataKey::SessionKey(public_key.clone()))
+}
+
+/// Public keys of every registered session key, in the order they were added.
+pub fn index(env: &Env) -> Vec<BytesN<32>> {
+    env.storage()
+        .persistent()
+        .get(&DataKey::SessionKeyIndex)
+        .unwrap_or_else(|| Vec::new(env))
+}
+
+pub fn add_to_index(env: &Env, public_key: &BytesN<32>) {
+    let mut index = index(env);
+    if !index.contains(public_key) {
+        index.push_back(public_key.clone());
+        env.storage()
+            .persistent()
+            .set(&DataKey::SessionKeyIndex, &index);
+    }
+}
+
+pub fn remove_from_index(env: &Env, public_key: &BytesN<32>) {
+    let mut index = index(env);
+    if let Some(i) = index.first_index_of(public_key) {
+        index.remove(i);
+        env.storage()
+            .persistent()
+            .set(&DataKey::SessionKeyIndex, &index);
+    }
+}
+
+/// Remove a session key and its index entry; its nonce is kept.
+pub fn remove(env: &Env, public_key: &BytesN<32>) {
+    env.storage()
+        .persistent()
+        .remove(&DataKey::SessionKey(public_key.clone()));
+    remove_from_index(env, public_key);
+}
+
+/// Remove every registered session key, returning their public keys; their
+/// nonces are kept.
+pub fn remove_all(env: &Env) -> Vec<BytesN<32>> {
+    let public_keys = index(env);
+    for public_key in public_keys.iter() {
+        env.storage()
+            .persistent()
+            .remove(&DataKey::SessionKey(public_key));
+    }
+    env.storage().persistent().remove(&DataKey::SessionKeyIndex);
+    public_keys
+}
+
+/// Remove up to `limit` expired session keys, returning their public keys.
+pub fn prune_expired(env: &Env, limit: u32) -> Vec<BytesN<32>> {
+    let now = env.ledger().timestamp();
+    let mut pruned = Vec::new(env);
+    for public_key in index(env).iter() {
+        if pruned.len() >= limit {
+            break;
+        }
+        let expired = get(env, &public_key).map_or(true, |key| key.expires_at <= now);
+        if expired {
+            remove(env, &public_key);
+            pruned.push_back(public_key);
+        }
+    }
+    pruned
+}
+
+/// Up to `limit` registered session keys, starting at position `offset`.
+pub fn list(env: &Env, offset: u32, limit: u32) -> Vec<SessionKey> {
+    let mut session_keys = Vec::new(env);
+    for public_key in index(env).iter().skip(offset as usize).take(limit as usize) {
+        if let Some(session_key) = get(env, &public_key) {
+            session_keys.push_back(session_key);
+        }
+    }
+    session_keys
+}
+
+pub fn save(env: &Env, session_key: &SessionKey) {
+    env.storage().persistent().set(
+        &DataKey::SessionKey(session_key.public_key.clone()),
+        session_key,
+    );
+}
+
+/// Cap the amount of `token` the session key can move per `period_ledgers`.
+///
+/// Replaces any earlier cap for the token and starts a fresh window.
+pub fn set_spend_limit(
+    env: &Env,
+    public_key: &BytesN<32>,
+    token: &Address,
+    max_amount: i128,
+    period_ledgers: u32,
+) -> Result<(), ContractError> {
+    if max_amount < 0 || period_ledgers == 0 {
+        return Err(ContractError::InvalidLimit);
+    }
+    let mut session_key = get(env, public_key).ok_or(ContractError::UnknownSigner)?;
+
+    let limit = SessionSpendLimit {
+        token: token.clone(),
+        max_amount,
+        period_ledgers,
+        spent: 0,
+        window_start: env.ledger().sequence(),
+    };
+    match session_key
+        .spend_limits
+        .iter()
+        .position(|existing| existing.token == *token)
+    {
+        Some(i) => session_key.spend_limits.set(i as u32, limit),
+        None => session_key.spend_limits.push_back(limit),
+    }
+    save(env, &session_key);
+
+    events::publish(
+        env,
+        (
+            symbol_short!("ancore"),
+            symbol_short!("sk_limit"),
+            public_key.clone(),
+        ),
+        (token.clone(), max_amount, period_ledgers),
+    );
+    Ok(())
+}
+
+/// Restrict the session key to calling `contracts`; empty allows any contract.
+pub fn set_allowed_contracts(
+    env: &Env,
+    public_key: &BytesN<32>,
+    contracts: &Vec<Address>,
+) -> Result<(), ContractError> {
+    let mut session_key = get(env, public_key).ok_or(ContractError::UnknownSigner)?;
+    session_key.allowed_contracts = contracts.clone();
+    save(env, &session_key);
+
+    events::publish(
+        env,
+        (
+            symbol_short!("ancore"),
+            symbol_short!("sk_allow"),
+            public_key.clone(),
+        ),
+        contracts.clone(),
+    );
+    Ok(())
+}
+
+/// Restrict the session key to calling `functions`; empty allows any function.
+pub fn set_allowed_functions(
+    env: &Env,
+    public_key: &BytesN<32>,
+    functions: &Vec<Symbol>,
+) -> Result<(), ContractError> {
+    let mut session_key = get(env, public_key).ok_or(ContractError::UnknownSigner)?;
+    session_key.allowed_functions = functions.clone();
+    save(env, &session_key);
+
+    events::publish(
+        env,
+        (
+            symbol_short!("ancore"),
+            symbol_short!("sk_fns"),
+            public_key.clone(),
+        ),
+        functions.clone(),
+    );
+    Ok(())
+}
+
+/// Limit the session key to `max_uses` operations in total; zero is unlimited.
+///
+/// Operations already authorized keep counting against the new limit.
+pub fn set_max_uses(
+    env: &Env,
+    public_key: &BytesN<32>,
+    max_uses: u32,
+) -> Result<(), ContractError> {
+    let mut session_key = get(env, public_key).ok_or(ContractError::UnknownSigner)?;
+    session_key.max_uses = max_uses;
+    save(env, &session_key);
+
+    events::publish(
+        env,
+        (
+            symbol_short!("ancore"),
+            symbol_short!("sk_uses"),
+            public_key.clone(),
+        ),
+        max_uses,
+    );
+    Ok(())
+}
+
+/// Keep the session key unusable until the ledger timestamp `valid_after`.
+pub fn set_valid_after(
+    env: &Env,
+    public_key: &BytesN<32>,
+    valid_after: u64,
+) -> Result<(), ContractError> {
+    let mut session_key = get(env, public_key).ok_or(ContractError::UnknownSigner)?;
+    session_key.valid_after = valid_after;
+    save(env, &session_key);
+
+    events::publish(
+        env,
+        (
+            symbol_short!("ancore"),
+            symbol_short!("sk_after"),
+            public_key.clone(),
+        ),
+        valid_after,
+    );
+    Ok(())
+}
+
+/// Move the session key's expiry to `expires_at`, keeping all other state.
+pub fn extend(env: &Env, public_key: &BytesN<32>, expires_at: u64) -> Result<(), ContractError> {
+    let mut session_key = get(env, public_key).ok_or(ContractError::UnknownSigner)?;
+    session_key.expires_at = expires_at;
+    save(env, &session_key);
+
+    events::publish(
+        env,
+        (
+            symbol_short!("ancore"),
+            symbol_short!("sk_extend"),
+            public_key.clone(),
+        ),
+        expires_at,
+    );
+    Ok(())
+}
+
+/// Next nonce `public_key` must sign with.
+///
+/// Each key counts on its own so sessions do not contend with each other or
+/// with the owner. The count survives revocation: resetting it would make
+/// signatures from before the revocation valid again.
+pub fn nonce(env: &Env, public_key: &BytesN<32>) -> u64 {
+    env.storage()
+        .persistent()
+        .get(&DataKey::SessionNonce(public_key.clone()))
+        .unwrap_or(0)
+}
+
+pub fn set_nonce(env: &Env, public_key: &BytesN<32>, nonce: u64) {
+    env.storage()
+        .persistent()
+        .set(&DataKey::SessionNonce(public_key.clone()), &nonce);
+}
+
+/// Whether the ledger time is inside the key's validity window.
+pub fn is_live(env: &Env, session_key: &SessionKey) -> bool {
+    let now = env.ledger().timestamp();
+    session_key.valid_after <= now && now < session_key.expires_at
+}
+
+/// Hash a session key signs to have the account make a call.
+///
+/// `sha256("ancore-session-execute" || account || network_id || to || function
+/// || args || nonce)`, with each value XDR-encoded except the 32-byte network
+/// id, and the nonce as 8 big-endian bytes.
+pub fn execute_payload(
+    env: &Env,
+    to: &Address,
+    function: &Symbol,
+    args: &Vec<Val>,
+    nonce: u64,
+) -> BytesN<32> {
+    let mut preimage = Bytes::from_slice(env, EXECUTE_DOMAIN);
+    preimage.append(&env.current_contract_address().to_xdr(env));
+    preimage.append(&Bytes::from(env.ledger().network_id()));
+    preimage.append(&to.clone().to_xdr(env));
+    preimage.append(&function.clone().to_xdr(env));
+    preimage.append(&args.clone().to_xdr(env));
+    preimage.extend_from_array(&nonce.to_be_bytes());
+    env.crypto().sha256(&preimage).into()
+}
+
+/// Apply the session key's own restrictions to a call it authorizes.
+///
+/// Usage is recorded on `session_key`; the caller saves it once every call
+/// has been checked.
+pub fn check_call(
+    env: &Env,
+    session_key: &mut SessionKey,
+    to: &Address,
+    function: &Symbol,
+    args: &Vec<Val>,
+) -> Result<(), ContractError> {
+    // The account's own entrypoints are for its owner.
+    if *to == env.current_contract_address() {
+        return Err(ContractError::InsufficientPermission);
+    }
+    if !permissions::permits(env, &session_key.permissions, to, function, args) {
+        return Err(ContractError::InsufficientPermission);
+    }
+    if !session_key.allowed_contracts.is_empty() && !session_key.allowed_contracts.contains(to) {
+        return Err(ContractError::InsufficientPermission);
+    }
+    if !session_key.allowed_functions.is_empty()
+        && !session_key.allowed_functions.contains(function)
+    {
+        return Err(ContractError::InsufficientPermission);
+    }
+    if let Some(transfer) = transfer::detect(env, to, function, args) {
+        record_spend(env, session_key, &transfer)?;
+    }
+    Ok(())
+}
+
+fn record_spend(
+    env: &Env,
+    session_key: &mut SessionKey,
+    transfer: &Transfer,
+) -> Result<(), ContractError> {
+    let now = env.ledger().sequence();
+    for i in 0..session_key.spend_limits.len() {
+        let mut limit = session_key.spend_limits.get_unchecked(i);
+        if limit.token != transfer.token {
+            continue;
+        }
+
+        if now >= limit.window_start.saturating_add(limit.period_ledgers) {
+            limit.spent = 0;
+            limit.window_start = now;
+        }
+        let spent = limit
+            .spent
+            .checked_add(transfer.amount)
+            .ok_or(ContractError::Overflow)?;
+        if spent > limit.max_amount {
+            return Err(ContractError::InsufficientPermission);
+        }
+        limit.spent = spent;
+        session_key.spend_limits.set(i, limit);
+    }
+    Ok(())
+}
+
+/// Check that `public_key` is a usable session key and signed `payload`.
+///
+/// A bad signature fails the call outright, as `ed25519_verify` traps. The
+/// returned key has this use counted; callers save it once the operation
+/// succeeds.
+pub fn authorize(
+    env: &Env,
+    public_key: &BytesN<32>,
+    payload: &BytesN<32>,
+    signature: &BytesN<64>,
+) -> Result<SessionKey, ContractError> {
+    let mut session_key = get(env, public_key).ok_or(ContractError::UnknownSigner)?;
+    if env.ledger().timestamp() < session_key.valid_after {
+        return Err(ContractError::SessionKeyNotYetValid);
+    }
+    if !is_live(env, &session_key) {
+        return Err(ContractError::SessionKeyExpired);
+    }
+    if session_key.permissions.is_empty() {
+        return Err(ContractError::InsufficientPermission);
+    }
+    if session_key.max_uses != 0 && session_key.uses >= session_key.max_uses {
+        return Err(ContractError::SessionKeyExhausted);
+    }
+
+    env.crypto()
+        .ed25519_verify(public_key, &payload.clone().into(), signature);
+    session_key.uses += 1;
+    Ok(session_key)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        testutils::{register_target, MockTargetClient},
+        AccountSignature, AncoreAccount, AncoreAccountClient, Permission,
+    };
+    use ed25519_dalek::{Signer, SigningKey};
+    use soroban_sdk::{
+        testutils::{Address as _, Events as _, Ledger as _},
+        vec, Address, IntoVal, Vec,
+    };
+
+    struct Setup<'a> {
+        env: Env,
+        client: AncoreAccountClient<'a>,
+    }
+
+    fn setup<'a>() -> Setup<'a> {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(&env, &contract_id);
+        client.initialize(&Address::generate(&env));
+        Setup { env, client }
+    }
+
+    fn public_key(env: &Env, key: &SigningKey) -> BytesN<32> {
+        BytesN::from_array(env, &key.verifying_key().to_bytes())
+    }
+
+    fn check_auth(s: &Setup, key: &SigningKey) -> Result<(), ContractError> {
+        let payload = BytesN::from_array(&s.env, &[9u8; 32]);
+        let signature = AccountSignature {
+            public_key: public_key(&s.env, key),
+            signature: BytesN::from_array(&s.env, &key.sign(&payload.to_array()).to_bytes()),
+        };
+        s.env
+            .try_invoke_contract_check_auth::<ContractError>(
+                &s.client.address,
+                &payload,
+                vec![&s.env, signature].into_val(&s.env),
+                &Vec::new(&s.env),
+            )
+            .map_err(|error| error.unwrap())
+    }
+
+    #[test]
+    fn test_session_key_authorizes_calls() {
+        let s = setup();
+        let key = SigningKey::from_bytes(&[3u8; 32]);
+        let permissions = Vec::from_array(&s.env, [Permission::Execute]);
+        s.client
+            .add_session_key(&public_key(&s.env, &key), &1000, &permissions);
+
+        assert_eq!(check_auth(&s, &key), Ok(()));
+
+        s.env.ledger().with_mut(|li| li.timestamp = 1000);
+        assert_eq!(check_auth(&s, &key), Err(ContractError::SessionKeyExpired));
+    }
+
+    #[test]
+    fn test_session_key_limited_to_its_permissions() {
+        let s = setup();
+        let key = SigningKey::from_bytes(&[3u8; 32]);
+        let session_pk = public_key(&s.env, &key);
+        s.client
+            .add_session_key(&session_pk, &1000, &Vec::new(&s.env));
+        assert_eq!(
+            check_auth(&s, &key),
+            Err(ContractError::InsufficientPermission)
+        );
+
+        let target = register_target(&s.env);
+        let token = register_target(&s.env);
+        let permissions = vec![
+            &s.env,
+            Permission::CallContract(target.clone()),
+            Permission::Transfer(token.clone(), 50),
+        ];
+        s.client.add_session_key(&session_pk, &1000, &permissions);
+
+        let ping = Symbol::new(&s.env, "ping");
+        let transfer = Symbol::new(&s.env, "transfer");
+        let transfer_args = |amount: i128| -> Vec<Val> {
+            vec![
+                &s.env,
+                s.client.address.into_val(&s.env),
+                Address::generate(&s.env).into_val(&s.env),
+                amount.into_val(&s.env),
+            ]
+        };
+        let execute = |to: &Address, function: &Symbol, args: &Vec<Val>, nonce: u64| {
+            let payload = s.client.get_session_payload(to, function, args, &nonce);
+            let signature = BytesN::from_array(&s.env, &key.sign(&payload.to_array()).to_bytes());
+            s.client
+                .try_execute_with_session(&session_pk, &signature, to, function, args, &nonce)
+        };
+
+        assert_eq!(execute(&target, &ping, &Vec::new(&s.env), 0), Ok(Ok(true)));
+        assert_eq!(
+            execute(&token, &ping, &Vec::new(&s.env), 1),
+            Err(Ok(ContractError::InsufficientPermission))
+        );
+        assert_eq!(
+            execute(&token, &transfer, &transfer_args(70), 1),
+            Err(Ok(ContractError::InsufficientPermission))
+        );
+        assert_eq!(
+            execute(&token, &transfer, &transfer_args(50), 1),
+            Ok(Ok(true))
+        );
+    }
+
+    #[test]
+    fn test_unknown_or_revoked_session_key_rejected() {
+        let s = setup();
+        let key = SigningKey::from_bytes(&[3u8; 32]);
+        assert_eq!(check_auth(&s, &key), Err(ContractError::UnknownSigner));
+
+        let permissions = Vec::from_array(&s.env, [Permission::Execute]);
+        s.client
+            .add_session_key(&public_key(&s.env, &key), &1000, &permissions);
+        s.client.revoke_session_key(&public_key(&s.env, &key));
+        assert_eq!(check_auth(&s, &key), Err(ContractError::UnknownSigner));
+    }
+
+    #[test]
+    fn test_execute_with_session() {
+        let s = setup();
+        let key = SigningKey::from_bytes(&[3u8; 32]);
+        let session_pk = public_key(&s.env, &key);
+        let permissions = Vec::from_array(&s.env, [Permission::Execute]);
+        s.client.add_session_key(&session_pk, &1000, &permissions);
+
+        let token = MockTargetClient::new(&s.env, &register_target(&s.env));
+        let recipient = Address::generate(&s.env);
+        let transfer = Symbol::new(&s.env, "transfer");
+        let args: Vec<Val> = vec![
+            &s.env,
+            s.client.address.into_val(&s.env),
+            recipient.into_val(&s.env),
+            70i128.into_val(&s.env),
+        ];
+        let payload = s
+            .client
+            .get_session_payload(&token.address, &transfer, &args, &0);
+        let signature = BytesN::from_array(&s.env, &key.sign(&payload.to_array()).to_bytes());
+
+        s.client.execute_with_session(
+            &session_pk,
+            &signature,
+            &token.address,
+            &transfer,
+            &args,
+            &0,
+        );
+        assert_eq!(token.received(&recipient), 70);
+        assert_eq!(s.client.get_session_nonce(&session_pk), 1);
+        assert_eq!(s.client.get_nonce(&0), 0);
+
+        // The same signature cannot be replayed.
+        assert_eq!(
+            s.client.try_execute_with_session(
+                &session_pk,
+                &signature,
+                &token.address,
+                &transfer,
+                &args,
+                &0
+            ),
+            Err(Ok(ContractError::InvalidNonce))
+        );
+
+        // Nor reused for different arguments.
+        let other_args: Vec<Val> = vec![
+            &s.env,
+            s.client.address.into_val(&s.env),
+            Address::generate(&s.env).into_val(&s.env),
+            70i128.into_val(&s.env),
+        ];
+        assert!(s
+            .client
+            .try_execute_with_session(
+                &session_pk,
+                &signature,
+                &token.address,
+                &transfer,
+                &other_args,
+                &1
+            )
+            .is_err());
+        assert_eq!(s.client.get_session_nonce(&session_pk), 1);
+
+        // Nor replayed on another network.
+        let payload = s
+            .client
+            .get_session_payload(&token.address, &transfer, &args, &1);
+        let signature = BytesN::from_array(&s.env, &key.sign(&payload.to_array()).to_bytes());
+        s.env.ledger().with_mut(|li| li.network_id = [9u8; 32]);
+        assert!(s
+            .client
+            .try_execute_with_session(
+                &session_pk,
+                &signature,
+                &token.address,
+                &transfer,
+                &args,
+                &1
+            )
+            .is_err());
+        assert_eq!(s.client.get_session_nonce(&session_pk), 1);
+    }
+
+    #[test]
+    fn test_session_nonces_are_independent() {
+        let s = setup();
+        let target = register_target(&s.env);
+        let ping = Symbol::new(&s.env, "ping");
+        let args: Vec<Val> = Vec::new(&s.env);
+        let permissions = Vec::from_array(&s.env, [Permission::Execute]);
+        let first = SigningKey::from_bytes(&[3u8; 32]);
+        let second = SigningKey::from_bytes(&[4u8; 32]);
+        for key in [&first, &second] {
+            s.client
+                .add_session_key(&public_key(&s.env, key), &1000, &permissions);
+        }
+        let execute = |key: &SigningKey, nonce: u64| {
+            let payload = s.client.get_session_payload(&target, &ping, &args, &nonce);
+            let signature = BytesN::from_array(&s.env, &key.sign(&payload.to_array()).to_bytes());
+            s.client.execute_with_session(
+                &public_key(&s.env, key),
+                &signature,
+                &target,
+                &ping,
+                &args,
+                &nonce,
+            );
+        };
+
+        execute(&first, 0);
+        execute(&second, 0);
+        execute(&first, 1);
+        s.client.execute(&target, &ping, &args, &0, &0);
+
+        assert_eq!(s.client.get_session_nonce(&public_key(&s.env, &first)), 2);
+        assert_eq!(s.client.get_session_nonce(&public_key(&s.env, &second)), 1);
+        assert_eq!(s.client.get_nonce(&0), 1);
+
+        // Re-adding a revoked key does not reopen its old nonces.
+        s.client.revoke_session_key(&public_key(&s.env, &first));
+        s.client
+            .add_session_key(&public_key(&s.env, &first), &1000, &permissions);
+        assert_eq!(s.client.get_session_nonce(&public_key(&s.env, &first)), 2);
+    }
+
+    #[test]
+    fn test_execute_with_session_applies_spend_limits() {
+        let s = setup();
+        let key = SigningKey::from_bytes(&[3u8; 32]);
+        let session_pk = public_key(&s.env, &key);
+        let permissions = Vec::from_array(&s.env, [Permission::Execute]);
+        s.client.add_session_key(&session_pk, &1000, &permissions);
+
+        let token = register_target(&s.env);
+        s.client.set_token_limit(&token, &50, &86_400);
+        let transfer = Symbol::new(&s.env, "transfer");
+        let args: Vec<Val> = vec![
+            &s.env,
+            s.client.address.into_val(&s.env),
+            Address::generate(&s.env).into_val(&s.env),
+            70i128.into_val(&s.env),
+        ];
+        let payload = s.client.get_session_payload(&token, &transfer, &args, &0);
+        let signature = BytesN::from_array(&s.env, &key.sign(&payload.to_array()).to_bytes());
+
+        assert_eq!(
+            s.client.try_execute_with_session(
+                &session_pk,
+                &signature,
+                &token,
+                &transfer,
+                &args,
+                &0
+            ),
+            Err(Ok(ContractError::SpendLimitExceeded))
+        );
+    }
+
+    #[test]
+    fn test_session_spend_limit_resets_each_period() {
+        let s = setup();
+        let key = SigningKey::from_bytes(&[3u8; 32]);
+        let session_pk = public_key(&s.env, &key);
+        let permissions = Vec::from_array(&s.env, [Permission::Execute]);
+        s.client.add_session_key(&session_pk, &1000, &permissions);
+
+        let token = register_target(&s.env);
+        assert_eq!(
+            s.client
+                .try_set_session_spend_limit(&session_pk, &token, &-1, &100),
+            Err(Ok(ContractError::InvalidLimit))
+        );
+        s.client
+            .set_session_spend_limit(&session_pk, &token, &100, &100);
+
+        let transfer = Symbol::new(&s.env, "transfer");
+        let execute = |amount: i128, nonce: u64| {
+            let args: Vec<Val> = vec![
+                &s.env,
+                s.client.address.into_val(&s.env),
+                Address::generate(&s.env).into_val(&s.env),
+                amount.into_val(&s.env),
+            ];
+            let payload = s
+                .client
+                .get_session_payload(&token, &transfer, &args, &nonce);
+            let signature = BytesN::from_array(&s.env, &key.sign(&payload.to_array()).to_bytes());
+            s.client.try_execute_with_session(
+                &session_pk,
+                &signature,
+                &token,
+                &transfer,
+                &args,
+                &nonce,
+            )
+        };
+
+        assert_eq!(execute(60, 0), Ok(Ok(true)));
+        assert_eq!(
+            execute(50, 1),
+            Err(Ok(ContractError::InsufficientPermission))
+        );
+        assert_eq!(execute(40, 1), Ok(Ok(true)));
+
+        // Other tokens are not capped.
+        let other = register_target(&s.env);
+        let args: Vec<Val> = vec![
+            &s.env,
+            s.client.address.into_val(&s.env),
+            Address::generate(&s.env).into_val(&s.env),
+            500i128.into_val(&s.env),
+        ];
+        let payload = s.client.get_session_payload(&other, &transfer, &args, &2);
+        let signature = BytesN::from_array(&s.env, &key.sign(&payload.to_array()).to_bytes());
+        s.client
+            .execute_with_session(&session_pk, &signature, &other, &transfer, &args, &2);
+
+        s.env.ledger().with_mut(|li| li.sequence_number += 100);
+        assert_eq!(execute(100, 3), Ok(Ok(true)));
+        assert_eq!(
+            s.client
+                .get_session_key(&session_pk)
+                .unwrap()
+                .spend_limits
+                .get_unchecked(0)
+                .spent,
+            100
+        );
+    }
+
+    #[test]
+    fn test_session_key_limited_to_allowed_contracts() {
+        let s = setup();
+        let key = SigningKey::from_bytes(&[3u8; 32]);
+        let session_pk = public_key(&s.env, &key);
+        let permissions = Vec::from_array(&s.env, [Permission::Execute]);
+        s.client.add_session_key(&session_pk, &1000, &permissions);
+
+        let allowed = register_target(&s.env);
+        let other = register_target(&s.env);
+        s.client
+            .set_session_allowed_contracts(&session_pk, &vec![&s.env, allowed.clone()]);
+
+        let ping = Symbol::new(&s.env, "ping");
+        let args: Vec<Val> = Vec::new(&s.env);
+        let execute = |target: &Address, nonce: u64| {
+            let payload = s.client.get_session_payload(target, &ping, &args, &nonce);
+            let signature = BytesN::from_array(&s.env, &key.sign(&payload.to_array()).to_bytes());
+            s.client
+                .try_execute_with_session(&session_pk, &signature, target, &ping, &args, &nonce)
+        };
+
+        assert_eq!(
+            execute(&other, 0),
+            Err(Ok(ContractError::InsufficientPermission))
+        );
+        assert_eq!(execute(&allowed, 0), Ok(Ok(true)));
+
+        // Clearing the list lifts the restriction.
+        s.client
+            .set_session_allowed_contracts(&session_pk, &Vec::new(&s.env));
+        assert_eq!(execute(&other, 1), Ok(Ok(true)));
+    }
+
+    #[test]
+    fn test_session_key_limited_to_allowed_functions() {
+        let s = setup();
+        let key = SigningKey::from_bytes(&[3u8; 32]);
+        let session_pk = public_key(&s.env, &key);
+        let permissions = Vec::from_array(&s.env, [Permission::Execute]);
+        s.client.add_session_key(&session_pk, &1000, &permissions);
+
+        let target = register_target(&s.env);
+        let ping = Symbol::new(&s.env, "ping");
+        let fail = Symbol::new(&s.env, "fail");
+        s.client
+            .set_session_allowed_functions(&session_pk, &vec![&s.env, ping.clone()]);
+
+        let args: Vec<Val> = Vec::new(&s.env);
+        let execute = |function: &Symbol, nonce: u64| {
+            let payload = s
+                .client
+                .get_session_payload(&target, function, &args, &nonce);
+            let signature = BytesN::from_array(&s.env, &key.sign(&payload.to_array()).to_bytes());
+            s.client.try_execute_with_session(
+                &session_pk,
+                &signature,
+                &target,
+                function,
+                &args,
+                &nonce,
+            )
+        };
+
+        // Rejected before the target is ever called.
+        assert_eq!(
+            execute(&fail, 0),
+            Err(Ok(ContractError::InsufficientPermission))
+        );
+        assert_eq!(execute(&ping, 0), Ok(Ok(true)));
+    }
+
+    #[test]
+    fn test_session_key_exhausted_after_max_uses() {
+        let s = setup();
+        let key = SigningKey::from_bytes(&[3u8; 32]);
+        let session_pk = public_key(&s.env, &key);
+        let permissions = Vec::from_array(&s.env, [Permission::Execute]);
+        s.client.add_session_key(&session_pk, &1000, &permissions);
+        s.client.set_session_max_uses(&session_pk, &2);
+
+        let target = register_target(&s.env);
+        let ping = Symbol::new(&s.env, "ping");
+        let fail = Symbol::new(&s.env, "fail");
+        let args: Vec<Val> = Vec::new(&s.env);
+        let execute = |function: &Symbol, nonce: u64| {
+            let payload = s
+                .client
+                .get_session_payload(&target, function, &args, &nonce);
+            let signature = BytesN::from_array(&s.env, &key.sign(&payload.to_array()).to_bytes());
+            s.client.try_execute_with_session(
+                &session_pk,
+                &signature,
+                &target,
+                function,
+                &args,
+                &nonce,
+            )
+        };
+
+        assert_eq!(execute(&ping, 0), Ok(Ok(true)));
+        // A failed call does not use up the key.
+        assert_eq!(execute(&fail, 1), Err(Ok(ContractError::InvocationFailed)));
+        assert_eq!(check_auth(&s, &key), Ok(()));
+        assert_eq!(s.client.get_session_key(&session_pk).unwrap().uses, 2);
+
+        assert_eq!(
+            execute(&ping, 1),
+            Err(Ok(ContractError::SessionKeyExhausted))
+        );
+        assert_eq!(
+            check_auth(&s, &key),
+            Err(ContractError::SessionKeyExhausted)
+        );
+    }
+
+    #[test]
+    fn test_session_key_not_usable_before_valid_after() {
+        let s = setup();
+        let key = SigningKey::from_bytes(&[3u8; 32]);
+        let session_pk = public_key(&s.env, &key);
+        let permissions = Vec::from_array(&s.env, [Permission::Execute]);
+        s.client.add_session_key(&session_pk, &1000, &permissions);
+        s.client.set_session_valid_after(&session_pk, &500);
+
+        assert_eq!(
+            check_auth(&s, &key),
+            Err(ContractError::SessionKeyNotYetValid)
+        );
+        let message = BytesN::from_array(&s.env, &[7u8; 32]);
+        let signature = BytesN::from_array(&s.env, &key.sign(&message.to_array()).to_bytes());
+        assert!(!s.client.verify_message(&message, &signature, &session_pk));
+
+        s.env.ledger().with_mut(|li| li.timestamp = 500);
+        assert_eq!(check_auth(&s, &key), Ok(()));
+        assert!(s.client.verify_message(&message, &signature, &session_pk));
+    }
+
+    #[test]
+    fn test_list_session_keys() {
+        let s = setup();
+        let permissions = Vec::from_array(&s.env, [Permission::Execute]);
+        let keys: [BytesN<32>; 3] =
+            core::array::from_fn(|i| BytesN::from_array(&s.env, &[i as u8 + 1; 32]));
+        for key in &keys {
+            s.client.add_session_key(key, &1000, &permissions);
+        }
+        // Re-adding a key replaces it without listing it twice.
+        s.client.add_session_key(&keys[0], &2000, &permissions);
+
+        let listed = |offset: u32, limit: u32| -> Vec<BytesN<32>> {
+            let mut public_keys = Vec::new(&s.env);
+            for session_key in s.client.list_session_keys(&offset, &limit).iter() {
+                public_keys.push_back(session_key.public_key);
+            }
+            public_keys
+        };
+        assert_eq!(
+            listed(0, 10),
+            vec![&s.env, keys[0].clone(), keys[1].clone(), keys[2].clone()]
+        );
+        assert_eq!(listed(1, 1), vec![&s.env, keys[1].clone()]);
+        assert!(listed(3, 10).is_empty());
+        assert_eq!(
+            s.client
+                .list_session_keys(&0, &1)
+                .get_unchecked(0)
+                .expires_at,
+            2000
+        );
+
+        s.client.revoke_session_key(&keys[1]);
+        assert_eq!(
+            listed(0, 10),
+            vec![&s.env, keys[0].clone(), keys[2].clone()]
+        );
+    }
+
+    #[test]
+    fn test_prune_expired_session_keys() {
+        let s = setup();
+        let permissions = Vec::from_array(&s.env, [Permission::Execute]);
+        let keys: [BytesN<32>; 4] =
+            core::array::from_fn(|i| BytesN::from_array(&s.env, &[i as u8 + 1; 32]));
+        for (key, expires_at) in keys.iter().zip([100u64, 5000, 200, 300]) {
+            s.client.add_session_key(key, &expires_at, &permissions);
+        }
+        s.env.ledger().with_mut(|li| li.timestamp = 1000);
+
+        assert_eq!(s.client.prune_expired(&2), 2);
+        assert!(s.client.get_session_key(&keys[0]).is_none());
+        assert!(s.client.get_session_key(&keys[2]).is_none());
+        assert!(s.client.get_session_key(&keys[3]).is_some());
+
+        let (_, topics, _) = s.env.events().all().last().unwrap();
+        assert_eq!(
+            topics,
+            (
+                symbol_short!("ancore"),
+                symbol_short!("sk_prune"),
+                keys[2].clone()
+            )
+                .into_val(&s.env)
+        );
+
+        assert_eq!(s.client.prune_expired(&10), 1);
+        assert_eq!(s.client.prune_expired(&10), 0);
+        assert_eq!(s.client.list_session_keys(&0, &10).len(), 1);
+    }
+
+    #[test]
+    fn test_extend_session_key_keeps_state() {
+        let s = setup();
+        let key = SigningKey::from_bytes(&[3u8; 32]);
+        let session_pk = public_key(&s.env, &key);
+        let permissions = Vec::from_array(&s.env, [Permission::Execute]);
+        s.client.add_session_key(&session_pk, &1000, &permissions);
+        s.client.set_session_max_uses(&session_pk, &5);
+        assert_eq!(check_auth(&s, &key), Ok(()));
+
+        s.env.ledger().with_mut(|li| li.timestamp = 1000);
+        assert_eq!(check_auth(&s, &key), Err(ContractError::SessionKeyExpired));
+
+        s.client.extend_session_key(&session_pk, &2000);
+        assert_eq!(check_auth(&s, &key), Ok(()));
+        let session_key = s.client.get_session_key(&session_pk).unwrap();
+        assert_eq!(session_key.expires_at, 2000);
+        assert_eq!((session_key.uses, session_key.max_uses), (2, 5));
+
+        assert_eq!(
+            s.client
+                .try_extend_session_key(&BytesN::from_array(&s.env, &[9u8; 32]), &2000),
+            Err(Ok(ContractError::UnknownSigner))
+        );
+    }
+}
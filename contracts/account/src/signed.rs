@@ -0,0 +1,290 @@
+//! Owner-signed calls any relayer can submit.
+//!
+//! `execute_signed` lets someone other than the owner pay for and submit a
+//! call: the account's multisig signers sign a [`SignedCall`] off-chain and
+//! the account checks the signatures itself instead of relying on the
+//! owner's Soroban auth. Only signers configured on the account count; a
+//! classic owner's key is never taken from its address. The
+//! signed hash binds the account, the network, the call, the account nonce
+//! and an expiry, so a signature cannot be replayed, redirected or held back
+//! indefinitely. Its format is defined by the `ancore-payload` crate, which
+//! wallets use to build the same hash off-chain.
+//!
+//! Factory addresses depend only on the owner and salt, so the same account
+//! address is likely to exist on testnet and mainnet. The payload names its
+//! network explicitly and is rejected anywhere else, so a testnet signature
+//! can never run on mainnet.
+
+use ancore_payload::Payload;
+use soroban_sdk::{contracttype, xdr::ToXdr, Bytes, BytesN, Env, Vec};
+
+use crate::{multisig, AccountSignature, Call, ContractError};
+
+/// A call the owner signed for a relayer to submit.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SignedCall {
+    pub call: Call,
+    /// Id of the network the call may run on: `sha256` of its passphrase
+    pub network_id: BytesN<32>,
+    /// Nonce channel the call runs on
+    pub channel: u32,
+    /// Account nonce the call must execute at on `channel`
+    pub nonce: u64,
+    /// Timestamp from which the signature is no longer accepted
+    pub expires_at: u64,
+}
+
+/// Hash the owner signs to authorize `payload`.
+///
+/// The `ancore-payload` hash over this account and `payload`, with the call
+/// XDR-encoded.
+pub fn payload_hash(env: &Env, payload: &SignedCall) -> BytesN<32> {
+    let preimage = Payload {
+        contract_id: contract_id(env),
+        network_id: payload.network_id.to_array(),
+        channel: payload.channel,
+        nonce: payload.nonce,
+        call_hash: env
+            .crypto()
+            .sha256(&payload.call.clone().to_xdr(env))
+            .to_array(),
+        expires_at: payload.expires_at,
+    }
+    .preimage();
+    env.crypto()
+        .sha256(&Bytes::from_array(env, &preimage))
+        .into()
+}
+
+/// Raw id of this contract, from its `ScVal::Address(ScAddress::Contract)`
+/// XDR.
+fn contract_id(env: &Env) -> [u8; 32] {
+    let mut id = [0u8; 32];
+    env.current_contract_address()
+        .to_xdr(env)
+        .slice(8..40)
+        .copy_into_slice(&mut id);
+    id
+}
+
+/// Check `payload` is for this network, has not expired and carries valid
+/// signer signatures meeting the threshold.
+///
+/// A bad signature fails the call outright, as `ed25519_verify` traps.
+pub fn verify(
+    env: &Env,
+    payload: &SignedCall,
+    signatures: &Vec<AccountSignature>,
+) -> Result<(), ContractError> {
+    if payload.network_id != env.ledger().network_id() {
+        return Err(ContractError::WrongNetwork);
+    }
+    if env.ledger().timestamp() >= payload.expires_at {
+        return Err(ContractError::PayloadExpired);
+    }
+    multisig::verify(env, &payload_hash(env, payload), signatures)
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use super::*;
+    use crate::{
+        testutils::{register_target, MockTargetClient},
+        AncoreAccount, AncoreAccountClient, Signer,
+    };
+    use ed25519_dalek::{Signer as _, SigningKey};
+    use soroban_sdk::{
+        testutils::{Address as _, Ledger as _},
+        vec,
+        xdr::{AccountId, Hash, PublicKey, ScAddress, Uint256},
+        Address, IntoVal, Symbol, TryFromVal,
+    };
+
+    struct Setup<'a> {
+        client: AncoreAccountClient<'a>,
+        owner_key: SigningKey,
+        target: MockTargetClient<'a>,
+    }
+
+    const TESTNET: &str = "Test SDF Network ; September 2015";
+    const MAINNET: &str = "Public Global Stellar Network ; September 2015";
+
+    /// An account on testnet whose only multisig signer is `owner_key`, with
+    /// no auth mocked once set up.
+    fn setup(env: &Env) -> Setup<'_> {
+        env.ledger()
+            .with_mut(|li| li.network_id = ancore_payload::network_id(TESTNET));
+        let owner_key = SigningKey::from_bytes(&[1u8; 32]);
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(env, &contract_id);
+        client.initialize(&Address::generate(env));
+        env.mock_all_auths();
+        let signer = Signer {
+            key: BytesN::from_array(env, &owner_key.verifying_key().to_bytes()),
+            weight: 1,
+        };
+        client.set_multisig(&vec![env, signer], &1);
+        env.set_auths(&[]);
+        Setup {
+            client,
+            owner_key,
+            target: MockTargetClient::new(env, &register_target(env)),
+        }
+    }
+
+    fn payload(env: &Env, s: &Setup, nonce: u64) -> SignedCall {
+        SignedCall {
+            call: Call {
+                to: s.target.address.clone(),
+                function: Symbol::new(env, "transfer"),
+                args: vec![
+                    env,
+                    s.client.address.into_val(env),
+                    s.target.address.into_val(env),
+                    9i128.into_val(env),
+                ],
+            },
+            network_id: env.ledger().network_id(),
+            channel: 0,
+            nonce,
+            expires_at: 1_000,
+        }
+    }
+
+    fn sign(env: &Env, key: &SigningKey, hash: &BytesN<32>) -> Vec<AccountSignature> {
+        vec![
+            env,
+            AccountSignature {
+                public_key: BytesN::from_array(env, &key.verifying_key().to_bytes()),
+                signature: BytesN::from_array(env, &key.sign(&hash.to_array()).to_bytes()),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_execute_signed_runs_owner_signed_call() {
+        let env = Env::default();
+        let s = setup(&env);
+        let payload = payload(&env, &s, 0);
+        let signature = sign(
+            &env,
+            &s.owner_key,
+            &s.client.get_signed_payload_hash(&payload),
+        );
+
+        s.client.execute_signed(&payload, &signature);
+        assert_eq!(s.client.get_nonce(&0), 1);
+        assert_eq!(s.target.received(&s.target.address), 9);
+
+        // The same signature cannot be replayed at the next nonce.
+        assert_eq!(
+            s.client.try_execute_signed(&payload, &signature),
+            Err(Ok(ContractError::InvalidNonce))
+        );
+    }
+
+    #[test]
+    fn test_execute_signed_rejects_expired_and_foreign_signatures() {
+        let env = Env::default();
+        let s = setup(&env);
+        let payload = payload(&env, &s, 0);
+        let hash = s.client.get_signed_payload_hash(&payload);
+
+        let stranger = SigningKey::from_bytes(&[2u8; 32]);
+        assert_eq!(
+            s.client
+                .try_execute_signed(&payload, &sign(&env, &stranger, &hash)),
+            Err(Ok(ContractError::UnknownSigner))
+        );
+
+        env.ledger().with_mut(|li| li.timestamp = 1_000);
+        assert_eq!(
+            s.client
+                .try_execute_signed(&payload, &sign(&env, &s.owner_key, &hash)),
+            Err(Ok(ContractError::PayloadExpired))
+        );
+        assert_eq!(s.client.get_nonce(&0), 0);
+    }
+
+    #[test]
+    fn test_execute_signed_ignores_classic_owner_key() {
+        let env = Env::default();
+        let s = setup(&env);
+        let classic_key = SigningKey::from_bytes(&[2u8; 32]);
+        let key = classic_key.verifying_key().to_bytes();
+        let owner = ScAddress::Account(AccountId(PublicKey::PublicKeyTypeEd25519(Uint256(key))));
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(&env, &contract_id);
+        client.initialize(&Address::try_from_val(&env, &owner).unwrap());
+
+        // The key behind a `G...` owner is not a signer: its account may have
+        // other signers or thresholds the contract cannot see.
+        let payload = payload(&env, &s, 0);
+        let hash = client.get_signed_payload_hash(&payload);
+        assert_eq!(
+            client.try_execute_signed(&payload, &sign(&env, &classic_key, &hash)),
+            Err(Ok(ContractError::UnknownSigner))
+        );
+    }
+
+    #[test]
+    fn test_execute_signed_rejects_other_network() {
+        let env = Env::default();
+        let s = setup(&env);
+        let mut payload = payload(&env, &s, 0);
+        payload.network_id = BytesN::from_array(&env, &ancore_payload::network_id(MAINNET));
+        let signature = sign(
+            &env,
+            &s.owner_key,
+            &s.client.get_signed_payload_hash(&payload),
+        );
+
+        assert_eq!(
+            s.client.try_execute_signed(&payload, &signature),
+            Err(Ok(ContractError::WrongNetwork))
+        );
+        assert_eq!(s.client.get_nonce(&0), 0);
+    }
+
+    #[test]
+    fn test_signed_payload_hash_matches_off_chain_hash() {
+        let env = Env::default();
+        let s = setup(&env);
+        let payload = payload(&env, &s, 7);
+
+        let ScAddress::Contract(Hash(contract_id)) = ScAddress::from(&s.client.address) else {
+            panic!("account is not a contract");
+        };
+        let call_xdr: std::vec::Vec<u8> = payload.call.clone().to_xdr(&env).iter().collect();
+        let expected = Payload {
+            contract_id,
+            network_id: ancore_payload::network_id(TESTNET),
+            channel: 0,
+            nonce: 7,
+            call_hash: ancore_payload::call_hash(&call_xdr),
+            expires_at: 1_000,
+        }
+        .hash();
+
+        assert_eq!(
+            s.client.get_signed_payload_hash(&payload).to_array(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_signed_payload_hash_binds_account() {
+        let env = Env::default();
+        let s = setup(&env);
+        let other = setup(&env);
+        let payload = payload(&env, &s, 0);
+
+        assert_ne!(
+            s.client.get_signed_payload_hash(&payload),
+            other.client.get_signed_payload_hash(&payload)
+        );
+    }
+}
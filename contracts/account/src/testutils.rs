@@ -0,0 +1,43 @@
+//! Contracts the unit tests point `execute` at.
+
+use soroban_sdk::{contract, contractimpl, Address, Env};
+
+/// Stand-in for any contract the account calls.
+///
+/// The token functions have the SEP-41 shape but only tally what each
+/// recipient was sent or approved, so tests can check a call went through
+/// without minting balances.
+#[contract]
+pub struct MockTarget;
+
+#[contractimpl]
+impl MockTarget {
+    pub fn transfer(env: Env, _from: Address, to: Address, amount: i128) {
+        let received = Self::received(env.clone(), to.clone());
+        env.storage().instance().set(&to, &(received + amount));
+    }
+
+    pub fn transfer_from(env: Env, _spender: Address, from: Address, to: Address, amount: i128) {
+        Self::transfer(env, from, to, amount);
+    }
+
+    pub fn approve(env: Env, _from: Address, spender: Address, amount: i128, _expiration: u32) {
+        env.storage().instance().set(&spender, &amount);
+    }
+
+    pub fn burn(_env: Env, _from: Address, _amount: i128) {}
+
+    pub fn received(env: Env, to: Address) -> i128 {
+        env.storage().instance().get(&to).unwrap_or(0)
+    }
+
+    pub fn ping(_env: Env) {}
+
+    pub fn fail(_env: Env) {
+        panic!("target failed");
+    }
+}
+
+pub fn register_target(env: &Env) -> Address {
+    env.register_contract(None, MockTarget)
+}
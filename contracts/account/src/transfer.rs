@@ -0,0 +1,71 @@
+//! Recognition of token transfers made by the account.
+//!
+//! Policies that meter or restrict value leaving the account need to know
+//! when a call moves tokens. Every SEP-41 call that can take tokens out of
+//! this account is recognised:
+//!
+//! - `transfer(from, to, amount)` and `transfer_from(spender, from, to,
+//!   amount)` with `from` equal to this account;
+//! - `approve(from, spender, amount, expiration_ledger)`, counted as moving
+//!   the whole allowance to `spender`, since the spender can then take it
+//!   with `transfer_from` without the account seeing it. Approving zero only
+//!   revokes an allowance and is not a transfer;
+//! - `burn(from, amount)` and `burn_from(spender, from, amount)`, counted as
+//!   a transfer to the token contract itself.
+//!
+//! Other functions are treated as opaque calls.
+
+use soroban_sdk::{symbol_short, Address, Env, Symbol, TryFromVal, Val, Vec};
+
+/// An outgoing token transfer found in a call the account is about to make.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Transfer {
+    pub token: Address,
+    pub to: Address,
+    pub amount: i128,
+}
+
+/// Returns the outgoing transfer described by a call, if it is one.
+pub fn detect(
+    env: &Env,
+    contract: &Address,
+    function: &Symbol,
+    args: &Vec<Val>,
+) -> Option<Transfer> {
+    // Positions of `from`, the recipient (if any) and `amount` in the args.
+    let (arity, from, to, amount) = if *function == symbol_short!("transfer") {
+        (3, 0, Some(1), 2)
+    } else if *function == Symbol::new(env, "transfer_from") {
+        (4, 1, Some(2), 3)
+    } else if *function == symbol_short!("approve") {
+        (4, 0, Some(1), 2)
+    } else if *function == symbol_short!("burn") {
+        (2, 0, None, 1)
+    } else if *function == symbol_short!("burn_from") {
+        (3, 1, None, 2)
+    } else {
+        return None;
+    };
+    if args.len() != arity {
+        return None;
+    }
+
+    let from = Address::try_from_val(env, &args.get_unchecked(from)).ok()?;
+    if from != env.current_contract_address() {
+        return None;
+    }
+
+    let amount = i128::try_from_val(env, &args.get_unchecked(amount)).ok()?;
+    if *function == symbol_short!("approve") && amount == 0 {
+        return None;
+    }
+
+    Some(Transfer {
+        token: contract.clone(),
+        to: match to {
+            Some(to) => Address::try_from_val(env, &args.get_unchecked(to)).ok()?,
+            None => contract.clone(),
+        },
+        amount,
+    })
+}
@@ -0,0 +1,300 @@
+//! Second-factor co-signing of high-value transfers.
+//!
+//! The owner can register a device key, such as a hardware wallet kept
+//! apart from the everyday owner key, and a threshold. An outgoing token
+//! transfer of more than `threshold`, including an approval or burn of that
+//! much (see [`crate::transfer`]), then also needs the device's ed25519
+//! signature over the call, given to `execute_with_second_factor`. Every
+//! other path (batches, session keys, proposals, owner-signed auth of
+//! another contract, token fee repayment) has no way to carry that
+//! signature, so such transfers fail there with `SecondFactorRequired`.
+//!
+//! The threshold is compared with each transfer's amount in the token's own
+//! units; pair it with the value cap to bound transfers across tokens.
+//! Changing or removing the second factor is an admin operation, so with an
+//! admin delay set a stolen owner key cannot switch it off at once.
+
+use soroban_sdk::{
+    contracttype, symbol_short, xdr::ToXdr, Address, Bytes, BytesN, Env, Symbol, Val, Vec,
+};
+
+use crate::{events, transfer::Transfer, ContractError, DataKey};
+
+const EXECUTE_DOMAIN: &[u8] = b"ancore-2fa-execute";
+
+/// The registered device key and the transfer amount above which it must
+/// co-sign.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TwoFactor {
+    pub public_key: BytesN<32>,
+    pub threshold: i128,
+}
+
+pub fn get(env: &Env) -> Option<TwoFactor> {
+    env.storage().instance().get(&DataKey::TwoFactor)
+}
+
+/// Register `public_key` as the second factor for transfers above
+/// `threshold`, replacing any earlier one.
+pub fn set(env: &Env, public_key: &BytesN<32>, threshold: i128) -> Result<(), ContractError> {
+    if threshold < 0 {
+        return Err(ContractError::InvalidLimit);
+    }
+
+    let config = TwoFactor {
+        public_key: public_key.clone(),
+        threshold,
+    };
+    env.storage().instance().set(&DataKey::TwoFactor, &config);
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("2fa_set")),
+        config,
+    );
+    Ok(())
+}
+
+pub fn disable(env: &Env) {
+    env.storage().instance().remove(&DataKey::TwoFactor);
+    events::publish(env, (symbol_short!("ancore"), symbol_short!("2fa_off")), ());
+}
+
+/// Hash the device signs to co-sign a call at `nonce` on `channel`, bound to
+/// this account and network.
+pub fn payload(
+    env: &Env,
+    to: &Address,
+    function: &Symbol,
+    args: &Vec<Val>,
+    channel: u32,
+    nonce: u64,
+) -> BytesN<32> {
+    let mut preimage = Bytes::from_slice(env, EXECUTE_DOMAIN);
+    preimage.append(&env.current_contract_address().to_xdr(env));
+    preimage.append(&Bytes::from(env.ledger().network_id()));
+    preimage.append(&to.clone().to_xdr(env));
+    preimage.append(&function.clone().to_xdr(env));
+    preimage.append(&args.clone().to_xdr(env));
+    preimage.extend_from_array(&channel.to_be_bytes());
+    preimage.extend_from_array(&nonce.to_be_bytes());
+    env.crypto().sha256(&preimage).into()
+}
+
+/// Check the device signed `payload`.
+///
+/// Fails with `Unauthorized` if no second factor is registered. A bad
+/// signature fails the call outright, as `ed25519_verify` traps.
+pub fn verify(
+    env: &Env,
+    payload: &BytesN<32>,
+    signature: &BytesN<64>,
+) -> Result<(), ContractError> {
+    let config = get(env).ok_or(ContractError::Unauthorized)?;
+    env.crypto()
+        .ed25519_verify(&config.public_key, &payload.clone().into(), signature);
+    Ok(())
+}
+
+/// Check `transfer` is below the threshold or was co-signed.
+pub fn check(env: &Env, transfer: &Transfer, co_signed: bool) -> Result<(), ContractError> {
+    match get(env) {
+        Some(config) if transfer.amount > config.threshold && !co_signed => {
+            Err(ContractError::SecondFactorRequired)
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        testutils::{register_target, MockTargetClient},
+        AdminOp, AncoreAccount, AncoreAccountClient, Call,
+    };
+    use ed25519_dalek::{Signer as _, SigningKey};
+    use soroban_sdk::{
+        testutils::{Address as _, Ledger as _},
+        vec, IntoVal,
+    };
+
+    struct Setup<'a> {
+        client: AncoreAccountClient<'a>,
+        token: MockTargetClient<'a>,
+        device: SigningKey,
+        recipient: Address,
+    }
+
+    fn setup(env: &Env) -> Setup<'_> {
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(env, &contract_id);
+        client.initialize(&Address::generate(env));
+        let device = SigningKey::from_bytes(&[6u8; 32]);
+        client.set_two_factor(
+            &BytesN::from_array(env, &device.verifying_key().to_bytes()),
+            &100,
+        );
+        Setup {
+            client,
+            token: MockTargetClient::new(env, &register_target(env)),
+            device,
+            recipient: Address::generate(env),
+        }
+    }
+
+    fn pay(env: &Env, s: &Setup, amount: i128) -> Vec<Val> {
+        vec![
+            env,
+            s.client.address.into_val(env),
+            s.recipient.into_val(env),
+            amount.into_val(env),
+        ]
+    }
+
+    #[test]
+    fn test_transfer_above_threshold_needs_second_factor() {
+        let env = Env::default();
+        let s = setup(&env);
+        let transfer = Symbol::new(&env, "transfer");
+
+        // At the threshold the owner alone is enough.
+        s.client
+            .execute(&s.token.address, &transfer, &pay(&env, &s, 100), &0, &0);
+        assert_eq!(
+            s.client
+                .try_execute(&s.token.address, &transfer, &pay(&env, &s, 101), &0, &1),
+            Err(Ok(ContractError::SecondFactorRequired))
+        );
+
+        let payload = s.client.get_two_factor_payload(
+            &s.token.address,
+            &transfer,
+            &pay(&env, &s, 101),
+            &0,
+            &1,
+        );
+        let signature = BytesN::from_array(&env, &s.device.sign(&payload.to_array()).to_bytes());
+        s.client.execute_with_second_factor(
+            &s.token.address,
+            &transfer,
+            &pay(&env, &s, 101),
+            &0,
+            &1,
+            &signature,
+        );
+        assert_eq!(s.token.received(&s.recipient), 201);
+        assert_eq!(s.client.get_nonce(&0), 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_second_factor_rejects_signature_for_other_call() {
+        let env = Env::default();
+        let s = setup(&env);
+        let transfer = Symbol::new(&env, "transfer");
+
+        let payload = s.client.get_two_factor_payload(
+            &s.token.address,
+            &transfer,
+            &pay(&env, &s, 101),
+            &0,
+            &0,
+        );
+        let signature = BytesN::from_array(&env, &s.device.sign(&payload.to_array()).to_bytes());
+        s.client.execute_with_second_factor(
+            &s.token.address,
+            &transfer,
+            &pay(&env, &s, 5_000),
+            &0,
+            &0,
+            &signature,
+        );
+    }
+
+    #[test]
+    fn test_large_approval_needs_second_factor() {
+        let env = Env::default();
+        let s = setup(&env);
+        let approve = Symbol::new(&env, "approve");
+        let args = |amount: i128| {
+            vec![
+                &env,
+                s.client.address.into_val(&env),
+                s.recipient.into_val(&env),
+                amount.into_val(&env),
+                1_000u32.into_val(&env),
+            ]
+        };
+
+        // Otherwise the spender could take it with `transfer_from`.
+        assert_eq!(
+            s.client
+                .try_execute(&s.token.address, &approve, &args(101), &0, &0),
+            Err(Ok(ContractError::SecondFactorRequired))
+        );
+        s.client
+            .execute(&s.token.address, &approve, &args(100), &0, &0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_second_factor_signature_bound_to_network() {
+        let env = Env::default();
+        let s = setup(&env);
+        let transfer = Symbol::new(&env, "transfer");
+        let payload = s.client.get_two_factor_payload(
+            &s.token.address,
+            &transfer,
+            &pay(&env, &s, 101),
+            &0,
+            &0,
+        );
+        let signature = BytesN::from_array(&env, &s.device.sign(&payload.to_array()).to_bytes());
+
+        env.ledger().with_mut(|li| li.network_id = [9u8; 32]);
+        s.client.execute_with_second_factor(
+            &s.token.address,
+            &transfer,
+            &pay(&env, &s, 101),
+            &0,
+            &0,
+            &signature,
+        );
+    }
+
+    #[test]
+    fn test_batch_cannot_bypass_second_factor() {
+        let env = Env::default();
+        let s = setup(&env);
+        let call = Call {
+            to: s.token.address.clone(),
+            function: Symbol::new(&env, "transfer"),
+            args: pay(&env, &s, 101),
+        };
+
+        assert_eq!(
+            s.client.try_execute_batch(&vec![&env, call], &0, &0),
+            Err(Ok(ContractError::SecondFactorRequired))
+        );
+    }
+
+    #[test]
+    fn test_second_factor_changes_are_admin_ops() {
+        let env = Env::default();
+        let s = setup(&env);
+        s.client.set_admin_delay(&10);
+
+        assert_eq!(
+            s.client.try_disable_two_factor(),
+            Err(Ok(ContractError::AdminOpTimelocked))
+        );
+        s.client.queue_admin_op(&AdminOp::DisableTwoFactor);
+        assert!(s.client.get_two_factor().is_some());
+
+        env.ledger().with_mut(|li| li.sequence_number += 10);
+        s.client.execute_admin_op();
+        assert_eq!(s.client.get_two_factor(), None);
+    }
+}
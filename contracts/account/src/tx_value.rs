@@ -0,0 +1,354 @@
+//! Cap on the total value a single transaction can move.
+//!
+//! Every outgoing transfer in a transaction is converted into a reference
+//! token through a price oracle and summed; the transaction is rejected when
+//! the total exceeds the configured cap. This bounds the blast radius of a
+//! single call independently of per-token limits. Converted values round up,
+//! so many small transfers cannot each count as nothing.
+
+use soroban_sdk::{contractclient, contracttype, symbol_short, Address, Env};
+
+use crate::{events, transfer::Transfer, ContractError, DataKey};
+
+/// Fixed-point scale of oracle prices: a price of `PRICE_SCALE` means one
+/// unit of the token is worth one unit of the reference token.
+pub const PRICE_SCALE: i128 = 10_000_000;
+
+/// Price source consulted when converting transfers into the reference token.
+#[contractclient(name = "PriceOracleClient")]
+pub trait PriceOracle {
+    /// Price of one unit of `token` in units of `reference`, scaled by
+    /// [`PRICE_SCALE`].
+    fn price(env: Env, token: Address, reference: Address) -> i128;
+}
+
+/// Configured per-transaction value cap.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MaxTxValue {
+    pub reference_token: Address,
+    pub oracle: Address,
+    pub amount: i128,
+}
+
+pub fn set(env: &Env, config: &MaxTxValue) -> Result<(), ContractError> {
+    if config.amount <= 0 {
+        return Err(ContractError::InvalidLimit);
+    }
+
+    env.storage().instance().set(&DataKey::MaxTxValue, config);
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("max_tx")),
+        config.clone(),
+    );
+    Ok(())
+}
+
+/// Whether replacing the current cap with `config` could let more through.
+pub fn loosens(env: &Env, config: &MaxTxValue) -> bool {
+    get(env).is_some_and(|current| {
+        config.amount > current.amount
+            || config.reference_token != current.reference_token
+            || config.oracle != current.oracle
+    })
+}
+
+/// Remove the cap.
+pub fn clear(env: &Env) {
+    env.storage().instance().remove(&DataKey::MaxTxValue);
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("maxtx_off")),
+        (),
+    );
+}
+
+pub fn get(env: &Env) -> Option<MaxTxValue> {
+    env.storage().instance().get(&DataKey::MaxTxValue)
+}
+
+/// Running total of the value moved by the transaction being executed.
+pub struct Meter {
+    config: Option<MaxTxValue>,
+    total: i128,
+}
+
+impl Meter {
+    pub fn new(env: &Env) -> Self {
+        Self {
+            config: get(env),
+            total: 0,
+        }
+    }
+
+    /// Add a transfer to the total, failing once the cap is exceeded.
+    pub fn add(&mut self, env: &Env, transfer: &Transfer) -> Result<(), ContractError> {
+        let Some(config) = &self.config else {
+            return Ok(());
+        };
+
+        let value = if transfer.token == config.reference_token {
+            transfer.amount
+        } else {
+            let price = PriceOracleClient::new(env, &config.oracle)
+                .price(&transfer.token, &config.reference_token);
+            if price <= 0 {
+                return Err(ContractError::InvalidPrice);
+            }
+            // Amounts are never negative here, so this rounds up.
+            transfer
+                .amount
+                .checked_mul(price)
+                .and_then(|scaled| scaled.checked_add(PRICE_SCALE - 1))
+                .ok_or(ContractError::Overflow)?
+                / PRICE_SCALE
+        };
+
+        self.total = self
+            .total
+            .checked_add(value)
+            .ok_or(ContractError::Overflow)?;
+        if self.total > config.amount {
+            return Err(ContractError::TxValueExceeded);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{testutils::register_target, AdminOp, AncoreAccount, AncoreAccountClient, Call};
+    use soroban_sdk::{
+        contract, contractimpl,
+        testutils::{Address as _, Ledger as _},
+        vec, IntoVal, Symbol, Val, Vec,
+    };
+
+    #[contract]
+    struct MockOracle;
+
+    #[contractimpl]
+    impl MockOracle {
+        pub fn set_price(env: Env, token: Address, price: i128) {
+            env.storage().instance().set(&token, &price);
+        }
+
+        pub fn price(env: Env, token: Address, _reference: Address) -> i128 {
+            env.storage().instance().get(&token).unwrap()
+        }
+    }
+
+    fn transfer_args(env: &Env, account: &Address, amount: i128) -> Vec<Val> {
+        vec![
+            env,
+            account.into_val(env),
+            Address::generate(env).into_val(env),
+            amount.into_val(env),
+        ]
+    }
+
+    fn setup(env: &Env) -> (Address, AncoreAccountClient<'_>, MockOracleClient<'_>) {
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(env, &contract_id);
+        client.initialize(&Address::generate(env));
+        let oracle = MockOracleClient::new(env, &env.register_contract(None, MockOracle));
+        (contract_id, client, oracle)
+    }
+
+    #[test]
+    fn test_transfer_within_cap() {
+        let env = Env::default();
+        let (account, client, oracle) = setup(&env);
+        let usdc = register_target(&env);
+        let xlm = register_target(&env);
+        let transfer = Symbol::new(&env, "transfer");
+
+        // 1 XLM = 0.1 USDC
+        oracle.set_price(&xlm, &(PRICE_SCALE / 10));
+        client.set_max_tx_value(&usdc, &oracle.address, &1_000);
+
+        client.execute(
+            &usdc,
+            &transfer,
+            &transfer_args(&env, &account, 1_000),
+            &0,
+            &0,
+        );
+        client.execute(
+            &xlm,
+            &transfer,
+            &transfer_args(&env, &account, 10_000),
+            &0,
+            &1,
+        );
+
+        assert_eq!(
+            client.get_max_tx_value(),
+            Some(MaxTxValue {
+                reference_token: usdc,
+                oracle: oracle.address.clone(),
+                amount: 1_000,
+            })
+        );
+        assert_eq!(client.get_nonce(&0), 2);
+    }
+
+    #[test]
+    fn test_transfer_over_cap_is_rejected() {
+        let env = Env::default();
+        let (account, client, oracle) = setup(&env);
+        let usdc = register_target(&env);
+        let xlm = register_target(&env);
+        let transfer = Symbol::new(&env, "transfer");
+
+        oracle.set_price(&xlm, &(PRICE_SCALE / 10));
+        client.set_max_tx_value(&usdc, &oracle.address, &1_000);
+
+        assert_eq!(
+            client.try_execute(
+                &usdc,
+                &transfer,
+                &transfer_args(&env, &account, 1_001),
+                &0,
+                &0
+            ),
+            Err(Ok(ContractError::TxValueExceeded))
+        );
+        assert_eq!(
+            client.try_execute(
+                &xlm,
+                &transfer,
+                &transfer_args(&env, &account, 10_010),
+                &0,
+                &0
+            ),
+            Err(Ok(ContractError::TxValueExceeded))
+        );
+        assert_eq!(client.get_nonce(&0), 0);
+    }
+
+    #[test]
+    fn test_batch_transfers_share_cap() {
+        let env = Env::default();
+        let (account, client, oracle) = setup(&env);
+        let usdc = register_target(&env);
+        let transfer = |amount: i128| Call {
+            to: usdc.clone(),
+            function: Symbol::new(&env, "transfer"),
+            args: transfer_args(&env, &account, amount),
+        };
+
+        client.set_max_tx_value(&usdc, &oracle.address, &1_000);
+        client.execute_batch(&vec![&env, transfer(600), transfer(400)], &0, &0);
+        assert_eq!(
+            client.try_execute_batch(&vec![&env, transfer(600), transfer(401)], &0, &1),
+            Err(Ok(ContractError::TxValueExceeded))
+        );
+        assert_eq!(client.get_nonce(&0), 1);
+    }
+
+    #[test]
+    fn test_batch_converts_other_tokens_through_oracle() {
+        let env = Env::default();
+        let (account, client, oracle) = setup(&env);
+        let usdc = register_target(&env);
+        let xlm = register_target(&env);
+        let transfer = |token: &Address, amount: i128| Call {
+            to: token.clone(),
+            function: Symbol::new(&env, "transfer"),
+            args: transfer_args(&env, &account, amount),
+        };
+
+        // 1 XLM = 0.1 USDC
+        oracle.set_price(&xlm, &(PRICE_SCALE / 10));
+        client.set_max_tx_value(&usdc, &oracle.address, &1_000);
+        client.execute_batch(
+            &vec![&env, transfer(&usdc, 600), transfer(&xlm, 4_000)],
+            &0,
+            &0,
+        );
+        assert_eq!(
+            client.try_execute_batch(
+                &vec![&env, transfer(&usdc, 600), transfer(&xlm, 4_010)],
+                &0,
+                &1
+            ),
+            Err(Ok(ContractError::TxValueExceeded))
+        );
+
+        // Dust worth a fraction of a unit still counts as a whole one.
+        let mut dust = vec![&env, transfer(&usdc, 995)];
+        for _ in 0..5 {
+            dust.push_back(transfer(&xlm, 1));
+        }
+        client.execute_batch(&dust, &0, &1);
+        dust.push_back(transfer(&xlm, 1));
+        assert_eq!(
+            client.try_execute_batch(&dust, &0, &2),
+            Err(Ok(ContractError::TxValueExceeded))
+        );
+
+        client.clear_max_tx_value();
+        assert_eq!(client.get_max_tx_value(), None);
+        client.execute_batch(&dust, &0, &2);
+    }
+
+    #[test]
+    fn test_loosening_cap_is_admin_op() {
+        let env = Env::default();
+        let (_, client, oracle) = setup(&env);
+        let usdc = register_target(&env);
+        client.set_max_tx_value(&usdc, &oracle.address, &1_000);
+        client.set_admin_delay(&10);
+
+        client.set_max_tx_value(&usdc, &oracle.address, &500);
+        for (reference_token, oracle, amount) in [
+            (usdc.clone(), oracle.address.clone(), 501),
+            (register_target(&env), oracle.address.clone(), 500),
+            (usdc.clone(), Address::generate(&env), 500),
+        ] {
+            assert_eq!(
+                client.try_set_max_tx_value(&reference_token, &oracle, &amount),
+                Err(Ok(ContractError::AdminOpTimelocked))
+            );
+        }
+        assert_eq!(
+            client.try_clear_max_tx_value(),
+            Err(Ok(ContractError::AdminOpTimelocked))
+        );
+
+        client.queue_admin_op(&AdminOp::ClearMaxTxValue);
+        env.ledger().with_mut(|li| li.sequence_number += 10);
+        client.execute_admin_op();
+        assert_eq!(client.get_max_tx_value(), None);
+    }
+
+    #[test]
+    fn test_invalid_price_rejected() {
+        let env = Env::default();
+        let (account, client, oracle) = setup(&env);
+        let usdc = register_target(&env);
+        let token = register_target(&env);
+
+        oracle.set_price(&token, &0);
+        client.set_max_tx_value(&usdc, &oracle.address, &1_000);
+
+        assert_eq!(
+            client.try_execute(
+                &token,
+                &Symbol::new(&env, "transfer"),
+                &transfer_args(&env, &account, 1),
+                &0,
+                &0
+            ),
+            Err(Ok(ContractError::InvalidPrice))
+        );
+        assert_eq!(
+            client.try_set_max_tx_value(&usdc, &oracle.address, &0),
+            Err(Ok(ContractError::InvalidLimit))
+        );
+    }
+}
@@ -0,0 +1,204 @@
+//! Timelocked code upgrades.
+//!
+//! The account replaces its own wasm with an already uploaded one, keeping
+//! its address and storage. An upgrade is never instant: the owner proposes
+//! a wasm hash, and it can only be applied once the upgrade delay has passed,
+//! giving users and integrators a window to review the new code. The owner
+//! can cancel until then.
+//!
+//! The delay defaults to, and can never be set below, [`UPGRADE_DELAY`], so
+//! even a stolen owner key cannot upgrade without warning. There is a single
+//! pending slot (`TimelockConflict`), a change of owner discards the pending
+//! upgrade, and upgrades are refused while the account is frozen.
+
+use soroban_sdk::{contracttype, symbol_short, BytesN, Env};
+
+use crate::{events, freeze, ContractError, DataKey};
+
+/// Minimum and default seconds between proposing and applying an upgrade.
+pub const UPGRADE_DELAY: u64 = 2 * 86_400;
+
+/// An upgrade waiting for its delay to pass.
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PendingUpgrade {
+    pub wasm_hash: BytesN<32>,
+    pub executable_at: u64,
+}
+
+/// Seconds a proposed upgrade waits.
+pub fn delay(env: &Env) -> u64 {
+    env.storage()
+        .instance()
+        .get(&DataKey::UpgradeDelay)
+        .unwrap_or(UPGRADE_DELAY)
+}
+
+/// Change the delay for later proposals; it cannot go below `UPGRADE_DELAY`.
+pub fn set_delay(env: &Env, delay: u64) -> Result<(), ContractError> {
+    if delay < UPGRADE_DELAY {
+        return Err(ContractError::InvalidLimit);
+    }
+    env.storage().instance().set(&DataKey::UpgradeDelay, &delay);
+
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("upg_delay")),
+        delay,
+    );
+    Ok(())
+}
+
+pub fn pending(env: &Env) -> Option<PendingUpgrade> {
+    env.storage().instance().get(&DataKey::PendingUpgrade)
+}
+
+pub fn propose(env: &Env, wasm_hash: &BytesN<32>) -> Result<PendingUpgrade, ContractError> {
+    freeze::check_not_frozen(env)?;
+    if pending(env).is_some() {
+        return Err(ContractError::TimelockConflict);
+    }
+
+    let upgrade = PendingUpgrade {
+        wasm_hash: wasm_hash.clone(),
+        executable_at: env.ledger().timestamp().saturating_add(delay(env)),
+    };
+    env.storage()
+        .instance()
+        .set(&DataKey::PendingUpgrade, &upgrade);
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("upg_prop")),
+        upgrade.clone(),
+    );
+    Ok(upgrade)
+}
+
+pub fn cancel(env: &Env) -> Result<(), ContractError> {
+    let upgrade = pending(env).ok_or(ContractError::NoUpgradePending)?;
+    discard(env);
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("upg_cncl")),
+        upgrade.wasm_hash,
+    );
+    Ok(())
+}
+
+/// Apply the pending upgrade once its delay has passed.
+pub fn execute(env: &Env) -> Result<(), ContractError> {
+    freeze::check_not_frozen(env)?;
+    let upgrade = pending(env).ok_or(ContractError::NoUpgradePending)?;
+    if env.ledger().timestamp() < upgrade.executable_at {
+        return Err(ContractError::TimelockNotExpired);
+    }
+
+    discard(env);
+    env.deployer()
+        .update_current_contract_wasm(upgrade.wasm_hash.clone());
+    events::publish(
+        env,
+        (symbol_short!("ancore"), symbol_short!("upgraded")),
+        upgrade.wasm_hash,
+    );
+    Ok(())
+}
+
+/// Drop any pending upgrade, e.g. because the owner changed.
+pub fn discard(env: &Env) {
+    env.storage().instance().remove(&DataKey::PendingUpgrade);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{AncoreAccount, AncoreAccountClient};
+    use soroban_sdk::testutils::{Address as _, Ledger as _};
+    use soroban_sdk::Address;
+
+    fn setup(env: &Env) -> AncoreAccountClient<'_> {
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreAccount);
+        let client = AncoreAccountClient::new(env, &contract_id);
+        client.initialize(&Address::generate(env));
+        client
+    }
+
+    #[test]
+    fn test_upgrade_waits_for_delay() {
+        let env = Env::default();
+        let client = setup(&env);
+        let hash = BytesN::from_array(&env, &[7u8; 32]);
+
+        assert_eq!(
+            client.try_execute_upgrade(),
+            Err(Ok(ContractError::NoUpgradePending))
+        );
+        let upgrade = client.propose_upgrade(&hash);
+        assert_eq!(
+            upgrade,
+            PendingUpgrade {
+                wasm_hash: hash.clone(),
+                executable_at: UPGRADE_DELAY,
+            }
+        );
+        assert_eq!(client.get_pending_upgrade(), Some(upgrade));
+        assert_eq!(
+            client.try_propose_upgrade(&hash),
+            Err(Ok(ContractError::TimelockConflict))
+        );
+        assert_eq!(
+            client.try_execute_upgrade(),
+            Err(Ok(ContractError::TimelockNotExpired))
+        );
+
+        // The hash was never uploaded, so applying it fails in the host.
+        env.ledger().with_mut(|li| li.timestamp = UPGRADE_DELAY);
+        assert!(client.try_execute_upgrade().is_err());
+        assert!(client.get_pending_upgrade().is_some());
+    }
+
+    #[test]
+    fn test_cancel_upgrade() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        client.propose_upgrade(&BytesN::from_array(&env, &[7u8; 32]));
+        client.cancel_upgrade();
+        assert_eq!(client.get_pending_upgrade(), None);
+        assert_eq!(
+            client.try_cancel_upgrade(),
+            Err(Ok(ContractError::NoUpgradePending))
+        );
+    }
+
+    #[test]
+    fn test_upgrade_delay_has_a_floor() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        assert_eq!(
+            client.try_set_upgrade_delay(&(UPGRADE_DELAY - 1)),
+            Err(Ok(ContractError::InvalidLimit))
+        );
+        client.set_upgrade_delay(&(UPGRADE_DELAY * 5));
+        assert_eq!(client.get_upgrade_delay(), UPGRADE_DELAY * 5);
+        assert_eq!(
+            client
+                .propose_upgrade(&BytesN::from_array(&env, &[7u8; 32]))
+                .executable_at,
+            UPGRADE_DELAY * 5
+        );
+    }
+
+    #[test]
+    fn test_upgrade_is_blocked_while_frozen() {
+        let env = Env::default();
+        let client = setup(&env);
+
+        client.propose_upgrade(&BytesN::from_array(&env, &[7u8; 32]));
+        client.freeze(&client.get_owner());
+        env.ledger().with_mut(|li| li.timestamp = UPGRADE_DELAY);
+        assert_eq!(client.try_execute_upgrade(), Err(Ok(ContractError::Frozen)));
+    }
+}
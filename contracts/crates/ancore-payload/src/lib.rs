@@ -0,0 +1,167 @@
+#![no_std]
+
+//! # Ancore Signing Payload
+//!
+//! The canonical hash an account owner signs to authorize a call that
+//! someone else submits. The account contract and off-chain signers both
+//! build it from this crate, so what a wallet signs and what the contract
+//! verifies cannot drift apart.
+//!
+//! ## Format
+//!
+//! The hash is `sha256` over a fixed-length preimage:
+//!
+//! | Field         | Bytes | Encoding                                 |
+//! |---------------|-------|------------------------------------------|
+//! | domain        | 24    | `b"ancore-signed-payload-v1"`            |
+//! | `contract_id` | 32    | account contract id                      |
+//! | `network_id`  | 32    | `sha256` of the network passphrase       |
+//! | `channel`     | 4     | big-endian `u32` nonce channel           |
+//! | `nonce`       | 8     | big-endian `u64`                         |
+//! | `call_hash`   | 32    | `sha256` of the XDR-encoded call         |
+//! | `expires_at`  | 8     | big-endian `u64` ledger timestamp        |
+//!
+//! Every field has a fixed width, so no two payloads share a preimage.
+//! Contracts hash [`Payload::preimage`] with the host's `sha256`; off-chain
+//! signers enable the `sha2` feature and use [`Payload::hash`].
+
+/// Domain separator, versioned so the format can change without old
+/// signatures verifying under the new one.
+pub const DOMAIN: &[u8; 24] = b"ancore-signed-payload-v1";
+
+/// Length of [`Payload::preimage`].
+pub const PREIMAGE_LEN: usize = DOMAIN.len() + 32 + 32 + 4 + 8 + 32 + 8;
+
+/// Everything an owner's signature over a relayed call commits to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Payload {
+    /// Id of the account contract the call runs on
+    pub contract_id: [u8; 32],
+    /// Id of the network the call runs on
+    pub network_id: [u8; 32],
+    /// Nonce channel the call runs on
+    pub channel: u32,
+    /// Account nonce the call must execute at on `channel`
+    pub nonce: u64,
+    /// `sha256` of the XDR-encoded call
+    pub call_hash: [u8; 32],
+    /// Ledger timestamp from which the signature is no longer accepted
+    pub expires_at: u64,
+}
+
+impl Payload {
+    /// The bytes whose `sha256` is signed.
+    pub fn preimage(&self) -> [u8; PREIMAGE_LEN] {
+        let mut out = [0u8; PREIMAGE_LEN];
+        let mut at = 0;
+        for field in [
+            &DOMAIN[..],
+            &self.contract_id,
+            &self.network_id,
+            &self.channel.to_be_bytes(),
+            &self.nonce.to_be_bytes(),
+            &self.call_hash,
+            &self.expires_at.to_be_bytes(),
+        ] {
+            out[at..at + field.len()].copy_from_slice(field);
+            at += field.len();
+        }
+        out
+    }
+
+    /// The hash the owner signs.
+    #[cfg(feature = "sha2")]
+    pub fn hash(&self) -> [u8; 32] {
+        sha256(&self.preimage())
+    }
+}
+
+/// `call_hash` of an XDR-encoded call.
+#[cfg(feature = "sha2")]
+pub fn call_hash(call_xdr: &[u8]) -> [u8; 32] {
+    sha256(call_xdr)
+}
+
+/// `network_id` of the network with `passphrase`, as Stellar defines it.
+#[cfg(feature = "sha2")]
+pub fn network_id(passphrase: &str) -> [u8; 32] {
+    sha256(passphrase.as_bytes())
+}
+
+#[cfg(feature = "sha2")]
+fn sha256(data: &[u8]) -> [u8; 32] {
+    use sha2::{Digest, Sha256};
+    Sha256::digest(data).into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn payload() -> Payload {
+        Payload {
+            contract_id: [1u8; 32],
+            network_id: [2u8; 32],
+            channel: 6,
+            nonce: 3,
+            call_hash: [4u8; 32],
+            expires_at: 5,
+        }
+    }
+
+    #[test]
+    fn test_preimage_layout() {
+        let preimage = payload().preimage();
+
+        assert_eq!(&preimage[..24], DOMAIN);
+        assert_eq!(preimage[24..56], [1u8; 32]);
+        assert_eq!(preimage[56..88], [2u8; 32]);
+        assert_eq!(preimage[88..92], 6u32.to_be_bytes());
+        assert_eq!(preimage[92..100], 3u64.to_be_bytes());
+        assert_eq!(preimage[100..132], [4u8; 32]);
+        assert_eq!(preimage[132..], 5u64.to_be_bytes());
+    }
+
+    #[test]
+    fn test_every_field_changes_preimage() {
+        let base = payload();
+        let variants = [
+            Payload {
+                contract_id: [9u8; 32],
+                ..base
+            },
+            Payload {
+                network_id: [9u8; 32],
+                ..base
+            },
+            Payload { channel: 7, ..base },
+            Payload { nonce: 4, ..base },
+            Payload {
+                call_hash: [9u8; 32],
+                ..base
+            },
+            Payload {
+                expires_at: 6,
+                ..base
+            },
+        ];
+
+        for variant in variants {
+            assert_ne!(variant.preimage(), base.preimage());
+        }
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn test_network_id_matches_stellar() {
+        // sha256("Test SDF Network ; September 2015")
+        assert_eq!(
+            network_id("Test SDF Network ; September 2015"),
+            [
+                0xce, 0xe0, 0x30, 0x2d, 0x59, 0x84, 0x4d, 0x32, 0xbd, 0xca, 0x91, 0x5c, 0x82, 0x03,
+                0xdd, 0x44, 0xb3, 0x3f, 0xbb, 0x7e, 0xdc, 0x19, 0x05, 0x1e, 0xa3, 0x7a, 0xbe, 0xdf,
+                0x28, 0xec, 0xd4, 0x72,
+            ]
+        );
+    }
+}
@@ -0,0 +1,414 @@
+#![no_std]
+
+//! # Ancore Account Factory
+//!
+//! Deploys new Ancore accounts from an uploaded account wasm.
+//!
+//! ## Deployment
+//! `deploy_account` creates the account contract and calls its `initialize`
+//! in the same invocation, so an account is never left deployed but
+//! uninitialized for someone else to claim. If initialization fails the
+//! deployment is rolled back with it.
+//!
+//! `deploy_and_execute` goes one step further and runs the owner's first
+//! call through the new account's `execute` in the same transaction. The
+//! owner authorizes the whole `deploy_and_execute`, with the account's
+//! `execute` as a sub-invocation, and if the call fails the account is not
+//! deployed either.
+//!
+//! ## Registry
+//! When the admin has set a registry, every deployed account is registered
+//! there under its owner.
+//!
+//! ## Addresses
+//! An account's address depends only on this factory, its owner and a
+//! caller-chosen salt: the deployment salt is `sha256(xdr(owner) || salt)`.
+//! `predict_address` returns it before deployment, so funds can be sent to
+//! an account that does not exist yet, and nobody can take an owner's
+//! address by deploying with the same salt for someone else.
+
+use soroban_sdk::{
+    contract, contractclient, contractimpl, contracttype, symbol_short, xdr::ToXdr, Address,
+    BytesN, Env, Symbol, Val, Vec,
+};
+
+/// The part of the account interface the factory calls.
+#[contractclient(name = "AccountClient")]
+pub trait Account {
+    fn initialize(env: Env, owner: Address);
+    fn execute(
+        env: Env,
+        to: Address,
+        function: Symbol,
+        args: Vec<Val>,
+        channel: u32,
+        expected_nonce: u64,
+    ) -> bool;
+}
+
+/// The part of the registry interface the factory calls.
+#[contractclient(name = "RegistryClient")]
+pub trait Registry {
+    fn register(env: Env, account: Address, owner: Address);
+}
+
+/// A call for a new account to make, as in the account's `execute`
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Call {
+    pub to: Address,
+    pub function: Symbol,
+    pub args: Vec<Val>,
+}
+
+#[contracttype]
+#[derive(Clone)]
+pub enum DataKey {
+    Admin,
+    AccountWasmHash,
+    Registry,
+}
+
+#[contract]
+pub struct AncoreFactory;
+
+#[contractimpl]
+impl AncoreFactory {
+    /// Initialize the factory with an admin and the account wasm to deploy
+    ///
+    /// `wasm_hash` must already be uploaded to the network.
+    pub fn initialize(env: Env, admin: Address, wasm_hash: BytesN<32>) {
+        if env.storage().instance().has(&DataKey::Admin) {
+            panic!("Already initialized");
+        }
+
+        env.storage().instance().set(&DataKey::Admin, &admin);
+        env.storage()
+            .instance()
+            .set(&DataKey::AccountWasmHash, &wasm_hash);
+
+        env.events().publish(
+            (symbol_short!("factory"), symbol_short!("init")),
+            (admin, wasm_hash),
+        );
+    }
+
+    /// Get the factory admin
+    pub fn get_admin(env: Env) -> Address {
+        env.storage()
+            .instance()
+            .get(&DataKey::Admin)
+            .expect("Not initialized")
+    }
+
+    /// Get the hash of the account wasm new accounts are deployed from
+    pub fn get_account_wasm_hash(env: Env) -> BytesN<32> {
+        env.storage()
+            .instance()
+            .get(&DataKey::AccountWasmHash)
+            .expect("Not initialized")
+    }
+
+    /// Switch the account wasm used for future deployments
+    ///
+    /// Accounts already deployed keep their code; they move to a new wasm
+    /// through their own upgrade flow.
+    pub fn set_account_wasm_hash(env: Env, wasm_hash: BytesN<32>) {
+        let admin = Self::get_admin(env.clone());
+        admin.require_auth();
+
+        env.storage()
+            .instance()
+            .set(&DataKey::AccountWasmHash, &wasm_hash);
+
+        env.events()
+            .publish((symbol_short!("factory"), symbol_short!("wasm")), wasm_hash);
+    }
+
+    /// Set the registry new accounts are registered with
+    ///
+    /// The registry must list this factory as the one allowed to register
+    /// accounts.
+    pub fn set_registry(env: Env, registry: Address) {
+        let admin = Self::get_admin(env.clone());
+        admin.require_auth();
+
+        env.storage().instance().set(&DataKey::Registry, &registry);
+
+        env.events().publish(
+            (symbol_short!("factory"), symbol_short!("registry")),
+            registry,
+        );
+    }
+
+    /// Get the registry new accounts are registered with, if any
+    pub fn get_registry(env: Env) -> Option<Address> {
+        env.storage().instance().get(&DataKey::Registry)
+    }
+
+    /// Deploy a new account for `owner` and initialize it
+    ///
+    /// The account is deployed at `predict_address(owner, salt)`, so
+    /// deploying twice for the same owner and salt fails.
+    pub fn deploy_account(env: Env, owner: Address, salt: BytesN<32>) -> Address {
+        Self::deploy(&env, owner, &salt)
+    }
+
+    /// Deploy a new account for `owner` and make its first call
+    ///
+    /// The account is deployed and initialized as in `deploy_account`, then
+    /// runs `call` through its `execute` with nonce 0 on channel 0. The
+    /// owner must authorize this invocation including that `execute`. If the
+    /// call fails, nothing is deployed.
+    pub fn deploy_and_execute(env: Env, owner: Address, salt: BytesN<32>, call: Call) -> Address {
+        owner.require_auth();
+        let account = Self::deploy(&env, owner, &salt);
+        AccountClient::new(&env, &account).execute(&call.to, &call.function, &call.args, &0, &0);
+        account
+    }
+
+    /// Get the address `deploy_account(owner, salt)` deploys to
+    ///
+    /// The address is the same whether or not the account has been
+    /// deployed yet.
+    pub fn predict_address(env: Env, owner: Address, salt: BytesN<32>) -> Address {
+        env.deployer()
+            .with_current_contract(account_salt(&env, &owner, &salt))
+            .deployed_address()
+    }
+}
+
+impl AncoreFactory {
+    fn deploy(env: &Env, owner: Address, salt: &BytesN<32>) -> Address {
+        let wasm_hash = Self::get_account_wasm_hash(env.clone());
+        let account = env
+            .deployer()
+            .with_current_contract(account_salt(env, &owner, salt))
+            .deploy(wasm_hash);
+
+        AccountClient::new(env, &account).initialize(&owner);
+        if let Some(registry) = Self::get_registry(env.clone()) {
+            RegistryClient::new(env, &registry).register(&account, &owner);
+        }
+
+        env.events().publish(
+            (symbol_short!("factory"), symbol_short!("deployed")),
+            (owner, account.clone()),
+        );
+
+        account
+    }
+}
+
+/// Deployment salt for `owner`'s account with the caller-chosen `salt`.
+fn account_salt(env: &Env, owner: &Address, salt: &BytesN<32>) -> BytesN<32> {
+    let mut preimage = owner.clone().to_xdr(env);
+    preimage.append(&salt.clone().into());
+    env.crypto().sha256(&preimage).into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use soroban_sdk::{
+        contract, contractimpl,
+        testutils::{Address as _, Events as _},
+        vec, IntoVal,
+    };
+
+    #[allow(clippy::too_many_arguments)]
+    mod account {
+        use soroban_sdk::auth::Context;
+        soroban_sdk::contractimport!(
+            file = "../target/wasm32-unknown-unknown/release/ancore_account.wasm"
+        );
+    }
+
+    #[contract]
+    struct Target;
+
+    #[contractimpl]
+    impl Target {
+        pub fn set(env: Env, value: u32) {
+            if value == 0 {
+                panic!("zero");
+            }
+            env.storage().instance().set(&0u32, &value);
+        }
+
+        pub fn get(env: Env) -> u32 {
+            env.storage().instance().get(&0u32).unwrap_or(0)
+        }
+    }
+
+    #[contract]
+    struct MockRegistry;
+
+    #[contractimpl]
+    impl MockRegistry {
+        pub fn register(env: Env, account: Address, owner: Address) {
+            env.storage().instance().set(&account, &owner);
+        }
+
+        pub fn owner_of(env: Env, account: Address) -> Option<Address> {
+            env.storage().instance().get(&account)
+        }
+    }
+
+    fn setup(env: &Env) -> (AncoreFactoryClient<'_>, Address) {
+        env.mock_all_auths();
+        // Every call into the account instantiates its full wasm module.
+        env.budget().reset_unlimited();
+        let wasm_hash = env.deployer().upload_contract_wasm(account::WASM);
+        let factory_id = env.register_contract(None, AncoreFactory);
+        let factory = AncoreFactoryClient::new(env, &factory_id);
+        let admin = Address::generate(env);
+        factory.initialize(&admin, &wasm_hash);
+        (factory, admin)
+    }
+
+    #[test]
+    fn test_deploy_account_initializes_owner() {
+        let env = Env::default();
+        let (factory, _) = setup(&env);
+        let owner = Address::generate(&env);
+
+        let address = factory.deploy_account(&owner, &BytesN::from_array(&env, &[1; 32]));
+        assert!(!env.events().all().is_empty());
+
+        let account = account::Client::new(&env, &address);
+        assert_eq!(account.get_owner(), owner);
+        assert_eq!(account.get_nonce(&0), 0);
+    }
+
+    #[test]
+    fn test_deploy_account_salts_give_distinct_accounts() {
+        let env = Env::default();
+        let (factory, _) = setup(&env);
+        let owner = Address::generate(&env);
+
+        let first = factory.deploy_account(&owner, &BytesN::from_array(&env, &[1; 32]));
+        let second = factory.deploy_account(&owner, &BytesN::from_array(&env, &[2; 32]));
+        assert_ne!(first, second);
+
+        let reused = factory.try_deploy_account(&owner, &BytesN::from_array(&env, &[1; 32]));
+        assert!(reused.is_err());
+    }
+
+    #[test]
+    fn test_predict_address_matches_deployment() {
+        let env = Env::default();
+        let (factory, _) = setup(&env);
+        let owner = Address::generate(&env);
+        let salt = BytesN::from_array(&env, &[3; 32]);
+
+        let predicted = factory.predict_address(&owner, &salt);
+        assert_eq!(factory.deploy_account(&owner, &salt), predicted);
+        assert_eq!(factory.predict_address(&owner, &salt), predicted);
+    }
+
+    #[test]
+    fn test_predict_address_is_bound_to_owner() {
+        let env = Env::default();
+        let (factory, _) = setup(&env);
+        let owner = Address::generate(&env);
+        let other = Address::generate(&env);
+        let salt = BytesN::from_array(&env, &[3; 32]);
+
+        let predicted = factory.predict_address(&owner, &salt);
+        assert_ne!(factory.predict_address(&other, &salt), predicted);
+
+        // Deploying the same salt for someone else leaves the owner's
+        // address free.
+        factory.deploy_account(&other, &salt);
+        let address = factory.deploy_account(&owner, &salt);
+        assert_eq!(address, predicted);
+        assert_eq!(account::Client::new(&env, &address).get_owner(), owner);
+    }
+
+    #[test]
+    fn test_deploy_and_execute_runs_first_call() {
+        let env = Env::default();
+        let (factory, _) = setup(&env);
+        let owner = Address::generate(&env);
+        let salt = BytesN::from_array(&env, &[4; 32]);
+        let target = TargetClient::new(&env, &env.register_contract(None, Target));
+        let call = Call {
+            to: target.address.clone(),
+            function: Symbol::new(&env, "set"),
+            args: vec![&env, 7u32.into_val(&env)],
+        };
+
+        let address = factory.deploy_and_execute(&owner, &salt, &call);
+        let auths = env.auths();
+        assert_eq!(auths.len(), 1);
+        assert_eq!(auths[0].0, owner);
+        // The account's `execute` is covered by the same authorization.
+        assert_eq!(auths[0].1.sub_invocations.len(), 1);
+        assert_eq!(address, factory.predict_address(&owner, &salt));
+        assert_eq!(target.get(), 7);
+
+        let account = account::Client::new(&env, &address);
+        assert_eq!(account.get_owner(), owner);
+        assert_eq!(account.get_nonce(&0), 1);
+    }
+
+    #[test]
+    fn test_deploy_and_execute_failed_call_deploys_nothing() {
+        let env = Env::default();
+        let (factory, _) = setup(&env);
+        let owner = Address::generate(&env);
+        let salt = BytesN::from_array(&env, &[4; 32]);
+        let target = env.register_contract(None, Target);
+        let call = Call {
+            to: target,
+            function: Symbol::new(&env, "set"),
+            args: vec![&env, 0u32.into_val(&env)],
+        };
+
+        assert!(factory
+            .try_deploy_and_execute(&owner, &salt, &call)
+            .is_err());
+
+        // The address is still free.
+        let address = factory.deploy_account(&owner, &salt);
+        assert_eq!(address, factory.predict_address(&owner, &salt));
+    }
+
+    #[test]
+    fn test_deploy_account_registers_with_registry() {
+        let env = Env::default();
+        let (factory, admin) = setup(&env);
+        let registry = env.register_contract(None, MockRegistry);
+        let owner = Address::generate(&env);
+
+        factory.set_registry(&registry);
+        assert_eq!(env.auths()[0].0, admin);
+        assert_eq!(factory.get_registry(), Some(registry.clone()));
+
+        let address = factory.deploy_account(&owner, &BytesN::from_array(&env, &[5; 32]));
+        assert_eq!(
+            MockRegistryClient::new(&env, &registry).owner_of(&address),
+            Some(owner)
+        );
+    }
+
+    #[test]
+    fn test_set_account_wasm_hash_requires_admin() {
+        let env = Env::default();
+        let (factory, admin) = setup(&env);
+        let wasm_hash = BytesN::from_array(&env, &[7; 32]);
+
+        factory.set_account_wasm_hash(&wasm_hash);
+        assert_eq!(env.auths()[0].0, admin);
+        assert_eq!(factory.get_account_wasm_hash(), wasm_hash);
+    }
+
+    #[test]
+    #[should_panic(expected = "Already initialized")]
+    fn test_initialize_twice_panics() {
+        let env = Env::default();
+        let (factory, admin) = setup(&env);
+        factory.initialize(&admin, &BytesN::from_array(&env, &[0; 32]));
+    }
+}
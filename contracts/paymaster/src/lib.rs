@@ -0,0 +1,505 @@
+#![no_std]
+
+//! # Ancore Paymaster
+//!
+//! Sponsors network fees for Ancore accounts so their users never need XLM.
+//!
+//! ## Sponsorship
+//! On Soroban the transaction source always pays the network fee, so a
+//! sponsored call is submitted by a relayer: the owner signs only the
+//! account's `execute_sponsored`, the relayer pays the fee, and the account
+//! asks the paymaster to reimburse the relayer from the XLM it holds.
+//!
+//! The admin sets the policy: which relayers may be reimbursed, and for each
+//! sponsored account the largest fee per call, the total budget and when the
+//! sponsorship ends. `sponsor` must be authorized by the account, so only
+//! owner-approved calls can spend an account's budget.
+//!
+//! ## Token repayment
+//! Accounts without a sponsorship can still avoid holding XLM by repaying
+//! the relayer's fee in another accepted token. `quote` converts the fee
+//! through the price oracle, and `sponsor_for_token` takes the quoted
+//! amount from the account and pays the relayer in XLM from the paymaster's
+//! funds, all in the same execution.
+
+use soroban_sdk::{
+    contract, contractclient, contracterror, contractimpl, contracttype, symbol_short, token,
+    Address, Env,
+};
+
+/// Fixed-point scale of oracle prices, as in the account's value cap.
+pub const PRICE_SCALE: i128 = 10_000_000;
+
+/// Price source used to quote fees in other tokens.
+#[contractclient(name = "PriceOracleClient")]
+pub trait PriceOracle {
+    /// Price of one unit of `token` in units of `reference`, scaled by
+    /// [`PRICE_SCALE`].
+    fn price(env: Env, token: Address, reference: Address) -> i128;
+}
+
+/// Errors returned by the paymaster contract.
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum ContractError {
+    /// The account has no sponsorship
+    NotSponsored = 1,
+    /// The account's sponsorship has ended
+    SponsorshipExpired = 2,
+    /// The fee is above the sponsorship's per-call maximum
+    FeeTooHigh = 3,
+    /// The fee is above what is left of the sponsorship's budget
+    BudgetExhausted = 4,
+    /// The relayer is not approved for reimbursement
+    UnknownRelayer = 5,
+    /// An amount is zero or negative
+    InvalidAmount = 6,
+    /// The token is not accepted for fee repayment
+    UnsupportedToken = 7,
+    /// The oracle returned a zero or negative price
+    InvalidPrice = 8,
+    /// A quote calculation overflowed
+    Overflow = 9,
+}
+
+/// Fees the paymaster covers for one account
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Sponsorship {
+    /// Largest fee reimbursed for a single call
+    pub max_fee: i128,
+    /// Total left to reimburse
+    pub remaining: i128,
+    /// Timestamp from which the sponsorship no longer applies
+    pub expires_at: u64,
+}
+
+#[contracttype]
+#[derive(Clone)]
+pub enum DataKey {
+    Admin,
+    /// Token fees are reimbursed in, normally the native XLM contract
+    Token,
+    Relayer(Address),
+    Sponsorship(Address),
+    Oracle,
+    /// Token accepted for fee repayment
+    FeeToken(Address),
+}
+
+#[contract]
+pub struct AncorePaymaster;
+
+#[contractimpl]
+impl AncorePaymaster {
+    /// Initialize the paymaster with an admin and the token it pays fees in
+    pub fn initialize(env: Env, admin: Address, token: Address) {
+        if env.storage().instance().has(&DataKey::Admin) {
+            panic!("Already initialized");
+        }
+
+        env.storage().instance().set(&DataKey::Admin, &admin);
+        env.storage().instance().set(&DataKey::Token, &token);
+    }
+
+    /// Get the paymaster admin
+    pub fn get_admin(env: Env) -> Address {
+        env.storage()
+            .instance()
+            .get(&DataKey::Admin)
+            .expect("Not initialized")
+    }
+
+    /// Get the token fees are reimbursed in
+    pub fn get_token(env: Env) -> Address {
+        env.storage()
+            .instance()
+            .get(&DataKey::Token)
+            .expect("Not initialized")
+    }
+
+    /// Allow or disallow reimbursing `relayer`
+    pub fn set_relayer(env: Env, relayer: Address, allowed: bool) {
+        let admin = Self::get_admin(env.clone());
+        admin.require_auth();
+
+        let key = DataKey::Relayer(relayer.clone());
+        if allowed {
+            env.storage().persistent().set(&key, &true);
+        } else {
+            env.storage().persistent().remove(&key);
+        }
+
+        env.events().publish(
+            (symbol_short!("paymaster"), symbol_short!("relayer")),
+            (relayer, allowed),
+        );
+    }
+
+    /// Whether `relayer` can be reimbursed
+    pub fn is_relayer(env: Env, relayer: Address) -> bool {
+        env.storage().persistent().has(&DataKey::Relayer(relayer))
+    }
+
+    /// Sponsor `account`'s fees, replacing any existing sponsorship
+    pub fn set_sponsorship(
+        env: Env,
+        account: Address,
+        max_fee: i128,
+        budget: i128,
+        expires_at: u64,
+    ) -> Result<(), ContractError> {
+        let admin = Self::get_admin(env.clone());
+        admin.require_auth();
+
+        if max_fee <= 0 || budget <= 0 {
+            return Err(ContractError::InvalidAmount);
+        }
+
+        let sponsorship = Sponsorship {
+            max_fee,
+            remaining: budget,
+            expires_at,
+        };
+        env.storage()
+            .persistent()
+            .set(&DataKey::Sponsorship(account.clone()), &sponsorship);
+
+        env.events().publish(
+            (symbol_short!("paymaster"), symbol_short!("sp_set")),
+            (account, sponsorship),
+        );
+        Ok(())
+    }
+
+    /// Stop sponsoring `account`
+    pub fn revoke_sponsorship(env: Env, account: Address) {
+        let admin = Self::get_admin(env.clone());
+        admin.require_auth();
+
+        env.storage()
+            .persistent()
+            .remove(&DataKey::Sponsorship(account.clone()));
+
+        env.events().publish(
+            (symbol_short!("paymaster"), symbol_short!("sp_revoke")),
+            account,
+        );
+    }
+
+    /// Get `account`'s sponsorship, if any
+    pub fn get_sponsorship(env: Env, account: Address) -> Option<Sponsorship> {
+        env.storage()
+            .persistent()
+            .get(&DataKey::Sponsorship(account))
+    }
+
+    /// Reimburse `relayer` the `fee` it paid to submit a call for `account`
+    ///
+    /// Must be authorized by the account. The fee is checked against the
+    /// account's sponsorship and deducted from its budget.
+    pub fn sponsor(
+        env: Env,
+        account: Address,
+        relayer: Address,
+        fee: i128,
+    ) -> Result<(), ContractError> {
+        account.require_auth();
+
+        if fee <= 0 {
+            return Err(ContractError::InvalidAmount);
+        }
+        if !Self::is_relayer(env.clone(), relayer.clone()) {
+            return Err(ContractError::UnknownRelayer);
+        }
+
+        let mut sponsorship = Self::get_sponsorship(env.clone(), account.clone())
+            .ok_or(ContractError::NotSponsored)?;
+        if env.ledger().timestamp() >= sponsorship.expires_at {
+            return Err(ContractError::SponsorshipExpired);
+        }
+        if fee > sponsorship.max_fee {
+            return Err(ContractError::FeeTooHigh);
+        }
+        if fee > sponsorship.remaining {
+            return Err(ContractError::BudgetExhausted);
+        }
+
+        sponsorship.remaining -= fee;
+        env.storage()
+            .persistent()
+            .set(&DataKey::Sponsorship(account.clone()), &sponsorship);
+
+        token::Client::new(&env, &Self::get_token(env.clone())).transfer(
+            &env.current_contract_address(),
+            &relayer,
+            &fee,
+        );
+
+        env.events().publish(
+            (symbol_short!("paymaster"), symbol_short!("sponsored")),
+            (account, relayer, fee),
+        );
+        Ok(())
+    }
+
+    /// Set the oracle fees are quoted in other tokens with
+    pub fn set_oracle(env: Env, oracle: Address) {
+        let admin = Self::get_admin(env.clone());
+        admin.require_auth();
+
+        env.storage().instance().set(&DataKey::Oracle, &oracle);
+    }
+
+    /// Get the oracle fees are quoted with, if any
+    pub fn get_oracle(env: Env) -> Option<Address> {
+        env.storage().instance().get(&DataKey::Oracle)
+    }
+
+    /// Accept or stop accepting `token` for fee repayment
+    pub fn set_fee_token(env: Env, token: Address, accepted: bool) {
+        let admin = Self::get_admin(env.clone());
+        admin.require_auth();
+
+        let key = DataKey::FeeToken(token.clone());
+        if accepted {
+            env.storage().persistent().set(&key, &true);
+        } else {
+            env.storage().persistent().remove(&key);
+        }
+
+        env.events().publish(
+            (symbol_short!("paymaster"), symbol_short!("fee_token")),
+            (token, accepted),
+        );
+    }
+
+    /// Whether `token` is accepted for fee repayment
+    pub fn is_fee_token(env: Env, token: Address) -> bool {
+        env.storage().persistent().has(&DataKey::FeeToken(token))
+    }
+
+    /// Get how much of `token` repays a `fee` paid in the paymaster's token
+    ///
+    /// Rounds up, so the paymaster is never repaid less than the fee.
+    pub fn quote(env: Env, fee: i128, token: Address) -> Result<i128, ContractError> {
+        if fee <= 0 {
+            return Err(ContractError::InvalidAmount);
+        }
+        if !Self::is_fee_token(env.clone(), token.clone()) {
+            return Err(ContractError::UnsupportedToken);
+        }
+
+        let fee_token = Self::get_token(env.clone());
+        if token == fee_token {
+            return Ok(fee);
+        }
+
+        let oracle = Self::get_oracle(env.clone()).ok_or(ContractError::UnsupportedToken)?;
+        let price = PriceOracleClient::new(&env, &oracle).price(&fee_token, &token);
+        if price <= 0 {
+            return Err(ContractError::InvalidPrice);
+        }
+        let scaled = fee
+            .checked_mul(price)
+            .and_then(|scaled| scaled.checked_add(PRICE_SCALE - 1))
+            .ok_or(ContractError::Overflow)?;
+        Ok(scaled / PRICE_SCALE)
+    }
+
+    /// Reimburse `relayer` the `fee` it paid for `account`, repaid in `token`
+    ///
+    /// Must be authorized by the account, which must also authorize the
+    /// paymaster to transfer the quoted amount of `token` from it. Needs no
+    /// sponsorship, since the account pays. Returns the amount taken.
+    pub fn sponsor_for_token(
+        env: Env,
+        account: Address,
+        relayer: Address,
+        fee: i128,
+        token: Address,
+    ) -> Result<i128, ContractError> {
+        account.require_auth();
+
+        if !Self::is_relayer(env.clone(), relayer.clone()) {
+            return Err(ContractError::UnknownRelayer);
+        }
+        let amount = Self::quote(env.clone(), fee, token.clone())?;
+
+        token::Client::new(&env, &token).transfer(
+            &account,
+            &env.current_contract_address(),
+            &amount,
+        );
+        token::Client::new(&env, &Self::get_token(env.clone())).transfer(
+            &env.current_contract_address(),
+            &relayer,
+            &fee,
+        );
+
+        env.events().publish(
+            (symbol_short!("paymaster"), symbol_short!("repaid")),
+            (account, relayer, fee, token, amount),
+        );
+        Ok(amount)
+    }
+
+    /// Withdraw `amount` of the fee token to `to`
+    pub fn withdraw(env: Env, to: Address, amount: i128) -> Result<(), ContractError> {
+        let admin = Self::get_admin(env.clone());
+        admin.require_auth();
+
+        if amount <= 0 {
+            return Err(ContractError::InvalidAmount);
+        }
+
+        token::Client::new(&env, &Self::get_token(env.clone())).transfer(
+            &env.current_contract_address(),
+            &to,
+            &amount,
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use soroban_sdk::testutils::{Address as _, Ledger as _};
+    use soroban_sdk::{contract, contractimpl};
+
+    struct Setup<'a> {
+        paymaster: AncorePaymasterClient<'a>,
+        token: token::Client<'a>,
+        account: Address,
+        relayer: Address,
+    }
+
+    fn setup(env: &Env) -> Setup<'_> {
+        env.mock_all_auths();
+        let admin = Address::generate(env);
+        let token = env.register_stellar_asset_contract_v2(admin.clone());
+        let contract_id = env.register_contract(None, AncorePaymaster);
+        let paymaster = AncorePaymasterClient::new(env, &contract_id);
+        paymaster.initialize(&admin, &token.address());
+        token::StellarAssetClient::new(env, &token.address()).mint(&contract_id, &1_000);
+
+        let account = Address::generate(env);
+        let relayer = Address::generate(env);
+        paymaster.set_relayer(&relayer, &true);
+        paymaster.set_sponsorship(&account, &100, &150, &1_000);
+
+        Setup {
+            paymaster,
+            token: token::Client::new(env, &token.address()),
+            account,
+            relayer,
+        }
+    }
+
+    #[test]
+    fn test_sponsor_reimburses_relayer() {
+        let env = Env::default();
+        let s = setup(&env);
+
+        s.paymaster.sponsor(&s.account, &s.relayer, &60);
+        assert_eq!(env.auths()[0].0, s.account);
+        assert_eq!(s.token.balance(&s.relayer), 60);
+        assert_eq!(s.token.balance(&s.paymaster.address), 940);
+        assert_eq!(
+            s.paymaster.get_sponsorship(&s.account).unwrap().remaining,
+            90
+        );
+    }
+
+    #[test]
+    fn test_sponsor_enforces_policy() {
+        let env = Env::default();
+        let s = setup(&env);
+
+        assert_eq!(
+            s.paymaster.try_sponsor(&s.account, &s.relayer, &101),
+            Err(Ok(ContractError::FeeTooHigh))
+        );
+        assert_eq!(
+            s.paymaster
+                .try_sponsor(&s.account, &Address::generate(&env), &10),
+            Err(Ok(ContractError::UnknownRelayer))
+        );
+        assert_eq!(
+            s.paymaster
+                .try_sponsor(&Address::generate(&env), &s.relayer, &10),
+            Err(Ok(ContractError::NotSponsored))
+        );
+
+        s.paymaster.sponsor(&s.account, &s.relayer, &100);
+        assert_eq!(
+            s.paymaster.try_sponsor(&s.account, &s.relayer, &60),
+            Err(Ok(ContractError::BudgetExhausted))
+        );
+
+        env.ledger().with_mut(|li| li.timestamp = 1_000);
+        assert_eq!(
+            s.paymaster.try_sponsor(&s.account, &s.relayer, &10),
+            Err(Ok(ContractError::SponsorshipExpired))
+        );
+    }
+
+    #[contract]
+    struct MockOracle;
+
+    #[contractimpl]
+    impl MockOracle {
+        /// One unit of anything is worth 0.12 units of anything else.
+        pub fn price(_env: Env, _token: Address, _reference: Address) -> i128 {
+            PRICE_SCALE * 12 / 100
+        }
+    }
+
+    #[test]
+    fn test_sponsor_for_token_takes_quoted_amount() {
+        let env = Env::default();
+        let s = setup(&env);
+        let usdc = env.register_stellar_asset_contract_v2(Address::generate(&env));
+        let usdc_client = token::Client::new(&env, &usdc.address());
+        let payer = Address::generate(&env);
+        token::StellarAssetClient::new(&env, &usdc.address()).mint(&payer, &1_000);
+
+        assert_eq!(
+            s.paymaster.try_quote(&50, &usdc.address()),
+            Err(Ok(ContractError::UnsupportedToken))
+        );
+        s.paymaster.set_fee_token(&usdc.address(), &true);
+        s.paymaster
+            .set_oracle(&env.register_contract(None, MockOracle));
+
+        // 0.12 * 55 = 6.6, rounded up.
+        assert_eq!(s.paymaster.quote(&55, &usdc.address()), 7);
+
+        let amount = s
+            .paymaster
+            .sponsor_for_token(&payer, &s.relayer, &55, &usdc.address());
+        assert_eq!(amount, 7);
+        assert_eq!(usdc_client.balance(&payer), 993);
+        assert_eq!(usdc_client.balance(&s.paymaster.address), 7);
+        assert_eq!(s.token.balance(&s.relayer), 55);
+    }
+
+    #[test]
+    fn test_revoke_and_withdraw() {
+        let env = Env::default();
+        let s = setup(&env);
+        let treasury = Address::generate(&env);
+
+        s.paymaster.revoke_sponsorship(&s.account);
+        assert_eq!(s.paymaster.get_sponsorship(&s.account), None);
+
+        s.paymaster.withdraw(&treasury, &400);
+        assert_eq!(s.token.balance(&treasury), 400);
+        assert_eq!(
+            s.paymaster.try_withdraw(&treasury, &0),
+            Err(Ok(ContractError::InvalidAmount))
+        );
+    }
+}
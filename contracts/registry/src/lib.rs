@@ -0,0 +1,331 @@
+#![no_std]
+
+//! # Ancore Account Registry
+//!
+//! On-chain index from owner and guardian addresses to the Ancore accounts
+//! they control, so a wallet can find "which accounts do I control?"
+//! without an off-chain indexer.
+//!
+//! ## Updates
+//! - The factory registers every account it deploys, with its owner.
+//! - A registered account pushes its new owner and guardians with `update`
+//!   once its owner has pointed it at this registry.
+//! - Anyone can `sync` a registered account, which reads its current owner
+//!   and guardians from the account itself.
+//!
+//! Only accounts registered by the factory are indexed, so other contracts
+//! cannot list themselves under someone else's address.
+
+use soroban_sdk::{
+    contract, contractclient, contracterror, contractimpl, contracttype, symbol_short, Address,
+    Env, Vec,
+};
+
+/// Errors returned by the registry contract.
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum ContractError {
+    /// The account was not registered by the factory
+    NotRegistered = 1,
+    /// The account is already registered
+    AlreadyRegistered = 2,
+}
+
+/// The part of the account interface the registry reads.
+#[contractclient(name = "AccountClient")]
+pub trait Account {
+    fn get_owner(env: Env) -> Address;
+    fn get_guardians(env: Env) -> Vec<Address>;
+}
+
+#[contracttype]
+#[derive(Clone)]
+pub enum DataKey {
+    Factory,
+    /// Current owner of a registered account
+    Owner(Address),
+    /// Current guardians of a registered account
+    Guardians(Address),
+    /// Accounts an address owns
+    Owned(Address),
+    /// Accounts an address guards
+    Guarded(Address),
+}
+
+#[contract]
+pub struct AncoreRegistry;
+
+#[contractimpl]
+impl AncoreRegistry {
+    /// Initialize the registry with the factory allowed to register accounts
+    pub fn initialize(env: Env, factory: Address) {
+        if env.storage().instance().has(&DataKey::Factory) {
+            panic!("Already initialized");
+        }
+
+        env.storage().instance().set(&DataKey::Factory, &factory);
+    }
+
+    /// Get the factory allowed to register accounts
+    pub fn get_factory(env: Env) -> Address {
+        env.storage()
+            .instance()
+            .get(&DataKey::Factory)
+            .expect("Not initialized")
+    }
+
+    /// Register a newly deployed account under its owner
+    ///
+    /// Only the factory can register accounts.
+    pub fn register(env: Env, account: Address, owner: Address) -> Result<(), ContractError> {
+        Self::get_factory(env.clone()).require_auth();
+
+        if owner_of(&env, &account).is_some() {
+            return Err(ContractError::AlreadyRegistered);
+        }
+
+        record(&env, &account, &owner, &Vec::new(&env));
+        Ok(())
+    }
+
+    /// Record a registered account's current owner and guardians
+    ///
+    /// Must be authorized by the account; accounts call it themselves when
+    /// their owner or guardians change.
+    pub fn update(
+        env: Env,
+        account: Address,
+        owner: Address,
+        guardians: Vec<Address>,
+    ) -> Result<(), ContractError> {
+        account.require_auth();
+        check_registered(&env, &account)?;
+
+        record(&env, &account, &owner, &guardians);
+        Ok(())
+    }
+
+    /// Refresh a registered account's owner and guardians from the account
+    pub fn sync(env: Env, account: Address) -> Result<(), ContractError> {
+        check_registered(&env, &account)?;
+
+        let client = AccountClient::new(&env, &account);
+        record(&env, &account, &client.get_owner(), &client.get_guardians());
+        Ok(())
+    }
+
+    /// Get the recorded owner of an account, if it is registered
+    pub fn get_account_owner(env: Env, account: Address) -> Option<Address> {
+        owner_of(&env, &account)
+    }
+
+    /// Get the recorded guardians of an account
+    pub fn get_account_guardians(env: Env, account: Address) -> Vec<Address> {
+        list(&env, &DataKey::Guardians(account))
+    }
+
+    /// Get the accounts `owner` owns
+    pub fn get_owned_accounts(env: Env, owner: Address) -> Vec<Address> {
+        list(&env, &DataKey::Owned(owner))
+    }
+
+    /// Get the accounts `guardian` guards
+    pub fn get_guarded_accounts(env: Env, guardian: Address) -> Vec<Address> {
+        list(&env, &DataKey::Guarded(guardian))
+    }
+}
+
+fn owner_of(env: &Env, account: &Address) -> Option<Address> {
+    env.storage()
+        .persistent()
+        .get(&DataKey::Owner(account.clone()))
+}
+
+fn check_registered(env: &Env, account: &Address) -> Result<(), ContractError> {
+    if owner_of(env, account).is_none() {
+        return Err(ContractError::NotRegistered);
+    }
+    Ok(())
+}
+
+fn list(env: &Env, key: &DataKey) -> Vec<Address> {
+    env.storage()
+        .persistent()
+        .get(key)
+        .unwrap_or_else(|| Vec::new(env))
+}
+
+fn add(env: &Env, key: DataKey, account: &Address) {
+    let mut accounts = list(env, &key);
+    if !accounts.contains(account) {
+        accounts.push_back(account.clone());
+        env.storage().persistent().set(&key, &accounts);
+    }
+}
+
+fn remove(env: &Env, key: DataKey, account: &Address) {
+    let mut accounts = list(env, &key);
+    if let Some(index) = accounts.first_index_of(account) {
+        accounts.remove(index);
+        if accounts.is_empty() {
+            env.storage().persistent().remove(&key);
+        } else {
+            env.storage().persistent().set(&key, &accounts);
+        }
+    }
+}
+
+/// Move `account` to `owner` and `guardians` in every index.
+fn record(env: &Env, account: &Address, owner: &Address, guardians: &Vec<Address>) {
+    if let Some(previous) = owner_of(env, account) {
+        remove(env, DataKey::Owned(previous), account);
+    }
+    for guardian in list(env, &DataKey::Guardians(account.clone())).iter() {
+        remove(env, DataKey::Guarded(guardian), account);
+    }
+
+    let storage = env.storage().persistent();
+    storage.set(&DataKey::Owner(account.clone()), owner);
+    add(env, DataKey::Owned(owner.clone()), account);
+    if guardians.is_empty() {
+        storage.remove(&DataKey::Guardians(account.clone()));
+    } else {
+        storage.set(&DataKey::Guardians(account.clone()), guardians);
+    }
+    for guardian in guardians.iter() {
+        add(env, DataKey::Guarded(guardian), account);
+    }
+
+    env.events().publish(
+        (symbol_short!("registry"), symbol_short!("update")),
+        (account.clone(), owner.clone(), guardians.clone()),
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use soroban_sdk::{testutils::Address as _, vec};
+
+    /// Stand-in for an account, with a settable owner and guardians.
+    #[contract]
+    struct MockAccount;
+
+    #[contractimpl]
+    impl MockAccount {
+        pub fn set(env: Env, owner: Address, guardians: Vec<Address>) {
+            env.storage().instance().set(&0u32, &owner);
+            env.storage().instance().set(&1u32, &guardians);
+        }
+
+        pub fn get_owner(env: Env) -> Address {
+            env.storage().instance().get(&0u32).unwrap()
+        }
+
+        pub fn get_guardians(env: Env) -> Vec<Address> {
+            env.storage().instance().get(&1u32).unwrap()
+        }
+    }
+
+    fn setup(env: &Env) -> (AncoreRegistryClient<'_>, Address) {
+        env.mock_all_auths();
+        let contract_id = env.register_contract(None, AncoreRegistry);
+        let registry = AncoreRegistryClient::new(env, &contract_id);
+        let factory = Address::generate(env);
+        registry.initialize(&factory);
+        (registry, factory)
+    }
+
+    #[test]
+    fn test_register_requires_factory() {
+        let env = Env::default();
+        let (registry, factory) = setup(&env);
+        let account = Address::generate(&env);
+        let owner = Address::generate(&env);
+
+        registry.register(&account, &owner);
+        assert_eq!(env.auths()[0].0, factory);
+        assert_eq!(registry.get_account_owner(&account), Some(owner.clone()));
+        assert_eq!(
+            registry.get_owned_accounts(&owner),
+            vec![&env, account.clone()]
+        );
+
+        assert_eq!(
+            registry.try_register(&account, &owner),
+            Err(Ok(ContractError::AlreadyRegistered))
+        );
+    }
+
+    #[test]
+    fn test_update_moves_account_between_indices() {
+        let env = Env::default();
+        let (registry, _) = setup(&env);
+        let account = Address::generate(&env);
+        let owner = Address::generate(&env);
+        let new_owner = Address::generate(&env);
+        let guardian = Address::generate(&env);
+        registry.register(&account, &owner);
+
+        registry.update(&account, &owner, &vec![&env, guardian.clone()]);
+        assert_eq!(env.auths()[0].0, account);
+        assert_eq!(
+            registry.get_guarded_accounts(&guardian),
+            vec![&env, account.clone()]
+        );
+
+        registry.update(&account, &new_owner, &Vec::new(&env));
+        assert!(registry.get_owned_accounts(&owner).is_empty());
+        assert_eq!(
+            registry.get_owned_accounts(&new_owner),
+            vec![&env, account.clone()]
+        );
+        assert!(registry.get_guarded_accounts(&guardian).is_empty());
+        assert!(registry.get_account_guardians(&account).is_empty());
+    }
+
+    #[test]
+    fn test_update_rejects_unregistered_account() {
+        let env = Env::default();
+        let (registry, _) = setup(&env);
+        let account = Address::generate(&env);
+
+        assert_eq!(
+            registry.try_update(&account, &Address::generate(&env), &Vec::new(&env)),
+            Err(Ok(ContractError::NotRegistered))
+        );
+        assert_eq!(
+            registry.try_sync(&account),
+            Err(Ok(ContractError::NotRegistered))
+        );
+    }
+
+    #[test]
+    fn test_sync_reads_account_state() {
+        let env = Env::default();
+        let (registry, _) = setup(&env);
+        let account = env.register_contract(None, MockAccount);
+        let owner = Address::generate(&env);
+        let new_owner = Address::generate(&env);
+        let guardian = Address::generate(&env);
+        registry.register(&account, &owner);
+
+        MockAccountClient::new(&env, &account).set(&new_owner, &vec![&env, guardian.clone()]);
+        registry.sync(&account);
+
+        assert_eq!(
+            registry.get_account_owner(&account),
+            Some(new_owner.clone())
+        );
+        assert!(registry.get_owned_accounts(&owner).is_empty());
+        assert_eq!(
+            registry.get_owned_accounts(&new_owner),
+            vec![&env, account.clone()]
+        );
+        assert_eq!(
+            registry.get_guarded_accounts(&guardian),
+            vec![&env, account]
+        );
+    }
+}